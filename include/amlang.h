@@ -0,0 +1,56 @@
+/* The AM language, embedded through its C ABI (src/capi.rs). */
+
+#ifndef AMLANG_H
+#define AMLANG_H
+
+/* Generated by cbindgen from src/capi.rs; do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// An interpreter and the message of its last failure.
+typedef struct AmInterpreter AmInterpreter;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// A new interpreter with no definitions, to be released with `am_free`.
+AmInterpreter *am_new(void);
+
+// Release an interpreter. Null is ignored.
+//
+// # Safety
+// `am` is null or came from `am_new` and has not been freed.
+void am_free(AmInterpreter *am);
+
+// Add the definitions in the UTF-8 text `src`. Nothing is added if any
+// of them has an error.
+//
+// # Safety
+// `am` came from `am_new`, and `src` is a NUL-terminated string.
+int am_load(AmInterpreter *am, const char *src);
+
+// Call algorithm (or builtin) `name` with the `nargs` numbers at `args`,
+// storing its result in `*out`. A result that is not a number fails.
+//
+// # Safety
+// `am` came from `am_new`, `name` is a NUL-terminated string, `args`
+// points at `nargs` doubles (or is anything when `nargs` is 0), and
+// `out` is writable.
+int am_call(AmInterpreter *am, const char *name, const double *args, uintptr_t nargs, double *out);
+
+// The message of the last failure, or null if nothing has failed yet.
+// It stays valid until the next call that fails or `am_free`.
+//
+// # Safety
+// `am` is null or came from `am_new`.
+const char *am_last_error(const AmInterpreter *am);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* AMLANG_H */
@@ -1,8 +1,175 @@
+use crate::token::Span;
+
 #[derive(Debug, Clone)]
-pub enum Expr {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+
+    /// Whether this is `_`, which stands for the piped value among a
+    /// pipeline step's arguments.
+    pub fn is_placeholder(&self) -> bool {
+        matches!(&self.kind, ExprKind::Ident(name) if name == "_")
+    }
+
+    /// The sub-expressions directly inside this one, in source order.
+    pub fn children(&self) -> Vec<&Expr> {
+        match &self.kind {
+            ExprKind::Number(_)
+            | ExprKind::Imag(_)
+            | ExprKind::Bool(_)
+            | ExprKind::Str(_)
+            | ExprKind::Ident(_)
+            | ExprKind::AlgRef(_) => Vec::new(),
+            ExprKind::Call { args, .. } => args.iter().collect(),
+            ExprKind::Unary { expr, .. } => vec![expr],
+            ExprKind::Bin { left, right, .. } => vec![left, right],
+            ExprKind::Case { arms, default } => arms
+                .iter()
+                .flat_map(|(c, r)| [c, r])
+                .chain(default.as_deref())
+                .collect(),
+            ExprKind::Pipe { head, steps } => std::iter::once(&**head).chain(steps).collect(),
+            ExprKind::List(items) | ExprKind::Tuple(items) => items.iter().collect(),
+            ExprKind::Lambda { body, .. } => vec![body],
+            ExprKind::Series { from, to, body, .. } => vec![from, to, body],
+            ExprKind::Solve { equation, .. } => vec![equation],
+        }
+    }
+
+    /// This expression rebuilt with `f` applied to each direct
+    /// sub-expression, in source order.
+    pub fn map_children(&self, mut f: impl FnMut(&Expr) -> Expr) -> Expr {
+        let kind = match &self.kind {
+            ExprKind::Call { is_alg, name, args } => ExprKind::Call {
+                is_alg: *is_alg,
+                name: name.clone(),
+                args: args.iter().map(f).collect(),
+            },
+            ExprKind::Unary { op, expr } => ExprKind::Unary {
+                op: *op,
+                expr: Box::new(f(expr)),
+            },
+            ExprKind::Bin { op, left, right } => ExprKind::Bin {
+                op: *op,
+                left: Box::new(f(left)),
+                right: Box::new(f(right)),
+            },
+            ExprKind::Case { arms, default } => ExprKind::Case {
+                arms: arms.iter().map(|(c, r)| (f(c), f(r))).collect(),
+                default: default.as_deref().map(|d| Box::new(f(d))),
+            },
+            ExprKind::List(items) => ExprKind::List(items.iter().map(f).collect()),
+            ExprKind::Tuple(items) => ExprKind::Tuple(items.iter().map(f).collect()),
+            ExprKind::Pipe { head, steps } => ExprKind::Pipe {
+                head: Box::new(f(head)),
+                steps: steps.iter().map(f).collect(),
+            },
+            ExprKind::Lambda { params, body } => ExprKind::Lambda {
+                params: params.clone(),
+                body: Box::new(f(body)),
+            },
+            ExprKind::Series {
+                kind,
+                index,
+                from,
+                to,
+                body,
+            } => ExprKind::Series {
+                kind: *kind,
+                index: index.clone(),
+                from: Box::new(f(from)),
+                to: Box::new(f(to)),
+                body: Box::new(f(body)),
+            },
+            ExprKind::Solve { equation, var } => ExprKind::Solve {
+                equation: Box::new(f(equation)),
+                var: var.clone(),
+            },
+            other => other.clone(),
+        };
+        Expr::new(kind, self.span)
+    }
+}
+
+/// A pass over an expression tree. `visit_expr` is called for each node,
+/// parents before children; its default just continues into the children,
+/// and an override calls `walk_expr` to do the same.
+///
+/// ```
+/// use amlang::ast::{Expr, ExprKind, Visitor, walk_expr};
+///
+/// struct Calls(Vec<String>);
+///
+/// impl Visitor for Calls {
+///     fn visit_expr(&mut self, e: &Expr) {
+///         if let ExprKind::Call { name, .. } = &e.kind {
+///             self.0.push(name.clone());
+///         }
+///         walk_expr(self, e);
+///     }
+/// }
+///
+/// let (program, _) = amlang::parse("@F(x) = sqrt(abs(x)) + @G(x)").unwrap();
+/// let mut calls = Calls(Vec::new());
+/// calls.visit_expr(&program.defs[0].body);
+/// assert_eq!(calls.0, ["sqrt", "abs", "G"]);
+/// ```
+pub trait Visitor {
+    fn visit_expr(&mut self, e: &Expr) {
+        walk_expr(self, e);
+    }
+}
+
+/// Visit each direct sub-expression of `e`.
+pub fn walk_expr<V: Visitor + ?Sized>(v: &mut V, e: &Expr) {
+    for child in e.children() {
+        v.visit_expr(child);
+    }
+}
+
+/// Call `f` on `e` and every expression inside it, parents first.
+pub fn for_each_expr<'e>(e: &'e Expr, f: &mut impl FnMut(&'e Expr)) {
+    f(e);
+    for child in e.children() {
+        for_each_expr(child, f);
+    }
+}
+
+/// The arguments of a pipeline step called with `piped`: it replaces each
+/// `_` placeholder, or goes first when there is none.
+pub fn pipe_args(args: &[Expr], piped: Expr) -> Vec<Expr> {
+    if !args.iter().any(Expr::is_placeholder) {
+        return std::iter::once(piped).chain(args.iter().cloned()).collect();
+    }
+    args.iter()
+        .map(|a| {
+            if a.is_placeholder() {
+                piped.clone()
+            } else {
+                a.clone()
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ExprKind {
     Number(f64),
+    /// An imaginary literal such as `2i`, holding its coefficient.
+    Imag(f64),
     Bool(bool),
+    Str(String),
     Ident(String),
+    /// `@Name` without arguments: the algorithm itself, as a value.
+    AlgRef(String),
     Call {
         is_alg: bool,
         name: String,
@@ -19,21 +186,64 @@ pub enum Expr {
     },
     Case {
         arms: Vec<(Expr, Expr)>,
-        default: Box<Expr>,
+        default: Option<Box<Expr>>,
     },
     Pipe {
         head: Box<Expr>,
         steps: Vec<Expr>,
     }, // x >> @f >> g
+    /// `[a, b, c]`: a list; a matrix is a list of rows.
+    List(Vec<Expr>),
+    /// `(q, r)`: several values kept together, e.g. to return them at once.
+    Tuple(Vec<Expr>),
+    /// `\x -> x * x`: an anonymous function of `params`.
+    Lambda {
+        params: Vec<String>,
+        body: Box<Expr>,
+    },
+    /// `Σ(i, from, to, body)` or `Π(...)`: `body` summed, or multiplied,
+    /// over `i` from `from` to `to` in steps of one.
+    Series {
+        kind: SeriesKind,
+        index: String,
+        from: Box<Expr>,
+        to: Box<Expr>,
+        body: Box<Expr>,
+    },
+    /// `solve(lhs = rhs, x)`: the real roots of `equation` in `var`, which
+    /// it binds.
+    Solve {
+        equation: Box<Expr>,
+        var: String,
+    },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SeriesKind {
+    Sum,
+    Product,
+}
+
+impl SeriesKind {
+    /// The ASCII name it is written with, `sum` or `prod`.
+    pub fn name(self) -> &'static str {
+        match self {
+            SeriesKind::Sum => "sum",
+            SeriesKind::Product => "prod",
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum UnOp {
     Neg,
     Not,
 }
 
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BinOp {
     Add,
     Sub,
@@ -47,57 +257,182 @@ pub enum BinOp {
     Le,
     Gt,
     Ge,
+    /// `~=`: equal within `Settings::epsilon`.
+    Approx,
+    /// `a..b`: the numbers from `a` to `b` in steps of one.
+    Range,
+    /// `a..<b`: like `Range`, without `b`.
+    RangeExcl,
     And,
     Or,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AlgorithmDef {
     pub name: String,
     pub params: Vec<String>,
     pub body: Expr,
+    // marked `@memo`: results are cached by argument values
+    pub memo: bool,
+    // the `@Name(params)` header
+    pub span: Span,
+    /// Helpers from a `where` clause, callable by name only inside this
+    /// definition. Their `span` is the `name(params)` header.
+    pub locals: Vec<AlgorithmDef>,
+    /// `requires` conditions, checked on the arguments before each call.
+    pub requires: Vec<Expr>,
+    /// `ensures` conditions, checked once the body's value is bound to
+    /// `result`.
+    pub ensures: Vec<Expr>,
+    /// The `///` comment lines just before the definition, without their
+    /// slashes.
+    pub doc: Option<String>,
+}
+
+impl AlgorithmDef {
+    /// The `where` helper called `name`, if this definition has one.
+    pub fn local(&self, name: &str) -> Option<&AlgorithmDef> {
+        self.locals.iter().find(|l| l.name == name)
+    }
+
+    /// From the header to the end of the body or of the last helper.
+    pub fn full_span(&self) -> Span {
+        self.span
+            .to(self.locals.last().map_or(self.body.span, |l| l.body.span))
+    }
+}
+
+/// `import "path"`: pull in the definitions of another file.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Import {
+    pub path: String,
+    pub span: Span,
+}
+
+/// `@test "name" : expr`: a check, run by `amlang test`, that `expr` is
+/// true.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Test {
+    pub name: String,
+    pub expr: Expr,
+    // from the `@test` to the end of `expr`
+    pub span: Span,
+}
+
+/// Everything in one source file.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Program {
+    pub imports: Vec<Import>,
+    pub defs: Vec<AlgorithmDef>,
+    /// Top-level expressions, run in order once the definitions are loaded.
+    pub script: Vec<Expr>,
+    pub tests: Vec<Test>,
+}
+
+/// The parse tree of `e` as `--ast` prints it, one node per line and
+/// indented two spaces a level, starting at `indent` levels.
+pub fn expr_tree(e: &Expr, indent: usize) -> String {
+    let mut out = String::new();
+    write_expr(&mut out, e, indent);
+    out
+}
+
+/// The parse tree of `d` and its local helpers, as `--ast` prints it.
+pub fn def_tree(d: &AlgorithmDef) -> String {
+    let mut out = format!("AlgorithmDef {}({})\nbody:\n", d.name, d.params.join(","));
+    write_expr(&mut out, &d.body, 1);
+    for l in &d.locals {
+        out.push_str(&format!("where {}({}):\n", l.name, l.params.join(",")));
+        write_expr(&mut out, &l.body, 1);
+    }
+    out
 }
 
 pub fn show_expr(e: &Expr, indent: usize) {
+    print!("{}", expr_tree(e, indent));
+}
+
+fn write_expr(out: &mut String, e: &Expr, indent: usize) {
     let pad = "  ".repeat(indent);
-    match e {
-        Expr::Number(v) => println!("{pad}Number({v})"),
-        Expr::Bool(b) => println!("{pad}Bool({b})"),
-        Expr::Ident(s) => println!("{pad}Ident({s})"),
-        Expr::Call { is_alg, name, args } => {
-            println!("{pad}Call(is_alg={is_alg}, name={name})");
+    match &e.kind {
+        ExprKind::Number(v) => out.push_str(&format!("{pad}Number({v})\n")),
+        ExprKind::Imag(v) => out.push_str(&format!("{pad}Imag({v})\n")),
+        ExprKind::Bool(b) => out.push_str(&format!("{pad}Bool({b})\n")),
+        ExprKind::Str(s) => out.push_str(&format!("{pad}Str({s:?})\n")),
+        ExprKind::Ident(s) => out.push_str(&format!("{pad}Ident({s})\n")),
+        ExprKind::AlgRef(s) => out.push_str(&format!("{pad}AlgRef({s})\n")),
+        ExprKind::Call { is_alg, name, args } => {
+            out.push_str(&format!("{pad}Call(is_alg={is_alg}, name={name})\n"));
             for a in args {
-                show_expr(a, indent + 1);
+                write_expr(out, a, indent + 1);
             }
         }
-        Expr::Unary { op, expr } => {
-            println!("{pad}Unary({:?})", op);
-            show_expr(expr, indent + 1);
+        ExprKind::Unary { op, expr } => {
+            out.push_str(&format!("{pad}Unary({:?})\n", op));
+            write_expr(out, expr, indent + 1);
         }
-        Expr::Bin { op, left, right } => {
-            println!("{pad}Bin({:?})", op);
-            show_expr(left, indent + 1);
-            show_expr(right, indent + 1);
+        ExprKind::Bin { op, left, right } => {
+            out.push_str(&format!("{pad}Bin({:?})\n", op));
+            write_expr(out, left, indent + 1);
+            write_expr(out, right, indent + 1);
         }
-        Expr::Case { arms, default } => {
-            println!("{pad}Case");
+        ExprKind::Case { arms, default } => {
+            out.push_str(&format!("{pad}Case\n"));
             for (c, r) in arms {
-                println!("{pad}  Arm:");
-                show_expr(c, indent + 2);
-                println!("{pad}  =>");
-                show_expr(r, indent + 2);
+                out.push_str(&format!("{pad}  Arm:\n"));
+                write_expr(out, c, indent + 2);
+                out.push_str(&format!("{pad}  =>\n"));
+                write_expr(out, r, indent + 2);
+            }
+            if let Some(default) = default {
+                out.push_str(&format!("{pad}  Default:\n"));
+                write_expr(out, default, indent + 2);
             }
-            println!("{pad}  Default:");
-            show_expr(default, indent + 2);
         }
-        Expr::Pipe { head, steps } => {
-            println!("{pad}Pipe");
-            println!("{pad}  Head:");
-            show_expr(head, indent + 2);
+        ExprKind::Pipe { head, steps } => {
+            out.push_str(&format!("{pad}Pipe\n"));
+            out.push_str(&format!("{pad}  Head:\n"));
+            write_expr(out, head, indent + 2);
             for s in steps {
-                println!("{pad}  >> Step:");
-                show_expr(s, indent + 2);
+                out.push_str(&format!("{pad}  >> Step:\n"));
+                write_expr(out, s, indent + 2);
+            }
+        }
+        ExprKind::List(items) => {
+            out.push_str(&format!("{pad}List\n"));
+            for item in items {
+                write_expr(out, item, indent + 1);
+            }
+        }
+        ExprKind::Tuple(items) => {
+            out.push_str(&format!("{pad}Tuple\n"));
+            for item in items {
+                write_expr(out, item, indent + 1);
             }
         }
+        ExprKind::Lambda { params, body } => {
+            out.push_str(&format!("{pad}Lambda({})\n", params.join(", ")));
+            write_expr(out, body, indent + 1);
+        }
+        ExprKind::Series {
+            kind,
+            index,
+            from,
+            to,
+            body,
+        } => {
+            out.push_str(&format!("{pad}Series({:?}, {index})\n", kind));
+            write_expr(out, from, indent + 1);
+            write_expr(out, to, indent + 1);
+            write_expr(out, body, indent + 1);
+        }
+        ExprKind::Solve { equation, var } => {
+            out.push_str(&format!("{pad}Solve({var})\n"));
+            write_expr(out, equation, indent + 1);
+        }
     }
 }
@@ -0,0 +1,275 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+/// Largest magnitude at which every integer is exactly representable in f64.
+pub const MAX_EXACT_INT: f64 = 9_007_199_254_740_992.0;
+
+/// Results estimated to need more bits than this are computed in floating
+/// point instead, so one operation cannot stall the evaluator.
+pub const MAX_EXACT_BITS: f64 = 65_536.0;
+
+/// An arbitrary-precision integer: a sign and base-2^32 digits, least
+/// significant first, without trailing zeros. Zero is never negative.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BigInt {
+    negative: bool,
+    digits: Vec<u32>,
+}
+
+impl BigInt {
+    pub fn zero() -> Self {
+        Self {
+            negative: false,
+            digits: Vec::new(),
+        }
+    }
+
+    pub fn from_u64(n: u64) -> Self {
+        Self::from_parts(false, vec![n as u32, (n >> 32) as u32])
+    }
+
+    pub fn from_i64(n: i64) -> Self {
+        let mut big = Self::from_u64(n.unsigned_abs());
+        big.negative = n < 0 && !big.is_zero();
+        big
+    }
+
+    /// The integer `x`, when it is one and exactly representable.
+    pub fn from_f64(x: f64) -> Option<Self> {
+        (x.fract() == 0.0 && x.abs() <= MAX_EXACT_INT).then(|| Self::from_i64(x as i64))
+    }
+
+    fn from_parts(negative: bool, mut digits: Vec<u32>) -> Self {
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        let negative = negative && !digits.is_empty();
+        Self { negative, digits }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.digits.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    /// Bits in the magnitude.
+    pub fn bits(&self) -> u64 {
+        match self.digits.last() {
+            Some(top) => self.digits.len() as u64 * 32 - top.leading_zeros() as u64,
+            None => 0,
+        }
+    }
+
+    /// Whether the value converts to f64 without rounding.
+    pub fn fits_f64(&self) -> bool {
+        self.bits() <= 53
+    }
+
+    /// The nearest f64, or infinity when out of range.
+    pub fn to_f64(&self) -> f64 {
+        let magnitude = self
+            .digits
+            .iter()
+            .rev()
+            .fold(0.0, |acc, &d| acc * 4_294_967_296.0 + d as f64);
+        if self.negative { -magnitude } else { magnitude }
+    }
+
+    pub fn abs(&self) -> Self {
+        Self::from_parts(false, self.digits.clone())
+    }
+
+    pub fn neg(&self) -> Self {
+        Self::from_parts(!self.negative, self.digits.clone())
+    }
+
+    pub fn add(&self, other: &Self) -> Self {
+        if self.negative == other.negative {
+            return Self::from_parts(self.negative, add_mag(&self.digits, &other.digits));
+        }
+        match cmp_mag(&self.digits, &other.digits) {
+            Ordering::Less => {
+                Self::from_parts(other.negative, sub_mag(&other.digits, &self.digits))
+            }
+            _ => Self::from_parts(self.negative, sub_mag(&self.digits, &other.digits)),
+        }
+    }
+
+    pub fn sub(&self, other: &Self) -> Self {
+        self.add(&other.neg())
+    }
+
+    pub fn mul(&self, other: &Self) -> Self {
+        let (a, b) = (&self.digits, &other.digits);
+        let mut out = vec![0u32; a.len() + b.len()];
+        for (i, &x) in a.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, &y) in b.iter().enumerate() {
+                let t = out[i + j] as u64 + x as u64 * y as u64 + carry;
+                out[i + j] = t as u32;
+                carry = t >> 32;
+            }
+            out[i + b.len()] = carry as u32;
+        }
+        Self::from_parts(self.negative != other.negative, out)
+    }
+
+    /// `self^exp` by repeated squaring.
+    pub fn pow(&self, mut exp: u64) -> Self {
+        let mut base = self.clone();
+        let mut acc = Self::from_u64(1);
+        while exp > 0 {
+            if exp & 1 == 1 {
+                acc = acc.mul(&base);
+            }
+            exp >>= 1;
+            if exp > 0 {
+                base = base.mul(&base);
+            }
+        }
+        acc
+    }
+
+    /// Quotient by a small divisor, rounded toward zero, and the remainder
+    /// of the magnitude.
+    pub fn div_rem_small(&self, divisor: u32) -> (Self, u32) {
+        let mut digits = self.digits.clone();
+        let mut rem = 0u64;
+        for d in digits.iter_mut().rev() {
+            let t = (rem << 32) | *d as u64;
+            *d = (t / divisor as u64) as u32;
+            rem = t % divisor as u64;
+        }
+        (Self::from_parts(self.negative, digits), rem as u32)
+    }
+
+    /// Quotient rounded toward zero and the remainder, which takes the
+    /// sign of `self`; `None` when dividing by zero.
+    pub fn div_rem(&self, other: &Self) -> Option<(Self, Self)> {
+        if other.is_zero() {
+            return None;
+        }
+        let (q, r) = match other.digits[..] {
+            [d] => {
+                let (q, r) = self.abs().div_rem_small(d);
+                (q.digits, vec![r])
+            }
+            _ => div_rem_mag(&self.digits, &other.digits),
+        };
+        Some((
+            Self::from_parts(self.negative != other.negative, q),
+            Self::from_parts(self.negative, r),
+        ))
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_mag(&self.digits, &other.digits),
+            (true, true) => cmp_mag(&other.digits, &self.digits),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        // peel off nine decimal digits at a time
+        let mut chunks = Vec::new();
+        let mut rest = self.abs();
+        while !rest.is_zero() {
+            let (q, r) = rest.div_rem_small(1_000_000_000);
+            chunks.push(r);
+            rest = q;
+        }
+        if self.negative {
+            write!(f, "-")?;
+        }
+        let mut chunks = chunks.iter().rev();
+        if let Some(top) = chunks.next() {
+            write!(f, "{}", top)?;
+        }
+        for c in chunks {
+            write!(f, "{:09}", c)?;
+        }
+        Ok(())
+    }
+}
+
+fn cmp_mag(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut out = Vec::with_capacity(long.len() + 1);
+    let mut carry = 0u64;
+    for (i, &x) in long.iter().enumerate() {
+        let t = x as u64 + short.get(i).copied().unwrap_or(0) as u64 + carry;
+        out.push(t as u32);
+        carry = t >> 32;
+    }
+    out.push(carry as u32);
+    out
+}
+
+// `a - b` for `a >= b`.
+fn sub_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut out = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, &x) in a.iter().enumerate() {
+        let mut t = x as i64 - b.get(i).copied().unwrap_or(0) as i64 - borrow;
+        borrow = (t < 0) as i64;
+        if t < 0 {
+            t += 1 << 32;
+        }
+        out.push(t as u32);
+    }
+    out
+}
+
+// Binary long division; `b` is non-zero.
+fn div_rem_mag(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if cmp_mag(a, b) == Ordering::Less {
+        return (Vec::new(), a.to_vec());
+    }
+    let mut q = vec![0u32; a.len()];
+    let mut r: Vec<u32> = Vec::new();
+    for i in (0..a.len() * 32).rev() {
+        // r = 2r + bit i of a
+        let mut carry = (a[i / 32] >> (i % 32)) & 1;
+        for d in r.iter_mut() {
+            let next = *d >> 31;
+            *d = (*d << 1) | carry;
+            carry = next;
+        }
+        if carry != 0 {
+            r.push(carry);
+        }
+        if cmp_mag(&r, b) != Ordering::Less {
+            r = sub_mag(&r, b);
+            while r.last() == Some(&0) {
+                r.pop();
+            }
+            q[i / 32] |= 1 << (i % 32);
+        }
+    }
+    (q, r)
+}
@@ -0,0 +1,599 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::bigint::{BigInt, MAX_EXACT_BITS, MAX_EXACT_INT};
+use crate::complex::Complex;
+use crate::eval::Value;
+use crate::matrix;
+use crate::stats;
+
+pub type BuiltinFn = fn(&[Value]) -> Result<Value, String>;
+
+/// A native function callable from AM code by name.
+pub struct Builtin {
+    pub name: &'static str,
+    pub min_args: usize,
+    pub max_args: usize,
+    pub func: BuiltinFn,
+}
+
+const fn builtin(name: &'static str, args: usize, func: BuiltinFn) -> Builtin {
+    Builtin {
+        name,
+        min_args: args,
+        max_args: args,
+        func,
+    }
+}
+
+// Lift a numeric function of one argument.
+fn num1(args: &[Value], f: fn(f64) -> f64) -> Result<Value, String> {
+    Ok(Value::Number(f(args[0].as_f64()?)))
+}
+
+// Lift a function of one argument that also has a complex form.
+fn cplx1(args: &[Value], f: fn(f64) -> f64, z: fn(Complex) -> Complex) -> Result<Value, String> {
+    match args[0] {
+        Value::Complex(c) => Ok(Value::from_complex(z(c))),
+        _ => mono1(args, f),
+    }
+}
+
+// Lift a nondecreasing function of one argument, which maps an interval
+// to the interval between the images of its bounds.
+fn mono1(args: &[Value], f: fn(f64) -> f64) -> Result<Value, String> {
+    match args[0] {
+        Value::Interval(lo, hi) => Ok(Value::Interval(f(lo), f(hi))),
+        _ => num1(args, f),
+    }
+}
+
+// Lift a numeric function of two arguments.
+fn num2(args: &[Value], f: fn(f64, f64) -> f64) -> Result<Value, String> {
+    Ok(Value::Number(f(args[0].as_f64()?, args[1].as_f64()?)))
+}
+
+pub static BUILTINS: &[Builtin] = &[
+    // roots and magnitude
+    // a negative number has an imaginary root
+    builtin("sqrt", 1, |a| match a[0] {
+        Value::Number(x) if x < 0.0 => Ok(Value::Complex(Complex::new(0.0, (-x).sqrt()))),
+        _ => cplx1(a, f64::sqrt, Complex::sqrt),
+    }),
+    builtin("root", 2, root),
+    builtin("abs", 1, |a| match a[0] {
+        Value::Interval(lo, hi) if lo < 0.0 && 0.0 < hi => Ok(Value::Interval(0.0, hi.max(-lo))),
+        Value::Interval(lo, hi) if hi <= 0.0 => Ok(Value::Interval(-hi, -lo)),
+        Value::Complex(z) => Ok(Value::Number(z.abs())),
+        _ => mono1(a, f64::abs),
+    }),
+    // trigonometry
+    builtin("sin", 1, |a| num1(a, f64::sin)),
+    builtin("cos", 1, |a| num1(a, f64::cos)),
+    builtin("tan", 1, |a| num1(a, f64::tan)),
+    builtin("asin", 1, |a| mono1(a, f64::asin)),
+    builtin("acos", 1, |a| num1(a, f64::acos)),
+    builtin("atan", 1, |a| mono1(a, f64::atan)),
+    builtin("atan2", 2, |a| num2(a, f64::atan2)),
+    // exponentials and logarithms; `log(x)` is natural, `log(x, b)` base b
+    builtin("exp", 1, |a| cplx1(a, f64::exp, Complex::exp)),
+    builtin("ln", 1, |a| cplx1(a, f64::ln, Complex::ln)),
+    Builtin {
+        name: "log",
+        min_args: 1,
+        max_args: 2,
+        func: |a| match a {
+            [_] => cplx1(a, f64::ln, Complex::ln),
+            _ => num2(a, f64::log),
+        },
+    },
+    builtin("log2", 1, |a| mono1(a, f64::log2)),
+    builtin("log10", 1, |a| mono1(a, f64::log10)),
+    // rounding and comparison
+    builtin("floor", 1, |a| mono1(a, f64::floor)),
+    builtin("ceil", 1, |a| mono1(a, f64::ceil)),
+    builtin("round", 1, |a| mono1(a, f64::round)),
+    builtin("trunc", 1, |a| mono1(a, f64::trunc)),
+    builtin("min", 2, |a| num2(a, f64::min)),
+    builtin("max", 2, |a| num2(a, f64::max)),
+    builtin("clamp", 3, clamp),
+    // number theory
+    builtin("gcd", 2, |a| {
+        let (x, y) = (integer(&a[0], "gcd")?, integer(&a[1], "gcd")?);
+        Ok(Value::Number(gcd(x.unsigned_abs(), y.unsigned_abs()) as f64))
+    }),
+    builtin("lcm", 2, |a| {
+        let (x, y) = (integer(&a[0], "lcm")?, integer(&a[1], "lcm")?);
+        let (x, y) = (x.unsigned_abs(), y.unsigned_abs());
+        if x == 0 || y == 0 {
+            return Ok(Value::Number(0.0));
+        }
+        Ok(Value::Number((x / gcd(x, y)) as f64 * y as f64))
+    }),
+    builtin("factorial", 1, |a| {
+        let n = natural(&a[0], "factorial")?;
+        if n > MAX_FACTORIAL {
+            return Ok(Value::Number(f64::INFINITY));
+        }
+        Ok(Value::Number((1..=n).map(|k| k as f64).product()))
+    }),
+    builtin("binomial", 2, |a| {
+        let (n, k) = (natural(&a[0], "binomial")?, natural(&a[1], "binomial")?);
+        Ok(Value::Number(binomial(n, k)))
+    }),
+    builtin("is_prime", 1, |a| {
+        let n = integer(&a[0], "is_prime")?;
+        Ok(Value::Bool(n >= 2 && is_prime(n as u64)))
+    }),
+    builtin("mod_pow", 3, mod_pow),
+    builtin("divmod", 2, divmod),
+    // complex numbers
+    builtin("complex", 2, |a| {
+        let z = Complex::new(a[0].as_f64()?, a[1].as_f64()?);
+        Ok(Value::from_complex(z))
+    }),
+    builtin("re", 1, |a| Ok(Value::Number(a[0].as_complex()?.re))),
+    builtin("im", 1, |a| Ok(Value::Number(a[0].as_complex()?.im))),
+    builtin("conj", 1, |a| {
+        Ok(Value::from_complex(a[0].as_complex()?.conj()))
+    }),
+    builtin("arg", 1, |a| Ok(Value::Number(a[0].as_complex()?.arg()))),
+    // intervals
+    builtin("interval", 2, |a| {
+        let (lo, hi) = (a[0].as_f64()?, a[1].as_f64()?);
+        if lo.is_nan() || hi.is_nan() || lo > hi {
+            return Err(format!(
+                "interval bounds must satisfy lo <= hi, got lo = {}, hi = {}",
+                lo, hi
+            ));
+        }
+        Ok(Value::Interval(lo, hi))
+    }),
+    builtin("lo", 1, |a| Ok(Value::Number(a[0].as_interval()?.0))),
+    builtin("hi", 1, |a| Ok(Value::Number(a[0].as_interval()?.1))),
+    builtin("mid", 1, |a| {
+        let (lo, hi) = a[0].as_interval()?;
+        Ok(Value::Number(lo + (hi - lo) / 2.0))
+    }),
+    builtin("width", 1, |a| {
+        let (lo, hi) = a[0].as_interval()?;
+        Ok(Value::Number(hi - lo))
+    }),
+    builtin("unknown", 0, |_| Ok(Value::Unknown)),
+    // sequences
+    Builtin {
+        name: "list",
+        min_args: 0,
+        max_args: usize::MAX,
+        func: |a| Ok(Value::List(a.to_vec())),
+    },
+    builtin("sum", 1, |a| {
+        let items = a[0].as_list()?;
+        let sum = items.iter().map(Value::as_f64).sum::<Result<f64, _>>()?;
+        Ok(Value::Number(sum))
+    }),
+    builtin("prod", 1, |a| {
+        let items = a[0].as_list()?;
+        let prod = items
+            .iter()
+            .map(Value::as_f64)
+            .product::<Result<f64, _>>()?;
+        Ok(Value::Number(prod))
+    }),
+    // vectors and matrices
+    builtin("transpose", 1, |a| matrix::transpose(&a[0])),
+    builtin("matmul", 2, |a| matrix::matmul(&a[0], &a[1])),
+    builtin("det", 1, |a| matrix::det(&a[0])),
+    builtin("inverse", 1, |a| matrix::inverse(&a[0])),
+    builtin("linsolve", 2, |a| matrix::linsolve(&a[0], &a[1])),
+    // statistics
+    builtin("mean", 1, |a| stats::mean(&a[0])),
+    builtin("median", 1, |a| stats::median(&a[0])),
+    builtin("variance", 1, |a| stats::variance(&a[0])),
+    builtin("stddev", 1, |a| stats::stddev(&a[0])),
+    builtin("quantile", 2, |a| stats::quantile(&a[0], &a[1])),
+    builtin("mode", 1, |a| stats::mode(&a[0])),
+    builtin("correlation", 2, |a| stats::correlation(&a[0], &a[1])),
+    // these call back into AM code, so the evaluator runs them itself
+    builtin("map", 2, |_| Err(EVALUATOR_ONLY.to_string())),
+    builtin("filter", 2, |_| Err(EVALUATOR_ONLY.to_string())),
+    builtin("fold", 3, |_| Err(EVALUATOR_ONLY.to_string())),
+    builtin("iterate", 3, |_| Err(EVALUATOR_ONLY.to_string())),
+    builtin("simulate", 2, |_| Err(EVALUATOR_ONLY.to_string())),
+    builtin("bisect", 4, |_| Err(EVALUATOR_ONLY.to_string())),
+    builtin("newton", 4, |_| Err(EVALUATOR_ONLY.to_string())),
+    // strings, and the length of a sequence
+    builtin("len", 1, |a| match &a[0] {
+        Value::List(items) => Ok(Value::Number(items.len() as f64)),
+        v => Ok(Value::Number(v.as_str()?.chars().count() as f64)),
+    }),
+    // numbers written in other bases
+    builtin("to_base", 2, to_base),
+    builtin("from_base", 2, from_base),
+    // sorting and searching
+    builtin("sort", 1, sort),
+    builtin("reverse", 1, |a| match &a[0] {
+        Value::List(items) => Ok(Value::List(items.iter().rev().cloned().collect())),
+        v => Ok(Value::Str(v.as_str()?.chars().rev().collect())),
+    }),
+    builtin("contains", 2, |a| {
+        let found = a[0]
+            .as_list()?
+            .iter()
+            .any(|x| x == &a[1] || compare(x, &a[1]) == Ok(Ordering::Equal));
+        Ok(Value::Bool(found))
+    }),
+    builtin("binary_search", 2, binary_search),
+    // failing on purpose; `assert` is `true` when it holds, so it can guard
+    // a case arm
+    builtin("assert", 2, |a| {
+        if a[0].as_bool()? {
+            Ok(Value::Bool(true))
+        } else {
+            Err(format!("assertion failed: {}", message(&a[1])))
+        }
+    }),
+    builtin("error", 1, |a| Err(message(&a[0]))),
+];
+
+const EVALUATOR_ONLY: &str = "higher-order builtins need the evaluator to call their function";
+
+/// Builtins the evaluator runs itself, since they call the function they
+/// are given.
+pub const HIGHER_ORDER: [&str; 7] = [
+    "map", "filter", "fold", "iterate", "simulate", "bisect", "newton",
+];
+
+// The real `n`-th root; an odd root of a negative number is negative.
+fn root(args: &[Value]) -> Result<Value, String> {
+    let (x, n) = (args[0].as_f64()?, args[1].as_f64()?);
+    if n == 0.0 {
+        return Err("root expects a non-zero degree".to_string());
+    }
+    let odd = n.fract() == 0.0 && n % 2.0 != 0.0;
+    match x {
+        _ if x >= 0.0 => Ok(Value::Number(x.powf(1.0 / n))),
+        _ if odd => Ok(Value::Number(-(-x).powf(1.0 / n))),
+        _ => Err(format!("root({}, {}) is not a real number", x, n)),
+    }
+}
+
+// `(q, r)` with `q = floor(a / b)` and `a = q * b + r`, so `r` has the
+// sign of `b`.
+fn divmod(a: &[Value]) -> Result<Value, String> {
+    let (x, y) = (a[0].as_f64()?, a[1].as_f64()?);
+    if y == 0.0 {
+        return Err("divmod by zero".to_string());
+    }
+    let q = (x / y).floor();
+    Ok(Value::Tuple(vec![
+        Value::Number(q),
+        Value::Number(x - q * y),
+    ]))
+}
+
+// A string as its text, anything else as it prints.
+fn message(v: &Value) -> String {
+    match v {
+        Value::Str(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn clamp(args: &[Value]) -> Result<Value, String> {
+    let (x, lo, hi) = (args[0].as_f64()?, args[1].as_f64()?, args[2].as_f64()?);
+    // f64::clamp panics on these, so report them instead
+    if lo.is_nan() || hi.is_nan() || lo > hi {
+        return Err(format!(
+            "clamp bounds must satisfy lo <= hi, got lo = {}, hi = {}",
+            lo, hi
+        ));
+    }
+    Ok(Value::Number(x.clamp(lo, hi)))
+}
+
+fn radix(v: &Value, fname: &str) -> Result<u32, String> {
+    let b = integer(v, fname)?;
+    if !(2..=36).contains(&b) {
+        return Err(format!("{} expects a base from 2 to 36, got {}", fname, b));
+    }
+    Ok(b as u32)
+}
+
+// The digits of `n` in base `b`, with letters for digits past 9.
+fn to_base(args: &[Value]) -> Result<Value, String> {
+    let (n, b) = (integer(&args[0], "to_base")?, radix(&args[1], "to_base")?);
+    let mut digits = Vec::new();
+    let mut rest = n.unsigned_abs();
+    loop {
+        digits.push(char::from_digit((rest % b as u64) as u32, b).expect("digit below the base"));
+        rest /= b as u64;
+        if rest == 0 {
+            break;
+        }
+    }
+    if n < 0 {
+        digits.push('-');
+    }
+    Ok(Value::Str(digits.into_iter().rev().collect()))
+}
+
+fn from_base(args: &[Value]) -> Result<Value, String> {
+    let (s, b) = (args[0].as_str()?, radix(&args[1], "from_base")?);
+    match i64::from_str_radix(s, b) {
+        Ok(n) if n.unsigned_abs() as f64 <= MAX_EXACT_INT => Ok(Value::Number(n as f64)),
+        _ => Err(format!(
+            "from_base: {:?} is not an integer in base {}",
+            s, b
+        )),
+    }
+}
+
+// The order of two numbers or of two strings.
+fn compare(a: &Value, b: &Value) -> Result<Ordering, String> {
+    match (a, b) {
+        (Value::Str(x), Value::Str(y)) => Ok(x.cmp(y)),
+        _ => {
+            let (x, y) = (a.as_f64(), b.as_f64());
+            x.and_then(|x| y.map(|y| x.partial_cmp(&y)))
+                .ok()
+                .flatten()
+                .ok_or_else(|| format!("cannot order {} and {}", a, b))
+        }
+    }
+}
+
+fn sort(args: &[Value]) -> Result<Value, String> {
+    let mut items = args[0].as_list()?.to_vec();
+    let mut problem = None;
+    items.sort_by(|a, b| {
+        compare(a, b).unwrap_or_else(|e| {
+            problem.get_or_insert(e);
+            Ordering::Equal
+        })
+    });
+    match problem {
+        Some(e) => Err(e),
+        None => Ok(Value::List(items)),
+    }
+}
+
+// The index of `v` in the sorted list, counting from 0, or -1 when it is
+// not there.
+fn binary_search(args: &[Value]) -> Result<Value, String> {
+    let (items, v) = (args[0].as_list()?, &args[1]);
+    let (mut lo, mut hi) = (0, items.len());
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        match compare(&items[mid], v)? {
+            Ordering::Less => lo = mid + 1,
+            Ordering::Greater => hi = mid,
+            Ordering::Equal => return Ok(Value::Number(mid as f64)),
+        }
+    }
+    Ok(Value::Number(-1.0))
+}
+
+fn integer(v: &Value, fname: &str) -> Result<i64, String> {
+    let x = v.as_f64()?;
+    if x.fract() != 0.0 || x.abs() > MAX_EXACT_INT {
+        return Err(format!("{} expects integer arguments, got {}", fname, x));
+    }
+    Ok(x as i64)
+}
+
+fn natural(v: &Value, fname: &str) -> Result<u64, String> {
+    let n = integer(v, fname)?;
+    u64::try_from(n).map_err(|_| format!("{} expects non-negative integers, got {}", fname, n))
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+// The largest n whose factorial an f64 can hold.
+const MAX_FACTORIAL: u64 = 170;
+
+// Multiplicative formula; exact while the running product fits in u128,
+// then continued in floating point until it overflows. Every partial
+// product is itself a binomial coefficient, so the loop only runs long
+// when the result is finite, which takes k below about 1100.
+fn binomial(n: u64, k: u64) -> f64 {
+    if k > n {
+        return 0.0;
+    }
+    let k = k.min(n - k);
+    let mut exact: u128 = 1;
+    for i in 1..=k {
+        match exact.checked_mul((n - k + i) as u128) {
+            Some(p) => exact = p / i as u128,
+            None => {
+                let mut approx = exact as f64;
+                for j in i..=k {
+                    approx = approx * (n - k + j) as f64 / j as f64;
+                    if approx.is_infinite() {
+                        return approx;
+                    }
+                }
+                return approx.round();
+            }
+        }
+    }
+    exact as f64
+}
+
+fn is_prime(n: u64) -> bool {
+    if n < 4 {
+        return n >= 2;
+    }
+    if n.is_multiple_of(2) || n.is_multiple_of(3) {
+        return false;
+    }
+    let mut d = 5;
+    while d * d <= n {
+        if n.is_multiple_of(d) || n.is_multiple_of(d + 2) {
+            return false;
+        }
+        d += 6;
+    }
+    true
+}
+
+fn mod_pow(args: &[Value]) -> Result<Value, String> {
+    let base = integer(&args[0], "mod_pow")?;
+    let exp = natural(&args[1], "mod_pow")?;
+    let m = integer(&args[2], "mod_pow")?;
+    if m <= 0 {
+        return Err(format!("mod_pow modulus must be positive, got {}", m));
+    }
+    let m = m as u128;
+    let mut b = base.rem_euclid(m as i64) as u128;
+    let mut e = exp;
+    let mut acc: u128 = 1 % m;
+    while e > 0 {
+        if e & 1 == 1 {
+            acc = acc * b % m;
+        }
+        b = b * b % m;
+        e >>= 1;
+    }
+    Ok(Value::Number(acc as f64))
+}
+
+/// `name` computed with big integers where its result would not fit an
+/// f64 exactly, or `None` for a builtin that has no exact form or a result
+/// too large to compute this way.
+pub fn exact_builtin(name: &str, args: &[Value]) -> Option<Result<Value, String>> {
+    match (name, args) {
+        ("abs", [Value::Int(n)]) => Some(Ok(Value::Int(n.abs()))),
+        ("factorial", [n]) => {
+            let n = match natural(n, "factorial") {
+                Ok(n) => n,
+                Err(e) => return Some(Err(e)),
+            };
+            if n as f64 * (n as f64).log2() > MAX_EXACT_BITS {
+                return None;
+            }
+            let product = (2..=n).fold(BigInt::from_u64(1), |acc, k| acc.mul(&BigInt::from_u64(k)));
+            Some(Ok(Value::from_big(product)))
+        }
+        ("binomial", [n, k]) => {
+            let (n, k) = match (natural(n, "binomial"), natural(k, "binomial")) {
+                (Ok(n), Ok(k)) => (n, k),
+                (Err(e), _) | (_, Err(e)) => return Some(Err(e)),
+            };
+            if k > n {
+                return Some(Ok(Value::Number(0.0)));
+            }
+            let k = k.min(n - k);
+            if k as f64 * (n as f64).log2() > MAX_EXACT_BITS || k > u32::MAX as u64 {
+                return None;
+            }
+            // each partial product is itself a binomial coefficient
+            let mut acc = BigInt::from_u64(1);
+            for i in 1..=k {
+                acc = acc
+                    .mul(&BigInt::from_u64(n - k + i))
+                    .div_rem_small(i as u32)
+                    .0;
+            }
+            Some(Ok(Value::from_big(acc)))
+        }
+        _ => None,
+    }
+}
+
+/// A native function registered at runtime, e.g. by an embedding host.
+pub type NativeFn = Box<dyn Fn(&[Value]) -> Result<Value, String> + Send + Sync>;
+
+struct Native {
+    min_args: usize,
+    max_args: usize,
+    func: NativeFn,
+    // still the entry from `BUILTINS`
+    standard: bool,
+}
+
+/// Registry of native functions callable by name, seeded with `BUILTINS`.
+pub struct Builtins {
+    fns: HashMap<String, Native>,
+}
+
+impl Builtins {
+    pub fn standard() -> Self {
+        let mut fns = HashMap::new();
+        for b in BUILTINS {
+            let func = b.func;
+            fns.insert(
+                b.name.to_string(),
+                Native {
+                    min_args: b.min_args,
+                    max_args: b.max_args,
+                    func: Box::new(func),
+                    standard: true,
+                },
+            );
+        }
+        Self { fns }
+    }
+
+    /// Add or replace a function taking exactly `arity` arguments.
+    pub fn register(&mut self, name: &str, arity: usize, func: NativeFn) {
+        self.fns.insert(
+            name.to_string(),
+            Native {
+                min_args: arity,
+                max_args: arity,
+                func,
+                standard: false,
+            },
+        );
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.fns.contains_key(name)
+    }
+
+    /// Whether `name` is a builtin not replaced by `register`.
+    pub fn is_standard(&self, name: &str) -> bool {
+        self.fns.get(name).is_some_and(|f| f.standard)
+    }
+
+    /// Minimum and maximum argument counts of `name`.
+    pub fn arity(&self, name: &str) -> Option<(usize, usize)> {
+        self.fns.get(name).map(|f| (f.min_args, f.max_args))
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.fns.keys()
+    }
+
+    pub fn call(&self, name: &str, args: &[Value]) -> Result<Value, String> {
+        self.check_arity(name, args.len())?;
+        (self.fns[name].func)(args)
+    }
+
+    /// Fail unless `name` exists and takes `n` arguments.
+    pub fn check_arity(&self, name: &str, n: usize) -> Result<(), String> {
+        let f = self
+            .fns
+            .get(name)
+            .ok_or_else(|| format!("unknown function: {}", name))?;
+        if n < f.min_args || n > f.max_args {
+            let expected = if f.min_args == f.max_args {
+                f.min_args.to_string()
+            } else {
+                format!("{} to {}", f.min_args, f.max_args)
+            };
+            let plural = if f.max_args == 1 { "arg" } else { "args" };
+            return Err(format!(
+                "{} expects {} {}, got {}",
+                name, expected, plural, n
+            ));
+        }
+        Ok(())
+    }
+}
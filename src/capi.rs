@@ -0,0 +1,154 @@
+//! A C ABI for embedding AM in C and C++ programs, built with
+//! `--features capi` and declared in `include/amlang.h` (generated with
+//! `cbindgen --config cbindgen.toml --output include/amlang.h`). Build the
+//! library to link against with
+//! `cargo rustc --lib --release --features capi --crate-type staticlib`,
+//! or `--crate-type cdylib` for a shared one:
+//!
+//! ```c
+//! AmInterpreter *am = am_new();
+//! if (am_load(am, "@Hyp(a, b) = sqrt(a^2 + b^2)") != 0)
+//!     fprintf(stderr, "%s\n", am_last_error(am));
+//! double args[] = {3, 4}, out;
+//! if (am_call(am, "Hyp", args, 2, &out) == 0)
+//!     printf("%g\n", out);
+//! am_free(am);
+//! ```
+//!
+//! Functions that can fail return 0 on success and -1 on failure, when
+//! `am_last_error` describes what went wrong.
+
+use std::ffi::{CStr, CString, c_char, c_int};
+use std::ptr;
+
+use crate::diagnostic::Diagnostic;
+use crate::eval::Value;
+use crate::interpreter::Interpreter;
+use crate::normalize::normalize_unicode_to_ascii;
+
+/// An interpreter and the message of its last failure.
+pub struct AmInterpreter {
+    am: Interpreter,
+    error: Option<CString>,
+}
+
+impl AmInterpreter {
+    fn fail(&mut self, message: String) -> c_int {
+        // a message cannot hold NUL bytes, though AM source could
+        self.error = Some(CString::new(message.replace('\0', "\\0")).unwrap_or_default());
+        -1
+    }
+}
+
+/// A new interpreter with no definitions, to be released with `am_free`.
+#[unsafe(no_mangle)]
+pub extern "C" fn am_new() -> *mut AmInterpreter {
+    Box::into_raw(Box::new(AmInterpreter {
+        am: Interpreter::new(),
+        error: None,
+    }))
+}
+
+/// Release an interpreter. Null is ignored.
+///
+/// # Safety
+/// `am` is null or came from `am_new` and has not been freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn am_free(am: *mut AmInterpreter) {
+    if !am.is_null() {
+        drop(unsafe { Box::from_raw(am) });
+    }
+}
+
+/// Add the definitions in the UTF-8 text `src`. Nothing is added if any
+/// of them has an error.
+///
+/// # Safety
+/// `am` came from `am_new`, and `src` is a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn am_load(am: *mut AmInterpreter, src: *const c_char) -> c_int {
+    let Some(am) = (unsafe { am.as_mut() }) else {
+        return -1;
+    };
+    let src = match unsafe { text(src) } {
+        Ok(src) => src,
+        Err(e) => return am.fail(e),
+    };
+    match am.am.load_str(src) {
+        Ok(_) => 0,
+        Err(errors) => {
+            let message = render(&errors, &normalize_unicode_to_ascii(src));
+            am.fail(message)
+        }
+    }
+}
+
+/// Call algorithm (or builtin) `name` with the `nargs` numbers at `args`,
+/// storing its result in `*out`. A result that is not a number fails.
+///
+/// # Safety
+/// `am` came from `am_new`, `name` is a NUL-terminated string, `args`
+/// points at `nargs` doubles (or is anything when `nargs` is 0), and
+/// `out` is writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn am_call(
+    am: *mut AmInterpreter,
+    name: *const c_char,
+    args: *const f64,
+    nargs: usize,
+    out: *mut f64,
+) -> c_int {
+    let Some(am) = (unsafe { am.as_mut() }) else {
+        return -1;
+    };
+    let name = match unsafe { text(name) } {
+        Ok(name) => name,
+        Err(e) => return am.fail(e),
+    };
+    if out.is_null() || (args.is_null() && nargs > 0) {
+        return am.fail("am_call: null argument or result pointer".to_string());
+    }
+    let args: Vec<Value> = match nargs {
+        0 => Vec::new(),
+        n => unsafe { std::slice::from_raw_parts(args, n) }
+            .iter()
+            .map(|&x| Value::Number(x))
+            .collect(),
+    };
+    match am.am.call(name, &args) {
+        Ok(Value::Number(x)) => {
+            unsafe { *out = x };
+            0
+        }
+        Ok(other) => am.fail(format!("result is not a number: {}", other)),
+        Err(d) => am.fail(render(&[d], "")),
+    }
+}
+
+/// The message of the last failure, or null if nothing has failed yet.
+/// It stays valid until the next call that fails or `am_free`.
+///
+/// # Safety
+/// `am` is null or came from `am_new`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn am_last_error(am: *const AmInterpreter) -> *const c_char {
+    match unsafe { am.as_ref() }.and_then(|am| am.error.as_ref()) {
+        Some(e) => e.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+unsafe fn text<'a>(s: *const c_char) -> Result<&'a str, String> {
+    if s.is_null() {
+        return Err("null string".to_string());
+    }
+    unsafe { CStr::from_ptr(s) }
+        .to_str()
+        .map_err(|_| "string is not valid UTF-8".to_string())
+}
+
+fn render(diags: &[Diagnostic], src: &str) -> String {
+    let src = (!src.is_empty()).then_some(src);
+    let lines: Vec<String> = diags.iter().map(|d| d.render(src)).collect();
+    lines.join("\n")
+}
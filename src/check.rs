@@ -0,0 +1,393 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::{AlgorithmDef, BinOp, Expr, ExprKind, UnOp, for_each_expr};
+use crate::builtins::Builtins;
+use crate::diagnostic::{
+    Diagnostic, E_CHECK_ARITY, E_CHECK_UNKNOWN_CALL, E_CHECK_UNKNOWN_NAME, E_DUPLICATE_ALG,
+    E_DUPLICATE_PARAM, W_CONSTANT_CONDITION, W_NON_EXHAUSTIVE, W_SHADOWED_BUILTIN, W_UNUSED_PARAM,
+    did_you_mean,
+};
+use crate::eval::{BODY_CONSTANTS, IMAGINARY_UNIT, RESULT, pipe_value_names};
+use crate::printer::expr_to_source;
+use crate::token::Span;
+use crate::types::{check_def_types, infer_returns};
+
+/// Validate definitions without running them: unknown identifiers, calls
+/// to unknown or wrongly-applied functions, and duplicate names. Missing
+/// case defaults are already rejected by the parser.
+///
+/// Operands whose inferred type can never suit their operator are errors
+/// too (see `types`).
+///
+/// Suspicious but valid code yields warnings in the same list: unused
+/// parameters, algorithms hiding a builtin, and constant case conditions.
+pub fn check_defs(defs: &[AlgorithmDef], builtins: &Builtins) -> Vec<Diagnostic> {
+    check_defs_with(defs, &[], builtins)
+}
+
+/// Like `check_defs`, for definitions added alongside already-loaded
+/// `known` algorithms. Calls may resolve to `known`, which are not checked
+/// again; a new definition replaces a known one of the same name.
+pub fn check_defs_with(
+    defs: &[AlgorithmDef],
+    known: &[&AlgorithmDef],
+    builtins: &Builtins,
+) -> Vec<Diagnostic> {
+    let mut out = Vec::new();
+    let mut algs: HashMap<&str, &AlgorithmDef> =
+        known.iter().map(|d| (d.name.as_str(), *d)).collect();
+    let mut names = HashSet::new();
+    for d in defs {
+        if builtins.arity(&d.name).is_some() {
+            out.push(
+                Diagnostic::warning(
+                    W_SHADOWED_BUILTIN,
+                    format!("@{} shadows the builtin {}", d.name, d.name),
+                )
+                .with_span(d.span),
+            );
+        }
+        algs.insert(&d.name, d);
+        if !names.insert(d.name.as_str()) {
+            out.push(
+                Diagnostic::error(
+                    E_DUPLICATE_ALG,
+                    format!("algorithm @{} is defined more than once", d.name),
+                )
+                .with_span(d.span),
+            );
+        }
+    }
+
+    let refs: Vec<&AlgorithmDef> = algs.values().copied().collect();
+    let returns = infer_returns(&refs);
+    let mut checker = Checker {
+        algs,
+        builtins,
+        locals: &[],
+    };
+    for d in defs {
+        checker.locals = &d.locals;
+        checker.def(d, &format!("@{}", d.name), &mut out);
+        for l in &d.locals {
+            checker.def(l, &format!("{} in @{}", l.name, d.name), &mut out);
+        }
+    }
+    out.extend(defs.iter().flat_map(|d| check_def_types(d, &returns)));
+    out
+}
+
+struct Checker<'a> {
+    algs: HashMap<&'a str, &'a AlgorithmDef>,
+    builtins: &'a Builtins,
+    // `where` helpers of the definition being checked
+    locals: &'a [AlgorithmDef],
+}
+
+impl Checker<'_> {
+    // The parameters and body of `d`, called `what` in messages.
+    fn def(&self, d: &AlgorithmDef, what: &str, out: &mut Vec<Diagnostic>) {
+        let mut seen = HashSet::new();
+        for p in &d.params {
+            if !seen.insert(p.as_str()) {
+                out.push(
+                    Diagnostic::error(
+                        E_DUPLICATE_PARAM,
+                        format!("parameter {} of {} is declared twice", p, what),
+                    )
+                    .with_span(d.span),
+                );
+            }
+        }
+        let mut used = HashSet::new();
+        collect_idents(&d.body, &mut used);
+        for c in d.requires.iter().chain(&d.ensures) {
+            collect_idents(c, &mut used);
+        }
+        for p in &d.params {
+            if !used.contains(p.as_str()) {
+                out.push(
+                    Diagnostic::warning(
+                        W_UNUSED_PARAM,
+                        format!("parameter {} of {} is never used", p, what),
+                    )
+                    .with_span(d.span),
+                );
+            }
+        }
+        let scope: Vec<String> = d
+            .params
+            .iter()
+            .cloned()
+            .chain(BODY_CONSTANTS.iter().map(|(n, _)| n.to_string()))
+            .chain([IMAGINARY_UNIT.to_string()])
+            .collect();
+        self.expr(&d.body, &scope, out);
+        for c in &d.requires {
+            self.expr(c, &scope, out);
+        }
+        let with_result: Vec<String> = scope.into_iter().chain([RESULT.to_string()]).collect();
+        for c in &d.ensures {
+            self.expr(c, &with_result, out);
+        }
+    }
+
+    fn expr(&self, e: &Expr, scope: &[String], out: &mut Vec<Diagnostic>) {
+        match &e.kind {
+            ExprKind::Number(_) | ExprKind::Imag(_) | ExprKind::Bool(_) | ExprKind::Str(_) => {}
+            ExprKind::Ident(name) => {
+                if !scope.contains(name) {
+                    out.push(
+                        Diagnostic::error(
+                            E_CHECK_UNKNOWN_NAME,
+                            format!(
+                                "unknown identifier: {}{}",
+                                name,
+                                did_you_mean(name, scope.iter())
+                            ),
+                        )
+                        .with_span(e.span),
+                    );
+                }
+            }
+            ExprKind::AlgRef(name) => self.alg_ref(name, e.span, out),
+            ExprKind::Call { is_alg, name, args } => {
+                // a parameter may hold any algorithm
+                if *is_alg || !scope.contains(name) {
+                    self.call(*is_alg, name, args.len(), e.span, out);
+                }
+                for a in args {
+                    self.expr(a, scope, out);
+                }
+            }
+            ExprKind::Unary { expr, .. } => self.expr(expr, scope, out),
+            ExprKind::Bin { left, right, .. } => {
+                self.expr(left, scope, out);
+                self.expr(right, scope, out);
+            }
+            ExprKind::Case { arms, default } => {
+                let mut flagged = HashSet::new();
+                for (c, r) in arms {
+                    // `c ? a | b` desugars into `c` and `!c` sharing a span
+                    if is_constant(c) && flagged.insert(c.span.start) {
+                        out.push(
+                            Diagnostic::warning(W_CONSTANT_CONDITION, "case condition is constant")
+                                .with_span(c.span),
+                        );
+                    }
+                    self.expr(c, scope, out);
+                    self.expr(r, scope, out);
+                }
+                match default {
+                    Some(default) => self.expr(default, scope, out),
+                    None if !exhaustive(arms) => out.push(
+                        Diagnostic::warning(
+                            W_NON_EXHAUSTIVE,
+                            "case arms may not cover every input and there is no '_' default",
+                        )
+                        .with_span(e.span),
+                    ),
+                    None => {}
+                }
+            }
+            ExprKind::List(items) | ExprKind::Tuple(items) => {
+                for item in items {
+                    self.expr(item, scope, out);
+                }
+            }
+            ExprKind::Pipe { head, steps } => {
+                self.expr(head, scope, out);
+                // the piped value is passed as an extra first argument, or
+                // in place of each `_`
+                for s in steps {
+                    match &s.kind {
+                        ExprKind::Call { is_alg, name, args } => {
+                            let placed = args.iter().any(Expr::is_placeholder);
+                            let argc = args.len() + usize::from(!placed);
+                            if *is_alg || !scope.contains(name) {
+                                self.call(*is_alg, name, argc, s.span, out);
+                            }
+                            for a in args.iter().filter(|a| !a.is_placeholder()) {
+                                self.expr(a, scope, out);
+                            }
+                        }
+                        ExprKind::Ident(name) if scope.contains(name) => {}
+                        ExprKind::Ident(name) => self.call(false, name, 1, s.span, out),
+                        ExprKind::AlgRef(name) => self.call(true, name, 1, s.span, out),
+                        ExprKind::Lambda { .. } => self.expr(s, scope, out),
+                        ExprKind::Case { .. } => {
+                            let inner: Vec<String> =
+                                scope.iter().cloned().chain(pipe_value_names()).collect();
+                            self.expr(s, &inner, out);
+                        }
+                        _ => out.push(
+                            Diagnostic::error(
+                                E_CHECK_UNKNOWN_CALL,
+                                "pipeline step must be a call or name",
+                            )
+                            .with_span(s.span),
+                        ),
+                    }
+                }
+            }
+            ExprKind::Lambda { params, body } => {
+                let inner: Vec<String> = scope.iter().chain(params).cloned().collect();
+                self.expr(body, &inner, out);
+            }
+            ExprKind::Series {
+                index,
+                from,
+                to,
+                body,
+                ..
+            } => {
+                self.expr(from, scope, out);
+                self.expr(to, scope, out);
+                let inner: Vec<String> = scope.iter().chain([index]).cloned().collect();
+                self.expr(body, &inner, out);
+            }
+            ExprKind::Solve { equation, var } => {
+                let inner: Vec<String> = scope.iter().chain([var]).cloned().collect();
+                self.expr(equation, &inner, out);
+            }
+        }
+    }
+
+    fn alg_ref(&self, name: &str, span: Span, out: &mut Vec<Diagnostic>) {
+        if !self.algs.contains_key(name) {
+            out.push(
+                Diagnostic::error(
+                    E_CHECK_UNKNOWN_CALL,
+                    format!(
+                        "unknown algorithm: {}{}",
+                        name,
+                        did_you_mean(name, self.algs.keys())
+                    ),
+                )
+                .with_span(span),
+            );
+        }
+    }
+
+    // Resolve a call the way the evaluator does: algorithms first, then
+    // builtins.
+    fn call(&self, is_alg: bool, name: &str, argc: usize, span: Span, out: &mut Vec<Diagnostic>) {
+        let local = self.locals.iter().find(|l| !is_alg && l.name == name);
+        let arity = match local.or_else(|| self.algs.get(name).copied()) {
+            Some(d) => Some((d.params.len(), d.params.len())),
+            None if is_alg => None,
+            None => self.builtins.arity(name),
+        };
+        let Some((min, max)) = arity else {
+            let hint = if is_alg {
+                did_you_mean(name, self.algs.keys())
+            } else {
+                did_you_mean(
+                    name,
+                    self.builtins
+                        .names()
+                        .map(String::as_str)
+                        .chain(self.algs.keys().copied()),
+                )
+            };
+            let what = if is_alg { "algorithm" } else { "function" };
+            out.push(
+                Diagnostic::error(
+                    E_CHECK_UNKNOWN_CALL,
+                    format!("unknown {}: {}{}", what, name, hint),
+                )
+                .with_span(span),
+            );
+            return;
+        };
+        if argc < min || argc > max {
+            let expected = if min == max {
+                min.to_string()
+            } else {
+                format!("{} to {}", min, max)
+            };
+            let plural = if max == 1 { "arg" } else { "args" };
+            out.push(
+                Diagnostic::error(
+                    E_CHECK_ARITY,
+                    format!("{} expects {} {}, got {}", name, expected, plural, argc),
+                )
+                .with_span(span),
+            );
+        }
+    }
+}
+
+fn collect_idents<'e>(e: &'e Expr, used: &mut HashSet<&'e str>) {
+    for_each_expr(e, &mut |e| match &e.kind {
+        ExprKind::Ident(name)
+        | ExprKind::Call {
+            is_alg: false,
+            name,
+            ..
+        } => {
+            used.insert(name);
+        }
+        _ => {}
+    });
+}
+
+// An expression built only from literals always has the same value.
+// Whether the arms of a case without a default are known to cover every
+// input: one condition is `true`, or two conditions are each other's negation.
+fn exhaustive(arms: &[(Expr, Expr)]) -> bool {
+    let conds: Vec<&Expr> = arms.iter().map(|(c, _)| c).collect();
+    conds.iter().any(|c| matches!(c.kind, ExprKind::Bool(true)))
+        || conds
+            .iter()
+            .enumerate()
+            .any(|(i, a)| conds[i + 1..].iter().any(|b| complementary(a, b)))
+}
+
+fn complementary(a: &Expr, b: &Expr) -> bool {
+    let negates = |n: &Expr, e: &Expr| match &n.kind {
+        ExprKind::Unary {
+            op: UnOp::Not,
+            expr,
+        } => expr_to_source(expr) == expr_to_source(e),
+        _ => false,
+    };
+    if negates(a, b) || negates(b, a) {
+        return true;
+    }
+    match (&a.kind, &b.kind) {
+        (
+            ExprKind::Bin {
+                op: x,
+                left: l1,
+                right: r1,
+            },
+            ExprKind::Bin {
+                op: y,
+                left: l2,
+                right: r2,
+            },
+        ) => {
+            matches!(
+                (x, y),
+                (BinOp::Lt, BinOp::Ge)
+                    | (BinOp::Ge, BinOp::Lt)
+                    | (BinOp::Le, BinOp::Gt)
+                    | (BinOp::Gt, BinOp::Le)
+                    | (BinOp::Eq, BinOp::Ne)
+                    | (BinOp::Ne, BinOp::Eq)
+            ) && expr_to_source(l1) == expr_to_source(l2)
+                && expr_to_source(r1) == expr_to_source(r2)
+        }
+        _ => false,
+    }
+}
+
+fn is_constant(e: &Expr) -> bool {
+    match &e.kind {
+        ExprKind::Number(_) | ExprKind::Imag(_) | ExprKind::Bool(_) | ExprKind::Str(_) => true,
+        ExprKind::Unary { expr, .. } => is_constant(expr),
+        ExprKind::Bin { left, right, .. } => is_constant(left) && is_constant(right),
+        _ => false,
+    }
+}
@@ -0,0 +1,259 @@
+use std::{env, fs};
+
+use amlang::normalize::{parse_map, set_user_map};
+
+use crate::file_processor::{format_files, process_file};
+use crate::repl::Repl;
+
+/// Read when `--normalize-map` is not given, from the home directory.
+pub const NORMALIZE_MAP_FILE: &str = ".amlang-normalize.toml";
+
+/// A subcommand, its help text, and the flags it accepts. Flags taking a
+/// value are listed without it.
+pub struct Command {
+    pub name: &'static str,
+    pub summary: &'static str,
+    pub usage: &'static str,
+    pub flags: &'static [&'static str],
+}
+
+pub const RUN: Command = Command {
+    name: "run",
+    summary: "load files, run their scripts and any --call expressions",
+    usage: "\
+usage: amlang run FILE... [options]
+
+FILE may also be a directory, standing for the .am files in it.
+
+options:
+  --call EXPR           evaluate EXPR against the definitions (repeatable)
+  --calls FILE          evaluate each line of FILE as a call
+  --json                print results and errors as JSON
+  --csv                 print call results as CSV rows
+  --latex               print the definitions as LaTeX instead of running
+  --mathml              print the definitions as MathML instead of running
+  --watch               re-run whenever an input file changes
+  --profile             count operations per algorithm and print a table
+  --trace               print each call, case arm, and pipeline stage
+  --explain[=FMT]       show the working of each call, as text or markdown
+  --diff VAR            print the derivative of each call with respect to VAR
+  --simplify            simplify --latex and --mathml output and derivatives
+  --equiv 'A B [over LO..HI]'
+                        compare two algorithms on many inputs instead of running
+  --map 'A FILE.csv'    call A on every row of FILE.csv in parallel, printing
+                        each row with its result
+  --include DIR         search DIR for imports (AM_PATH is searched too)
+  --max-depth N         limit nested algorithm calls
+  --max-steps N         limit the evaluation steps of each run or call
+  --timeout MS          limit the time of each run or call, in milliseconds
+  --epsilon E           tolerance of ~= (default 1e-9)
+  --mod MODE            % semantics: euclidean or truncated
+  --memoize             cache the results of every algorithm, not just @memo ones
+  --bigint              keep integers exact past 2^53
+  --strict              fail on NaN, infinity, or complex results of real inputs
+  --unicode             print results with math glyphs such as ≤, → and ∞
+  --deny-warnings       fail when there are warnings
+  --error-format=FMT    human or json",
+    flags: &[
+        "--call",
+        "--calls",
+        "--json",
+        "--csv",
+        "--latex",
+        "--mathml",
+        "--watch",
+        "--profile",
+        "--trace",
+        "--explain",
+        "--diff",
+        "--simplify",
+        "--equiv",
+        "--map",
+        "--include",
+        "--max-depth",
+        "--max-steps",
+        "--timeout",
+        "--epsilon",
+        "--mod",
+        "--memoize",
+        "--bigint",
+        "--strict",
+        "--unicode",
+        "--deny-warnings",
+        "--error-format",
+        // accepted for scripts written before the subcommands
+        "--check",
+        "--ast",
+        "--ast-json",
+    ],
+};
+
+pub const CHECK: Command = Command {
+    name: "check",
+    summary: "report errors and warnings without running anything",
+    usage: "\
+usage: amlang check FILE... [options]
+
+options:
+  --include DIR         search DIR for imports (AM_PATH is searched too)
+  --deny-warnings       fail when there are warnings
+  --json                print errors as JSON
+  --error-format=FMT    human or json",
+    flags: &["--include", "--deny-warnings", "--json", "--error-format"],
+};
+
+pub const AST: Command = Command {
+    name: "ast",
+    summary: "print the parsed definitions",
+    usage: "\
+usage: amlang ast FILE... [options]
+
+options:
+  --json                print the tree as JSON
+  --include DIR         search DIR for imports (AM_PATH is searched too)
+  --error-format=FMT    human or json",
+    flags: &["--json", "--include", "--error-format"],
+};
+
+pub const FMT: Command = Command {
+    name: "fmt",
+    summary: "lay out files canonically",
+    usage: "\
+usage: amlang fmt FILE... [options]
+
+Prints the formatted files unless told otherwise.
+
+options:
+  --check               list files that are not formatted, failing if any
+  --write               rewrite the files in place
+  --unicode             write operators as math glyphs such as ≤, → and ×",
+    flags: &["--check", "--write", "--unicode"],
+};
+
+pub const TEST: Command = Command {
+    name: "test",
+    summary: "run the @test declarations in files",
+    usage: "\
+usage: amlang test FILE... [options]
+       amlang test --golden DIR [--update] [options]
+
+Each `@test \"name\" : expr` passes when expr is true. Fails when any test
+does. With --golden, the output of each `.am` file in DIR is compared with
+the sibling `.expected` file instead.
+
+options:
+  --golden DIR          compare each file's output with its .expected file
+  --update              rewrite the .expected files from the current output
+  --include DIR         search DIR for imports (AM_PATH is searched too)
+  --max-depth N         limit nested algorithm calls
+  --max-steps N         limit the evaluation steps of each test
+  --timeout MS          limit the time of each test, in milliseconds
+  --epsilon E           tolerance of ~= (default 1e-9)
+  --mod MODE            % semantics: euclidean or truncated
+  --bigint              keep integers exact past 2^53
+  --error-format=FMT    human or json",
+    flags: &[
+        "--golden",
+        "--update",
+        "--include",
+        "--max-depth",
+        "--max-steps",
+        "--timeout",
+        "--epsilon",
+        "--mod",
+        "--bigint",
+        "--error-format",
+    ],
+};
+
+pub const DOC: Command = Command {
+    name: "doc",
+    summary: "print a reference for the algorithms in files",
+    usage: "\
+usage: amlang doc FILE... [options]
+
+Lists each algorithm's signature and /// documentation, as Markdown unless
+told otherwise.
+
+options:
+  --html                print a standalone HTML page
+  --latex               include each body rendered as LaTeX
+  --include DIR         search DIR for imports (AM_PATH is searched too)
+  --error-format=FMT    human or json",
+    flags: &["--html", "--latex", "--include", "--error-format"],
+};
+
+pub const REPL: Command = Command {
+    name: "repl",
+    summary: "start the interactive prompt (the default with no arguments)",
+    usage: "\
+usage: amlang repl",
+    flags: &[],
+};
+
+const COMMANDS: &[&Command] = &[&RUN, &CHECK, &AST, &FMT, &TEST, &DOC, &REPL];
+
+/// Run the command line `args`. Without a known subcommand the arguments
+/// are taken as `run`'s, so `amlang file.am --call ...` keeps working.
+pub fn dispatch(args: Vec<String>) -> Result<(), String> {
+    let first = args.first().map(String::as_str);
+    let (command, rest) = match COMMANDS.iter().find(|c| first == Some(c.name)) {
+        Some(c) => (*c, &args[1..]),
+        None if matches!(first, Some("help" | "--help" | "-h")) => {
+            println!("{}", usage());
+            return Ok(());
+        }
+        None => (&RUN, &args[..]),
+    };
+    if rest.iter().any(|a| a == "--help" || a == "-h") {
+        println!("{}", command.usage);
+        return Ok(());
+    }
+
+    match command.name {
+        "repl" if !rest.is_empty() => Err(format!(
+            "unexpected argument: {}\n\n{}",
+            rest[0], REPL.usage
+        )),
+        "repl" => Repl::new()?.run(),
+        "fmt" => format_files(rest),
+        _ => process_file(command, rest),
+    }
+}
+
+/// Take `--normalize-map FILE` out of `args`, which any command accepts,
+/// and install its mappings; without the flag, `~/.amlang-normalize.toml`
+/// is read if it exists.
+pub fn load_normalize_map(args: &mut Vec<String>) -> Result<(), String> {
+    let path = match args.iter().position(|a| a == "--normalize-map") {
+        Some(i) if i + 1 < args.len() => {
+            let path = args.remove(i + 1);
+            args.remove(i);
+            path
+        }
+        Some(_) => return Err("--normalize-map requires a file".to_string()),
+        None => match env::var_os("HOME") {
+            Some(home) => {
+                let path = std::path::Path::new(&home).join(NORMALIZE_MAP_FILE);
+                if !path.is_file() {
+                    return Ok(());
+                }
+                path.to_string_lossy().into_owned()
+            }
+            None => return Ok(()),
+        },
+    };
+    let text = fs::read_to_string(&path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+    set_user_map(parse_map(&text).map_err(|e| format!("{}: {}", path, e))?);
+    Ok(())
+}
+
+fn usage() -> String {
+    let mut out = String::from("usage: amlang [COMMAND] [ARGS]\n\ncommands:\n");
+    for c in COMMANDS {
+        out.push_str(&format!("  {:<8}{}\n", c.name, c.summary));
+    }
+    out.push_str("\nRun `amlang COMMAND --help` for its options. Without a command,\narguments are passed to `run`.");
+    out.push_str("\n\nAny command also takes --normalize-map FILE, a TOML table of\n\"from\" = \"to\" character mappings applied before parsing\n(default ~/.amlang-normalize.toml).");
+    out
+}
@@ -0,0 +1,54 @@
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+
+use amlang::ast::AlgorithmDef;
+use amlang::eval::Env;
+use amlang::session::Completions;
+
+/// Line-editor helper that completes REPL commands, algorithm names,
+/// builtins, parameters, and session variables.
+#[derive(Default)]
+pub struct ReplHelper {
+    completions: Completions,
+}
+
+impl ReplHelper {
+    /// Rebuild the candidate lists from the current session state.
+    pub fn refresh(&mut self, defs: &[AlgorithmDef], env: &Env) {
+        self.completions = Completions::new(defs, env);
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let (start, found) = self.completions.complete(line, pos);
+        let pairs = found
+            .into_iter()
+            .map(|c| Pair {
+                display: c.clone(),
+                replacement: c,
+            })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
@@ -0,0 +1,175 @@
+use crate::json;
+use crate::token::{Span, line_col, span_message};
+
+// Error codes. The hundreds digit groups them by phase: 0 lexing,
+// 1 parsing, 2 evaluation, 3 checking, 4 loading files, 5 formatting,
+// 6 testing.
+pub const E_LEX: &str = "E0001";
+pub const E_UNEXPECTED_TOKEN: &str = "E0101";
+pub const E_EXPECTED_TOKEN: &str = "E0102";
+pub const E_BAD_NUMBER: &str = "E0103";
+pub const E_BAD_CALLEE: &str = "E0104";
+pub const E_RUNTIME: &str = "E0200";
+pub const E_UNKNOWN_NAME: &str = "E0201";
+pub const E_UNKNOWN_CALL: &str = "E0202";
+pub const E_ARITY: &str = "E0203";
+pub const E_RECURSION_LIMIT: &str = "E0204";
+pub const E_BUDGET: &str = "E0205";
+pub const E_NO_ARM: &str = "E0206";
+pub const E_CONTRACT: &str = "E0207";
+pub const E_CHECK_UNKNOWN_NAME: &str = "E0301";
+pub const E_CHECK_UNKNOWN_CALL: &str = "E0302";
+pub const E_CHECK_ARITY: &str = "E0303";
+pub const E_DUPLICATE_PARAM: &str = "E0304";
+pub const E_DUPLICATE_ALG: &str = "E0305";
+pub const E_TYPE_MISMATCH: &str = "E0306";
+pub const E_IMPORT_NOT_FOUND: &str = "E0401";
+pub const E_IMPORT_CYCLE: &str = "E0402";
+pub const E_IMPORT_UNSUPPORTED: &str = "E0403";
+pub const E_FMT_COMMENT: &str = "E0501";
+pub const E_FMT_CHANGED: &str = "E0502";
+pub const E_TEST_FAILED: &str = "E0601";
+
+// Warning codes.
+pub const W_UNUSED_PARAM: &str = "W0001";
+pub const W_UNREACHABLE_ARM: &str = "W0002";
+pub const W_SHADOWED_BUILTIN: &str = "W0003";
+pub const W_CONSTANT_CONDITION: &str = "W0004";
+pub const W_IMPORTED_SCRIPT: &str = "W0005";
+pub const W_NON_EXHAUSTIVE: &str = "W0006";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn as_str(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+/// A located problem report shared by the lexer, parser, checker, and
+/// evaluator.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub code: &'static str,
+    pub severity: Severity,
+    pub span: Option<Span>,
+    pub message: String,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            severity: Severity::Error,
+            span: None,
+            message: message.into(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(code, message)
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// Human-readable form with a caret under the span. `src` must be the
+    /// normalized text the span points into.
+    pub fn render(&self, src: Option<&str>) -> String {
+        self.render_in(src, "input")
+    }
+
+    /// Like `render`, naming `file` as where `src` came from.
+    pub fn render_in(&self, src: Option<&str>, file: &str) -> String {
+        let label = format!("{}[{}]", self.severity.as_str(), self.code);
+        let mut out = match (self.span, src) {
+            (Some(span), Some(src)) => span_message(src, file, span, &label, &self.message),
+            _ => format!("{}: {}", label, self.message),
+        };
+        for note in &self.notes {
+            out.push_str("\n = note: ");
+            out.push_str(note);
+        }
+        out
+    }
+
+    /// One-line JSON object for `--error-format=json`.
+    pub fn to_json(&self, src: Option<&str>, file: &str) -> String {
+        let mut out = format!(
+            "{{\"code\":{},\"severity\":{},\"message\":{},\"file\":{}",
+            json::string(self.code),
+            json::string(self.severity.as_str()),
+            json::string(&self.message),
+            json::string(file)
+        );
+        if let Some(span) = self.span {
+            out.push_str(&format!(
+                ",\"span\":{{\"start\":{},\"end\":{}",
+                span.start, span.end
+            ));
+            if let Some(src) = src {
+                let (line, column) = line_col(src, span.start);
+                out.push_str(&format!(",\"line\":{},\"column\":{}", line, column));
+            }
+            out.push('}');
+        }
+        let notes: Vec<String> = self.notes.iter().map(|n| json::string(n)).collect();
+        out.push_str(&format!(",\"notes\":[{}]}}", notes.join(",")));
+        out
+    }
+}
+
+/// A " (did you mean `x`?)" hint naming the closest candidate, or nothing
+/// when no candidate is within a couple of typos.
+pub fn did_you_mean<S: AsRef<str>>(name: &str, candidates: impl IntoIterator<Item = S>) -> String {
+    let limit = (name.chars().count() / 3).max(1);
+    let best = candidates
+        .into_iter()
+        .map(|c| c.as_ref().to_string())
+        .filter(|c| c != name)
+        .map(|c| (edit_distance(name, &c), c))
+        .filter(|(d, _)| *d <= limit)
+        .min();
+    match best {
+        Some((_, c)) => format!(" (did you mean `{}`?)", c),
+        None => String::new(),
+    }
+}
+
+// Levenshtein distance over chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let sub = prev[j] + usize::from(ca != *cb);
+            cur[j + 1] = sub.min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
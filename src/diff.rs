@@ -0,0 +1,310 @@
+//! Symbolic differentiation: the derivative of an expression with respect
+//! to one variable, built by the usual rules on the tree.
+
+use crate::ast::{BinOp, Expr, ExprKind, SeriesKind, UnOp};
+use crate::eval::{World, free_names, substitute_exprs, unpipe};
+use crate::printer::expr_to_source;
+use crate::token::Span;
+
+/// How many levels of algorithm calls are inlined before giving up, which
+/// stops a recursive algorithm from unfolding forever.
+pub const DIFF_DEPTH: usize = 32;
+
+/// The derivative of `e` with respect to `var`. Calls to the algorithms
+/// of `world` are inlined, case blocks stay piecewise, and constants are
+/// folded as the result is built. `@Name` alone stands for the body of
+/// the algorithm, in terms of its parameters.
+pub fn differentiate(world: &World, e: &Expr, var: &str) -> Result<Expr, String> {
+    let differ = Differ { world, var };
+    match &e.kind {
+        ExprKind::AlgRef(name) => match world.algs.get(name) {
+            Some(def) => differ.diff(&def.body, 1),
+            None => Err(format!("unknown algorithm: @{}", name)),
+        },
+        _ => differ.diff(e, 0),
+    }
+}
+
+struct Differ<'w> {
+    world: &'w World,
+    var: &'w str,
+}
+
+impl Differ<'_> {
+    fn diff(&self, e: &Expr, depth: usize) -> Result<Expr, String> {
+        let at = e.span;
+        let d = |e: &Expr| self.diff(e, depth);
+        Ok(match &e.kind {
+            ExprKind::Number(_) | ExprKind::Imag(_) => num(0.0, at),
+            ExprKind::Ident(name) => num(if name == self.var { 1.0 } else { 0.0 }, at),
+            ExprKind::Unary {
+                op: UnOp::Neg,
+                expr,
+            } => neg(d(expr)?),
+            ExprKind::Bin { op, left, right } => {
+                let (u, v) = (&**left, &**right);
+                match op {
+                    BinOp::Add => add(d(u)?, d(v)?),
+                    BinOp::Sub => sub(d(u)?, d(v)?),
+                    BinOp::Mul => add(mul(d(u)?, v.clone()), mul(u.clone(), d(v)?)),
+                    BinOp::Div => div(
+                        sub(mul(d(u)?, v.clone()), mul(u.clone(), d(v)?)),
+                        pow(v.clone(), num(2.0, at)),
+                    ),
+                    BinOp::Pow => self.diff_pow(u, v, depth)?,
+                    _ => return Err(cannot(e)),
+                }
+            }
+            // the conditions pick a piece; each piece is differentiated
+            ExprKind::Case { arms, default } => {
+                let arms = arms
+                    .iter()
+                    .map(|(c, r)| Ok((c.clone(), d(r)?)))
+                    .collect::<Result<_, String>>()?;
+                Expr::new(
+                    ExprKind::Case {
+                        arms,
+                        default: match default {
+                            Some(default) => Some(Box::new(d(default)?)),
+                            None => None,
+                        },
+                    },
+                    at,
+                )
+            }
+            // a vector is differentiated element by element
+            ExprKind::List(items) => Expr::new(
+                ExprKind::List(items.iter().map(d).collect::<Result<_, _>>()?),
+                at,
+            ),
+            ExprKind::Tuple(items) => Expr::new(
+                ExprKind::Tuple(items.iter().map(d).collect::<Result<_, _>>()?),
+                at,
+            ),
+            ExprKind::Pipe { head, steps } => match unpipe(head, steps) {
+                Some(calls) => d(&calls)?,
+                None => return Err(cannot(e)),
+            },
+            ExprKind::Call { is_alg, name, args } => {
+                let builtin = !is_alg && self.world.builtins.contains(name);
+                if let Some(def) = self.world.algs.get(name).filter(|_| !builtin) {
+                    if depth >= DIFF_DEPTH {
+                        return Err(format!(
+                            "cannot differentiate @{}: calls nest more than {} deep",
+                            name, DIFF_DEPTH
+                        ));
+                    }
+                    if def.params.len() != args.len() {
+                        return Err(cannot(e));
+                    }
+                    let inlined = substitute_exprs(&def.body, &def.params, args);
+                    return self.diff(&inlined, depth + 1);
+                }
+                if !builtin || !self.world.builtins.is_standard(name) || args.len() != 1 {
+                    return Err(cannot(e));
+                }
+                let u = &args[0];
+                mul(self.diff_builtin(name, u).ok_or_else(|| cannot(e))?, d(u)?)
+            }
+            ExprKind::Series {
+                kind,
+                index,
+                from,
+                to,
+                body,
+            } => {
+                if index == self.var {
+                    return Ok(num(0.0, at));
+                }
+                if self.mentions(from) || self.mentions(to) {
+                    return Err(format!(
+                        "cannot differentiate {}: its bounds depend on {}",
+                        expr_to_source(e),
+                        self.var
+                    ));
+                }
+                let series = |kind, body| {
+                    Expr::new(
+                        ExprKind::Series {
+                            kind,
+                            index: index.clone(),
+                            from: from.clone(),
+                            to: to.clone(),
+                            body: Box::new(body),
+                        },
+                        at,
+                    )
+                };
+                match kind {
+                    SeriesKind::Sum => series(SeriesKind::Sum, d(body)?),
+                    // (Π f)' = Π f · Σ f'/f
+                    SeriesKind::Product => mul(
+                        e.clone(),
+                        series(SeriesKind::Sum, div(d(body)?, (**body).clone())),
+                    ),
+                }
+            }
+            ExprKind::Unary { op: UnOp::Not, .. }
+            | ExprKind::Bool(_)
+            | ExprKind::Str(_)
+            | ExprKind::AlgRef(_)
+            | ExprKind::Lambda { .. }
+            | ExprKind::Solve { .. } => return Err(cannot(e)),
+        })
+    }
+
+    fn diff_pow(&self, u: &Expr, v: &Expr, depth: usize) -> Result<Expr, String> {
+        let at = u.span;
+        if !self.mentions(v) {
+            // n·u^(n-1)·u'
+            let lowered = pow(u.clone(), sub(v.clone(), num(1.0, at)));
+            return Ok(mul(mul(v.clone(), lowered), self.diff(u, depth)?));
+        }
+        let whole = pow(u.clone(), v.clone());
+        let ln_u = call("ln", u.clone());
+        if !self.mentions(u) {
+            // a^v·ln(a)·v'
+            return Ok(mul(mul(whole, ln_u), self.diff(v, depth)?));
+        }
+        // u^v·(v'·ln(u) + v·u'/u)
+        let inner = add(
+            mul(self.diff(v, depth)?, ln_u),
+            div(mul(v.clone(), self.diff(u, depth)?), u.clone()),
+        );
+        Ok(mul(whole, inner))
+    }
+
+    // The derivative of builtin `name` at `u`, to be multiplied by `u'`.
+    fn diff_builtin(&self, name: &str, u: &Expr) -> Option<Expr> {
+        let at = u.span;
+        let u = || u.clone();
+        let one = || num(1.0, at);
+        // sqrt(1 - u^2)
+        let circle = || call("sqrt", sub(one(), pow(u(), num(2.0, at))));
+        Some(match name {
+            "sqrt" => div(one(), mul(num(2.0, at), call("sqrt", u()))),
+            "abs" => div(u(), call("abs", u())),
+            "sin" => call("cos", u()),
+            "cos" => neg(call("sin", u())),
+            "tan" => div(one(), pow(call("cos", u()), num(2.0, at))),
+            "asin" => div(one(), circle()),
+            "acos" => neg(div(one(), circle())),
+            "atan" => div(one(), add(one(), pow(u(), num(2.0, at)))),
+            "exp" => call("exp", u()),
+            "ln" => div(one(), u()),
+            "log2" => div(one(), mul(u(), call("ln", num(2.0, at)))),
+            "log10" => div(one(), mul(u(), call("ln", num(10.0, at)))),
+            _ => return None,
+        })
+    }
+
+    // Whether `e` can depend on the variable.
+    fn mentions(&self, e: &Expr) -> bool {
+        let mut names = Vec::new();
+        free_names(e, &[], &mut names);
+        names.contains(&self.var)
+    }
+}
+
+fn cannot(e: &Expr) -> String {
+    format!("cannot differentiate {}", expr_to_source(e))
+}
+
+fn num(n: f64, at: Span) -> Expr {
+    Expr::new(ExprKind::Number(n), at)
+}
+
+fn as_num(e: &Expr) -> Option<f64> {
+    match e.kind {
+        ExprKind::Number(n) => Some(n),
+        _ => None,
+    }
+}
+
+fn bin(op: BinOp, left: Expr, right: Expr) -> Expr {
+    let at = left.span;
+    Expr::new(
+        ExprKind::Bin {
+            op,
+            left: Box::new(left),
+            right: Box::new(right),
+        },
+        at,
+    )
+}
+
+fn call(name: &str, arg: Expr) -> Expr {
+    let at = arg.span;
+    Expr::new(
+        ExprKind::Call {
+            is_alg: false,
+            name: name.to_string(),
+            args: vec![arg],
+        },
+        at,
+    )
+}
+
+// The builders below drop the zeros and ones the rules leave behind, so
+// `d/dx 3x` comes out as `3` rather than `0 * x + 3 * 1`.
+
+fn neg(e: Expr) -> Expr {
+    match as_num(&e) {
+        Some(n) => num(-n, e.span),
+        None => {
+            let at = e.span;
+            Expr::new(
+                ExprKind::Unary {
+                    op: UnOp::Neg,
+                    expr: Box::new(e),
+                },
+                at,
+            )
+        }
+    }
+}
+
+fn add(a: Expr, b: Expr) -> Expr {
+    match (as_num(&a), as_num(&b)) {
+        (Some(x), Some(y)) => num(x + y, a.span),
+        (Some(0.0), _) => b,
+        (_, Some(0.0)) => a,
+        _ => bin(BinOp::Add, a, b),
+    }
+}
+
+fn sub(a: Expr, b: Expr) -> Expr {
+    match (as_num(&a), as_num(&b)) {
+        (Some(x), Some(y)) => num(x - y, a.span),
+        (Some(0.0), _) => neg(b),
+        (_, Some(0.0)) => a,
+        _ => bin(BinOp::Sub, a, b),
+    }
+}
+
+fn mul(a: Expr, b: Expr) -> Expr {
+    match (as_num(&a), as_num(&b)) {
+        (Some(x), Some(y)) => num(x * y, a.span),
+        (Some(0.0), _) | (_, Some(0.0)) => num(0.0, a.span),
+        (Some(1.0), _) => b,
+        (_, Some(1.0)) => a,
+        (Some(-1.0), _) => neg(b),
+        (_, Some(-1.0)) => neg(a),
+        _ => bin(BinOp::Mul, a, b),
+    }
+}
+
+fn div(a: Expr, b: Expr) -> Expr {
+    match (as_num(&a), as_num(&b)) {
+        (Some(0.0), _) | (_, Some(1.0)) => a,
+        _ => bin(BinOp::Div, a, b),
+    }
+}
+
+fn pow(a: Expr, b: Expr) -> Expr {
+    match as_num(&b) {
+        Some(0.0) => num(1.0, b.span),
+        Some(1.0) => a,
+        _ => bin(BinOp::Pow, a, b),
+    }
+}
@@ -0,0 +1,88 @@
+use crate::ast::AlgorithmDef;
+use crate::latex::def_to_latex;
+use crate::loader::SourceFile;
+use crate::printer::contracts_to_source;
+
+// A reference for the algorithms of a set of files, as printed by
+// `amlang doc`: a section per file, and under it each algorithm's
+// signature, its `///` documentation, and optionally its body as LaTeX.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DocFormat {
+    Markdown,
+    /// A standalone page; any LaTeX is left in `\[ \]` for MathJax or
+    /// KaTeX to render.
+    Html,
+}
+
+/// The reference for `files` in `format`, with each body rendered as
+/// LaTeX when `latex` is set. Files without definitions are left out.
+pub fn files_to_reference(files: &[SourceFile], format: DocFormat, latex: bool) -> String {
+    let files: Vec<&SourceFile> = files.iter().filter(|f| !f.defs.is_empty()).collect();
+    match format {
+        DocFormat::Markdown => markdown(&files, latex),
+        DocFormat::Html => html(&files, latex),
+    }
+}
+
+/// `Name(a, b)` followed by any contracts.
+pub fn signature(d: &AlgorithmDef) -> String {
+    format!(
+        "{}({}){}",
+        d.name,
+        d.params.join(", "),
+        contracts_to_source(d)
+    )
+}
+
+fn markdown(files: &[&SourceFile], latex: bool) -> String {
+    let mut out = String::new();
+    for f in files {
+        out.push_str(&format!("# {}\n\n", f.path));
+        for d in &f.defs {
+            out.push_str(&format!("## `{}`\n\n", signature(d)));
+            if let Some(doc) = &d.doc {
+                out.push_str(&format!("{}\n\n", doc));
+            }
+            if latex {
+                out.push_str(&format!("$$\n{}\n$$\n\n", def_to_latex(d)));
+            }
+        }
+    }
+    out
+}
+
+fn html(files: &[&SourceFile], latex: bool) -> String {
+    let mut out = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Algorithm reference</title>\n</head>\n<body>\n",
+    );
+    for f in files {
+        out.push_str(&format!("<h1>{}</h1>\n", escape(&f.path)));
+        for d in &f.defs {
+            out.push_str(&format!(
+                "<h2 id=\"{}\"><code>{}</code></h2>\n",
+                escape(&d.name),
+                escape(&signature(d))
+            ));
+            // blank doc lines separate paragraphs
+            for para in d.doc.iter().flat_map(|doc| doc.split("\n\n")) {
+                out.push_str(&format!("<p>{}</p>\n", escape(para.trim())));
+            }
+            if latex {
+                out.push_str(&format!(
+                    "<div class=\"math\">\\[{}\\]</div>\n",
+                    escape(&def_to_latex(d))
+                ));
+            }
+        }
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
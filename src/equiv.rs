@@ -0,0 +1,270 @@
+//! Comparing an algorithm with another on many inputs: `equivalent`
+//! evaluates two algorithms on each argument list a `Sampler` produces,
+//! and `check_against` an algorithm and a Rust function, returning the
+//! first inputs on which they disagree.
+
+use std::fmt;
+
+use crate::ast::{AlgorithmDef, Expr, ExprKind};
+use crate::builtins::Builtins;
+use crate::check::check_defs_with;
+use crate::eval::{Env, Value, World, eval_expr};
+use crate::token::Span;
+
+/// How many argument lists are tried when the range is too large to try
+/// every combination.
+pub const EQUIV_SAMPLES: usize = 1000;
+
+/// Each call `check_against` makes is limited to this many evaluation
+/// steps, so an algorithm that never returns fails rather than hangs.
+pub const CHECK_STEPS: u64 = 100_000;
+
+/// Integer arguments between `lo` and `hi`, inclusive. Every combination
+/// is tried when there are at most `samples` of them; otherwise `samples`
+/// are drawn from a fixed seed, so a report can be reproduced.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sampler {
+    pub lo: i64,
+    pub hi: i64,
+    pub samples: usize,
+}
+
+impl Sampler {
+    pub fn new(lo: i64, hi: i64) -> Self {
+        Self {
+            lo,
+            hi,
+            samples: EQUIV_SAMPLES,
+        }
+    }
+
+    /// A range written as in AM: `lo..hi`, or `lo..<hi` without `hi`.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let (lo, hi, exclusive) = match text.split_once("..<") {
+            Some((lo, hi)) => (lo, hi, true),
+            None => match text.split_once("..") {
+                Some((lo, hi)) => (lo, hi, false),
+                None => return Err(format!("expected a range such as 0..100, got {}", text)),
+            },
+        };
+        let bound = |s: &str| {
+            s.trim()
+                .parse::<i64>()
+                .map_err(|_| format!("range bounds must be integers, got {}", s.trim()))
+        };
+        let (lo, mut hi) = (bound(lo)?, bound(hi)?);
+        if exclusive {
+            hi -= 1;
+        }
+        if hi < lo {
+            return Err(format!("the range {} is empty", text.trim()));
+        }
+        Ok(Self::new(lo, hi))
+    }
+
+    /// The argument lists for an algorithm of `arity` parameters.
+    pub fn inputs(&self, arity: usize) -> Vec<Vec<Value>> {
+        let width = (self.hi - self.lo) as u64 + 1;
+        let total = u32::try_from(arity)
+            .ok()
+            .and_then(|a| width.checked_pow(a))
+            .filter(|&n| n <= self.samples as u64);
+        let mut seed = 0x9e37_79b9_7f4a_7c15_u64;
+        let count = total.unwrap_or(self.samples as u64);
+        (0..count)
+            .map(|i| {
+                let mut rest = i;
+                (0..arity)
+                    .map(|_| {
+                        let offset = match total {
+                            Some(_) => {
+                                let o = rest % width;
+                                rest /= width;
+                                o
+                            }
+                            None => next_random(&mut seed) % width,
+                        };
+                        Value::Number((self.lo + offset as i64) as f64)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Sampler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..{}", self.lo, self.hi)
+    }
+}
+
+// xorshift64: cheap, and the same sequence on every platform.
+fn next_random(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
+
+/// `@A @B over 0..100`, as given to `:equiv` and `--equiv`. Without
+/// `over`, arguments are drawn from 0..100.
+pub fn parse_equiv(spec: &str) -> Result<(String, String, Sampler), String> {
+    let usage = "expected @A @B [over LO..HI]";
+    let (names, range) = match spec.split_once(" over ") {
+        Some((names, range)) => (names, Some(range)),
+        None => (spec, None),
+    };
+    let names: Vec<&str> = names
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|n| !n.is_empty())
+        .map(|n| n.trim_start_matches('@'))
+        .collect();
+    let [left, right] = names[..] else {
+        return Err(usage.to_string());
+    };
+    let sampler = match range {
+        Some(range) => Sampler::parse(range)?,
+        None => Sampler::new(0, 100),
+    };
+    Ok((left.to_string(), right.to_string(), sampler))
+}
+
+/// Arguments on which two algorithms disagree, and what each produced.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Counterexample {
+    pub args: Vec<Value>,
+    pub left: Result<Value, String>,
+    pub right: Result<Value, String>,
+}
+
+impl Counterexample {
+    /// E.g. `@A(3) = 1 but @B(3) = 2`, for `left` and `right` of `@A`
+    /// and `@B`.
+    pub fn describe(&self, left: &str, right: &str) -> String {
+        let args: Vec<String> = self.args.iter().map(Value::to_string).collect();
+        let args = args.join(", ");
+        let outcome = |r: &Result<Value, String>| match r {
+            Ok(v) => format!("= {}", v),
+            Err(e) => format!("fails: {}", e),
+        };
+        format!(
+            "{left}({args}) {} but {right}({args}) {}",
+            outcome(&self.left),
+            outcome(&self.right)
+        )
+    }
+}
+
+/// Call `left` and `right` on each of `sampler`'s inputs. Returns the
+/// number of inputs tried when they always agree: on equal values, or
+/// both failing.
+pub fn equivalent(
+    world: &World,
+    left: &str,
+    right: &str,
+    sampler: &Sampler,
+) -> Result<Result<usize, Counterexample>, String> {
+    let arity = |name: &str| match world.algs.get(name) {
+        Some(d) => Ok(d.params.len()),
+        None => Err(format!("unknown algorithm: @{}", name)),
+    };
+    let arity_left = arity(left)?;
+    if arity(right)? != arity_left {
+        return Err(format!(
+            "@{} takes {} argument(s) but @{} takes {}",
+            left,
+            arity_left,
+            right,
+            arity(right)?
+        ));
+    }
+    Ok(compare(
+        &sampler.inputs(arity_left),
+        |args| call(world, left, args),
+        |args| call(world, right, args),
+    ))
+}
+
+/// Call algorithm `name` of `defs` and `reference` on each of `sampler`'s
+/// inputs, as `equivalent` does for two algorithms. `defs` are checked
+/// first, and the first error found is returned. Calls are limited to
+/// `CHECK_STEPS` steps.
+///
+/// ```
+/// use amlang::equiv::{Sampler, check_against};
+/// use amlang::{Value, parse};
+///
+/// let (program, _) = parse("@Double(x) = x + x").unwrap();
+/// let twice = |args: &[Value]| Ok(Value::Number(2.0 * args[0].as_f64()?));
+/// let tried = check_against(&program.defs, "Double", twice, &Sampler::new(-50, 50));
+/// assert_eq!(tried, Ok(Ok(101)));
+/// ```
+pub fn check_against<F>(
+    defs: &[AlgorithmDef],
+    name: &str,
+    reference: F,
+    sampler: &Sampler,
+) -> Result<Result<usize, Counterexample>, String>
+where
+    F: Fn(&[Value]) -> Result<Value, String>,
+{
+    let problems = check_defs_with(defs, &[], &Builtins::standard());
+    if let Some(d) = problems.iter().find(|d| d.is_error()) {
+        return Err(d.message.clone());
+    }
+    let mut world = World::new(defs);
+    world.settings.max_steps = Some(CHECK_STEPS);
+    let Some(def) = world.algs.get(name) else {
+        return Err(format!("unknown algorithm: @{}", name));
+    };
+    Ok(compare(
+        &sampler.inputs(def.params.len()),
+        |args| call(&world, name, args),
+        reference,
+    ))
+}
+
+// The first of `inputs` on which `left` and `right` disagree.
+fn compare(
+    inputs: &[Vec<Value>],
+    left: impl Fn(&[Value]) -> Result<Value, String>,
+    right: impl Fn(&[Value]) -> Result<Value, String>,
+) -> Result<usize, Counterexample> {
+    for args in inputs {
+        let l = left(args);
+        let r = right(args);
+        let agree = match (&l, &r) {
+            (Ok(a), Ok(b)) => same(a, b),
+            (Err(_), Err(_)) => true,
+            _ => false,
+        };
+        if !agree {
+            return Err(Counterexample {
+                args: args.clone(),
+                left: l,
+                right: r,
+            });
+        }
+    }
+    Ok(inputs.len())
+}
+
+fn call(world: &World, name: &str, args: &[Value]) -> Result<Value, String> {
+    let call = Expr::new(
+        ExprKind::Call {
+            is_alg: true,
+            name: name.to_string(),
+            args: args.iter().map(Value::to_expr).collect(),
+        },
+        Span::new(0, 0),
+    );
+    eval_expr(world, &mut Env::base(), &call).map_err(|e| e.message)
+}
+
+// Equal values, counting NaN as equal to itself.
+fn same(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => x == y || (x.is_nan() && y.is_nan()),
+        _ => a == b,
+    }
+}
@@ -1,37 +1,369 @@
 use core::f64;
 use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
 
-use crate::ast::{AlgorithmDef, BinOp, Expr, UnOp};
+use crate::ast::{AlgorithmDef, BinOp, Expr, ExprKind, SeriesKind, UnOp, pipe_args};
+use crate::bigint::{BigInt, MAX_EXACT_BITS, MAX_EXACT_INT};
+use crate::builtins::{Builtins, HIGHER_ORDER, NativeFn, exact_builtin};
+use crate::complex::Complex;
+use crate::diagnostic::{
+    Diagnostic, E_ARITY, E_BUDGET, E_CONTRACT, E_NO_ARM, E_RECURSION_LIMIT, E_RUNTIME,
+    E_UNKNOWN_CALL, E_UNKNOWN_NAME, did_you_mean,
+};
+use crate::printer::{bin_symbol, expr_to_source};
+use crate::profile::Profile;
+use crate::settings::{ModMode, Settings};
+use crate::solve::solve;
+use crate::token::Span;
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Value {
     Number(f64),
     Bool(bool),
+    Str(String),
+    /// An algorithm passed around by name, from `@Name`.
+    AlgRef(String),
+    /// An integer too large for `Number` to hold exactly; only produced
+    /// when `Settings::bigint` is on.
+    Int(BigInt),
+    Complex(Complex),
+    /// Every number between the bounds, inclusive.
+    Interval(f64, f64),
+    /// A lambda together with the variables it captured.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Closure(Arc<Closure>),
+    /// The third truth value, from a comparison of intervals that holds
+    /// for some of their numbers but not others.
+    Unknown,
+    /// A sequence of values, from a range such as `1..n` or `map`.
+    List(Vec<Value>),
+    /// A fixed group of values, such as the quotient and remainder from
+    /// `divmod`. Passed as the only argument to a function of as many
+    /// parameters, it is spread over them.
+    Tuple(Vec<Value>),
+}
+
+/// The value of `\x -> body`: the lambda and the variables of its body
+/// bound where it was evaluated, so it still sees them once called
+/// elsewhere.
+pub struct Closure {
+    pub params: Vec<String>,
+    pub body: Expr,
+    pub captured: HashMap<String, Value>,
+    // algorithm whose body the lambda was written in, for error locations
+    origin: Option<String>,
+}
+
+impl Closure {
+    /// The lambda with its captured variables written in as literals.
+    pub fn to_expr(&self) -> Expr {
+        let (names, values): (Vec<String>, Vec<Value>) = self
+            .captured
+            .iter()
+            .map(|(n, v)| (n.clone(), v.clone()))
+            .unzip();
+        let kind = ExprKind::Lambda {
+            params: self.params.clone(),
+            body: Box::new(substitute(&self.body, &names, &values)),
+        };
+        Expr::new(kind, Span::new(0, 0))
+    }
+}
+
+// Closures are equal only to themselves.
+impl PartialEq for Closure {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Debug for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Closure({})", expr_to_source(&self.to_expr()))
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Number(x) => write!(f, "{}", x),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Str(s) => write!(f, "{:?}", s),
+            Value::AlgRef(name) => write!(f, "@{}", name),
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Complex(z) => write!(f, "{}", z),
+            Value::Interval(lo, hi) => write!(f, "[{}, {}]", lo, hi),
+            Value::Unknown => write!(f, "unknown"),
+            Value::Closure(c) => write!(f, "{}", expr_to_source(&c.to_expr())),
+            Value::List(items) => {
+                let items: Vec<String> = items.iter().map(Value::to_string).collect();
+                write!(f, "[{}]", items.join(", "))
+            }
+            Value::Tuple(items) => {
+                let items: Vec<String> = items.iter().map(Value::to_string).collect();
+                write!(f, "({})", items.join(", "))
+            }
+        }
+    }
 }
 
 impl Value {
-    fn as_f64(&self) -> Result<f64, String> {
+    pub fn as_f64(&self) -> Result<f64, String> {
         match self {
             Value::Number(x) => Ok(*x),
+            Value::Int(n) => Ok(n.to_f64()),
+            Value::Complex(z) => Err(format!("expected a real number, got {}", z)),
             other => Err(format!("expected number, got {:?}", other)),
         }
     }
-    fn as_bool(&self) -> Result<bool, String> {
+    pub fn as_bool(&self) -> Result<bool, String> {
         match self {
             Value::Bool(b) => Ok(*b),
+            Value::Unknown => Err(
+                "expected bool, got unknown: the comparison holds for only part of an interval"
+                    .to_string(),
+            ),
             other => Err(format!("expected bool, got {:?}", other)),
         }
     }
+    pub fn as_str(&self) -> Result<&str, String> {
+        match self {
+            Value::Str(s) => Ok(s),
+            other => Err(format!("expected string, got {:?}", other)),
+        }
+    }
+    /// The bounds of a number or interval; a number is its own bounds.
+    pub fn as_interval(&self) -> Result<(f64, f64), String> {
+        match self {
+            Value::Number(x) => Ok((*x, *x)),
+            Value::Int(n) => Ok((n.to_f64(), n.to_f64())),
+            Value::Interval(lo, hi) => Ok((*lo, *hi)),
+            other => Err(format!("expected number or interval, got {:?}", other)),
+        }
+    }
+    pub fn as_list(&self) -> Result<&[Value], String> {
+        match self {
+            Value::List(items) => Ok(items),
+            other => Err(format!("expected a sequence, got {}", other)),
+        }
+    }
+    /// A number as a complex one with no imaginary part.
+    pub fn as_complex(&self) -> Result<Complex, String> {
+        match self {
+            Value::Complex(z) => Ok(*z),
+            other => other.as_f64().map(Complex::real),
+        }
+    }
+    /// `z` as a number when it has no imaginary part.
+    pub fn from_complex(z: Complex) -> Value {
+        if z.im == 0.0 {
+            Value::Number(z.re)
+        } else {
+            Value::Complex(z)
+        }
+    }
+    /// `n` as a number when that is exact, and as a big integer otherwise.
+    pub fn from_big(n: BigInt) -> Value {
+        if n.fits_f64() {
+            Value::Number(n.to_f64())
+        } else {
+            Value::Int(n)
+        }
+    }
+    // An integer as a big one, whatever its representation.
+    fn as_big(&self) -> Option<BigInt> {
+        match self {
+            Value::Number(x) => BigInt::from_f64(*x),
+            Value::Int(n) => Some(n.clone()),
+            _ => None,
+        }
+    }
+    // A bool, or `None` for unknown.
+    fn as_truth(&self) -> Result<Option<bool>, String> {
+        match self {
+            Value::Unknown => Ok(None),
+            other => other.as_bool().map(Some),
+        }
+    }
+    /// The value as a literal expression, with an empty span.
+    pub fn to_expr(&self) -> Expr {
+        let kind = match self {
+            Value::Number(n) => ExprKind::Number(*n),
+            Value::Bool(b) => ExprKind::Bool(*b),
+            Value::Str(s) => ExprKind::Str(s.clone()),
+            Value::AlgRef(name) => ExprKind::AlgRef(name.clone()),
+            // source has no big integer literals
+            Value::Int(n) => ExprKind::Number(n.to_f64()),
+            // written as the builtin calls that make them
+            Value::Interval(lo, hi) => ExprKind::Call {
+                is_alg: false,
+                name: "interval".to_string(),
+                args: vec![Value::Number(*lo).to_expr(), Value::Number(*hi).to_expr()],
+            },
+            Value::Complex(z) => ExprKind::Call {
+                is_alg: false,
+                name: "complex".to_string(),
+                args: vec![Value::Number(z.re).to_expr(), Value::Number(z.im).to_expr()],
+            },
+            Value::Unknown => ExprKind::Call {
+                is_alg: false,
+                name: "unknown".to_string(),
+                args: Vec::new(),
+            },
+            Value::Closure(c) => return c.to_expr(),
+            Value::List(items) => ExprKind::List(items.iter().map(Value::to_expr).collect()),
+            Value::Tuple(items) => ExprKind::Tuple(items.iter().map(Value::to_expr).collect()),
+        };
+        Expr::new(kind, Span::new(0, 0))
+    }
+}
+
+// calls kept at each end of a long trace
+const TRACE_EDGE: usize = 4;
+
+/// A runtime failure, located at the expression that raised it.
+#[derive(Debug, Clone)]
+pub struct EvalError {
+    pub code: &'static str,
+    pub message: String,
+    pub span: Option<Span>,
+    // algorithm whose body contains `span`; `None` for the top-level input
+    pub alg: Option<String>,
+    // active algorithm calls, outermost first, e.g. `@Outer(3)`
+    pub trace: Vec<String>,
 }
 
+impl EvalError {
+    /// Render the error with a caret under the failing expression. `src`
+    /// must be the (normalized) text that `alg`'s body, or the top-level
+    /// input, was parsed from.
+    pub fn render(&self, src: Option<&str>) -> String {
+        self.to_diagnostic().render(src)
+    }
+
+    /// The error as a diagnostic, with the call trace as a note.
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let mut d = Diagnostic::error(self.code, self.message.clone());
+        if let Some(span) = self.span {
+            d = d.with_span(span);
+        }
+        if !self.trace.is_empty() {
+            d = d.with_note(format!("trace: {}", self.trace_line()));
+        }
+        d
+    }
+
+    /// The call trace as `in @Outer(3) → in @Inner(7)`; deep traces keep
+    /// only their outermost and innermost calls.
+    pub fn trace_line(&self) -> String {
+        let mut parts: Vec<String> = self.trace.iter().map(|c| format!("in {}", c)).collect();
+        if parts.len() > 2 * TRACE_EDGE + 1 {
+            let hidden = parts.len() - 2 * TRACE_EDGE;
+            parts.splice(
+                TRACE_EDGE..parts.len() - TRACE_EDGE,
+                [format!("... {} more calls ...", hidden)],
+            );
+        }
+        parts.join(" → ")
+    }
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+// An error raised inside the machine before it is located; plain string
+// errors from values and builtins get the generic runtime code.
+struct Fault {
+    code: &'static str,
+    message: String,
+    // already located inside a closure's body
+    located: Option<Box<EvalError>>,
+}
+
+impl Fault {
+    fn new(code: &'static str, message: String) -> Self {
+        Self {
+            code,
+            message,
+            located: None,
+        }
+    }
+}
+
+impl From<EvalError> for Fault {
+    fn from(err: EvalError) -> Self {
+        Self {
+            code: err.code,
+            message: err.message.clone(),
+            located: Some(Box::new(err)),
+        }
+    }
+}
+
+impl From<String> for Fault {
+    fn from(message: String) -> Self {
+        Fault::new(E_RUNTIME, message)
+    }
+}
+
+/// The name a case block used as a pipeline step gives the piped value,
+/// besides `_`.
+pub const PIPE_VALUE: &str = "it";
+
+/// The name `ensures` conditions give an algorithm's return value.
+pub const RESULT: &str = "result";
+
+/// Both names a case step binds, for rewriting it with the piped value.
+pub(crate) fn pipe_value_names() -> [String; 2] {
+    [PIPE_VALUE.to_string(), "_".to_string()]
+}
+
+/// The golden ratio, `(1 + sqrt(5)) / 2`.
+pub const PHI: f64 = 1.618_033_988_749_895;
+
+/// Constants visible inside every algorithm body and at the top level. A
+/// parameter of the same name hides one.
+pub const BODY_CONSTANTS: [(&str, f64); 9] = [
+    ("inf", f64::INFINITY),
+    ("NaN", f64::NAN),
+    ("pi", std::f64::consts::PI),
+    ("π", std::f64::consts::PI),
+    ("e", std::f64::consts::E),
+    ("tau", std::f64::consts::TAU),
+    ("τ", std::f64::consts::TAU),
+    ("phi", PHI),
+    ("φ", PHI),
+];
+
+/// The imaginary unit, bound like the constants above, so that `i * i` is
+/// -1 unless a parameter named `i` hides it.
+pub const IMAGINARY_UNIT: &str = "i";
+
 #[derive(Default)]
 pub struct Env {
     // simple variable/constant bindings: a -> 3.0, true -> true, etc.
     vars: HashMap<String, Value>,
+    // number of algorithm calls between this frame and the top level
+    depth: usize,
 }
 
 impl Env {
     pub fn with_params(params: &[String], args: &[Value]) -> Result<Self, String> {
+        // a lone tuple is unpacked into the parameters
+        if let [Value::Tuple(items)] = args
+            && params.len() > 1
+            && items.len() == params.len()
+        {
+            return Self::with_params(params, items);
+        }
         if params.len() != args.len() {
             return Err(format!(
                 "argument count mismatch: expected {}, got {}",
@@ -39,254 +371,2303 @@ impl Env {
                 args.len()
             ));
         }
-        let mut vars = HashMap::new();
+        let mut env = Self::base();
         for (p, v) in params.iter().zip(args.iter()) {
-            vars.insert(p.clone(), v.clone());
+            env.vars.insert(p.clone(), v.clone());
         }
-        // Built-in constants
-        vars.insert("inf".to_string(), Value::Number(f64::INFINITY));
-        vars.insert("NaN".to_string(), Value::Number(f64::NAN));
-        Ok(Self { vars })
+        Ok(env)
     }
     pub fn base() -> Self {
-        let mut vars = HashMap::new();
-        vars.insert("inf".to_string(), Value::Number(f64::INFINITY));
-        vars.insert("NaN".to_string(), Value::Number(f64::NAN));
-        vars.insert("pi".to_string(), Value::Number(std::f64::consts::PI));
-        vars.insert("e".to_string(), Value::Number(std::f64::consts::E));
-        vars.insert("tau".to_string(), Value::Number(std::f64::consts::TAU));
-        Self { vars }
+        let mut vars: HashMap<String, Value> = BODY_CONSTANTS
+            .iter()
+            .map(|&(name, v)| (name.to_string(), Value::Number(v)))
+            .collect();
+        let unit = Value::from_complex(Complex::new(0.0, 1.0));
+        vars.insert(IMAGINARY_UNIT.to_string(), unit);
+        Self { vars, depth: 0 }
     }
     fn get(&self, name: &str) -> Option<&Value> {
         self.vars.get(name)
     }
-    // fn set(&mut self, name: String, val: Value) {
-    //     self.vars.insert(name, val);
-    // }
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.vars.keys()
+    }
+    pub fn set(&mut self, name: String, val: Value) {
+        self.vars.insert(name, val);
+    }
+    // Bind `name` to `val` again, or unbind it for `None`.
+    fn restore(&mut self, name: &str, val: Option<Value>) {
+        match val {
+            Some(v) => self.vars.insert(name.to_string(), v),
+            None => self.vars.remove(name),
+        };
+    }
+}
+
+/// Results of memoized algorithm calls. Entries stay valid only while the
+/// definitions and settings they were computed under do.
+#[derive(Default)]
+pub struct Memo {
+    entries: HashMap<(String, Vec<MemoArg>), Value>,
+    pub hits: usize,
+    pub misses: usize,
+}
+
+impl Memo {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        *self = Memo::default();
+    }
+}
+
+// An argument as a hashable key; numbers compare by bit pattern.
+#[derive(PartialEq, Eq, Hash)]
+enum MemoArg {
+    Number(u64),
+    Bool(bool),
+    Str(String),
+    AlgRef(String),
+    Int(BigInt),
+    Complex(u64, u64),
+    Interval(u64, u64),
+    Unknown,
+    // by source, captured values included
+    Closure(String),
+    List(Vec<MemoArg>),
+    Tuple(Vec<MemoArg>),
+}
+
+impl MemoArg {
+    fn of(v: &Value) -> Self {
+        match v {
+            Value::Number(n) => MemoArg::Number(n.to_bits()),
+            Value::Bool(b) => MemoArg::Bool(*b),
+            Value::Str(s) => MemoArg::Str(s.clone()),
+            Value::AlgRef(name) => MemoArg::AlgRef(name.clone()),
+            Value::Int(n) => MemoArg::Int(n.clone()),
+            Value::Complex(z) => MemoArg::Complex(z.re.to_bits(), z.im.to_bits()),
+            Value::Interval(lo, hi) => MemoArg::Interval(lo.to_bits(), hi.to_bits()),
+            Value::Unknown => MemoArg::Unknown,
+            Value::Closure(c) => MemoArg::Closure(expr_to_source(&c.to_expr())),
+            Value::List(items) => MemoArg::List(items.iter().map(MemoArg::of).collect()),
+            Value::Tuple(items) => MemoArg::Tuple(items.iter().map(MemoArg::of).collect()),
+        }
+    }
 }
 
-pub struct World<'a> {
+fn memo_key(name: &str, args: &[Value]) -> (String, Vec<MemoArg>) {
+    (name.to_string(), args.iter().map(MemoArg::of).collect())
+}
+
+/// A step of evaluation reported to `World::trace`.
+pub enum TraceEvent<'a> {
+    /// An algorithm was called; `cached` holds a memoized result.
+    Enter {
+        name: &'a str,
+        args: &'a [Value],
+        cached: Option<&'a Value>,
+    },
+    /// An algorithm returned. Tail calls return once, as the last callee.
+    Return { name: &'a str, value: &'a Value },
+    /// The arm with condition `cond` was chosen, or the default for `None`.
+    Arm { cond: Option<&'a Expr> },
+    /// `value` is fed into the pipeline step `step`.
+    Pipe { value: &'a Value, step: &'a Expr },
+}
+
+impl fmt::Display for TraceEvent<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceEvent::Enter {
+                name,
+                args,
+                cached: None,
+            } => write!(f, "→ {}", format_call(name, args)),
+            TraceEvent::Enter {
+                name,
+                args,
+                cached: Some(v),
+            } => write!(f, "→ {} = {} (memoized)", format_call(name, args), v),
+            TraceEvent::Return { name, value } => write!(f, "← @{} = {}", name, value),
+            TraceEvent::Arm { cond: Some(c) } => write!(f, "case {} matched", expr_to_source(c)),
+            TraceEvent::Arm { cond: None } => write!(f, "case _ (default)"),
+            TraceEvent::Pipe { value, step } => {
+                write!(f, "pipe {} >> {}", value, expr_to_source(step))
+            }
+        }
+    }
+}
+
+/// Receives each traced step with the number of algorithm calls active
+/// around it, for indentation.
+pub type Tracer = Box<dyn Fn(usize, &TraceEvent) + Send + Sync>;
+
+/// Everything an evaluation can see. A `World` owns its definitions and
+/// is `Send + Sync`, so one set of definitions can serve evaluations on
+/// many threads at once; they share the memo table and any profile.
+pub struct World {
     // registry of algorithms by name
-    pub algs: HashMap<String, &'a AlgorithmDef>,
+    pub algs: HashMap<String, Arc<AlgorithmDef>>,
+    // native functions reachable by plain (non-@) calls
+    pub builtins: Builtins,
+    pub settings: Settings,
+    // filled in while evaluating calls to `@memo` algorithms, or to every
+    // algorithm with `settings.memoize`
+    pub memo: Mutex<Memo>,
+    // operation counts, gathered only when present
+    pub profile: Option<Mutex<Profile>>,
+    pub trace: Option<Tracer>,
 }
 
-impl<'a> World<'a> {
-    pub fn new(defs: &'a [AlgorithmDef]) -> Self {
-        let mut algs = HashMap::new();
+impl World {
+    pub fn new(defs: &[AlgorithmDef]) -> Self {
+        let mut world = Self {
+            algs: HashMap::new(),
+            builtins: Builtins::standard(),
+            settings: Settings::default(),
+            memo: Mutex::default(),
+            profile: None,
+            trace: None,
+        };
         for d in defs {
-            algs.insert(d.name.clone(), d);
+            world.define(d.clone());
         }
-        Self { algs }
+        world
     }
-}
 
-fn call_name<'a>(
-    world: &World<'a>,
-    _env: &mut Env,
-    is_alg: bool,
-    name: &str,
-    vals: Vec<Value>,
-) -> Result<Value, String> {
-    // If it's an algorithm (explicit @ or known by name), run that algorithm body
-    if is_alg || world.algs.contains_key(name) {
-        let alg = world
-            .algs
-            .get(name)
-            .ok_or_else(|| format!("unknown algorithm: {}", name))?;
-        let mut local = Env::with_params(&alg.params, &vals)?;
-        return eval_expr(world, &mut local, &alg.body);
+    /// Add `def`, replacing any algorithm of the same name.
+    pub fn define(&mut self, def: AlgorithmDef) {
+        self.memo.get_mut().unwrap().clear();
+        self.algs.insert(def.name.clone(), Arc::new(def));
     }
 
-    // Otherwise: handle tiny built-in functions here
-    match name {
-        "sqrt" => {
-            if vals.len() != 1 {
-                return Err(format!("sqrt expects 1 arg, got {}", vals.len()));
+    /// Expose a native function to AM code under `name`; algorithms of the
+    /// same name still take precedence.
+    pub fn register_fn<F>(&mut self, name: &str, arity: usize, f: F)
+    where
+        F: Fn(&[Value]) -> Result<Value, String> + Send + Sync + 'static,
+    {
+        let f: NativeFn = Box::new(f);
+        self.builtins.register(name, arity, f);
+    }
+
+    /// Call an algorithm, or failing that a builtin, by name. The
+    /// arguments are bound as they are, so nothing is lost to printing
+    /// them as source and reading them back.
+    pub fn call(&self, name: &str, args: &[Value]) -> Result<Value, EvalError> {
+        Machine::calling(name, args.to_vec()).run(self, &mut Env::base())
+    }
+
+    /// `call` with each of `inputs`, spread over as many threads as there
+    /// are cores. The results are in the order of `inputs`, and each call
+    /// has its own step and time budget.
+    pub fn call_batch(&self, name: &str, inputs: &[Vec<Value>]) -> Vec<Result<Value, EvalError>> {
+        let threads = thread::available_parallelism()
+            .map_or(1, |n| n.get())
+            .min(inputs.len());
+        // each worker takes the next input when it is free, so a few slow
+        // calls do not hold up the rest
+        let next = AtomicUsize::new(0);
+        let mut done: Vec<(usize, Result<Value, EvalError>)> = thread::scope(|s| {
+            let workers: Vec<_> = (0..threads)
+                .map(|_| {
+                    s.spawn(|| {
+                        let mut done = Vec::new();
+                        loop {
+                            let i = next.fetch_add(1, Ordering::Relaxed);
+                            let Some(args) = inputs.get(i) else {
+                                return done;
+                            };
+                            done.push((i, self.call(name, args)));
+                        }
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|w| w.join().expect("batch worker panicked"))
+                .collect()
+        });
+        done.sort_by_key(|(i, _)| *i);
+        done.into_iter().map(|(_, r)| r).collect()
+    }
+}
+
+// Work items for the evaluator. Sub-expressions are scheduled as `Eval`
+// tasks and their results land on the value stack, so neither deeply nested
+// expressions nor deep recursion grow the native stack.
+enum Task<'e> {
+    Eval(&'e Expr),
+    Unary(UnOp, Span),
+    Bin(BinOp, Span),
+    // `&&`/`||` with the left operand on top of the value stack; the right
+    // one is only evaluated when it can still change the result
+    ShortCircuit {
+        op: BinOp,
+        right: &'e Expr,
+        span: Span,
+    },
+    // the right operand of `&&`/`||` must itself be a bool
+    ExpectBool(Span),
+    // the condition of `arms[next]` is on top of the value stack
+    Case {
+        arms: &'e [(Expr, Expr)],
+        next: usize,
+        default: Option<&'e Expr>,
+        span: Span,
+    },
+    // every arm of the case at `Span` was false and it has no default
+    Unmatched(Span),
+    // the top `argc` values are the call's arguments
+    Call {
+        is_alg: bool,
+        name: &'e str,
+        argc: usize,
+        span: Span,
+    },
+    // the callee is on top of the value stack, its `argc` arguments below
+    CallValue {
+        argc: usize,
+        span: Span,
+    },
+    // feed the value on top of the stack through a pipeline step
+    PipeStep(&'e Expr),
+    // `map`, `filter`, `fold`, `iterate` or `simulate` partway through
+    // `items`; the function's result for `last` is on top of the value
+    // stack
+    Sweep {
+        sweep: Sweep,
+        f: Value,
+        items: std::vec::IntoIter<Value>,
+        last: Option<Value>,
+        span: Span,
+    },
+    // `bisect` or `newton` after `steps` calls; when `probe` is set, the
+    // value of the function called there is on top of the value stack
+    Root {
+        root: Root,
+        steps: u32,
+        probe: Option<f64>,
+        span: Span,
+    },
+    // find the roots of a `solve`
+    Solve(&'e Expr),
+    // the items of a list literal are on top of the value stack
+    MakeList(usize),
+    MakeTuple(usize),
+    // the bounds of a `Σ` or `Π` are on top of the value stack
+    SeriesBounds(&'e Expr),
+    // the term of a `Σ` or `Π` for `index = i` is on top of the value
+    // stack, above the total so far; `saved` is what `index` was bound to
+    SeriesTerm {
+        op: BinOp,
+        index: &'e str,
+        body: &'e Expr,
+        i: f64,
+        to: f64,
+        saved: Option<Value>,
+    },
+    // a case step finished: put back what `it` and `_` were bound to
+    Unbind(Option<Value>, Option<Value>),
+    // the step's arguments other than `_` are on top of the value stack,
+    // the piped value below them; put it in place of each `_`
+    Placeholders(&'e [Expr]),
+    // the result of a memoized call is on top of the value stack
+    Memoize(&'e str, Vec<Value>),
+    // the body of `@Name` left its result on top of the value stack; bind
+    // it to `result` and check each `ensures` condition
+    Ensure(&'e AlgorithmDef),
+    // a contract condition of `@Name` is on top of the value stack; it is
+    // a postcondition when `post`
+    Contract {
+        cond: &'e Expr,
+        alg: &'e str,
+        post: bool,
+    },
+    // an algorithm body finished: restore the caller's frame
+    Return,
+}
+
+impl Task<'_> {
+    // where an error raised by this task is reported
+    fn span(&self) -> Option<Span> {
+        match self {
+            Task::Eval(e) | Task::PipeStep(e) | Task::SeriesBounds(e) | Task::Solve(e) => {
+                Some(e.span)
             }
-            Ok(Value::Number(vals[0].as_f64()?.sqrt()))
+            Task::SeriesTerm { body, .. } => Some(body.span),
+            Task::Contract { cond, .. } => Some(cond.span),
+            Task::Unary(_, span)
+            | Task::Bin(_, span)
+            | Task::ShortCircuit { span, .. }
+            | Task::ExpectBool(span)
+            | Task::Call { span, .. }
+            | Task::CallValue { span, .. }
+            | Task::Sweep { span, .. }
+            | Task::Root { span, .. }
+            | Task::Unmatched(span) => Some(*span),
+            Task::Case { arms, next, .. } => Some(arms[*next].0.span),
+            Task::Unbind(..)
+            | Task::Placeholders(_)
+            | Task::MakeList(_)
+            | Task::MakeTuple(_)
+            | Task::Memoize(..)
+            | Task::Ensure(_)
+            | Task::Return => None,
         }
-        "abs" => {
-            if vals.len() != 1 {
-                return Err(format!("abs expects 1 arg, got {}", vals.len()));
-            }
-            Ok(Value::Number(vals[0].as_f64()?.abs()))
+    }
+}
+
+// What a sweep over a sequence builds.
+enum Sweep {
+    Map(Vec<Value>),
+    Filter(Vec<Value>),
+    // the accumulator stays on the value stack between calls
+    Fold,
+    // as does the current value of an iteration, with this many
+    // applications of `f` left
+    Iterate(u64),
+    // the results of `f()`, called once per item
+    Simulate(Vec<Value>),
+}
+
+// A root search in progress.
+enum Root {
+    // once `f_lo` is known and `bracketed` is set, `f` changes sign
+    // between `lo` and `hi`
+    Bisect {
+        f: Value,
+        lo: f64,
+        hi: f64,
+        f_lo: Option<f64>,
+        bracketed: bool,
+        tol: f64,
+    },
+    // `df` is called at `x` after `f`, whose value waits in `fx`
+    Newton {
+        f: Value,
+        df: Value,
+        x: f64,
+        fx: Option<f64>,
+        tol: f64,
+    },
+}
+
+// What a root search does next.
+enum RootStep {
+    Done(f64),
+    Call(Value, f64),
+}
+
+impl Root {
+    // Take in `y`, the value of the last function called at `x`, and
+    // decide where to look next.
+    fn advance(&mut self, seen: Option<(f64, f64)>) -> Result<RootStep, Fault> {
+        if let Some((x, y)) = seen
+            && !y.is_finite()
+        {
+            return Err(Fault::from(format!(
+                "root search reached a non-finite value {} at x = {}",
+                Value::Number(y),
+                Value::Number(x)
+            )));
         }
-        "sin" => {
-            if vals.len() != 1 {
-                return Err(format!("sin expects 1 arg, got {}", vals.len()));
+        match self {
+            Root::Bisect {
+                f,
+                lo,
+                hi,
+                f_lo,
+                bracketed,
+                tol,
+            } => {
+                let Some((x, y)) = seen else {
+                    return Ok(RootStep::Call(f.clone(), *lo));
+                };
+                if y == 0.0 {
+                    return Ok(RootStep::Done(x));
+                }
+                match *f_lo {
+                    None => {
+                        *f_lo = Some(y);
+                        return Ok(RootStep::Call(f.clone(), *hi));
+                    }
+                    Some(fl) if !*bracketed => {
+                        if fl.signum() == y.signum() {
+                            return Err(Fault::from(format!(
+                                "bisect needs f(lo) and f(hi) of opposite signs, got {} and {}",
+                                Value::Number(fl),
+                                Value::Number(y)
+                            )));
+                        }
+                        *bracketed = true;
+                    }
+                    Some(fl) if fl.signum() == y.signum() => {
+                        *lo = x;
+                        *f_lo = Some(y);
+                    }
+                    Some(_) => *hi = x,
+                }
+                let mid = *lo + (*hi - *lo) / 2.0;
+                // stop at the tolerance, or once the midpoint can no
+                // longer be told apart from an end
+                if (*hi - *lo).abs() <= 2.0 * *tol || mid == *lo || mid == *hi {
+                    Ok(RootStep::Done(mid))
+                } else {
+                    Ok(RootStep::Call(f.clone(), mid))
+                }
             }
-            Ok(Value::Number(vals[0].as_f64()?.sin()))
+            Root::Newton { f, df, x, fx, tol } => {
+                let Some((_, y)) = seen else {
+                    return Ok(RootStep::Call(f.clone(), *x));
+                };
+                let Some(fy) = fx.take() else {
+                    if y == 0.0 {
+                        return Ok(RootStep::Done(*x));
+                    }
+                    *fx = Some(y);
+                    return Ok(RootStep::Call(df.clone(), *x));
+                };
+                if y == 0.0 {
+                    return Err(Fault::from(format!(
+                        "newton reached a zero derivative at x = {}",
+                        Value::Number(*x)
+                    )));
+                }
+                let next = *x - fy / y;
+                if (next - *x).abs() <= *tol {
+                    return Ok(RootStep::Done(next));
+                }
+                *x = next;
+                Ok(RootStep::Call(f.clone(), next))
+            }
+        }
+    }
+}
+
+struct Machine<'e> {
+    tasks: Vec<Task<'e>>,
+    vals: Vec<Value>,
+    // caller frames saved while an algorithm body runs, with the name of
+    // the algorithm each one belongs to
+    frames: Vec<(Env, Option<&'e str>)>,
+    // algorithm whose body is currently running
+    alg: Option<&'e str>,
+    // name and arguments of every active algorithm call, outermost first;
+    // a tail call replaces its caller's entry
+    calls: Vec<(&'e str, Vec<Value>)>,
+    // tasks run so far, counted against `Settings::max_steps`
+    steps: u64,
+    // when the first step ran, read only under a timeout: there is no
+    // clock on every target (wasm32-unknown-unknown has none)
+    started: Option<Instant>,
+    // closure calls this machine runs inside; each one runs its own
+    // machine on the native stack
+    nesting: usize,
+}
+
+impl<'e> Machine<'e> {
+    fn new(e: &'e Expr) -> Self {
+        Self::starting(Task::Eval(e), Vec::new())
+    }
+
+    // A machine that calls `name` with `args` as if from the top level.
+    fn calling(name: &'e str, args: Vec<Value>) -> Self {
+        let call = Task::Call {
+            is_alg: false,
+            name,
+            argc: args.len(),
+            span: Span::new(0, 0),
+        };
+        Self::starting(call, args)
+    }
+
+    fn starting(task: Task<'e>, vals: Vec<Value>) -> Self {
+        Self {
+            tasks: vec![task],
+            vals,
+            frames: Vec::new(),
+            alg: None,
+            calls: Vec::new(),
+            steps: 0,
+            started: None,
+            nesting: 0,
         }
-        "cos" => {
-            if vals.len() != 1 {
-                return Err(format!("cos expects 1 arg, got {}", vals.len()));
+    }
+
+    fn run(&mut self, world: &'e World, env: &mut Env) -> Result<Value, EvalError> {
+        let done = self.run_until(world, env, |_, _| false)?;
+        Ok(done.expect("evaluation stopped without pausing"))
+    }
+
+    // Run tasks until `pause` accepts the sub-expression about to be
+    // evaluated, which is left as the next task, or until none are left
+    // and the result is returned. The first task always runs, so a paused
+    // machine can be resumed.
+    fn run_until(
+        &mut self,
+        world: &'e World,
+        env: &mut Env,
+        mut pause: impl FnMut(&Self, &'e Expr) -> bool,
+    ) -> Result<Option<Value>, EvalError> {
+        let mut first = true;
+        while let Some(task) = self.tasks.pop() {
+            if let Task::Eval(e) = task
+                && !first
+                && pause(self, e)
+            {
+                self.tasks.push(task);
+                return Ok(None);
+            }
+            first = false;
+            let span = task.span();
+            let result = self
+                .spend(&world.settings)
+                .and_then(|()| self.step(world, env, task));
+            if let Err(Fault {
+                code,
+                message,
+                located,
+            }) = result
+            {
+                self.unwind(env);
+                if let Some(err) = located {
+                    return Err(*err);
+                }
+                return Err(EvalError {
+                    code,
+                    message,
+                    span,
+                    alg: self.alg.map(str::to_string),
+                    trace: self.calls.iter().map(|(n, a)| format_call(n, a)).collect(),
+                });
             }
-            Ok(Value::Number(vals[0].as_f64()?.cos()))
         }
-        "tan" => {
-            if vals.len() != 1 {
-                return Err(format!("tan expects 1 arg, got {}", vals.len()));
+        Ok(Some(self.pop()))
+    }
+
+    // Drop the tasks an error left pending, first undoing the bindings
+    // they would have undone, so that neither a series index nor a piped
+    // `it` outlives a failed evaluation. `env` is the innermost frame's environment, and
+    // each `Return` passes to the frame saved before it.
+    fn unwind(&mut self, env: &mut Env) {
+        let mut callers = self.frames.iter_mut().rev().map(|(caller, _)| caller);
+        let mut env = env;
+        while let Some(task) = self.tasks.pop() {
+            match task {
+                Task::Return => match callers.next() {
+                    Some(caller) => env = caller,
+                    None => break,
+                },
+                Task::SeriesTerm { index, saved, .. } => env.restore(index, saved),
+                Task::Unbind(it, placeholder) => {
+                    env.restore(PIPE_VALUE, it);
+                    env.restore("_", placeholder);
+                }
+                _ => {}
             }
-            Ok(Value::Number(vals[0].as_f64()?.tan()))
         }
-        "log" => {
-            if vals.len() != 1 {
-                return Err(format!("log expects 1 arg, got {}", vals.len()));
+        self.tasks.clear();
+    }
+
+    fn step(&mut self, world: &'e World, env: &mut Env, task: Task<'e>) -> Result<(), Fault> {
+        match task {
+            Task::Eval(e) => self.schedule(env, e)?,
+            Task::Unary(op, _) => {
+                let v = self.pop();
+                self.vals.push(eval_unary_operation(op, v)?);
+            }
+            Task::Bin(op, _) => {
+                if let Some(p) = &world.profile {
+                    p.lock().unwrap().record_op(self.alg, op);
+                }
+                let rv = self.pop();
+                let lv = self.pop();
+                let v = eval_binary_operation(&world.settings, op, lv.clone(), rv.clone())?;
+                if world.settings.strict
+                    && let Some(problem) = ieee_problem(&v, &[&lv, &rv])
+                {
+                    return Err(Fault::from(match op {
+                        BinOp::Div if rv.as_f64() == Ok(0.0) => "division by zero".to_string(),
+                        BinOp::Mod if rv.as_f64() == Ok(0.0) => "modulo by zero".to_string(),
+                        _ => format!("{} {} {} {}", lv, bin_symbol(op), rv, problem),
+                    }));
+                }
+                self.vals.push(v);
+            }
+            Task::ShortCircuit { op, right, span } => {
+                let l = self.pop().as_truth()?;
+                match (op, l) {
+                    (BinOp::And, Some(false)) | (BinOp::Or, Some(true)) => {
+                        self.vals.push(Value::Bool(l == Some(true)))
+                    }
+                    // unknown still needs the right operand to settle
+                    (_, None) => {
+                        self.vals.push(Value::Unknown);
+                        self.tasks.push(Task::Bin(op, span));
+                        self.tasks.push(Task::Eval(right));
+                    }
+                    _ => {
+                        self.tasks.push(Task::ExpectBool(right.span));
+                        self.tasks.push(Task::Eval(right));
+                    }
+                }
+            }
+            Task::ExpectBool(_) => {
+                let v = self.pop();
+                self.vals.push(match v.as_truth()? {
+                    Some(b) => Value::Bool(b),
+                    None => Value::Unknown,
+                });
+            }
+            Task::Case {
+                arms,
+                next,
+                default,
+                span,
+            } => {
+                if self.pop().as_bool()? {
+                    self.trace(
+                        world,
+                        TraceEvent::Arm {
+                            cond: Some(&arms[next].0),
+                        },
+                    );
+                    self.tasks.push(Task::Eval(&arms[next].1));
+                } else if next + 1 < arms.len() {
+                    self.tasks.push(Task::Case {
+                        arms,
+                        next: next + 1,
+                        default,
+                        span,
+                    });
+                    self.tasks.push(Task::Eval(&arms[next + 1].0));
+                } else if let Some(default) = default {
+                    self.trace(world, TraceEvent::Arm { cond: None });
+                    self.tasks.push(Task::Eval(default));
+                } else {
+                    self.tasks.push(Task::Unmatched(span));
+                }
+            }
+            Task::Unmatched(_) => {
+                return Err(Fault::new(
+                    E_NO_ARM,
+                    "no case arm matched and there is no '_' default".to_string(),
+                ));
+            }
+            Task::Call {
+                is_alg,
+                name,
+                argc,
+                span,
+            } => {
+                let args = self.vals.split_off(self.vals.len() - argc);
+                self.call(world, env, is_alg, name, args, span)?;
+            }
+            Task::CallValue { argc, span } => {
+                let callee = self.pop();
+                let args = self.vals.split_off(self.vals.len() - argc);
+                match callee {
+                    Value::Closure(c) => self.call_closure(world, env, &c, args)?,
+                    Value::AlgRef(name) => self.call(world, env, true, &name, args, span)?,
+                    other => {
+                        return Err(Fault::new(E_UNKNOWN_CALL, format!("cannot call {}", other)));
+                    }
+                }
+            }
+            Task::PipeStep(step) => {
+                if let Some(value) = self.vals.last() {
+                    self.trace(world, TraceEvent::Pipe { value, step });
+                }
+                self.schedule_step(env, step)?;
+            }
+            Task::Sweep {
+                mut sweep,
+                f,
+                mut items,
+                last,
+                span,
+            } => {
+                match (&mut sweep, last) {
+                    (Sweep::Map(out) | Sweep::Simulate(out), Some(_)) => out.push(self.pop()),
+                    (Sweep::Filter(out), Some(item)) => {
+                        let keep = self.pop().as_bool()?;
+                        if keep {
+                            out.push(item);
+                        }
+                    }
+                    (Sweep::Iterate(left), _) if *left > 0 => {
+                        *left -= 1;
+                        self.vals.push(f.clone());
+                        self.tasks.push(Task::Sweep {
+                            sweep,
+                            f,
+                            items,
+                            last: None,
+                            span,
+                        });
+                        self.tasks.push(Task::CallValue { argc: 1, span });
+                        return Ok(());
+                    }
+                    _ => {}
+                }
+                let Some(item) = items.next() else {
+                    if let Sweep::Map(out) | Sweep::Filter(out) | Sweep::Simulate(out) = sweep {
+                        self.vals.push(Value::List(out));
+                    }
+                    return Ok(());
+                };
+                let argc = match sweep {
+                    Sweep::Fold => 2,
+                    Sweep::Simulate(_) => 0,
+                    _ => 1,
+                };
+                if argc > 0 {
+                    self.vals.push(item.clone());
+                }
+                self.vals.push(f.clone());
+                self.tasks.push(Task::Sweep {
+                    sweep,
+                    f,
+                    items,
+                    last: Some(item),
+                    span,
+                });
+                self.tasks.push(Task::CallValue { argc, span });
+            }
+            Task::Root {
+                mut root,
+                steps,
+                probe,
+                span,
+            } => {
+                let seen = match probe {
+                    Some(x) => Some((x, self.pop().as_f64()?)),
+                    None => None,
+                };
+                match root.advance(seen)? {
+                    RootStep::Done(x) => self.vals.push(Value::Number(x)),
+                    RootStep::Call(_, _) if steps >= MAX_ROOT_STEPS => {
+                        return Err(Fault::from(format!(
+                            "root search did not converge within {} steps",
+                            MAX_ROOT_STEPS
+                        )));
+                    }
+                    RootStep::Call(f, x) => {
+                        self.vals.push(Value::Number(x));
+                        self.vals.push(f);
+                        self.tasks.push(Task::Root {
+                            root,
+                            steps: steps + 1,
+                            probe: Some(x),
+                            span,
+                        });
+                        self.tasks.push(Task::CallValue { argc: 1, span });
+                    }
+                }
+            }
+            Task::MakeList(n) => {
+                let items = self.vals.split_off(self.vals.len() - n);
+                self.vals.push(Value::List(items));
+            }
+            Task::MakeTuple(n) => {
+                let items = self.vals.split_off(self.vals.len() - n);
+                self.vals.push(Value::Tuple(items));
+            }
+            Task::Solve(e) => {
+                let ExprKind::Solve { equation, var } = &e.kind else {
+                    unreachable!("solving a non-solve")
+                };
+                self.solve(world, env, equation, var)?;
+            }
+            Task::SeriesBounds(e) => {
+                let ExprKind::Series {
+                    kind, index, body, ..
+                } = &e.kind
+                else {
+                    unreachable!("series bounds of a non-series")
+                };
+                let to = self.pop().as_f64()?;
+                let from = self.pop().as_f64()?;
+                if (to - from).is_nan() || to - from > MAX_RANGE_LEN {
+                    return Err(Fault::from(format!(
+                        "{} over {}..{} has too many terms",
+                        kind.name(),
+                        from,
+                        to
+                    )));
+                }
+                let (op, empty) = match kind {
+                    SeriesKind::Sum => (BinOp::Add, 0.0),
+                    SeriesKind::Product => (BinOp::Mul, 1.0),
+                };
+                self.vals.push(Value::Number(empty));
+                if from <= to {
+                    let saved = env.vars.insert(index.clone(), Value::Number(from));
+                    self.tasks.push(Task::SeriesTerm {
+                        op,
+                        index,
+                        body,
+                        i: from,
+                        to,
+                        saved,
+                    });
+                    self.tasks.push(Task::Eval(body));
+                }
+            }
+            Task::SeriesTerm {
+                op,
+                index,
+                body,
+                i,
+                to,
+                saved,
+            } => {
+                let term = self.pop();
+                let total = self.pop();
+                self.vals
+                    .push(eval_binary_operation(&world.settings, op, total, term)?);
+                if i + 1.0 <= to {
+                    env.set(index.to_string(), Value::Number(i + 1.0));
+                    self.tasks.push(Task::SeriesTerm {
+                        op,
+                        index,
+                        body,
+                        i: i + 1.0,
+                        to,
+                        saved,
+                    });
+                    self.tasks.push(Task::Eval(body));
+                } else {
+                    env.restore(index, saved);
+                }
+            }
+            Task::Unbind(it, placeholder) => {
+                env.restore(PIPE_VALUE, it);
+                env.restore("_", placeholder);
+            }
+            Task::Placeholders(args) => {
+                let given = args.iter().filter(|a| !a.is_placeholder()).count();
+                let mut given = self.vals.split_off(self.vals.len() - given).into_iter();
+                let piped = self.pop();
+                for a in args {
+                    let v = if a.is_placeholder() {
+                        piped.clone()
+                    } else {
+                        given.next().expect("argument left unevaluated")
+                    };
+                    self.vals.push(v);
+                }
+            }
+            Task::Ensure(alg) => {
+                let v = self.vals.last().expect("body left no value").clone();
+                env.set(RESULT.to_string(), v);
+                for cond in alg.ensures.iter().rev() {
+                    self.tasks.push(Task::Contract {
+                        cond,
+                        alg: &alg.name,
+                        post: true,
+                    });
+                    self.tasks.push(Task::Eval(cond));
+                }
+            }
+            Task::Contract { cond, alg, post } => {
+                if !self.pop().as_bool()? {
+                    let what = if post {
+                        "postcondition"
+                    } else {
+                        "precondition"
+                    };
+                    let mut message =
+                        format!("{} of @{} violated: {}", what, alg, expr_to_source(cond));
+                    if post && let Some(v) = env.get(RESULT) {
+                        message.push_str(&format!(" (result = {})", v));
+                    }
+                    return Err(Fault::new(E_CONTRACT, message));
+                }
+            }
+            Task::Memoize(name, args) => {
+                let v = self
+                    .vals
+                    .last()
+                    .expect("memoized call left no value")
+                    .clone();
+                world
+                    .memo
+                    .lock()
+                    .unwrap()
+                    .entries
+                    .insert(memo_key(name, &args), v);
+            }
+            Task::Return => {
+                if let (Some((name, _)), Some(value)) = (self.calls.last(), self.vals.last()) {
+                    self.trace(world, TraceEvent::Return { name, value });
+                }
+                let (caller, alg) = self.frames.pop().expect("return without a saved frame");
+                *env = caller;
+                self.alg = alg;
+                self.calls.pop();
             }
-            Ok(Value::Number(vals[0].as_f64()?.ln()))
         }
-        "log10" => {
-            if vals.len() != 1 {
-                return Err(format!("log10 expects 1 arg, got {}", vals.len()));
+        Ok(())
+    }
+
+    // Count one step against the budget. The clock is only read every
+    // 1024 steps.
+    fn spend(&mut self, settings: &Settings) -> Result<(), Fault> {
+        self.steps += 1;
+        if settings.timeout.is_some() && self.started.is_none() {
+            self.started = Some(Instant::now());
+        }
+        if let Some(max) = settings.max_steps
+            && self.steps > max
+        {
+            return Err(Fault::new(
+                E_BUDGET,
+                format!("evaluation budget exceeded ({} steps)", max),
+            ));
+        }
+        if let Some(limit) = settings.timeout
+            && self.steps.is_multiple_of(1024)
+            && self.started.is_some_and(|t| t.elapsed() > limit)
+        {
+            return Err(Fault::new(
+                E_BUDGET,
+                format!(
+                    "evaluation budget exceeded (timed out after {} ms)",
+                    limit.as_millis()
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    // Report `event` at the depth of the body it happens in; a call is
+    // entered, and returns, one level above its own body.
+    fn trace(&self, world: &World, event: TraceEvent) {
+        if let Some(trace) = &world.trace {
+            let depth = match event {
+                TraceEvent::Return { .. } => self.calls.len() - 1,
+                _ => self.calls.len(),
+            };
+            trace(depth, &event);
+        }
+    }
+
+    fn pop(&mut self) -> Value {
+        self.vals.pop().expect("evaluator value stack underflow")
+    }
+
+    fn schedule(&mut self, env: &Env, e: &'e Expr) -> Result<(), Fault> {
+        use ExprKind::*;
+        match &e.kind {
+            Number(x) => self.vals.push(Value::Number(*x)),
+            Imag(y) => self.vals.push(Value::from_complex(Complex::new(0.0, *y))),
+            Bool(b) => self.vals.push(Value::Bool(*b)),
+            Str(s) => self.vals.push(Value::Str(s.clone())),
+            AlgRef(name) => self.vals.push(Value::AlgRef(name.clone())),
+            Ident(name) => match env.get(name) {
+                Some(v) => self.vals.push(v.clone()),
+                None => {
+                    return Err(Fault::new(
+                        E_UNKNOWN_NAME,
+                        format!(
+                            "unknown identifier: {}{}",
+                            name,
+                            did_you_mean(name, env.names())
+                        ),
+                    ));
+                }
+            },
+            Unary { op, expr } => {
+                self.tasks.push(Task::Unary(*op, e.span));
+                self.tasks.push(Task::Eval(expr));
+            }
+            Bin {
+                op: op @ (BinOp::And | BinOp::Or),
+                left,
+                right,
+            } => {
+                self.tasks.push(Task::ShortCircuit {
+                    op: *op,
+                    right,
+                    span: e.span,
+                });
+                self.tasks.push(Task::Eval(left));
+            }
+            Bin { op, left, right } => {
+                self.tasks.push(Task::Bin(*op, e.span));
+                self.tasks.push(Task::Eval(right));
+                self.tasks.push(Task::Eval(left));
+            }
+            Case { arms, default } => match (arms.first(), default) {
+                (Some((cond, _)), _) => {
+                    self.tasks.push(Task::Case {
+                        arms,
+                        next: 0,
+                        default: default.as_deref(),
+                        span: e.span,
+                    });
+                    self.tasks.push(Task::Eval(cond));
+                }
+                (None, Some(default)) => self.tasks.push(Task::Eval(default)),
+                (None, None) => self.tasks.push(Task::Unmatched(e.span)),
+            },
+            Call { is_alg, name, args } => {
+                self.tasks.push(Task::Call {
+                    is_alg: *is_alg,
+                    name,
+                    argc: args.len(),
+                    span: e.span,
+                });
+                self.schedule_args(args);
+            }
+            Pipe { head, steps } => {
+                // Evaluate head once, then feed through each step
+                for step in steps.iter().rev() {
+                    self.tasks.push(Task::PipeStep(step));
+                }
+                self.tasks.push(Task::Eval(head));
+            }
+            Solve { .. } => self.tasks.push(Task::Solve(e)),
+            List(items) => {
+                self.tasks.push(Task::MakeList(items.len()));
+                self.schedule_args(items);
+            }
+            Tuple(items) => {
+                self.tasks.push(Task::MakeTuple(items.len()));
+                self.schedule_args(items);
+            }
+            Series { from, to, .. } => {
+                self.tasks.push(Task::SeriesBounds(e));
+                self.tasks.push(Task::Eval(to));
+                self.tasks.push(Task::Eval(from));
             }
-            Ok(Value::Number(vals[0].as_f64()?.log10()))
+            Lambda { params, body } => {
+                let mut free = Vec::new();
+                free_names(body, params, &mut free);
+                let captured = free
+                    .into_iter()
+                    .filter_map(|name| Some((name.to_string(), env.get(name)?.clone())))
+                    .collect();
+                self.vals.push(Value::Closure(Arc::new(Closure {
+                    params: params.clone(),
+                    body: (**body).clone(),
+                    captured,
+                    origin: self.alg.map(str::to_string),
+                })));
+            }
+        }
+        Ok(())
+    }
+
+    fn schedule_args(&mut self, args: &'e [Expr]) {
+        for a in args.iter().rev() {
+            self.tasks.push(Task::Eval(a));
         }
-        "floor" => {
-            if vals.len() != 1 {
-                return Err(format!("floor expects 1 arg, got {}", vals.len()));
+    }
+
+    // The piped value is already on the stack and becomes the first argument,
+    // or takes the place of each `_` among the arguments.
+    fn schedule_step(&mut self, env: &mut Env, step: &'e Expr) -> Result<(), Fault> {
+        use ExprKind::*;
+        match &step.kind {
+            // the arms see the value as `it` and `_` until the case is done
+            Case { .. } => {
+                let v = self.pop();
+                let it = env.vars.insert(PIPE_VALUE.to_string(), v.clone());
+                let placeholder = env.vars.insert("_".to_string(), v);
+                self.tasks.push(Task::Unbind(it, placeholder));
+                self.tasks.push(Task::Eval(step));
+            }
+            Call { is_alg, name, args } if args.iter().any(Expr::is_placeholder) => {
+                self.tasks.push(Task::Call {
+                    is_alg: *is_alg,
+                    name,
+                    argc: args.len(),
+                    span: step.span,
+                });
+                self.tasks.push(Task::Placeholders(args));
+                for a in args.iter().rev().filter(|a| !a.is_placeholder()) {
+                    self.tasks.push(Task::Eval(a));
+                }
+            }
+            Call { is_alg, name, args } => {
+                self.tasks.push(Task::Call {
+                    is_alg: *is_alg,
+                    name,
+                    argc: args.len() + 1,
+                    span: step.span,
+                });
+                self.schedule_args(args);
+            }
+            Ident(name) => self.tasks.push(Task::Call {
+                is_alg: false,
+                name,
+                argc: 1,
+                span: step.span,
+            }),
+            AlgRef(name) => self.tasks.push(Task::Call {
+                is_alg: true,
+                name,
+                argc: 1,
+                span: step.span,
+            }),
+            Lambda { .. } => {
+                self.tasks.push(Task::CallValue {
+                    argc: 1,
+                    span: step.span,
+                });
+                self.tasks.push(Task::Eval(step));
+            }
+            other => {
+                return Err(Fault::new(
+                    E_UNKNOWN_CALL,
+                    format!("pipeline step must be a call or name, got {:?}", other),
+                ));
             }
-            Ok(Value::Number(vals[0].as_f64()?.floor()))
         }
-        "ceil" => {
-            if vals.len() != 1 {
-                return Err(format!("ceil expects 1 arg, got {}", vals.len()));
+        Ok(())
+    }
+
+    // Make `local` the environment of the body about to run, as the call
+    // of `what`. A pending `Memoize` keeps a call out of tail position: the
+    // caller's result still has to be recorded.
+    fn push_frame(
+        &mut self,
+        world: &World,
+        env: &mut Env,
+        mut local: Env,
+        what: &str,
+    ) -> Result<(), Fault> {
+        if let Some(Task::Return) = self.tasks.last() {
+            // Tail call: the current body has nothing left to do after
+            // this call, so its frame is replaced instead of saved.
+            local.depth = env.depth;
+            *env = local;
+            self.calls.pop();
+            return Ok(());
+        }
+        if env.depth >= world.settings.max_depth {
+            return Err(Fault::new(
+                E_RECURSION_LIMIT,
+                format!(
+                    "maximum recursion depth exceeded ({}) while calling {}",
+                    world.settings.max_depth, what
+                ),
+            ));
+        }
+        local.depth = env.depth + 1;
+        self.frames.push((std::mem::replace(env, local), self.alg));
+        self.tasks.push(Task::Return);
+        Ok(())
+    }
+
+    fn call(
+        &mut self,
+        world: &'e World,
+        env: &mut Env,
+        is_alg: bool,
+        name: &str,
+        args: Vec<Value>,
+        span: Span,
+    ) -> Result<(), Fault> {
+        // A name bound to an algorithm, e.g. a parameter `f` given `@Square`,
+        // calls that algorithm ahead of any global of the same name.
+        match env.get(name) {
+            Some(Value::AlgRef(target)) if !is_alg => {
+                let target = target.clone();
+                return self.call(world, env, true, &target, args, span);
+            }
+            Some(Value::Closure(c)) if !is_alg => {
+                let c = Arc::clone(c);
+                return self.call_closure(world, env, &c, args);
             }
-            Ok(Value::Number(vals[0].as_f64()?.ceil()))
+            _ => {}
         }
-        "round" => {
-            if vals.len() != 1 {
-                return Err(format!("round expects 1 arg, got {}", vals.len()));
+        // so does a `where` helper of the running algorithm, which runs in
+        // a frame of its own as an algorithm does
+        if !is_alg
+            && let Some(helper) = self
+                .alg
+                .and_then(|a| world.algs.get(a))
+                .and_then(|d| d.local(name))
+        {
+            let local = Env::with_params(&helper.params, &args)
+                .map_err(|m| Fault::new(E_ARITY, format!("{}: {}", name, m)))?;
+            self.push_frame(world, env, local, name)?;
+            self.trace(
+                world,
+                TraceEvent::Enter {
+                    name: &helper.name,
+                    args: &args,
+                    cached: None,
+                },
+            );
+            self.calls.push((&helper.name, args));
+            self.tasks.push(Task::Eval(&helper.body));
+            return Ok(());
+        }
+        // If it's an algorithm (explicit @ or known by name), run that algorithm body
+        if is_alg || world.algs.contains_key(name) {
+            let alg: &'e AlgorithmDef = world.algs.get(name).ok_or_else(|| {
+                Fault::new(
+                    E_UNKNOWN_CALL,
+                    format!(
+                        "unknown algorithm: {}{}",
+                        name,
+                        did_you_mean(name, world.algs.keys())
+                    ),
+                )
+            })?;
+            let local = Env::with_params(&alg.params, &args)
+                .map_err(|m| Fault::new(E_ARITY, format!("@{}: {}", name, m)))?;
+            if let Some(p) = &world.profile {
+                p.lock().unwrap().record_call(&alg.name);
+            }
+            let memoize = alg.memo || world.settings.memoize;
+            if memoize {
+                let mut memo = world.memo.lock().unwrap();
+                if let Some(v) = memo.entries.get(&memo_key(name, &args)).cloned() {
+                    memo.hits += 1;
+                    self.trace(
+                        world,
+                        TraceEvent::Enter {
+                            name: &alg.name,
+                            args: &args,
+                            cached: Some(&v),
+                        },
+                    );
+                    self.vals.push(v);
+                    return Ok(());
+                }
+                memo.misses += 1;
+            }
+            self.push_frame(world, env, local, &format!("@{}", name))?;
+            self.trace(
+                world,
+                TraceEvent::Enter {
+                    name: &alg.name,
+                    args: &args,
+                    cached: None,
+                },
+            );
+            self.alg = Some(&alg.name);
+            if memoize {
+                self.tasks.push(Task::Memoize(&alg.name, args.clone()));
+            }
+            self.calls.push((&alg.name, args));
+            // a pending `Ensure` keeps calls in the body out of tail
+            // position too
+            if !alg.ensures.is_empty() {
+                self.tasks.push(Task::Ensure(alg));
+            }
+            self.tasks.push(Task::Eval(&alg.body));
+            for cond in alg.requires.iter().rev() {
+                self.tasks.push(Task::Contract {
+                    cond,
+                    alg: &alg.name,
+                    post: false,
+                });
+                self.tasks.push(Task::Eval(cond));
             }
-            Ok(Value::Number(vals[0].as_f64()?.round()))
+            return Ok(());
         }
-        "min" => {
-            if vals.len() != 2 {
-                return Err(format!("min expects 2 args, got {}", vals.len()));
+
+        if !world.builtins.contains(name) {
+            return Err(Fault::new(
+                E_UNKNOWN_CALL,
+                format!(
+                    "unknown function: {}{}",
+                    name,
+                    did_you_mean(name, world.builtins.names().chain(world.algs.keys()))
+                ),
+            ));
+        }
+        if world.builtins.is_standard(name) && HIGHER_ORDER.contains(&name) {
+            return match name {
+                "bisect" | "newton" => self.start_root(world, name, args, span),
+                _ => self.start_sweep(world, name, args, span),
+            };
+        }
+        if world.settings.bigint
+            && world.builtins.is_standard(name)
+            && let Some(v) = exact_builtin(name, &args)
+        {
+            self.vals.push(v?);
+            return Ok(());
+        }
+        let v = world.builtins.call(name, &args)?;
+        if world.settings.strict
+            && let Some(problem) = ieee_problem(&v, &args.iter().collect::<Vec<_>>())
+        {
+            let args: Vec<String> = args.iter().map(Value::to_string).collect();
+            return Err(Fault::from(format!(
+                "{}({}) {}",
+                name,
+                args.join(", "),
+                problem
+            )));
+        }
+        self.vals.push(v);
+        Ok(())
+    }
+
+    // Begin `map(xs, f)`, `filter(xs, f)`, `fold(xs, init, f)`,
+    // `iterate(x0, n, f)` or `simulate(n, f)`. Each call of `f` runs as a
+    // task of its own, so a long sweep takes no more stack than a short one.
+    fn start_sweep(
+        &mut self,
+        world: &World,
+        name: &str,
+        args: Vec<Value>,
+        span: Span,
+    ) -> Result<(), Fault> {
+        world.builtins.check_arity(name, args.len())?;
+        let mut args = args.into_iter();
+        let first = args.next().expect("arity checked");
+        let items = match name {
+            "iterate" => Vec::new(),
+            // one item per run, standing in for the arguments `f` is not given
+            "simulate" => (0..count(name, "runs", &first)?)
+                .map(|i| Value::Number(i as f64))
+                .collect(),
+            _ => first.as_list()?.to_vec(),
+        };
+        let sweep = match name {
+            "map" => Sweep::Map(Vec::with_capacity(items.len())),
+            "filter" => Sweep::Filter(Vec::new()),
+            "simulate" => Sweep::Simulate(Vec::with_capacity(items.len())),
+            "iterate" => {
+                let n = count(name, "steps", args.next().as_ref().expect("arity checked"))?;
+                self.vals.push(first);
+                Sweep::Iterate(n)
+            }
+            _ => {
+                self.vals.push(args.next().expect("arity checked"));
+                Sweep::Fold
             }
-            Ok(Value::Number(vals[0].as_f64()?.min(vals[1].as_f64()?)))
+        };
+        let f = expect_function(name, args.next().expect("arity checked"))?;
+        self.tasks.push(Task::Sweep {
+            sweep,
+            f,
+            items: items.into_iter(),
+            last: None,
+            span,
+        });
+        Ok(())
+    }
+
+    // Begin `bisect(f, lo, hi, tol)` or `newton(f, df, x0, tol)`.
+    fn start_root(
+        &mut self,
+        world: &World,
+        name: &str,
+        args: Vec<Value>,
+        span: Span,
+    ) -> Result<(), Fault> {
+        world.builtins.check_arity(name, args.len())?;
+        let tol = args[3].as_f64()?;
+        if tol.is_nan() || tol <= 0.0 {
+            return Err(Fault::from(format!(
+                "{} expects a positive tolerance, got {}",
+                name, args[3]
+            )));
         }
-        "max" => {
-            if vals.len() != 2 {
-                return Err(format!("max expects 2 args, got {}", vals.len()));
+        let f = expect_function(name, args[0].clone())?;
+        let root = if name == "bisect" {
+            Root::Bisect {
+                f,
+                lo: args[1].as_f64()?,
+                hi: args[2].as_f64()?,
+                f_lo: None,
+                bracketed: false,
+                tol,
+            }
+        } else {
+            Root::Newton {
+                f,
+                df: expect_function(name, args[1].clone())?,
+                x: args[2].as_f64()?,
+                fx: None,
+                tol,
             }
-            Ok(Value::Number(vals[0].as_f64()?.max(vals[1].as_f64()?)))
+        };
+        self.tasks.push(Task::Root {
+            root,
+            steps: 0,
+            probe: None,
+            span,
+        });
+        Ok(())
+    }
+
+    // Push the roots of `equation` in `var` as a list. Each evaluation of
+    // the equation runs in a machine of its own, with `var` bound in the
+    // current frame; the step budget carries over.
+    fn solve(
+        &mut self,
+        world: &World,
+        env: &mut Env,
+        equation: &Expr,
+        var: &str,
+    ) -> Result<(), Fault> {
+        let saved = env.vars.remove(var);
+        let roots = solve(world, equation, var, |f, x| {
+            env.vars.insert(var.to_string(), Value::Number(x));
+            let mut sub = Machine::new(f);
+            sub.alg = self.alg;
+            sub.calls = self.calls.clone();
+            sub.steps = self.steps;
+            sub.started = self.started;
+            sub.nesting = self.nesting + 1;
+            let result = sub.run(world, env);
+            self.steps = sub.steps;
+            Ok::<f64, Fault>(result?.as_f64()?)
+        });
+        env.restore(var, saved);
+        self.vals
+            .push(Value::List(roots?.into_iter().map(Value::Number).collect()));
+        Ok(())
+    }
+
+    // Run the closure's body to completion in a machine of its own: the
+    // body belongs to the closure rather than to the tree this machine
+    // walks. The step budget and call trace carry over.
+    fn call_closure(
+        &mut self,
+        world: &World,
+        env: &Env,
+        c: &Closure,
+        args: Vec<Value>,
+    ) -> Result<(), Fault> {
+        let mut local = Env::with_params(&c.params, &args)
+            .map_err(|m| Fault::new(E_ARITY, format!("lambda: {}", m)))?;
+        for (name, v) in &c.captured {
+            if !c.params.contains(name) {
+                local.set(name.clone(), v.clone());
+            }
+        }
+        self.run_nested(world, env, local, &c.body, c.origin.as_deref(), ("λ", args))
+    }
+
+    // Evaluate `body` in `local` on a machine of its own, as written in
+    // algorithm `alg`, and push its value. `call` is its entry in the trace.
+    fn run_nested(
+        &mut self,
+        world: &World,
+        env: &Env,
+        mut local: Env,
+        body: &Expr,
+        alg: Option<&str>,
+        call: (&'e str, Vec<Value>),
+    ) -> Result<(), Fault> {
+        if env.depth >= world.settings.max_depth || self.nesting >= MAX_CLOSURE_NESTING {
+            let limit = world.settings.max_depth.min(MAX_CLOSURE_NESTING);
+            let what = if call.0 == "λ" { "a lambda" } else { call.0 };
+            return Err(Fault::new(
+                E_RECURSION_LIMIT,
+                format!(
+                    "maximum recursion depth exceeded ({}) while calling {}",
+                    limit, what
+                ),
+            ));
         }
-        _ => Err(format!("unknown function: {}", name)),
+        local.depth = env.depth + 1;
+        let mut sub = Machine::new(body);
+        sub.alg = alg;
+        sub.calls = self.calls.clone();
+        sub.calls.push(call);
+        sub.steps = self.steps;
+        sub.started = self.started;
+        sub.nesting = self.nesting + 1;
+        let result = sub.run(world, &mut local);
+        self.steps = sub.steps;
+        self.vals.push(result?);
+        Ok(())
     }
 }
 
-pub fn eval_expr<'a>(world: &World<'a>, env: &mut Env, e: &Expr) -> Result<Value, String> {
-    use Expr::*;
-    match e {
-        Number(x) => Ok(Value::Number(*x)),
-        Bool(b) => Ok(Value::Bool(*b)),
-        Ident(name) => {
-            if let Some(v) = env.get(name) {
-                Ok(v.clone())
-            } else {
-                Err(format!("unknown identifier: {}", name))
+// The number of steps or runs `name` was asked for.
+fn count(name: &str, what: &str, n: &Value) -> Result<u64, Fault> {
+    let n = n.as_f64()?;
+    if n < 0.0 || n.fract() != 0.0 || n > MAX_RANGE_LEN {
+        return Err(Fault::from(format!(
+            "{} expects a whole number of {} up to {}, got {}",
+            name,
+            what,
+            MAX_RANGE_LEN,
+            Value::Number(n)
+        )));
+    }
+    Ok(n as u64)
+}
+
+fn expect_function(name: &str, f: Value) -> Result<Value, Fault> {
+    if matches!(f, Value::AlgRef(_) | Value::Closure(_)) {
+        Ok(f)
+    } else {
+        Err(Fault::from(format!(
+            "{} expects a function such as @Name or \\x -> ..., got {}",
+            name, f
+        )))
+    }
+}
+
+/// Calls a root search may make before it gives up.
+pub const MAX_ROOT_STEPS: u32 = 1000;
+
+// Closures calling closures nest machines on the native stack, so their
+// depth is capped well below what would overflow it.
+const MAX_CLOSURE_NESTING: usize = 256;
+
+// Names `e` reads that are not among `bound`: identifiers and the plain
+// calls that may go through a variable.
+pub(crate) fn free_names<'e>(e: &'e Expr, bound: &[String], out: &mut Vec<&'e str>) {
+    let mut add = |name: &'e str| {
+        if !bound.iter().any(|b| b == name) && !out.contains(&name) {
+            out.push(name);
+        }
+    };
+    match &e.kind {
+        ExprKind::Ident(name) => add(name),
+        ExprKind::Call { is_alg, name, args } => {
+            if !is_alg {
+                add(name);
             }
+            args.iter().for_each(|a| free_names(a, bound, out));
+        }
+        ExprKind::Unary { expr, .. } => free_names(expr, bound, out),
+        ExprKind::Bin { left, right, .. } => {
+            free_names(left, bound, out);
+            free_names(right, bound, out);
         }
-        Unary { op, expr } => {
-            let v = eval_expr(world, env, expr)?;
-            match op {
-                UnOp::Neg => Ok(Value::Number(-v.as_f64()?)),
-                UnOp::Not => Ok(Value::Bool(!v.as_bool()?)),
+        ExprKind::Case { arms, default } => {
+            for (c, r) in arms {
+                free_names(c, bound, out);
+                free_names(r, bound, out);
+            }
+            if let Some(default) = default {
+                free_names(default, bound, out);
             }
         }
-        Bin { op, left, right } => {
-            let lv = eval_expr(world, env, left)?;
-            let rv = eval_expr(world, env, right)?;
-            eval_binary_operation(*op, lv, rv)
+        ExprKind::List(items) | ExprKind::Tuple(items) => {
+            items.iter().for_each(|i| free_names(i, bound, out))
+        }
+        ExprKind::Pipe { head, steps } => {
+            free_names(head, bound, out);
+            steps.iter().for_each(|s| free_names(s, bound, out));
+        }
+        ExprKind::Lambda { params, body } => {
+            let inner: Vec<String> = bound.iter().chain(params).cloned().collect();
+            free_names(body, &inner, out);
+        }
+        ExprKind::Series {
+            index,
+            from,
+            to,
+            body,
+            ..
+        } => {
+            free_names(from, bound, out);
+            free_names(to, bound, out);
+            let inner: Vec<String> = bound.iter().chain([index]).cloned().collect();
+            free_names(body, &inner, out);
         }
-        Case { arms, default } => {
-            for (cond, rhs) in arms {
-                let c = eval_expr(world, env, cond)?;
-                if c.as_bool()? {
-                    return eval_expr(world, env, rhs);
+        ExprKind::Solve { equation, var } => {
+            let inner: Vec<String> = bound.iter().chain([var]).cloned().collect();
+            free_names(equation, &inner, out);
+        }
+        ExprKind::Number(_)
+        | ExprKind::Imag(_)
+        | ExprKind::Bool(_)
+        | ExprKind::Str(_)
+        | ExprKind::AlgRef(_) => {}
+    }
+}
+
+/// `e` with each of `params` replaced by the matching argument.
+pub(crate) fn substitute(e: &Expr, params: &[String], args: &[Value]) -> Expr {
+    let args: Vec<Expr> = args.iter().map(Value::to_expr).collect();
+    substitute_exprs(e, params, &args)
+}
+
+/// Like `substitute`, with expressions for the arguments. A call through
+/// a parameter bound to an algorithm calls it directly, and one through a
+/// parameter bound to a lambda becomes the lambda's body.
+pub(crate) fn substitute_exprs(e: &Expr, params: &[String], args: &[Expr]) -> Expr {
+    let sub = |e: &Expr| Box::new(substitute_exprs(e, params, args));
+    let bound = |name: &str| params.iter().position(|p| p == name).map(|i| &args[i]);
+    let kind = match &e.kind {
+        ExprKind::Ident(name) => match bound(name) {
+            Some(arg) => return arg.clone(),
+            None => ExprKind::Ident(name.clone()),
+        },
+        ExprKind::Call {
+            is_alg,
+            name,
+            args: a,
+        } => {
+            let a: Vec<Expr> = a
+                .iter()
+                .map(|x| substitute_exprs(x, params, args))
+                .collect();
+            match bound(name).map(|arg| &arg.kind) {
+                Some(ExprKind::AlgRef(target)) if !is_alg => ExprKind::Call {
+                    is_alg: true,
+                    name: target.clone(),
+                    args: a,
+                },
+                Some(ExprKind::Lambda { params: lp, body }) if !is_alg && lp.len() == a.len() => {
+                    return substitute_exprs(body, lp, &a);
                 }
+                _ => ExprKind::Call {
+                    is_alg: *is_alg,
+                    name: name.clone(),
+                    args: a,
+                },
             }
-            eval_expr(world, env, default)
         }
-        Call { is_alg, name, args } => {
-            // Evaluate arguments to Values
-            let mut vals = Vec::with_capacity(args.len());
-            for a in args {
-                vals.push(eval_expr(world, env, a)?);
+        ExprKind::Unary { op, expr } => ExprKind::Unary {
+            op: *op,
+            expr: sub(expr),
+        },
+        ExprKind::Bin { op, left, right } => ExprKind::Bin {
+            op: *op,
+            left: sub(left),
+            right: sub(right),
+        },
+        ExprKind::Case { arms, default } => ExprKind::Case {
+            arms: arms
+                .iter()
+                .map(|(c, r)| {
+                    (
+                        substitute_exprs(c, params, args),
+                        substitute_exprs(r, params, args),
+                    )
+                })
+                .collect(),
+            default: default.as_deref().map(sub),
+        },
+        ExprKind::List(items) => ExprKind::List(
+            items
+                .iter()
+                .map(|i| substitute_exprs(i, params, args))
+                .collect(),
+        ),
+        ExprKind::Tuple(items) => ExprKind::Tuple(
+            items
+                .iter()
+                .map(|i| substitute_exprs(i, params, args))
+                .collect(),
+        ),
+        ExprKind::Pipe { head, steps } => ExprKind::Pipe {
+            head: sub(head),
+            steps: steps
+                .iter()
+                .map(|s| substitute_exprs(s, params, args))
+                .collect(),
+        },
+        // the lambda's own parameters hide any of the same name
+        ExprKind::Lambda { params: lp, body } => ExprKind::Lambda {
+            params: lp.clone(),
+            body: Box::new(substitute_hidden(body, lp, params, args)),
+        },
+        // as does a series' index, in its body
+        ExprKind::Series {
+            kind,
+            index,
+            from,
+            to,
+            body,
+        } => ExprKind::Series {
+            kind: *kind,
+            index: index.clone(),
+            from: sub(from),
+            to: sub(to),
+            body: Box::new(substitute_hidden(
+                body,
+                std::slice::from_ref(index),
+                params,
+                args,
+            )),
+        },
+        // and the variable of a `solve`
+        ExprKind::Solve { equation, var } => ExprKind::Solve {
+            equation: Box::new(substitute_hidden(
+                equation,
+                std::slice::from_ref(var),
+                params,
+                args,
+            )),
+            var: var.clone(),
+        },
+        other => other.clone(),
+    };
+    Expr::new(kind, e.span)
+}
+
+// `substitute_exprs` in a scope where the `hidden` names are bound afresh.
+fn substitute_hidden(e: &Expr, hidden: &[String], params: &[String], args: &[Expr]) -> Expr {
+    let (outer, outer_args): (Vec<String>, Vec<Expr>) = params
+        .iter()
+        .zip(args)
+        .filter(|(p, _)| !hidden.contains(p))
+        .map(|(p, a)| (p.clone(), a.clone()))
+        .unzip();
+    substitute_exprs(e, &outer, &outer_args)
+}
+
+/// A pipeline written as the nested calls it stands for, or `None` when a
+/// step is neither a call, a one-parameter lambda, nor a case block.
+pub(crate) fn unpipe(head: &Expr, steps: &[Expr]) -> Option<Expr> {
+    let mut acc = head.clone();
+    for step in steps {
+        let (is_alg, name, args) = match &step.kind {
+            ExprKind::Call { is_alg, name, args } => (*is_alg, name, &args[..]),
+            ExprKind::Ident(name) => (false, name, &[][..]),
+            ExprKind::AlgRef(name) => (true, name, &[][..]),
+            ExprKind::Lambda { params, body } if params.len() == 1 => {
+                acc = substitute_exprs(body, params, &[acc]);
+                continue;
+            }
+            ExprKind::Case { .. } => {
+                acc = substitute_exprs(step, &pipe_value_names(), &[acc.clone(), acc]);
+                continue;
             }
-            call_name(world, env, *is_alg, name, vals)
+            _ => return None,
+        };
+        let args = pipe_args(args, acc);
+        acc = Expr::new(
+            ExprKind::Call {
+                is_alg,
+                name: name.clone(),
+                args,
+            },
+            step.span,
+        );
+    }
+    Some(acc)
+}
+
+pub fn eval_expr(world: &World, env: &mut Env, e: &Expr) -> Result<Value, EvalError> {
+    let mut machine = Machine::new(e);
+    let result = machine.run(world, env);
+    // an error can leave callee frames active; the caller's is the oldest one
+    if !machine.frames.is_empty() {
+        *env = machine.frames.swap_remove(0).0;
+    }
+    result
+}
+
+/// Where a `Stepper` stopped.
+pub enum Paused<'e> {
+    /// About to evaluate this sub-expression.
+    At(&'e Expr),
+    /// Evaluation finished.
+    Done(Value),
+}
+
+/// An evaluation that pauses before each sub-expression, for debuggers.
+/// It starts paused at the whole expression.
+pub struct Stepper<'e> {
+    machine: Machine<'e>,
+    world: &'e World,
+    env: Env,
+}
+
+impl<'e> Stepper<'e> {
+    pub fn new(world: &'e World, env: Env, e: &'e Expr) -> Self {
+        Self {
+            machine: Machine::new(e),
+            world,
+            env,
         }
+    }
 
-        Pipe { head, steps } => {
-            // Evaluate head once, then feed through each step
-            let mut val = eval_expr(world, env, head)?;
-            for step in steps {
-                val = apply_step(world, env, step, val)?;
-            }
-            Ok(val)
+    /// Run to the next sub-expression, wherever it is.
+    pub fn step(&mut self) -> Result<Paused<'e>, EvalError> {
+        self.resume(|_, _, _| true)
+    }
+
+    /// Run to the next sub-expression outside the algorithms called from
+    /// here.
+    pub fn step_over(&mut self) -> Result<Paused<'e>, EvalError> {
+        let depth = self.depth();
+        self.resume(move |m, _, _| m.calls.len() <= depth)
+    }
+
+    /// Run until the body of one of `breakpoints` starts.
+    pub fn continue_to(&mut self, breakpoints: &[String]) -> Result<Paused<'e>, EvalError> {
+        self.resume(|m, world, e| {
+            m.alg.is_some_and(|name| {
+                breakpoints.iter().any(|b| b == name)
+                    && world
+                        .algs
+                        .get(name)
+                        .is_some_and(|d| std::ptr::eq(&d.body, e))
+            })
+        })
+    }
+
+    fn resume(
+        &mut self,
+        pause: impl Fn(&Machine<'e>, &World, &Expr) -> bool,
+    ) -> Result<Paused<'e>, EvalError> {
+        let world = self.world;
+        let done = self
+            .machine
+            .run_until(world, &mut self.env, |m, e| pause(m, world, e))?;
+        Ok(match done {
+            Some(v) => Paused::Done(v),
+            None => match self.machine.tasks.last() {
+                Some(Task::Eval(e)) => Paused::At(e),
+                _ => unreachable!("paused away from a sub-expression"),
+            },
+        })
+    }
+
+    /// Number of algorithm calls active.
+    pub fn depth(&self) -> usize {
+        self.machine.calls.len()
+    }
+
+    /// Algorithm whose body is running; `None` at the top level.
+    pub fn alg(&self) -> Option<&str> {
+        self.machine.alg
+    }
+
+    /// A variable visible where evaluation is paused.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.env.get(name)
+    }
+
+    /// Active calls, outermost first, e.g. `@Outer(3)`.
+    pub fn calls(&self) -> Vec<String> {
+        let calls = &self.machine.calls;
+        calls.iter().map(|(n, a)| format_call(n, a)).collect()
+    }
+
+    /// The top-level environment, with any assignments made while stepping.
+    pub fn into_env(mut self) -> Env {
+        if self.machine.frames.is_empty() {
+            self.env
+        } else {
+            self.machine.frames.swap_remove(0).0
         }
     }
 }
 
-fn eval_binary_operation(op: BinOp, lv: Value, rv: Value) -> Result<Value, String> {
+// `@Name(args)`, or `λ(args)` for a lambda.
+fn format_call(name: &str, args: &[Value]) -> String {
+    let args: Vec<String> = args.iter().map(Value::to_string).collect();
+    let at = if name == "λ" { "" } else { "@" };
+    format!("{}{}({})", at, name, args.join(", "))
+}
+
+// Strict mode: finite real inputs must give a finite real result, so
+// NaN, infinity, or an imaginary part is reported where it first appears
+// rather than carried through the computation.
+fn ieee_problem(v: &Value, inputs: &[&Value]) -> Option<&'static str> {
+    let finite = |v: &&Value| match v {
+        Value::Number(x) => x.is_finite(),
+        Value::Int(_) => true,
+        _ => false,
+    };
+    if !inputs.iter().all(finite) {
+        return None;
+    }
+    match v {
+        Value::Number(x) if x.is_nan() => Some("is undefined"),
+        Value::Number(x) if x.is_infinite() => Some("overflows"),
+        Value::Complex(_) => Some("is not a real number"),
+        _ => None,
+    }
+}
+
+fn eval_unary_operation(op: UnOp, v: Value) -> Result<Value, String> {
+    match op {
+        UnOp::Neg => match v {
+            Value::Interval(lo, hi) => Ok(Value::Interval(-hi, -lo)),
+            Value::Int(n) => Ok(Value::Int(n.neg())),
+            Value::Complex(z) => Ok(Value::Complex(-z)),
+            Value::List(items) => items
+                .into_iter()
+                .map(|v| eval_unary_operation(op, v))
+                .collect::<Result<_, _>>()
+                .map(Value::List),
+            v => Ok(Value::Number(-v.as_f64()?)),
+        },
+        UnOp::Not => match v.as_truth()? {
+            Some(b) => Ok(Value::Bool(!b)),
+            None => Ok(Value::Unknown),
+        },
+    }
+}
+
+// Arithmetic on sequences goes item by item; a plain value on one side
+// meets every item of the other.
+fn elementwise(settings: &Settings, op: BinOp, lv: Value, rv: Value) -> Result<Value, String> {
+    let items = match (lv, rv) {
+        (Value::List(a), Value::List(b)) => {
+            if a.len() != b.len() {
+                return Err(format!(
+                    "element-wise {:?} needs sequences of one length, got {} and {}",
+                    op,
+                    a.len(),
+                    b.len()
+                ));
+            }
+            a.into_iter()
+                .zip(b)
+                .map(|(x, y)| eval_binary_operation(settings, op, x, y))
+                .collect::<Result<_, _>>()?
+        }
+        (Value::List(a), y) => a
+            .into_iter()
+            .map(|x| eval_binary_operation(settings, op, x, y.clone()))
+            .collect::<Result<_, _>>()?,
+        (x, Value::List(b)) => b
+            .into_iter()
+            .map(|y| eval_binary_operation(settings, op, x.clone(), y))
+            .collect::<Result<_, _>>()?,
+        _ => unreachable!("one side is a sequence"),
+    };
+    Ok(Value::List(items))
+}
+
+fn eval_binary_operation(
+    settings: &Settings,
+    op: BinOp,
+    lv: Value,
+    rv: Value,
+) -> Result<Value, String> {
     use BinOp::*;
+    if let Range | RangeExcl = op {
+        return range(lv.as_f64()?, rv.as_f64()?, matches!(op, RangeExcl));
+    }
+    if let (Value::Str(_), _) | (_, Value::Str(_)) = (&lv, &rv) {
+        return eval_string_operation(op, lv, rv);
+    }
+    if let (Value::List(_), _) | (_, Value::List(_)) = (&lv, &rv) {
+        return match op {
+            Eq => Ok(Value::Bool(lv == rv)),
+            Ne => Ok(Value::Bool(lv != rv)),
+            Add | Sub | Mul | Div | Pow | Mod => elementwise(settings, op, lv, rv),
+            other => Err(format!("operator {:?} is not defined for sequences", other)),
+        };
+    }
+    if let (Value::Tuple(_), _) | (_, Value::Tuple(_)) = (&lv, &rv) {
+        return match op {
+            Eq => Ok(Value::Bool(lv == rv)),
+            Ne => Ok(Value::Bool(lv != rv)),
+            other => Err(format!("operator {:?} is not defined for tuples", other)),
+        };
+    }
+    if let (Value::AlgRef(_) | Value::Closure(_), _) | (_, Value::AlgRef(_) | Value::Closure(_)) =
+        (&lv, &rv)
+    {
+        return match op {
+            Eq => Ok(Value::Bool(lv == rv)),
+            Ne => Ok(Value::Bool(lv != rv)),
+            other => Err(format!(
+                "operator {:?} is not defined for algorithms",
+                other
+            )),
+        };
+    }
+    if let (Value::Complex(_), _) | (_, Value::Complex(_)) = (&lv, &rv) {
+        return eval_complex_operation(settings, op, lv, rv);
+    }
+    if let (Value::Interval(..), _) | (_, Value::Interval(..)) = (&lv, &rv) {
+        return eval_interval_operation(settings, op, lv, rv);
+    }
+    if let (Value::Unknown, _) | (_, Value::Unknown) = (&lv, &rv) {
+        return eval_truth_operation(op, lv, rv);
+    }
+    if settings.bigint
+        && let Some(v) = eval_integer_operation(settings, op, &lv, &rv)
+    {
+        return Ok(v);
+    }
     match op {
         Add => Ok(Value::Number(lv.as_f64()? + rv.as_f64()?)),
         Sub => Ok(Value::Number(lv.as_f64()? - rv.as_f64()?)),
         Mul => Ok(Value::Number(lv.as_f64()? * rv.as_f64()?)),
         Div => Ok(Value::Number(lv.as_f64()? / rv.as_f64()?)),
         Pow => Ok(Value::Number(lv.as_f64()?.powf(rv.as_f64()?))),
-        Mod => Ok(Value::Number(lv.as_f64()? % rv.as_f64()?)),
+        Mod => {
+            let (a, b) = (lv.as_f64()?, rv.as_f64()?);
+            Ok(Value::Number(match settings.mod_mode {
+                ModMode::Truncated => a % b,
+                ModMode::Euclidean => a.rem_euclid(b),
+            }))
+        }
         Eq => Ok(Value::Bool(num_eq(lv.as_f64()?, rv.as_f64()?))),
         Ne => Ok(Value::Bool(!num_eq(lv.as_f64()?, rv.as_f64()?))),
         Lt => Ok(Value::Bool(lv.as_f64()? < rv.as_f64()?)),
         Le => Ok(Value::Bool(lv.as_f64()? <= rv.as_f64()?)),
         Gt => Ok(Value::Bool(lv.as_f64()? > rv.as_f64()?)),
         Ge => Ok(Value::Bool(lv.as_f64()? >= rv.as_f64()?)),
+        Approx => Ok(Value::Bool(approx_eq(
+            settings.epsilon,
+            lv.as_f64()?,
+            rv.as_f64()?,
+        ))),
         And => Ok(Value::Bool(lv.as_bool()? && rv.as_bool()?)),
         Or => Ok(Value::Bool(lv.as_bool()? || rv.as_bool()?)),
+        Range | RangeExcl => unreachable!("ranges are built before dispatch"),
+    }
+}
+
+/// Ranges longer than this are refused rather than built.
+pub const MAX_RANGE_LEN: f64 = 10_000_000.0;
+
+// `from, from + 1, ...` up to `to`, which is left out when `exclusive`.
+fn range(from: f64, to: f64, exclusive: bool) -> Result<Value, String> {
+    let span = to - from;
+    if span.is_nan() || span > MAX_RANGE_LEN {
+        return Err(format!("range {}..{} is too long", from, to));
+    }
+    let mut n = span.floor() + 1.0;
+    if exclusive && from + n - 1.0 >= to {
+        n -= 1.0;
+    }
+    let items = (0..n.max(0.0) as u64)
+        .map(|i| Value::Number(from + i as f64))
+        .collect();
+    Ok(Value::List(items))
+}
+
+// Strings support concatenation with `+` (the other side is stringified) and
+// lexicographic comparison against other strings.
+fn eval_string_operation(op: BinOp, lv: Value, rv: Value) -> Result<Value, String> {
+    use BinOp::*;
+    match op {
+        Add => Ok(Value::Str(format!(
+            "{}{}",
+            string_contents(&lv),
+            string_contents(&rv)
+        ))),
+        Eq => Ok(Value::Bool(lv == rv)),
+        Ne => Ok(Value::Bool(lv != rv)),
+        Lt => Ok(Value::Bool(lv.as_str()? < rv.as_str()?)),
+        Le => Ok(Value::Bool(lv.as_str()? <= rv.as_str()?)),
+        Gt => Ok(Value::Bool(lv.as_str()? > rv.as_str()?)),
+        Ge => Ok(Value::Bool(lv.as_str()? >= rv.as_str()?)),
+        other => Err(format!("operator {:?} is not defined for strings", other)),
     }
 }
 
-fn apply_step<'a>(
-    world: &World<'a>,
-    env: &mut Env,
-    step: &Expr,
-    input: Value,
+// A real operand is promoted to complex; results with no imaginary part
+// are real again.
+fn eval_complex_operation(
+    settings: &Settings,
+    op: BinOp,
+    lv: Value,
+    rv: Value,
 ) -> Result<Value, String> {
-    use Expr::*;
-    match step {
-        Call { is_alg, name, args } => apply_call_step(world, env, *is_alg, name, args, input),
-        Ident(name) => call_name(world, env, false, name, vec![input]),
-        other => Err(format!(
-            "pipeline step must be a call or name, got {:?}",
-            other
-        )),
-    }
-}
-
-fn apply_call_step<'a>(
-    world: &World<'a>,
-    env: &mut Env,
-    is_alg: bool,
-    name: &str,
-    args: &[Expr],
-    input: Value,
+    use BinOp::*;
+    let (a, b) = (lv.as_complex()?, rv.as_complex()?);
+    Ok(Value::from_complex(match op {
+        Add => a + b,
+        Sub => a - b,
+        Mul => a * b,
+        Div => a / b,
+        Pow => a.pow(b),
+        Eq => return Ok(Value::Bool(a == b)),
+        Ne => return Ok(Value::Bool(a != b)),
+        Approx => {
+            let scale = 1f64.max(a.abs()).max(b.abs());
+            return Ok(Value::Bool((a - b).abs() <= settings.epsilon * scale));
+        }
+        Lt | Le | Gt | Ge => return Err("complex numbers are not ordered".to_string()),
+        other => {
+            return Err(format!(
+                "operator {:?} is not defined for complex numbers",
+                other
+            ));
+        }
+    }))
+}
+
+// Interval arithmetic: each result bounds the operation over every pair
+// of numbers drawn from the operands, with a number as a one-point
+// interval. Bounds are computed in ordinary floating point, so they are
+// exact only up to rounding of the endpoints. Comparisons are decided
+// when they hold for all pairs or for none, and unknown otherwise.
+fn eval_interval_operation(
+    settings: &Settings,
+    op: BinOp,
+    lv: Value,
+    rv: Value,
 ) -> Result<Value, String> {
-    let mut vals = Vec::with_capacity(1 + args.len());
-    vals.push(input);
-    for a in args {
-        vals.push(eval_expr(world, env, a)?);
+    use BinOp::*;
+    let ((a, b), (c, d)) = (lv.as_interval()?, rv.as_interval()?);
+    let decided = |all: bool, none: bool| {
+        Ok(match (all, none) {
+            (true, _) => Value::Bool(true),
+            (_, true) => Value::Bool(false),
+            _ => Value::Unknown,
+        })
+    };
+    match op {
+        Add => Ok(Value::Interval(a + c, b + d)),
+        Sub => Ok(Value::Interval(a - d, b - c)),
+        Mul => Ok(interval_hull(&[
+            interval_mul(a, c),
+            interval_mul(a, d),
+            interval_mul(b, c),
+            interval_mul(b, d),
+        ])),
+        Div if c <= 0.0 && 0.0 <= d => Ok(Value::Interval(f64::NEG_INFINITY, f64::INFINITY)),
+        Div => Ok(interval_hull(&[a / c, a / d, b / c, b / d])),
+        Pow => interval_pow((a, b), (c, d)),
+        Eq => decided(a == b && b == c && c == d, b < c || d < a),
+        Ne => decided(b < c || d < a, a == b && b == c && c == d),
+        Approx => {
+            let tol = settings.epsilon
+                * [1.0, a.abs(), b.abs(), c.abs(), d.abs()]
+                    .into_iter()
+                    .fold(0.0, f64::max);
+            let (far, near) = ((b - c).max(d - a), (c - b).max(a - d).max(0.0));
+            decided(far <= tol, near > tol)
+        }
+        Lt => decided(b < c, a >= d),
+        Le => decided(b <= c, a > d),
+        Gt => decided(a > d, b <= c),
+        Ge => decided(a >= d, b < c),
+        other => Err(format!("operator {:?} is not defined for intervals", other)),
     }
-    call_name(world, env, is_alg, name, vals)
 }
 
-fn expect_arity(vals: &[Value], n: usize) -> Result<&[Value], String> {
-    if vals.len() != n {
-        Err(format!("expected {} argument(s), got {}", n, vals.len()))
-    } else {
-        Ok(vals)
+// A product in which zero wins over infinity, so bounds stay numbers.
+fn interval_mul(x: f64, y: f64) -> f64 {
+    if x == 0.0 || y == 0.0 { 0.0 } else { x * y }
+}
+
+fn interval_hull(points: &[f64]) -> Value {
+    let lo = points.iter().copied().fold(f64::INFINITY, f64::min);
+    let hi = points.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    Value::Interval(lo, hi)
+}
+
+fn interval_pow((a, b): (f64, f64), (c, d): (f64, f64)) -> Result<Value, String> {
+    // an integer power is defined for negative bases too
+    if c == d && c.fract() == 0.0 {
+        let n = c;
+        if n < 0.0 {
+            let (lo, hi) = interval_pow((a, b), (-n, -n))?.as_interval()?;
+            return Ok(if lo <= 0.0 && 0.0 <= hi {
+                Value::Interval(f64::NEG_INFINITY, f64::INFINITY)
+            } else {
+                interval_hull(&[1.0 / lo, 1.0 / hi])
+            });
+        }
+        let (pa, pb) = (a.powf(n), b.powf(n));
+        return Ok(if n % 2.0 == 0.0 && a < 0.0 && 0.0 < b {
+            Value::Interval(0.0, pa.max(pb))
+        } else {
+            interval_hull(&[pa, pb])
+        });
+    }
+    if a < 0.0 {
+        return Err(format!(
+            "a fractional power needs a non-negative base, got [{}, {}]",
+            a, b
+        ));
+    }
+    // x^y is monotone in each of x and y here, so the corners bound it
+    Ok(interval_hull(&[a.powf(c), a.powf(d), b.powf(c), b.powf(d)]))
+}
+
+// Exact integer arithmetic, for when `Settings::bigint` is on. Numbers
+// stay numbers while their results are exact; `None` leaves the operation
+// to floating point, e.g. for a fraction or a result too large to compute.
+fn eval_integer_operation(settings: &Settings, op: BinOp, lv: &Value, rv: &Value) -> Option<Value> {
+    use BinOp::*;
+    if let (Value::Number(x), Value::Number(y)) = (lv, rv) {
+        let float = match op {
+            Add => x + y,
+            Sub => x - y,
+            Mul => x * y,
+            Pow => x.powf(*y),
+            _ => return None,
+        };
+        if float.abs() <= MAX_EXACT_INT {
+            return None;
+        }
+    }
+    let (a, b) = (lv.as_big()?, rv.as_big()?);
+    let bool = Value::Bool;
+    Some(match op {
+        Add => Value::from_big(a.add(&b)),
+        Sub => Value::from_big(a.sub(&b)),
+        Mul if (a.bits() + b.bits()) as f64 <= MAX_EXACT_BITS => Value::from_big(a.mul(&b)),
+        Pow if !b.is_negative() && b.fits_f64() => {
+            let exp = b.to_f64();
+            if a.bits() as f64 * exp > MAX_EXACT_BITS {
+                return None;
+            }
+            Value::from_big(a.pow(exp as u64))
+        }
+        Div => match a.div_rem(&b)? {
+            (q, r) if r.is_zero() => Value::from_big(q),
+            _ => return None,
+        },
+        Mod => {
+            let (_, r) = a.div_rem(&b)?;
+            let r = match settings.mod_mode {
+                ModMode::Euclidean if r.is_negative() => r.add(&b.abs()),
+                _ => r,
+            };
+            Value::from_big(r)
+        }
+        Eq => bool(a == b),
+        Ne => bool(a != b),
+        Lt => bool(a < b),
+        Le => bool(a <= b),
+        Gt => bool(a > b),
+        Ge => bool(a >= b),
+        _ => return None,
+    })
+}
+
+// Kleene logic: unknown is true or false, but not known which.
+fn eval_truth_operation(op: BinOp, lv: Value, rv: Value) -> Result<Value, String> {
+    let (l, r) = (lv.as_truth()?, rv.as_truth()?);
+    let v = match op {
+        BinOp::And if l == Some(false) || r == Some(false) => Some(false),
+        BinOp::Or if l == Some(true) || r == Some(true) => Some(true),
+        BinOp::And | BinOp::Or | BinOp::Eq | BinOp::Ne => None,
+        other => return Err(format!("operator {:?} is not defined for bools", other)),
+    };
+    Ok(v.map_or(Value::Unknown, Value::Bool))
+}
+
+fn string_contents(v: &Value) -> String {
+    match v {
+        Value::Str(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+// `a` and `b` within `epsilon`, scaled by the larger of them when it
+// exceeds 1; infinities are only close to themselves.
+fn approx_eq(epsilon: f64, a: f64, b: f64) -> bool {
+    if a == b || !a.is_finite() || !b.is_finite() {
+        return a == b;
     }
+    let scale = 1f64.max(a.abs()).max(b.abs());
+    (a - b).abs() <= epsilon * scale
 }
 
 // Equality helper: floating-point equality with NaN handling
@@ -297,17 +2678,3 @@ fn num_eq(a: f64, b: f64) -> bool {
         a == b
     }
 }
-
-// Convenience: run an algorithm by name with f64 args
-pub fn run_alg(defs: &[AlgorithmDef], name: &str, args: Vec<f64>) -> Result<Value, String> {
-    let world = World::new(defs);
-    let alg = world
-        .algs
-        .get(name)
-        .ok_or_else(|| format!("no algorithm named {}", name))?;
-    let mut env = Env::with_params(
-        &alg.params,
-        &args.into_iter().map(Value::Number).collect::<Vec<_>>(),
-    )?;
-    eval_expr(&world, &mut env, &alg.body)
-}
@@ -0,0 +1,277 @@
+use crate::ast::{BinOp, Expr, ExprKind};
+use crate::eval::{Env, EvalError, Value, World, eval_expr, substitute, unpipe};
+use crate::printer::expr_to_source;
+use crate::token::Span;
+
+/// How many levels of algorithm calls inside the explained one get their
+/// own derivation; deeper calls are evaluated in one step.
+pub const EXPLAIN_DEPTH: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExplainFormat {
+    Text,
+    Markdown,
+}
+
+/// A "show your work" derivation: each algorithm call with its
+/// arguments, the case conditions tried, and the expression reduced one
+/// operation at a time down to its value.
+pub struct Explanation {
+    lines: Vec<(usize, Line)>,
+    pub value: Value,
+}
+
+enum Line {
+    // an algorithm call being explained, e.g. `@Add(2, 1)`
+    Call(String),
+    // its parameters bound to the arguments
+    Bind(Vec<(String, Value)>),
+    // a case condition, with parameters substituted, and its value
+    Cond(String, bool),
+    // no condition held, so the default arm was taken
+    Default,
+    // the expression after one more reduction
+    Step(String),
+}
+
+impl Explanation {
+    pub fn render(&self, format: ExplainFormat) -> String {
+        let mut out = String::new();
+        for (depth, line) in &self.lines {
+            let code = |s: &str| match format {
+                ExplainFormat::Text => s.to_string(),
+                ExplainFormat::Markdown => format!("`{}`", s),
+            };
+            let text = match line {
+                Line::Call(call) => match format {
+                    ExplainFormat::Text => call.clone(),
+                    ExplainFormat::Markdown => format!("**`{}`**", call),
+                },
+                Line::Bind(binds) => {
+                    let binds: Vec<String> = binds
+                        .iter()
+                        .map(|(p, v)| code(&format!("{} = {}", p, v)))
+                        .collect();
+                    format!("where {}", binds.join(", "))
+                }
+                Line::Cond(cond, holds) => format!("if {}: {}", code(cond), holds),
+                Line::Default => "otherwise".to_string(),
+                Line::Step(e) => format!("= {}", code(e)),
+            };
+            out.push_str(&"  ".repeat(*depth));
+            if format == ExplainFormat::Markdown {
+                out.push_str("- ");
+            }
+            out.push_str(&text);
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// Explain how `expr`, a top-level expression, evaluates.
+pub fn explain(world: &World, expr: &Expr) -> Result<Explanation, EvalError> {
+    let mut ex = Explainer {
+        world,
+        lines: Vec::new(),
+        nesting: 0,
+    };
+    let value = match &expr.kind {
+        // a call with plain arguments is explained from its header
+        ExprKind::Call { is_alg, name, args }
+            if (*is_alg || world.algs.contains_key(name)) && args.iter().all(is_literal) =>
+        {
+            let args: Vec<Value> = args.iter().map(|a| ex.eval(a)).collect::<Result<_, _>>()?;
+            ex.explain_call(name, &args, 0)?
+        }
+        _ => {
+            ex.lines.push((0, Line::Step(expr_to_source(expr))));
+            ex.reduce(expr.clone(), 0)?
+        }
+    };
+    Ok(Explanation {
+        lines: ex.lines,
+        value,
+    })
+}
+
+struct Explainer<'w> {
+    world: &'w World,
+    lines: Vec<(usize, Line)>,
+    // algorithm calls being explained
+    nesting: usize,
+}
+
+impl Explainer<'_> {
+    fn explain_call(
+        &mut self,
+        name: &str,
+        args: &[Value],
+        depth: usize,
+    ) -> Result<Value, EvalError> {
+        let Some(alg) = self.world.algs.get(name) else {
+            return self.eval(&call(name, args));
+        };
+        if self.nesting > EXPLAIN_DEPTH || alg.params.len() != args.len() {
+            return self.eval(&call(name, args));
+        }
+        let call_text: Vec<String> = args.iter().map(Value::to_string).collect();
+        self.lines.push((
+            depth,
+            Line::Call(format!("@{}({})", name, call_text.join(", "))),
+        ));
+        if !args.is_empty() {
+            let binds = alg
+                .params
+                .iter()
+                .cloned()
+                .zip(args.iter().cloned())
+                .collect();
+            self.lines.push((depth + 1, Line::Bind(binds)));
+        }
+        let body = substitute(&alg.body, &alg.params, args);
+        // a case block shows through its conditions instead
+        if !matches!(body.kind, ExprKind::Case { .. }) {
+            self.lines
+                .push((depth + 1, Line::Step(expr_to_source(&body))));
+        }
+        self.nesting += 1;
+        let value = self.reduce(body, depth + 1);
+        self.nesting -= 1;
+        value
+    }
+
+    // Reduce `e` one operation at a time, recording each result.
+    fn reduce(&mut self, mut e: Expr, depth: usize) -> Result<Value, EvalError> {
+        while !is_literal(&e) {
+            e = self.reduce_once(e, depth)?;
+            self.lines.push((depth, Line::Step(expr_to_source(&e))));
+        }
+        self.eval(&e)
+    }
+
+    // Rewrite the leftmost innermost operation of `e` to its value; a case
+    // block is replaced by its chosen arm.
+    fn reduce_once(&mut self, e: Expr, depth: usize) -> Result<Expr, EvalError> {
+        let span = e.span;
+        // evaluated as a whole when it cannot be taken apart, for the error
+        let whole =
+            matches!(e.kind, ExprKind::Case { .. } | ExprKind::Pipe { .. }).then(|| e.clone());
+        let fail = |ex: &Self| {
+            ex.eval(whole.as_ref().expect("kept for case and pipe"))
+                .map(|v| v.to_expr())
+        };
+        let kind = match e.kind {
+            ExprKind::Unary { op, expr } if !is_literal(&expr) => ExprKind::Unary {
+                op,
+                expr: Box::new(self.reduce_once(*expr, depth)?),
+            },
+            ExprKind::Bin { op, left, right } if !is_literal(&left) => ExprKind::Bin {
+                op,
+                left: Box::new(self.reduce_once(*left, depth)?),
+                right,
+            },
+            // `false && x` and `true || x` are done without `x`
+            ExprKind::Bin {
+                op: op @ (BinOp::And | BinOp::Or),
+                left,
+                ..
+            } if matches!(
+                (op, &left.kind),
+                (BinOp::And, ExprKind::Bool(false)) | (BinOp::Or, ExprKind::Bool(true))
+            ) =>
+            {
+                return Ok(*left);
+            }
+            ExprKind::Bin { op, left, right } if !is_literal(&right) => ExprKind::Bin {
+                op,
+                left,
+                right: Box::new(self.reduce_once(*right, depth)?),
+            },
+            ExprKind::Call {
+                is_alg,
+                name,
+                mut args,
+            } if !args.iter().all(is_literal) => {
+                let i = args.iter().position(|a| !is_literal(a)).unwrap_or(0);
+                let arg = std::mem::replace(&mut args[i], Value::Bool(false).to_expr());
+                args[i] = self.reduce_once(arg, depth)?;
+                ExprKind::Call { is_alg, name, args }
+            }
+            ExprKind::Call { name, args, .. } if self.world.algs.contains_key(&name) => {
+                let args: Vec<Value> = args
+                    .iter()
+                    .map(|a| self.eval(a))
+                    .collect::<Result<_, _>>()?;
+                return Ok(self.explain_call(&name, &args, depth + 1)?.to_expr());
+            }
+            ExprKind::Case { arms, default } => {
+                for (cond, result) in arms {
+                    let Ok(holds) = self.eval(&cond)?.as_bool() else {
+                        return fail(self);
+                    };
+                    self.lines
+                        .push((depth, Line::Cond(expr_to_source(&cond), holds)));
+                    if holds {
+                        return Ok(result);
+                    }
+                }
+                let Some(default) = default else {
+                    return fail(self);
+                };
+                self.lines.push((depth, Line::Default));
+                return Ok(*default);
+            }
+            // `x >> F(a)` is `F(x, a)`, and `x >> \y -> b` is `b` with `x`
+            // for `y`, as a case step is with `x` for `it`
+            ExprKind::Pipe { head, steps } => {
+                return match unpipe(&head, &steps) {
+                    Some(calls) => Ok(calls),
+                    None => fail(self),
+                };
+            }
+            // operands are values now, or a name or builtin call is left
+            kind => return self.eval(&Expr::new(kind, span)).map(|v| v.to_expr()),
+        };
+        Ok(Expr::new(kind, span))
+    }
+
+    fn eval(&self, e: &Expr) -> Result<Value, EvalError> {
+        eval_expr(self.world, &mut Env::base(), e)
+    }
+}
+
+// A value, including the builtin calls that write out complex numbers,
+// intervals, and unknown.
+fn is_literal(e: &Expr) -> bool {
+    match &e.kind {
+        ExprKind::Number(_)
+        | ExprKind::Imag(_)
+        | ExprKind::Bool(_)
+        | ExprKind::Str(_)
+        | ExprKind::AlgRef(_)
+        | ExprKind::Lambda { .. } => true,
+        ExprKind::List(items) | ExprKind::Tuple(items) => items.iter().all(is_literal),
+        ExprKind::Call {
+            is_alg: false,
+            name,
+            args,
+        } => match name.as_str() {
+            "interval" | "complex" => {
+                args.len() == 2 && args.iter().all(|a| matches!(a.kind, ExprKind::Number(_)))
+            }
+            "unknown" => args.is_empty(),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn call(name: &str, args: &[Value]) -> Expr {
+    let kind = ExprKind::Call {
+        is_alg: true,
+        name: name.to_string(),
+        args: args.iter().map(Value::to_expr).collect(),
+    };
+    Expr::new(kind, Span::new(0, 0))
+}
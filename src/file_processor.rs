@@ -1,44 +1,171 @@
+use std::collections::HashMap;
 use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
 
-use crate::ast::{AlgorithmDef, show_expr};
-use crate::error_handling::safe_parse;
-use crate::eval::{Env, Value, World, eval_expr};
-use crate::lexer::lex;
-use crate::normalize::normalize_unicode_to_ascii;
-use crate::parser::{Tokens, parse_expr};
+use crate::cli::{AST, CHECK, Command, DOC, FMT, TEST};
+use amlang::ast::{AlgorithmDef, Expr, def_tree};
+use amlang::builtins::Builtins;
+use amlang::check::check_defs_with;
+use amlang::diagnostic::{Diagnostic, E_DUPLICATE_ALG, E_TEST_FAILED};
+use amlang::diff::differentiate;
+use amlang::docs::{DocFormat, files_to_reference};
+use amlang::equiv::{Sampler, equivalent, parse_equiv};
+use amlang::eval::{Env, EvalError, Value, World, eval_expr};
+use amlang::explain::{ExplainFormat, explain};
+use amlang::format::format_source;
+use amlang::json::{defs_to_json, result_to_json};
+use amlang::latex::defs_to_latex;
+use amlang::lexer::lex_checked;
+use amlang::loader::{SourceFile, Watcher, load_files, read_source, search_path};
+use amlang::mathml::defs_to_mathml;
+use amlang::normalize::{denormalize, normalize_unicode_to_ascii};
+use amlang::parser::{Tokens, parse_expr, parse_whole};
+use amlang::printer::expr_to_source;
+use amlang::settings::Settings;
+use amlang::simplify::{simplify, simplify_def};
+use amlang::token::Span;
 
-fn parse_all_defs(tokens: &mut Tokens) -> Vec<AlgorithmDef> {
-    let mut defs = Vec::new();
-    while let Some(t) = tokens.peek() {
-        match t {
-            crate::token::Token::At => {
-                let d = crate::parser::parse_alg_def(tokens);
-                defs.push(d);
-            }
-            _ => break,
-        }
-    }
-    defs
+#[derive(Clone, Copy, PartialEq)]
+enum ErrorFormat {
+    Human,
+    Json,
 }
 
 struct FileProcessorConfig {
+    paths: Vec<String>,
+    // extra directories to search for imports
+    includes: Vec<String>,
     print_ast: bool,
-    call_expr: Option<String>,
+    ast_json: bool,
+    latex: bool,
+    mathml: bool,
+    // re-run when an input file changes
+    watch: bool,
+    // print call results as JSON, or as CSV rows
+    json: bool,
+    csv: bool,
+    check: bool,
+    // run the files' `@test` declarations instead of their scripts
+    test: bool,
+    // compare the output of each file in this directory with its
+    // `.expected` file, or with `update` rewrite those files
+    golden: Option<String>,
+    update: bool,
+    deny_warnings: bool,
+    // count operations and print a table to stderr afterwards
+    profile: bool,
+    // print each evaluation step to stderr
+    trace: bool,
+    // print a derivation of each call instead of just its value
+    explain: Option<ExplainFormat>,
+    // print the derivative of each call with respect to this variable
+    diff: Option<String>,
+    // simplify exported definitions and derivatives
+    simplify: bool,
+    // print a reference for the definitions instead of running; with
+    // `latex` it includes their bodies
+    doc: Option<DocFormat>,
+    // compare two algorithms on sampled inputs instead of running
+    equiv: Option<(String, String, Sampler)>,
+    // call an algorithm on every row of a CSV file instead of running,
+    // as (algorithm, path)
+    map: Option<(String, String)>,
+    calls: Vec<CallSource>,
+    settings: Settings,
+    error_format: ErrorFormat,
+}
+
+// A call expression to run: `text[range]` of normalized text read from
+// `origin`, so errors point at the right line of a --calls file.
+struct CallSource {
+    origin: String,
+    text: Rc<str>,
+    range: Range<usize>,
+}
+
+impl CallSource {
+    fn expr(&self) -> &str {
+        &self.text[self.range.clone()]
+    }
 }
 
 impl FileProcessorConfig {
     fn new() -> Self {
         Self {
+            paths: Vec::new(),
+            includes: Vec::new(),
             print_ast: false,
-            call_expr: None,
+            ast_json: false,
+            latex: false,
+            mathml: false,
+            watch: false,
+            json: false,
+            csv: false,
+            check: false,
+            test: false,
+            golden: None,
+            update: false,
+            deny_warnings: false,
+            profile: false,
+            trace: false,
+            explain: None,
+            diff: None,
+            simplify: false,
+            doc: None,
+            equiv: None,
+            map: None,
+            calls: Vec::new(),
+            settings: Settings::default(),
+            error_format: ErrorFormat::Human,
+        }
+    }
+
+    // `text` as it is printed: with glyphs under `--unicode`.
+    fn pretty(&self, text: &str) -> String {
+        if self.settings.unicode {
+            denormalize(text)
+        } else {
+            text.to_string()
         }
     }
 
-    fn parse_args(&mut self, args: &mut Vec<String>) -> Result<(), String> {
+    // Render diagnostics located in `src`, which was read from `file`.
+    fn report(&self, diags: &[Diagnostic], src: &str, file: &str) -> String {
+        let lines: Vec<String> = diags
+            .iter()
+            .map(|d| match self.error_format {
+                ErrorFormat::Human => d.render_in(Some(src), file),
+                ErrorFormat::Json => d.to_json(Some(src), file),
+            })
+            .collect();
+        lines.join("\n")
+    }
+
+    // Flags outside `command.flags` are rejected with its usage text.
+    fn parse_args(&mut self, command: &Command, args: &[String]) -> Result<(), String> {
         let mut i = 0;
         while i < args.len() {
+            let flag = args[i].split('=').next().unwrap_or_default();
+            if flag.starts_with("--") && !command.flags.contains(&flag) {
+                return Err(format!("unknown flag: {}\n\n{}", args[i], command.usage));
+            }
             i = self.parse_single_arg(args, i)?;
         }
+        if command.name == CHECK.name {
+            self.check = true;
+        } else if command.name == TEST.name {
+            self.test = true;
+        } else if command.name == DOC.name {
+            self.doc.get_or_insert(DocFormat::Markdown);
+        } else if command.name == AST.name {
+            self.print_ast = !self.json;
+            self.ast_json = self.json;
+        }
         Ok(())
     }
 
@@ -48,8 +175,146 @@ impl FileProcessorConfig {
                 self.print_ast = true;
                 Ok(i + 1)
             }
+            "--ast-json" => {
+                self.ast_json = true;
+                Ok(i + 1)
+            }
+            "--latex" => {
+                self.latex = true;
+                Ok(i + 1)
+            }
+            "--html" => {
+                self.doc = Some(DocFormat::Html);
+                Ok(i + 1)
+            }
+            "--mathml" => {
+                self.mathml = true;
+                Ok(i + 1)
+            }
+            // results and errors alike become JSON
+            "--json" => {
+                self.json = true;
+                self.error_format = ErrorFormat::Json;
+                Ok(i + 1)
+            }
+            "--check" => {
+                self.check = true;
+                Ok(i + 1)
+            }
+            "--deny-warnings" => {
+                self.deny_warnings = true;
+                Ok(i + 1)
+            }
+            "--profile" => {
+                self.profile = true;
+                Ok(i + 1)
+            }
+            "--trace" => {
+                self.trace = true;
+                Ok(i + 1)
+            }
+            "--explain" => {
+                self.explain = Some(ExplainFormat::Text);
+                Ok(i + 1)
+            }
+            "--simplify" => {
+                self.simplify = true;
+                Ok(i + 1)
+            }
+            "--watch" => {
+                self.watch = true;
+                Ok(i + 1)
+            }
+            "--csv" => {
+                self.csv = true;
+                Ok(i + 1)
+            }
             "--call" => self.parse_call_arg(args, i),
-            other => Err(format!("unknown flag: {}", other)),
+            "--calls" => self.parse_calls_file_arg(args, i),
+            "--diff" => {
+                let var = args
+                    .get(i + 1)
+                    .ok_or("--diff requires a variable, e.g. --diff x")?;
+                self.diff = Some(var.clone());
+                Ok(i + 2)
+            }
+            "--golden" => {
+                let dir = args
+                    .get(i + 1)
+                    .ok_or("--golden requires a directory, e.g. --golden tests/golden")?;
+                self.golden = Some(dir.clone());
+                Ok(i + 2)
+            }
+            "--update" => {
+                self.update = true;
+                Ok(i + 1)
+            }
+            "--equiv" => {
+                let spec = args
+                    .get(i + 1)
+                    .ok_or("--equiv requires two algorithms, e.g. --equiv 'A B over 0..100'")?;
+                self.equiv = Some(parse_equiv(spec).map_err(|e| format!("--equiv: {e}"))?);
+                Ok(i + 2)
+            }
+            "--map" => {
+                let spec = args
+                    .get(i + 1)
+                    .ok_or("--map requires an algorithm and a file, e.g. --map 'Hyp grid.csv'")?;
+                let (name, path) = spec
+                    .trim()
+                    .split_once(char::is_whitespace)
+                    .ok_or("--map requires an algorithm and a file, e.g. --map 'Hyp grid.csv'")?;
+                let name = name.trim_start_matches('@').to_string();
+                self.map = Some((name, path.trim().to_string()));
+                Ok(i + 2)
+            }
+            "--include" => {
+                let dir = args
+                    .get(i + 1)
+                    .ok_or("--include requires a directory, e.g. --include lib")?;
+                self.includes.push(dir.clone());
+                Ok(i + 2)
+            }
+            "--max-depth" => self.parse_setting_arg(args, i, "depth"),
+            "--mod" => self.parse_setting_arg(args, i, "mod"),
+            "--max-steps" => self.parse_setting_arg(args, i, "steps"),
+            "--timeout" => self.parse_setting_arg(args, i, "timeout"),
+            "--epsilon" => self.parse_setting_arg(args, i, "epsilon"),
+            "--memoize" => {
+                self.settings.memoize = true;
+                Ok(i + 1)
+            }
+            "--bigint" => {
+                self.settings.bigint = true;
+                Ok(i + 1)
+            }
+            "--strict" => {
+                self.settings.strict = true;
+                Ok(i + 1)
+            }
+            "--unicode" => {
+                self.settings.unicode = true;
+                Ok(i + 1)
+            }
+            "--explain=text" => {
+                self.explain = Some(ExplainFormat::Text);
+                Ok(i + 1)
+            }
+            "--explain=markdown" => {
+                self.explain = Some(ExplainFormat::Markdown);
+                Ok(i + 1)
+            }
+            other => match other.strip_prefix("--error-format=") {
+                Some(format) => {
+                    self.error_format = parse_error_format(format)?;
+                    Ok(i + 1)
+                }
+                None if other.starts_with("--") => Err(format!("unknown flag: {}", other)),
+                None => {
+                    self.paths.push(other.to_string());
+                    Ok(i + 1)
+                }
+            },
         }
     }
 
@@ -57,76 +322,679 @@ impl FileProcessorConfig {
         if i + 1 >= args.len() {
             return Err("--call requires an expression, e.g. --call \"SafeDiv(1,0)\"".to_string());
         }
-        self.call_expr = Some(args[i + 1].clone());
+        let text: Rc<str> = normalize_unicode_to_ascii(&args[i + 1]).into();
+        self.calls.push(CallSource {
+            origin: "<call>".to_string(),
+            range: 0..text.len(),
+            text,
+        });
+        Ok(i + 2)
+    }
+
+    // One call per line; blank lines and `//` comments are skipped.
+    fn parse_calls_file_arg(&mut self, args: &[String], i: usize) -> Result<usize, String> {
+        let path = args
+            .get(i + 1)
+            .ok_or("--calls requires a file with one call expression per line")?;
+        let text: Rc<str> = read_source(path)?.into();
+        let mut start = 0;
+        for line in text.split_inclusive('\n') {
+            let trimmed = line.trim();
+            if !trimmed.is_empty() && !trimmed.starts_with("//") {
+                let from = start + (line.len() - line.trim_start().len());
+                self.calls.push(CallSource {
+                    origin: path.clone(),
+                    text: Rc::clone(&text),
+                    range: from..from + trimmed.len(),
+                });
+            }
+            start += line.len();
+        }
+        Ok(i + 2)
+    }
+
+    fn parse_setting_arg(&mut self, args: &[String], i: usize, key: &str) -> Result<usize, String> {
+        let value = args
+            .get(i + 1)
+            .ok_or_else(|| format!("{} requires a value", args[i]))?;
+        self.settings.set(key, value)?;
         Ok(i + 2)
     }
 }
 
-pub fn process_file(mut args: Vec<String>) -> Result<(), String> {
-    let path = args.remove(0);
+fn parse_error_format(s: &str) -> Result<ErrorFormat, String> {
+    match s {
+        "human" => Ok(ErrorFormat::Human),
+        "json" => Ok(ErrorFormat::Json),
+        other => Err(format!(
+            "unknown error format: {} (expected human or json)",
+            other
+        )),
+    }
+}
 
-    let src_raw =
-        fs::read_to_string(&path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+/// Run `command` (run, check, ast, test, or doc) with its command-line `args`.
+pub fn process_file(command: &Command, args: &[String]) -> Result<(), String> {
+    let mut config = FileProcessorConfig::new();
+    config.parse_args(command, args)?;
+    if let Some(dir) = &config.golden {
+        return run_golden(dir, &config);
+    }
+    if config.paths.is_empty() {
+        return Err(format!("no input files given\n\n{}", command.usage));
+    }
+    if config.watch {
+        watch(&config);
+    }
+    run(&config, &mut Vec::new())
+}
 
-    let src = normalize_unicode_to_ascii(&src_raw);
-    let tokens = lex(&src);
-    let mut ts = Tokens::new_with_src(tokens, &src);
-    let defs = parse_all_defs(&mut ts);
+// Re-run whenever one of the files last loaded (imports included) changes.
+// Failures are printed rather than ending the loop; only Ctrl-C does.
+fn watch(config: &FileProcessorConfig) -> ! {
+    let mut watcher = Watcher::default();
+    loop {
+        if let Err(e) = watcher.rerun(|loaded| run(config, loaded)) {
+            eprintln!("{e}");
+        }
+        eprintln!("-- watching {} file(s) for changes", watcher.paths().len());
+        while !watcher.changed() {
+            thread::sleep(Duration::from_millis(250));
+        }
+    }
+}
 
-    if defs.is_empty() {
-        return Err(format!("No algorithms found in {}", path));
+// Load, check, and run as configured, recording the paths read in `loaded`.
+fn run(config: &FileProcessorConfig, loaded: &mut Vec<String>) -> Result<(), String> {
+    let roots = expand_paths(&config.paths)?;
+    if loaded.is_empty() {
+        loaded.clone_from(&roots);
     }
+    let search = search_path(&config.includes);
+    let mut files = load_files(&roots, &search)?;
+    *loaded = files.iter().map(|f| f.path.clone()).collect();
+    let failures: Vec<String> = files
+        .iter()
+        .filter(|f| f.has_errors())
+        .map(|f| config.report(&f.diags, &f.src, &f.path))
+        .collect();
+    if !failures.is_empty() {
+        return Err(failures.join("\n"));
+    }
+    report_duplicates(&mut files);
 
-    let mut config = FileProcessorConfig::new();
-    config.parse_args(&mut args)?;
+    let defs: Vec<AlgorithmDef> = files.iter().flat_map(|f| f.defs.clone()).collect();
+    let has_script = files.iter().any(|f| !f.script.is_empty());
+    let has_tests = files.iter().any(|f| !f.tests.is_empty());
+    if defs.is_empty() && !has_script && !has_tests {
+        return Err(format!(
+            "No algorithms or expressions found in {}",
+            config.paths.join(", ")
+        ));
+    }
 
+    if let Some(format) = config.doc {
+        print!("{}", files_to_reference(&files, format, config.latex));
+        return Ok(());
+    }
     if config.print_ast {
         print_ast(&defs);
     }
+    if config.ast_json {
+        println!("{}", defs_to_json(&defs));
+    }
+    let exported: Vec<AlgorithmDef> = match config.simplify {
+        true => defs.iter().map(simplify_def).collect(),
+        false => Vec::new(),
+    };
+    let export = if config.simplify { &exported } else { &defs };
+    if config.latex {
+        print!("{}", defs_to_latex(export));
+    }
+    if config.mathml {
+        print!("{}", defs_to_mathml(export));
+    }
+
+    // definitions are always checked before anything runs
+    let exports = config.print_ast || config.ast_json || config.latex || config.mathml;
+    let batch = config.equiv.is_some() || config.map.is_some();
+    let runs = !config.check && !config.test && !batch && !exports;
+    let checks = config.check || config.test || batch;
+    if checks || !config.calls.is_empty() || (runs && has_script) {
+        check_files(&mut files);
+    }
+
+    let has_errors = files.iter().any(SourceFile::has_errors);
+    let reports: Vec<String> = files
+        .iter()
+        .filter(|f| !f.diags.is_empty())
+        .map(|f| config.report(&f.diags, &f.src, &f.path))
+        .collect();
+    if has_errors {
+        return Err(reports.join("\n"));
+    }
+    if !reports.is_empty() {
+        eprintln!("{}", reports.join("\n"));
+        if config.deny_warnings {
+            let count: usize = files.iter().map(|f| f.diags.len()).sum();
+            return Err(format!(
+                "{} warning(s) treated as errors (--deny-warnings)",
+                count
+            ));
+        }
+    }
 
-    if let Some(call_src) = config.call_expr {
-        execute_call(&call_src, &defs, &src)?;
-    } else if !config.print_ast {
-        print_summary(&defs, &path);
+    if config.check {
+        for f in &files {
+            println!(
+                "{}: {} algorithm(s) checked, no errors",
+                f.path,
+                f.defs.len()
+            );
+        }
     }
 
+    let mut world = World::new(&defs);
+    world.settings = config.settings.clone();
+    if config.profile {
+        world.profile = Some(Mutex::default());
+    }
+    if config.trace {
+        world.trace = Some(Box::new(|depth, event| {
+            eprintln!("{}{}", "  ".repeat(depth), event)
+        }));
+    }
+    if config.test {
+        return run_tests(&world, &files, config);
+    }
+    if let Some((left, right, sampler)) = &config.equiv {
+        return match equivalent(&world, left, right, sampler)? {
+            Ok(n) => {
+                println!("@{left} and @{right} agree on {n} input(s) from {sampler}");
+                Ok(())
+            }
+            Err(c) => Err(format!(
+                "counterexample: {}",
+                c.describe(&format!("@{left}"), &format!("@{right}"))
+            )),
+        };
+    }
+    if let Some((name, path)) = &config.map {
+        return map_csv(&world, name, path, &files, config);
+    }
+    if runs {
+        for f in &files {
+            for e in &f.script {
+                let val = eval_in(&world, e, &files, (&f.src, &f.path), config)?;
+                print_value(&val, config);
+            }
+        }
+    }
+    if !config.calls.is_empty() {
+        execute_calls(&world, &files, config)?;
+    } else if runs && !has_script {
+        print_summary(&defs, &config.paths.join(" "));
+    }
+    if let Some(p) = &world.profile {
+        eprint!("{}", p.lock().unwrap().table());
+    }
+
+    Ok(())
+}
+
+// Check each file's definitions against those of all the others.
+fn check_files(files: &mut [SourceFile]) {
+    let builtins = Builtins::standard();
+    for i in 0..files.len() {
+        let others: Vec<&AlgorithmDef> = files
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != i)
+            .flat_map(|(_, f)| &f.defs)
+            .collect();
+        let problems = check_defs_with(&files[i].defs, &others, &builtins);
+        files[i].diags.extend(problems);
+    }
+}
+
+// Directories stand for the `.am` files directly inside them, in name order.
+fn expand_paths(paths: &[String]) -> Result<Vec<String>, String> {
+    let mut out = Vec::new();
+    for path in paths {
+        if !Path::new(path).is_dir() {
+            out.push(path.clone());
+            continue;
+        }
+        let entries = fs::read_dir(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+        let mut found: Vec<String> = entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|x| x == "am"))
+            .map(|p| p.to_string_lossy().into_owned())
+            .collect();
+        found.sort();
+        out.extend(found);
+    }
+    Ok(out)
+}
+
+// An algorithm defined in more than one file is an error in every file
+// after the first.
+fn report_duplicates(files: &mut [SourceFile]) {
+    let mut first: HashMap<String, String> = HashMap::new();
+    for f in files.iter_mut() {
+        for d in &f.defs {
+            match first.get(&d.name) {
+                Some(path) if *path != f.path => f.diags.push(
+                    Diagnostic::error(
+                        E_DUPLICATE_ALG,
+                        format!("algorithm @{} is defined more than once", d.name),
+                    )
+                    .with_span(d.span)
+                    .with_note(format!("first defined in {}", path)),
+                ),
+                Some(_) => {}
+                None => {
+                    first.insert(d.name.clone(), f.path.clone());
+                }
+            }
+        }
+    }
+}
+
+/// Format each file named in `args`: print it, or with `--check` list
+/// the files that would change, or with `--write` rewrite them.
+pub fn format_files(args: &[String]) -> Result<(), String> {
+    let (flags, paths): (Vec<&String>, Vec<&String>) =
+        args.iter().partition(|a| a.starts_with("--"));
+    if let Some(bad) = flags.iter().find(|f| !FMT.flags.contains(&f.as_str())) {
+        return Err(format!("unknown flag: {}\n\n{}", bad, FMT.usage));
+    }
+    if paths.is_empty() {
+        return Err(format!("no input files given\n\n{}", FMT.usage));
+    }
+    let check = flags.iter().any(|f| *f == "--check");
+    let write = flags.iter().any(|f| *f == "--write");
+    let unicode = flags.iter().any(|f| *f == "--unicode");
+
+    let mut unformatted = 0;
+    for path in paths {
+        let src = read_source(path)?;
+        let mut formatted = format_source(&src).map_err(|d| {
+            let lines: Vec<String> = d.iter().map(|d| d.render_in(Some(&src), path)).collect();
+            lines.join("\n")
+        })?;
+        if unicode {
+            formatted = denormalize(&formatted);
+        }
+        let raw =
+            fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+        if check {
+            if formatted != raw {
+                println!("{} is not formatted", path);
+                unformatted += 1;
+            }
+        } else if write {
+            if formatted != raw {
+                fs::write(path, &formatted)
+                    .map_err(|e| format!("Could not write {}: {}", path, e))?;
+            }
+        } else {
+            print!("{}", formatted);
+        }
+    }
+    if unformatted > 0 {
+        return Err(format!("{} file(s) need formatting", unformatted));
+    }
     Ok(())
 }
 
-fn print_ast(defs: &[AlgorithmDef]) {
+pub fn print_ast(defs: &[AlgorithmDef]) {
     for d in defs {
-        println!("AlgorithmDef {}({})", d.name, d.params.join(","));
-        println!("body:");
-        show_expr(&d.body, 1);
+        print!("{}", def_tree(d));
     }
 }
 
-fn execute_call(call_src: &str, defs: &[AlgorithmDef], src: &str) -> Result<(), String> {
-    let norm = normalize_unicode_to_ascii(call_src);
-    let toks = lex(&norm);
-    let mut t2 = Tokens::new_with_src(toks, src);
+// Run every call in order. A failing call is reported without stopping
+// the rest; with several calls the errors are printed as they happen.
+fn execute_calls(
+    world: &World,
+    files: &[SourceFile],
+    config: &FileProcessorConfig,
+) -> Result<(), String> {
+    if config.csv {
+        println!("call,result");
+    }
+    let mut failed = 0;
+    for call in &config.calls {
+        match execute_call(call, world, files, config) {
+            Ok(_) if config.explain.is_some() => {}
+            Ok(val) if config.csv => println!("{},{}", csv_field(call.expr()), csv_value(&val)),
+            Ok(Value::Str(derivative)) if config.diff.is_some() && !config.json => {
+                println!("{}", config.pretty(&derivative))
+            }
+            Ok(val) => print_value(&val, config),
+            Err(e) if config.calls.len() == 1 => return Err(e),
+            Err(e) => {
+                if config.csv {
+                    println!("{},", csv_field(call.expr()));
+                }
+                eprintln!("{e}");
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(format!("{} of {} calls failed", failed, config.calls.len()));
+    }
+    Ok(())
+}
 
-    let call = safe_parse(|| parse_expr(&mut t2))?;
-    let world = World::new(defs);
-    let mut env = Env::base();
+fn execute_call(
+    call: &CallSource,
+    world: &World,
+    files: &[SourceFile],
+    config: &FileProcessorConfig,
+) -> Result<Value, String> {
+    // spans are shifted from the call's own text to the whole of `text`
+    let offset = call.range.start;
+    let report = |d: &[Diagnostic]| config.report(d, &call.text, &call.origin);
+    let mut toks = lex_checked(call.expr()).map_err(|mut d| {
+        for d in &mut d {
+            d.span = d.span.map(|s| Span::new(s.start + offset, s.end + offset));
+        }
+        report(&d)
+    })?;
+    for t in &mut toks {
+        t.start += offset;
+        t.end += offset;
+    }
+    let mut t2 = Tokens::new(toks);
 
-    let val = eval_expr(&world, &mut env, &call).map_err(|e| format!("runtime error: {e}"))?;
+    let expr = parse_whole(&mut t2, parse_expr).map_err(|d| report(&d))?;
+    let origin = (&*call.text, call.origin.as_str());
+    // the derivative stands in for the call's result
+    if let Some(var) = &config.diff {
+        let mut derivative = differentiate(world, &expr, var)?;
+        if config.simplify {
+            derivative = simplify(&derivative);
+        }
+        return Ok(Value::Str(expr_to_source(&derivative)));
+    }
+    if let Some(format) = config.explain {
+        let ex = explain(world, &expr).map_err(|e| report_eval_error(&e, files, origin, config))?;
+        print!("{}", ex.render(format));
+        return Ok(ex.value);
+    }
+    eval_in(world, &expr, files, origin, config)
+}
 
-    match val {
-        Value::Number(x) => println!("= {}", x),
-        Value::Bool(b) => println!("= {}", b),
+// Evaluate `expr`, which was parsed from `origin` (source and file name).
+fn eval_in(
+    world: &World,
+    expr: &Expr,
+    files: &[SourceFile],
+    origin: (&str, &str),
+    config: &FileProcessorConfig,
+) -> Result<Value, String> {
+    eval_expr(world, &mut Env::base(), expr)
+        .map_err(|e| report_eval_error(&e, files, origin, config))
+}
+
+// Errors inside an algorithm point into its file, others into `origin`.
+fn report_eval_error(
+    e: &EvalError,
+    files: &[SourceFile],
+    origin: (&str, &str),
+    config: &FileProcessorConfig,
+) -> String {
+    let d = e.to_diagnostic();
+    let file = e.alg.as_ref().and_then(|name| {
+        files
+            .iter()
+            .find(|f| f.defs.iter().any(|d| d.name == *name))
+    });
+    match file {
+        Some(f) => config.report(&[d], &f.src, &f.path),
+        None => config.report(&[d], origin.0, origin.1),
+    }
+}
+
+// Call `name` on every row of the CSV file at `path`, in parallel, and
+// print each row with its result appended. A first row with no numbers
+// in it is a header. Fields are numbers, true or false, or else strings.
+fn map_csv(
+    world: &World,
+    name: &str,
+    path: &str,
+    files: &[SourceFile],
+    config: &FileProcessorConfig,
+) -> Result<(), String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+    let mut rows: Vec<&str> = text.lines().filter(|l| !l.trim().is_empty()).collect();
+    let fields = |row: &str| -> Vec<Value> {
+        row.split(',')
+            .map(|f| match f.trim() {
+                "true" => Value::Bool(true),
+                "false" => Value::Bool(false),
+                f => f
+                    .parse()
+                    .map_or_else(|_| Value::Str(f.to_string()), Value::Number),
+            })
+            .collect()
+    };
+    let header = rows
+        .first()
+        .is_some_and(|r| fields(r).iter().all(|v| !matches!(v, Value::Number(_))));
+    if header {
+        println!("{},result", rows.remove(0));
+    }
+    let inputs: Vec<Vec<Value>> = rows.iter().map(|r| fields(r)).collect();
+    let mut failed = 0;
+    for (row, result) in rows.iter().zip(world.call_batch(name, &inputs)) {
+        match result {
+            Ok(val) => println!("{},{}", row, csv_value(&val)),
+            Err(mut e) => {
+                println!("{},", row);
+                // a failure outside any algorithm has no location
+                if e.alg.is_none() {
+                    e.span = None;
+                }
+                eprintln!("{}", report_eval_error(&e, files, ("", path), config));
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(format!("{} of {} rows failed", failed, rows.len()));
+    }
+    Ok(())
+}
+
+// Run every `@test`, printing a line for each and the reason for each
+// failure.
+fn run_tests(
+    world: &World,
+    files: &[SourceFile],
+    config: &FileProcessorConfig,
+) -> Result<(), String> {
+    let (mut passed, mut failed) = (0, 0);
+    for f in files {
+        for t in &f.tests {
+            let origin = (f.src.as_str(), f.path.as_str());
+            let problem = match eval_expr(world, &mut Env::base(), &t.expr) {
+                Ok(Value::Bool(true)) => None,
+                Ok(v) => {
+                    let got = match v {
+                        Value::Bool(_) => "false".to_string(),
+                        other => format!("{}, not a bool", other),
+                    };
+                    let d = Diagnostic::error(
+                        E_TEST_FAILED,
+                        format!("test {:?} failed: got {}", t.name, got),
+                    )
+                    .with_span(t.expr.span);
+                    Some(config.report(&[d], origin.0, origin.1))
+                }
+                Err(e) => Some(report_eval_error(&e, files, origin, config)),
+            };
+            match problem {
+                None => {
+                    println!("test {} ... ok", t.name);
+                    passed += 1;
+                }
+                Some(report) => {
+                    println!("test {} ... FAILED", t.name);
+                    eprintln!("{report}");
+                    failed += 1;
+                }
+            }
+        }
+    }
+    println!("{} passed, {} failed", passed, failed);
+    if failed > 0 {
+        return Err(format!("{} of {} tests failed", failed, passed + failed));
     }
+    Ok(())
+}
 
+// Compare what each `.am` file in `dir` prints with its `.expected` file.
+fn run_golden(dir: &str, config: &FileProcessorConfig) -> Result<(), String> {
+    let paths = expand_paths(&[dir.to_string()])?;
+    if paths.is_empty() {
+        return Err(format!("no .am files found in {}", dir));
+    }
+    let (mut passed, mut failed) = (0, 0);
+    for path in &paths {
+        let expected_path = Path::new(path).with_extension("expected");
+        let actual = golden_output(path, config);
+        if config.update {
+            fs::write(&expected_path, &actual)
+                .map_err(|e| format!("Could not write {}: {}", expected_path.display(), e))?;
+            println!("golden {} ... updated", path);
+            passed += 1;
+            continue;
+        }
+        let expected = fs::read_to_string(&expected_path).unwrap_or_default();
+        if actual == expected {
+            println!("golden {} ... ok", path);
+            passed += 1;
+            continue;
+        }
+        println!("golden {} ... FAILED", path);
+        if !expected_path.exists() {
+            eprintln!(
+                "  {} is missing (run with --update)",
+                expected_path.display()
+            );
+        } else if let Some((n, want, got)) = first_difference(&expected, &actual) {
+            eprintln!("  line {} differs", n);
+            eprintln!("  expected: {}", want);
+            eprintln!("       got: {}", got);
+        }
+        failed += 1;
+    }
+    if config.update {
+        println!("{} file(s) updated", passed);
+        return Ok(());
+    }
+    println!("{} passed, {} failed", passed, failed);
+    if failed > 0 {
+        return Err(format!(
+            "{} of {} golden files differ",
+            failed,
+            passed + failed
+        ));
+    }
     Ok(())
 }
 
+// What running `path` prints: a line per script expression, with errors
+// rendered in place so that expected failures can be recorded too.
+fn golden_output(path: &str, config: &FileProcessorConfig) -> String {
+    let search = search_path(&config.includes);
+    let mut files = match load_files(&[path.to_string()], &search) {
+        Ok(files) => files,
+        Err(e) => return format!("{e}\n"),
+    };
+    // paths are shown relative to the directory, wherever it was run from
+    let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+    for f in &mut files {
+        if let Ok(rel) = Path::new(&f.path).strip_prefix(dir) {
+            f.path = rel.to_string_lossy().into_owned();
+        }
+    }
+    check_files(&mut files);
+    let mut out = String::new();
+    for f in files.iter().filter(|f| !f.diags.is_empty()) {
+        out.push_str(&config.report(&f.diags, &f.src, &f.path));
+        out.push('\n');
+    }
+    if files.iter().any(SourceFile::has_errors) {
+        return out;
+    }
+    let defs: Vec<AlgorithmDef> = files.iter().flat_map(|f| f.defs.clone()).collect();
+    let mut world = World::new(&defs);
+    world.settings = config.settings.clone();
+    for f in &files {
+        for e in &f.script {
+            match eval_in(&world, e, &files, (&f.src, &f.path), config) {
+                Ok(val) => out.push_str(&format!("= {}\n", val)),
+                Err(report) => {
+                    out.push_str(&report);
+                    out.push('\n');
+                }
+            }
+        }
+    }
+    out
+}
+
+// The first line (counted from 1) where `expected` and `actual` differ.
+fn first_difference<'a>(expected: &'a str, actual: &'a str) -> Option<(usize, &'a str, &'a str)> {
+    let mut want = expected.lines();
+    let mut got = actual.lines();
+    for n in 1.. {
+        match (want.next(), got.next()) {
+            (None, None) => return None,
+            (w, g) if w != g => return Some((n, w.unwrap_or("<end>"), g.unwrap_or("<end>"))),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn print_value(val: &Value, config: &FileProcessorConfig) {
+    if config.json {
+        println!("{}", result_to_json(val));
+    } else {
+        println!("= {}", config.pretty(&val.to_string()));
+    }
+}
+
+// Strings are written unquoted unless CSV needs the quotes.
+fn csv_value(val: &Value) -> String {
+    match val {
+        Value::Str(s) => csv_field(s),
+        other => csv_field(&other.to_string()),
+    }
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
 fn print_summary(defs: &[AlgorithmDef], path: &str) {
     println!("Loaded {} algorithm(s):", defs.len());
     for d in defs {
         println!("  {}({})", d.name, d.params.join(", "));
     }
-    println!(
-        "Try:  cargo run -- {} --call \"{}(1,0)\"",
-        path, defs[0].name
-    );
+    match defs.first() {
+        Some(d) => println!("Try:  cargo run -- {} --call \"{}(1,0)\"", path, d.name),
+        // the file has only `@test` declarations
+        None => println!("Try:  cargo run -- test {}", path),
+    }
 }
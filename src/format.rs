@@ -0,0 +1,138 @@
+use crate::ast::Program;
+use crate::diagnostic::{Diagnostic, E_FMT_CHANGED, E_FMT_COMMENT};
+use crate::lexer::comments;
+use crate::parser::parse;
+use crate::printer::{
+    def_to_canonical, def_to_source, expr_to_canonical, expr_to_source, import_to_source,
+    test_to_source,
+};
+use crate::token::Span;
+
+/// Lay out normalized source text canonically: items in their original
+/// order, case blocks one arm per line, and at most one blank line
+/// between items where the source had any.
+///
+/// Comments on their own lines or after an item are kept. One inside a
+/// definition or expression cannot be placed, so it is an error rather
+/// than being dropped.
+pub fn format_source(src: &str) -> Result<String, Vec<Diagnostic>> {
+    let (program, _) = parse(src)?;
+    let items = items(&program, true);
+
+    let comments = comments(src);
+    let misplaced: Vec<Diagnostic> = comments
+        .iter()
+        .filter(|c| {
+            items
+                .iter()
+                .any(|i| i.span.start < c.start && c.start < i.span.end)
+        })
+        .map(|c| {
+            Diagnostic::error(E_FMT_COMMENT, "comment inside a definition would be lost")
+                .with_span(*c)
+                .with_note("move it onto its own line before the definition")
+        })
+        .collect();
+    if !misplaced.is_empty() {
+        return Err(misplaced);
+    }
+
+    // every item and comment as (span, text), in source order
+    let mut pieces: Vec<(Span, String)> = comments
+        .iter()
+        .map(|c| (*c, src[c.start..c.end].trim_end().to_string()))
+        .collect();
+    for (k, item) in items.iter().enumerate() {
+        let mut text = item.text.clone();
+        // a script expression starting with `-`, `(`, or `[` would
+        // otherwise continue the item before it
+        if let Some(next) = items.get(k + 1)
+            && next.script
+            && next.text.starts_with(['-', '(', '['])
+        {
+            text.push(';');
+        }
+        pieces.push((item.span, text));
+    }
+    pieces.sort_by_key(|(span, _)| span.start);
+
+    let mut out = String::new();
+    let mut prev: Option<Span> = None;
+    for (span, text) in pieces {
+        if let Some(p) = prev {
+            let between = &src[p.end..span.start];
+            if !between.contains('\n') {
+                // a comment trailing the previous line
+                out.push(' ');
+            } else if between.matches('\n').count() > 1 {
+                out.push_str("\n\n");
+            } else {
+                out.push('\n');
+            }
+        }
+        out.push_str(&text);
+        prev = Some(span);
+    }
+    if !out.is_empty() {
+        out.push('\n');
+    }
+
+    // the layout must never change what the file means
+    match parse(&out) {
+        Ok((again, _)) if same_items(&program, &again) => Ok(out),
+        _ => Err(vec![Diagnostic::error(
+            E_FMT_CHANGED,
+            "formatting would change the program",
+        )]),
+    }
+}
+
+struct Item {
+    span: Span,
+    text: String,
+    script: bool,
+}
+
+fn items(program: &Program, canonical: bool) -> Vec<Item> {
+    let mut items: Vec<Item> = program
+        .imports
+        .iter()
+        .map(|i| Item {
+            span: i.span,
+            text: import_to_source(i),
+            script: false,
+        })
+        .collect();
+    items.extend(program.defs.iter().map(|d| Item {
+        span: d.full_span(),
+        text: if canonical {
+            def_to_canonical(d)
+        } else {
+            def_to_source(d)
+        },
+        script: false,
+    }));
+    items.extend(program.script.iter().map(|e| Item {
+        span: e.span,
+        text: if canonical {
+            expr_to_canonical(e)
+        } else {
+            expr_to_source(e)
+        },
+        script: true,
+    }));
+    items.extend(program.tests.iter().map(|t| Item {
+        span: t.span,
+        text: test_to_source(t, canonical),
+        script: false,
+    }));
+    items.sort_by_key(|i| i.span.start);
+    items
+}
+
+// One-line renderings are equal exactly when the trees are.
+fn same_items(a: &Program, b: &Program) -> bool {
+    let text =
+        |p: &Program| -> Vec<String> { items(p, false).into_iter().map(|i| i.text).collect() };
+    text(a) == text(b)
+}
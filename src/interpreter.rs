@@ -0,0 +1,189 @@
+use crate::ast::AlgorithmDef;
+use crate::check::check_defs_with;
+use crate::diagnostic::{Diagnostic, E_IMPORT_UNSUPPORTED, E_UNEXPECTED_TOKEN};
+use crate::eval::{Env, EvalError, Value, World, eval_expr};
+use crate::lexer::lex_checked;
+use crate::native::NativeFunction;
+use crate::normalize::normalize_unicode_to_ascii;
+use crate::parser::{Tokens, parse, parse_expr, parse_whole};
+use crate::settings::Settings;
+
+/// An AM session for embedding: load definitions from source text, then
+/// call them from Rust.
+///
+/// ```
+/// use amlang::{Interpreter, Value};
+///
+/// let mut am = Interpreter::new();
+/// am.load_str("@Double(x) = x * 2").unwrap();
+/// let v = am.call("Double", &[Value::Number(21.0)]).unwrap();
+/// assert_eq!(v.to_string(), "42");
+/// ```
+///
+/// Once loaded, an interpreter can be shared between threads, each calling
+/// into it through `&Interpreter`.
+pub struct Interpreter {
+    world: World,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self {
+            world: World::new(&[]),
+        }
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.world.settings
+    }
+
+    pub fn settings_mut(&mut self) -> &mut Settings {
+        &mut self.world.settings
+    }
+
+    /// Parse and check every definition in `src` and add them, replacing
+    /// algorithms of the same name. Nothing is added if any error is found.
+    /// Text that is not read from a file cannot `import`, and expressions
+    /// belong in `eval_str`.
+    ///
+    /// Returns the warnings on success. Diagnostic spans point into
+    /// `normalize_unicode_to_ascii(src)`.
+    pub fn load_str(&mut self, src: &str) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+        let src = normalize_unicode_to_ascii(src);
+        let (program, mut warnings) = parse(&src)?;
+        if !program.imports.is_empty() {
+            return Err(program
+                .imports
+                .iter()
+                .map(|i| {
+                    Diagnostic::error(E_IMPORT_UNSUPPORTED, "import is only allowed in files")
+                        .with_span(i.span)
+                })
+                .collect());
+        }
+        if !program.script.is_empty() {
+            return Err(program
+                .script
+                .iter()
+                .map(|e| {
+                    Diagnostic::error(E_UNEXPECTED_TOKEN, "expected a definition")
+                        .with_span(e.span)
+                        .with_note("use eval_str to evaluate expressions")
+                })
+                .collect());
+        }
+        warnings.extend(self.install(program.defs)?);
+        Ok(warnings)
+    }
+
+    /// Check and add an already-parsed definition, as `load_str` does.
+    pub fn define(&mut self, def: AlgorithmDef) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+        self.install(vec![def])
+    }
+
+    /// Native functions are visible to definitions loaded afterwards.
+    pub fn register_fn<F>(&mut self, name: &str, arity: usize, f: F)
+    where
+        F: Fn(&[Value]) -> Result<Value, String> + Send + Sync + 'static,
+    {
+        self.world.register_fn(name, arity, f);
+    }
+
+    /// Like `register_fn` for a closure over plain Rust types, such as
+    /// `|x: f64, label: String| ...`: its arity comes from the closure,
+    /// and arguments of the wrong type fail before it is called. See
+    /// `native` for the types that convert.
+    pub fn register<Args, F: NativeFunction<Args>>(&mut self, name: &str, f: F) {
+        self.world
+            .register_fn(name, F::ARITY, move |args| f.call(args));
+    }
+
+    /// Call an algorithm, or failing that a builtin, by name.
+    pub fn call(&self, name: &str, args: &[Value]) -> Result<Value, Diagnostic> {
+        self.world.call(name, args).map_err(call_diagnostic)
+    }
+
+    /// `call` with each of `inputs` in parallel, giving the results in the
+    /// same order.
+    ///
+    /// ```
+    /// use amlang::{Interpreter, Value};
+    ///
+    /// let mut am = Interpreter::new();
+    /// am.load_str("@Hyp(a, b) = sqrt(a^2 + b^2)").unwrap();
+    /// let grid: Vec<Vec<Value>> = (1..=3)
+    ///     .map(|a| vec![Value::Number(a as f64 * 3.0), Value::Number(a as f64 * 4.0)])
+    ///     .collect();
+    /// let hyps: Vec<String> = am
+    ///     .call_batch("Hyp", &grid)
+    ///     .into_iter()
+    ///     .map(|r| r.unwrap().to_string())
+    ///     .collect();
+    /// assert_eq!(hyps, ["5", "10", "15"]);
+    /// ```
+    pub fn call_batch(&self, name: &str, inputs: &[Vec<Value>]) -> Vec<Result<Value, Diagnostic>> {
+        self.world
+            .call_batch(name, inputs)
+            .into_iter()
+            .map(|r| r.map_err(call_diagnostic))
+            .collect()
+    }
+
+    /// Evaluate one expression against the loaded definitions. Only the
+    /// first of several parse errors is returned.
+    pub fn eval_str(&self, src: &str) -> Result<Value, Diagnostic> {
+        let src = normalize_unicode_to_ascii(src);
+        let first = |mut diags: Vec<Diagnostic>| diags.swap_remove(0);
+        let mut ts = Tokens::new(lex_checked(&src).map_err(first)?);
+        let expr = parse_whole(&mut ts, parse_expr).map_err(first)?;
+        eval_expr(&self.world, &mut Env::base(), &expr).map_err(|e| e.to_diagnostic())
+    }
+
+    /// The loaded definitions, in no particular order.
+    pub fn defs(&self) -> impl Iterator<Item = &AlgorithmDef> {
+        self.world.algs.values().map(|d| &**d)
+    }
+
+    fn install(&mut self, defs: Vec<AlgorithmDef>) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+        let known: Vec<&AlgorithmDef> = self.defs().collect();
+        let (errors, warnings) = check_defs_with(&defs, &known, &self.world.builtins)
+            .into_iter()
+            .partition::<Vec<_>, _>(Diagnostic::is_error);
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        for d in defs {
+            self.world.define(d);
+        }
+        Ok(warnings)
+    }
+}
+
+fn call_diagnostic(e: EvalError) -> Diagnostic {
+    let mut d = e.to_diagnostic();
+    // only a location inside a loaded definition means anything
+    if e.alg.is_none() {
+        d.span = None;
+    }
+    d
+}
+
+/// Evaluate a standalone expression, e.g. `eval_str("1 + 2 * 3")`.
+pub fn eval_str(src: &str) -> Result<Value, Diagnostic> {
+    Interpreter::new().eval_str(src)
+}
+
+/// Load the definitions in `src` and call algorithm `name` with numeric
+/// arguments. Only the first load error is returned.
+pub fn run_program(src: &str, name: &str, args: &[f64]) -> Result<Value, Diagnostic> {
+    let mut am = Interpreter::new();
+    am.load_str(src).map_err(|mut diags| diags.swap_remove(0))?;
+    let args: Vec<Value> = args.iter().map(|&n| Value::Number(n)).collect();
+    am.call(name, &args)
+}
@@ -0,0 +1,170 @@
+use crate::ast::{AlgorithmDef, Expr, ExprKind};
+use crate::eval::Value;
+use crate::token::Span;
+
+// JSON for the AST, in the shape `serde_json` gives the `serde` derives:
+// structs are objects, enum variants are `{"Variant": payload}` and
+// unit variants bare strings. The CLI writes it without depending on serde.
+
+/// A JSON array of `defs`, as printed by `--ast-json`.
+pub fn defs_to_json(defs: &[AlgorithmDef]) -> String {
+    let items: Vec<String> = defs.iter().map(def_to_json).collect();
+    format!("[{}]", items.join(","))
+}
+
+pub fn def_to_json(d: &AlgorithmDef) -> String {
+    let params: Vec<String> = d.params.iter().map(|p| string(p)).collect();
+    format!(
+        "{{\"name\":{},\"params\":[{}],\"body\":{},\"memo\":{},\"span\":{},\"locals\":{},\"requires\":{},\"ensures\":{},\"doc\":{}}}",
+        string(&d.name),
+        params.join(","),
+        expr_to_json(&d.body),
+        d.memo,
+        span(d.span),
+        defs_to_json(&d.locals),
+        exprs(&d.requires),
+        exprs(&d.ensures),
+        d.doc.as_deref().map_or("null".to_string(), string)
+    )
+}
+
+pub fn expr_to_json(e: &Expr) -> String {
+    let kind = match &e.kind {
+        ExprKind::Number(n) => format!("{{\"Number\":{}}}", number(*n)),
+        ExprKind::Imag(n) => format!("{{\"Imag\":{}}}", number(*n)),
+        ExprKind::Bool(b) => format!("{{\"Bool\":{}}}", b),
+        ExprKind::Str(s) => format!("{{\"Str\":{}}}", string(s)),
+        ExprKind::Ident(name) => format!("{{\"Ident\":{}}}", string(name)),
+        ExprKind::AlgRef(name) => format!("{{\"AlgRef\":{}}}", string(name)),
+        ExprKind::Call { is_alg, name, args } => format!(
+            "{{\"Call\":{{\"is_alg\":{},\"name\":{},\"args\":{}}}}}",
+            is_alg,
+            string(name),
+            exprs(args)
+        ),
+        ExprKind::Unary { op, expr } => format!(
+            "{{\"Unary\":{{\"op\":\"{:?}\",\"expr\":{}}}}}",
+            op,
+            expr_to_json(expr)
+        ),
+        ExprKind::Bin { op, left, right } => format!(
+            "{{\"Bin\":{{\"op\":\"{:?}\",\"left\":{},\"right\":{}}}}}",
+            op,
+            expr_to_json(left),
+            expr_to_json(right)
+        ),
+        ExprKind::Case { arms, default } => {
+            let arms: Vec<String> = arms
+                .iter()
+                .map(|(c, r)| format!("[{},{}]", expr_to_json(c), expr_to_json(r)))
+                .collect();
+            format!(
+                "{{\"Case\":{{\"arms\":[{}],\"default\":{}}}}}",
+                arms.join(","),
+                default
+                    .as_deref()
+                    .map_or_else(|| "null".to_string(), expr_to_json)
+            )
+        }
+        ExprKind::List(items) => format!("{{\"List\":{}}}", exprs(items)),
+        ExprKind::Tuple(items) => format!("{{\"Tuple\":{}}}", exprs(items)),
+        ExprKind::Pipe { head, steps } => format!(
+            "{{\"Pipe\":{{\"head\":{},\"steps\":{}}}}}",
+            expr_to_json(head),
+            exprs(steps)
+        ),
+        ExprKind::Lambda { params, body } => {
+            let params: Vec<String> = params.iter().map(|p| string(p)).collect();
+            format!(
+                "{{\"Lambda\":{{\"params\":[{}],\"body\":{}}}}}",
+                params.join(","),
+                expr_to_json(body)
+            )
+        }
+        ExprKind::Series {
+            kind,
+            index,
+            from,
+            to,
+            body,
+        } => format!(
+            "{{\"Series\":{{\"kind\":\"{:?}\",\"index\":{},\"from\":{},\"to\":{},\"body\":{}}}}}",
+            kind,
+            string(index),
+            expr_to_json(from),
+            expr_to_json(to),
+            expr_to_json(body)
+        ),
+        ExprKind::Solve { equation, var } => format!(
+            "{{\"Solve\":{{\"equation\":{},\"var\":{}}}}}",
+            expr_to_json(equation),
+            string(var)
+        ),
+    };
+    format!("{{\"kind\":{},\"span\":{}}}", kind, span(e.span))
+}
+
+/// `{"result":5,"type":"number"}`, as printed by `--call --json`.
+pub fn result_to_json(v: &Value) -> String {
+    let (result, ty) = result_and_type(v);
+    format!("{{\"result\":{},\"type\":\"{}\"}}", result, ty)
+}
+
+// A sequence's result is the array of its items' results.
+fn result_and_type(v: &Value) -> (String, &'static str) {
+    match v {
+        Value::Number(n) if n.is_finite() => (n.to_string(), "number"),
+        Value::Number(_) => ("null".to_string(), "number"),
+        Value::AlgRef(name) => (string(&format!("@{}", name)), "algorithm"),
+        Value::Int(n) => (n.to_string(), "number"),
+        Value::Complex(z) => (format!("[{},{}]", number(z.re), number(z.im)), "complex"),
+        Value::Bool(b) => (b.to_string(), "bool"),
+        Value::Str(s) => (string(s), "string"),
+        Value::Interval(lo, hi) => (format!("[{},{}]", number(*lo), number(*hi)), "interval"),
+        Value::Unknown => ("null".to_string(), "unknown"),
+        Value::Closure(_) => (string(&v.to_string()), "function"),
+        Value::List(items) => {
+            let items: Vec<String> = items.iter().map(|v| result_and_type(v).0).collect();
+            (format!("[{}]", items.join(",")), "list")
+        }
+        Value::Tuple(items) => {
+            let items: Vec<String> = items.iter().map(|v| result_and_type(v).0).collect();
+            (format!("[{}]", items.join(",")), "tuple")
+        }
+    }
+}
+
+fn exprs(es: &[Expr]) -> String {
+    let items: Vec<String> = es.iter().map(expr_to_json).collect();
+    format!("[{}]", items.join(","))
+}
+
+fn span(s: Span) -> String {
+    format!("{{\"start\":{},\"end\":{}}}", s.start, s.end)
+}
+
+/// JSON has no NaN or infinity; like `serde_json`, write them as null.
+pub fn number(n: f64) -> String {
+    if n.is_finite() {
+        format!("{:?}", n)
+    } else {
+        "null".to_string()
+    }
+}
+
+pub fn string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -0,0 +1,381 @@
+use crate::ast::{AlgorithmDef, BinOp, Expr, ExprKind, SeriesKind, UnOp, pipe_args};
+use crate::eval::{pipe_value_names, substitute_exprs};
+use crate::printer::{PREC_ATOM, PREC_UNARY, bin_prec, expr_prec};
+
+// LaTeX for definitions, for pasting into documents. Case blocks become a
+// `cases` environment, `/` a `\frac`, and known builtins their usual
+// notation (`\sqrt`, `\lfloor`, `\binom`, ...).
+
+/// Each definition as a display-math block, blank-line separated.
+pub fn defs_to_latex(defs: &[AlgorithmDef]) -> String {
+    let items: Vec<String> = defs
+        .iter()
+        .map(|d| format!("\\[\n{}\n\\]\n", def_to_latex(d)))
+        .collect();
+    items.join("\n")
+}
+
+/// `\operatorname{Name}(x, y) = body`, without math delimiters.
+pub fn def_to_latex(d: &AlgorithmDef) -> String {
+    let params: Vec<String> = d.params.iter().map(|p| ident(p)).collect();
+    let mut out = format!("\\operatorname{{{}}}({}) = ", d.name, params.join(", "));
+    write_expr(&mut out, &d.body);
+    if !d.locals.is_empty() {
+        let locals: Vec<String> = d.locals.iter().map(def_to_latex).collect();
+        out.push_str(",\\quad\\text{where } ");
+        out.push_str(&locals.join(",\\; "));
+    }
+    out
+}
+
+pub fn expr_to_latex(e: &Expr) -> String {
+    let mut out = String::new();
+    write_expr(&mut out, e);
+    out
+}
+
+fn bin_symbol(op: BinOp) -> &'static str {
+    use BinOp::*;
+    match op {
+        Add => "+",
+        Sub => "-",
+        Mul => "\\cdot",
+        Div => "/",
+        Pow => "^",
+        Mod => "\\bmod",
+        Eq => "=",
+        Ne => "\\neq",
+        Lt => "<",
+        Le => "\\leq",
+        Gt => ">",
+        Ge => "\\geq",
+        Approx => "\\approx",
+        Range => "..",
+        RangeExcl => "..<",
+        And => "\\land",
+        Or => "\\lor",
+    }
+}
+
+// A fraction, like a pipeline (set as nested calls), is never wrapped; a
+// series, whose body has no closing bracket, always is.
+fn prec(e: &Expr) -> u8 {
+    match &e.kind {
+        ExprKind::Bin { op: BinOp::Div, .. } | ExprKind::Pipe { .. } => PREC_ATOM,
+        ExprKind::Series { .. } => 0,
+        _ => expr_prec(e),
+    }
+}
+
+fn write_expr(out: &mut String, e: &Expr) {
+    match &e.kind {
+        ExprKind::Number(v) => out.push_str(&v.to_string()),
+        ExprKind::Imag(v) => out.push_str(&format!("{}i", v)),
+        ExprKind::Bool(b) => out.push_str(&format!("\\text{{{}}}", b)),
+        ExprKind::Str(s) => out.push_str(&format!("\\text{{``{}''}}", escape_text(s))),
+        ExprKind::Ident(name) => out.push_str(&ident(name)),
+        ExprKind::AlgRef(name) => out.push_str(&format!("\\operatorname{{{}}}", name)),
+        ExprKind::Call { is_alg, name, args } => write_call(out, *is_alg, name, args),
+        ExprKind::Unary { op, expr } => {
+            out.push_str(match op {
+                UnOp::Neg => "-",
+                UnOp::Not => "\\lnot ",
+            });
+            write_operand(out, expr, prec(expr) < PREC_UNARY);
+        }
+        ExprKind::Bin {
+            op: BinOp::Div,
+            left,
+            right,
+        } => {
+            out.push_str("\\frac{");
+            write_expr(out, left);
+            out.push_str("}{");
+            write_expr(out, right);
+            out.push('}');
+        }
+        ExprKind::Bin {
+            op: BinOp::Pow,
+            left,
+            right,
+        } => {
+            write_operand(out, left, prec(left) < PREC_ATOM);
+            out.push_str("^{");
+            write_expr(out, right);
+            out.push('}');
+        }
+        ExprKind::Bin { op, left, right } => {
+            let p = bin_prec(*op);
+            let (lp, rp) = (prec(left), prec(right));
+            // same grouping rules as the source printer
+            let (wrap_l, wrap_r) = if p == 4 {
+                (lp <= p, rp <= p)
+            } else {
+                (lp < p, rp <= p)
+            };
+            write_operand(out, left, wrap_l);
+            out.push(' ');
+            out.push_str(bin_symbol(*op));
+            out.push(' ');
+            write_operand(out, right, wrap_r);
+        }
+        ExprKind::Case { arms, default } => {
+            out.push_str("\\begin{cases}\n");
+            for (c, r) in arms {
+                out.push_str("  ");
+                write_expr(out, r);
+                out.push_str(" & \\text{if } ");
+                write_expr(out, c);
+                out.push_str(" \\\\\n");
+            }
+            if let Some(default) = default {
+                out.push_str("  ");
+                write_expr(out, default);
+                out.push_str(" & \\text{otherwise}\n");
+            }
+            out.push_str("\\end{cases}");
+        }
+        // a list of lists is a matrix, set row by row
+        ExprKind::List(items) if is_matrix_literal(items) => {
+            out.push_str("\\begin{pmatrix}\n");
+            for (i, row) in items.iter().enumerate() {
+                let ExprKind::List(cells) = &row.kind else {
+                    unreachable!("matrix rows are lists")
+                };
+                if i > 0 {
+                    out.push_str(" \\\\\n");
+                }
+                for (j, cell) in cells.iter().enumerate() {
+                    if j > 0 {
+                        out.push_str(" & ");
+                    }
+                    write_expr(out, cell);
+                }
+            }
+            out.push_str("\n\\end{pmatrix}");
+        }
+        ExprKind::List(items) => {
+            out.push_str("\\left[");
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_expr(out, item);
+            }
+            out.push_str("\\right]");
+        }
+        ExprKind::Tuple(items) => {
+            out.push_str("\\left(");
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_expr(out, item);
+            }
+            out.push_str("\\right)");
+        }
+        // `x >> F(a) >> G` is G(F(x, a))
+        ExprKind::Pipe { head, steps } => {
+            let mut acc = (**head).clone();
+            for s in steps {
+                let (is_alg, name, args) = match &s.kind {
+                    ExprKind::Call { is_alg, name, args } => (*is_alg, name.clone(), args.clone()),
+                    ExprKind::Ident(name) => (false, name.clone(), Vec::new()),
+                    ExprKind::AlgRef(name) => (true, name.clone(), Vec::new()),
+                    ExprKind::Lambda { params, body } if params.len() == 1 => {
+                        acc = substitute_exprs(body, params, &[acc]);
+                        continue;
+                    }
+                    ExprKind::Case { .. } => {
+                        acc = substitute_exprs(s, &pipe_value_names(), &[acc.clone(), acc]);
+                        continue;
+                    }
+                    _ => (false, String::from("?"), Vec::new()),
+                };
+                let args = pipe_args(&args, acc);
+                acc = Expr::new(ExprKind::Call { is_alg, name, args }, s.span);
+            }
+            write_expr(out, &acc);
+        }
+        ExprKind::Lambda { params, body } => {
+            let params: Vec<String> = params.iter().map(|p| ident(p)).collect();
+            match &params[..] {
+                [p] => out.push_str(p),
+                _ => out.push_str(&format!("({})", params.join(", "))),
+            }
+            out.push_str(" \\mapsto ");
+            write_expr(out, body);
+        }
+        ExprKind::Series {
+            kind,
+            index,
+            from,
+            to,
+            body,
+        } => {
+            out.push_str(match kind {
+                SeriesKind::Sum => "\\sum_{",
+                SeriesKind::Product => "\\prod_{",
+            });
+            out.push_str(&format!("{} = ", ident(index)));
+            write_expr(out, from);
+            out.push_str("}^{");
+            write_expr(out, to);
+            out.push_str("} ");
+            write_operand(out, body, prec(body) < bin_prec(BinOp::Mul));
+        }
+        ExprKind::Solve { equation, var } => {
+            let var = Expr::new(ExprKind::Ident(var.clone()), e.span);
+            write_call(out, false, "solve", &[(**equation).clone(), var]);
+        }
+    }
+}
+
+fn is_matrix_literal(items: &[Expr]) -> bool {
+    !items.is_empty() && items.iter().all(|r| matches!(r.kind, ExprKind::List(_)))
+}
+
+fn write_call(out: &mut String, is_alg: bool, name: &str, args: &[Expr]) {
+    let wrapped = |open: &str, close: &str, out: &mut String| {
+        out.push_str(open);
+        write_expr(out, &args[0]);
+        out.push_str(close);
+    };
+    match (name, args.len()) {
+        _ if is_alg => {}
+        ("sqrt", 1) => return wrapped("\\sqrt{", "}", out),
+        ("abs", 1) => return wrapped("\\left|", "\\right|", out),
+        ("floor", 1) => return wrapped("\\left\\lfloor ", " \\right\\rfloor", out),
+        ("ceil", 1) => return wrapped("\\left\\lceil ", " \\right\\rceil", out),
+        ("exp", 1) => return wrapped("e^{", "}", out),
+        ("log2", 1) => return wrapped("\\log_{2}\\left(", "\\right)", out),
+        ("log10", 1) => return wrapped("\\log_{10}\\left(", "\\right)", out),
+        ("log", 2) => {
+            out.push_str("\\log_{");
+            write_expr(out, &args[1]);
+            out.push('}');
+            return wrapped("\\left(", "\\right)", out);
+        }
+        ("factorial", 1) => {
+            write_operand(out, &args[0], prec(&args[0]) < PREC_ATOM);
+            out.push('!');
+            return;
+        }
+        ("binomial", 2) => {
+            out.push_str("\\binom{");
+            write_expr(out, &args[0]);
+            out.push_str("}{");
+            write_expr(out, &args[1]);
+            out.push('}');
+            return;
+        }
+        _ => {}
+    }
+    let command = match name {
+        "sin" | "cos" | "tan" | "ln" | "log" | "min" | "max" | "gcd" => {
+            format!("\\{}", name)
+        }
+        "asin" | "acos" | "atan" => format!("\\arc{}", &name[1..]),
+        _ => format!("\\operatorname{{{}}}", name.replace('_', "\\_")),
+    };
+    out.push_str(&command);
+    out.push_str("\\left(");
+    for (i, a) in args.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        write_expr(out, a);
+    }
+    out.push_str("\\right)");
+}
+
+fn write_operand(out: &mut String, e: &Expr, wrap: bool) {
+    if wrap {
+        out.push_str("\\left(");
+        write_expr(out, e);
+        out.push_str("\\right)");
+    } else {
+        write_expr(out, e);
+    }
+}
+
+const GREEK: &[&str] = &[
+    "alpha", "beta", "gamma", "delta", "epsilon", "zeta", "eta", "theta", "iota", "kappa",
+    "lambda", "mu", "nu", "xi", "pi", "rho", "sigma", "tau", "upsilon", "phi", "chi", "psi",
+    "omega",
+];
+
+// Greek letters written as themselves, with their commands.
+const GREEK_LETTERS: &[(char, &str)] = &[
+    ('α', "alpha"),
+    ('β', "beta"),
+    ('γ', "gamma"),
+    ('δ', "delta"),
+    ('ε', "epsilon"),
+    ('ζ', "zeta"),
+    ('η', "eta"),
+    ('θ', "theta"),
+    ('ι', "iota"),
+    ('κ', "kappa"),
+    ('λ', "lambda"),
+    ('μ', "mu"),
+    ('ν', "nu"),
+    ('ξ', "xi"),
+    ('π', "pi"),
+    ('ρ', "rho"),
+    ('σ', "sigma"),
+    ('τ', "tau"),
+    ('υ', "upsilon"),
+    ('φ', "phi"),
+    ('χ', "chi"),
+    ('ψ', "psi"),
+    ('ω', "omega"),
+    ('Γ', "Gamma"),
+    ('Δ', "Delta"),
+    ('Θ', "Theta"),
+    ('Λ', "Lambda"),
+    ('Ξ', "Xi"),
+    ('Π', "Pi"),
+    ('Σ', "Sigma"),
+    ('Φ', "Phi"),
+    ('Ψ', "Psi"),
+    ('Ω', "Omega"),
+];
+
+// Single letters stay italic, Greek names become letters, and longer
+// names are set as one italic word rather than a product of letters.
+// Greek letters in a name become their commands.
+fn ident(name: &str) -> String {
+    let mut text = String::new();
+    for ch in name.chars() {
+        match GREEK_LETTERS.iter().find(|(c, _)| *c == ch) {
+            Some((_, command)) => text.push_str(&format!("\\{}{{}}", command)),
+            None if ch == '_' => text.push_str("\\_"),
+            None => text.push(ch),
+        }
+    }
+    if name.chars().count() == 1 {
+        text.trim_end_matches("{}").to_string()
+    } else if GREEK.contains(&name) {
+        format!("\\{}", name)
+    } else {
+        format!("\\mathit{{{}}}", text)
+    }
+}
+
+fn escape_text(s: &str) -> String {
+    let mut out = String::new();
+    for ch in s.chars() {
+        match ch {
+            '\\' => out.push_str("\\textbackslash{}"),
+            '{' | '}' | '$' | '&' | '#' | '%' | '_' => {
+                out.push('\\');
+                out.push(ch);
+            }
+            '^' => out.push_str("\\^{}"),
+            '~' => out.push_str("\\~{}"),
+            c => out.push(c),
+        }
+    }
+    out
+}
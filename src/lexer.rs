@@ -1,61 +1,76 @@
-use crate::token::{TokSpan, Token, span};
+use crate::diagnostic::{Diagnostic, E_LEX};
+use crate::token::{Span, TokSpan, Token, span};
 
-fn is_ident_start(c: char) -> bool {
-    c.is_ascii_alphabetic() || c == '_'
+// Any letter starts a name, so `θ` and `Δx` are names too.
+pub(crate) fn is_ident_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
 }
-fn is_ident_continue(c: char) -> bool {
-    c.is_ascii_alphanumeric() || c == '_'
+pub(crate) fn is_ident_continue(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
 }
 
-fn peek2(bytes: &[u8], i: usize) -> Option<(char, char)> {
-    if i + 1 >= bytes.len() {
-        None
-    } else {
-        Some((bytes[i] as char, bytes[i + 1] as char))
+// The input as characters with the byte offset each starts at. Tokens
+// and comments are scanned by character and spanned in bytes, so a
+// multi-byte character is never split.
+struct Chars<'a> {
+    input: &'a str,
+    chars: Vec<(usize, char)>,
+}
+
+impl Chars<'_> {
+    fn at(&self, j: usize) -> Option<char> {
+        self.chars.get(j).map(|&(_, c)| c)
+    }
+    // the byte offset of character `j`, or the input's length past the end
+    fn pos(&self, j: usize) -> usize {
+        self.chars.get(j).map_or(self.input.len(), |&(p, _)| p)
+    }
+    fn is(&self, j: usize, f: impl Fn(char) -> bool) -> bool {
+        self.at(j).is_some_and(f)
+    }
+    fn text(&self, from: usize, to: usize) -> &str {
+        &self.input[self.pos(from)..self.pos(to)]
     }
 }
 
-fn consume_block_content(bytes: &[u8], mut i: usize) -> usize {
-    let len = bytes.len();
+fn consume_block_content(cs: &Chars, mut j: usize) -> usize {
     let mut depth = 1usize;
-    // `i` points at the first '*' in "/*", move past it
-    i += 1;
+    // `j` points at the first '*' in "/*", move past it
+    j += 1;
 
-    while i < len {
-        let c = bytes[i] as char;
-        if c == '/' && i + 1 < len && (bytes[i + 1] as char) == '*' {
+    while let Some(c) = cs.at(j) {
+        if c == '/' && cs.at(j + 1) == Some('*') {
             depth += 1;
-            i += 2;
+            j += 2;
             continue;
         }
-        if c == '*' && i + 1 < len && (bytes[i + 1] as char) == '/' {
+        if c == '*' && cs.at(j + 1) == Some('/') {
             depth -= 1;
-            i += 2;
+            j += 2;
             if depth == 0 {
                 break;
             }
             continue;
         }
-        i += 1;
+        j += 1;
     }
-    i
+    j
 }
-fn lex_string_literal(bytes: &[u8], _input: &str, start: usize, out: &mut Vec<TokSpan>) -> usize {
-    let mut i = start + 1; // skip opening quote
-    let len = bytes.len();
+
+fn lex_string_literal(cs: &Chars, start: usize, out: &mut Vec<TokSpan>) -> usize {
+    let mut j = start + 1; // skip opening quote
     let mut s = String::new();
 
-    while i < len {
-        let ch = bytes[i] as char;
-        i += 1;
+    while let Some(ch) = cs.at(j) {
+        j += 1;
 
         if ch == '"' {
-            out.push(span(Token::String(s), start, i));
-            return i;
+            out.push(span(Token::String(s), cs.pos(start), cs.pos(j)));
+            return j;
         }
 
-        if ch == '\\' && i < len {
-            i = process_escape_sequence(bytes, i, &mut s);
+        if ch == '\\' && j < cs.chars.len() {
+            j = process_escape_sequence(cs, j, &mut s);
         } else {
             s.push(ch);
         }
@@ -64,14 +79,14 @@ fn lex_string_literal(bytes: &[u8], _input: &str, start: usize, out: &mut Vec<To
     // Unterminated string
     out.push(span(
         Token::Error("unterminated string literal".into()),
-        start,
-        i,
+        cs.pos(start),
+        cs.pos(j),
     ));
-    i
+    j
 }
 
-fn process_escape_sequence(bytes: &[u8], i: usize, s: &mut String) -> usize {
-    let esc = bytes[i] as char;
+fn process_escape_sequence(cs: &Chars, j: usize, s: &mut String) -> usize {
+    let esc = cs.at(j).expect("caller checked for a character");
     match esc {
         '\\' => s.push('\\'),
         '"' => s.push('"'),
@@ -80,245 +95,223 @@ fn process_escape_sequence(bytes: &[u8], i: usize, s: &mut String) -> usize {
         'r' => s.push('\r'),
         _ => s.push(esc),
     }
-    i + 1
+    j + 1
 }
 
 pub fn lex(input: &str) -> Vec<TokSpan> {
-    let bytes = input.as_bytes();
-    let len = bytes.len();
-    let mut i: usize = 0;
+    scan(input, &mut Vec::new())
+}
+
+/// Where the comments in `input` are; `lex` drops them.
+pub fn comments(input: &str) -> Vec<Span> {
+    let mut found = Vec::new();
+    scan(input, &mut found);
+    found
+}
+
+fn scan(input: &str, comments: &mut Vec<Span>) -> Vec<TokSpan> {
+    let cs = Chars {
+        input,
+        chars: input.char_indices().collect(),
+    };
+    let mut j: usize = 0;
     let mut out: Vec<TokSpan> = Vec::new();
 
-    while i < len {
-        let b = bytes[i];
+    while let Some(ch) = cs.at(j) {
+        if ch.is_whitespace() {
+            j += 1;
+            continue;
+        }
 
-        if b.is_ascii_whitespace() {
-            i += 1;
+        // `..<` before `..`
+        if cs.input[cs.pos(j)..].starts_with("..<") {
+            out.push(span(Token::DotDotLt, cs.pos(j), cs.pos(j + 3)));
+            j += 3;
             continue;
         }
 
         // two-char operators first
-        if i + 1 < len {
-            let a = bytes[i] as char;
-            let c = bytes[i + 1] as char;
-            match (a, c) {
-                ('-', '>') => {
-                    out.push(span(Token::Arrow, i, i + 2));
-                    i += 2;
-                    continue;
-                }
-                ('>', '>') => {
-                    out.push(span(Token::DblGt, i, i + 2));
-                    i += 2;
-                    continue;
-                }
-                ('|', '|') => {
-                    out.push(span(Token::DblPipe, i, i + 2));
-                    i += 2;
-                    continue;
-                }
-                ('&', '&') => {
-                    out.push(span(Token::DblAmp, i, i + 2));
-                    i += 2;
-                    continue;
-                }
-                ('=', '=') => {
-                    out.push(span(Token::EqEq, i, i + 2));
-                    i += 2;
-                    continue;
-                }
-                ('!', '=') => {
-                    out.push(span(Token::Neq, i, i + 2));
-                    i += 2;
-                    continue;
-                }
-                ('<', '=') => {
-                    out.push(span(Token::Le, i, i + 2));
-                    i += 2;
-                    continue;
-                }
-                ('>', '=') => {
-                    out.push(span(Token::Ge, i, i + 2));
-                    i += 2;
-                    continue;
-                }
-                _ => {}
+        if let Some(next) = cs.at(j + 1) {
+            let tok = match (ch, next) {
+                ('-', '>') => Some(Token::Arrow),
+                ('>', '>') => Some(Token::DblGt),
+                ('|', '|') => Some(Token::DblPipe),
+                ('&', '&') => Some(Token::DblAmp),
+                ('=', '=') => Some(Token::EqEq),
+                ('!', '=') => Some(Token::Neq),
+                ('~', '=') => Some(Token::Approx),
+                ('.', '.') => Some(Token::DotDot),
+                ('<', '=') => Some(Token::Le),
+                ('>', '=') => Some(Token::Ge),
+                _ => None,
+            };
+            if let Some(tok) = tok {
+                out.push(span(tok, cs.pos(j), cs.pos(j + 2)));
+                j += 2;
+                continue;
             }
         }
 
         // comments
-        if let Some((a, c)) = peek2(bytes, i) {
-            if a == '/' && c == '/' {
-                // // line comment: skip until newline
-                i += 2;
-                while i < len && (bytes[i] as char) != '\n' {
-                    i += 1;
-                }
-                continue;
-            }
-            if a == '/' && c == '*' {
-                i = consume_block_content(bytes, i + 1);
-                continue;
+        if ch == '/' && cs.at(j + 1) == Some('/') {
+            // // line comment: skip until newline
+            let start = j;
+            j += 2;
+            while cs.is(j, |c| c != '\n') {
+                j += 1;
             }
+            comments.push(Span::new(cs.pos(start), cs.pos(j)));
+            continue;
+        }
+        if ch == '/' && cs.at(j + 1) == Some('*') {
+            let start = j;
+            j = consume_block_content(&cs, j + 1);
+            comments.push(Span::new(cs.pos(start), cs.pos(j)));
+            continue;
         }
 
         // single-char
-        match b as char {
-            '@' => {
-                out.push(span(Token::At, i, i + 1));
-                i += 1;
-                continue;
-            }
-            '(' => {
-                out.push(span(Token::LParen, i, i + 1));
-                i += 1;
-                continue;
-            }
-            ')' => {
-                out.push(span(Token::RParen, i, i + 1));
-                i += 1;
-                continue;
-            }
-            '[' => {
-                out.push(span(Token::LBracket, i, i + 1));
-                i += 1;
-                continue;
-            }
-            ']' => {
-                out.push(span(Token::RBracket, i, i + 1));
-                i += 1;
-                continue;
-            }
-            ',' => {
-                out.push(span(Token::Comma, i, i + 1));
-                i += 1;
-                continue;
-            }
-            ';' => {
-                out.push(span(Token::Semicolon, i, i + 1));
-                i += 1;
-                continue;
-            }
-            '_' => {
-                out.push(span(Token::Underscore, i, i + 1));
-                i += 1;
-                continue;
-            }
-            '=' => {
-                out.push(span(Token::Equal, i, i + 1));
-                i += 1;
-                continue;
-            }
-            '|' => {
-                out.push(span(Token::Pipe, i, i + 1));
-                i += 1;
-                continue;
-            }
-            '?' => {
-                out.push(span(Token::QMark, i, i + 1));
-                i += 1;
-                continue;
-            }
-            '!' => {
-                out.push(span(Token::Bang, i, i + 1));
-                i += 1;
-                continue;
-            }
-            '+' => {
-                out.push(span(Token::Plus, i, i + 1));
-                i += 1;
-                continue;
-            }
-            '-' => {
-                out.push(span(Token::Minus, i, i + 1));
-                i += 1;
-                continue;
-            }
-            '*' => {
-                out.push(span(Token::Star, i, i + 1));
-                i += 1;
-                continue;
-            }
-            '/' => {
-                out.push(span(Token::Slash, i, i + 1));
-                i += 1;
-                continue;
-            }
-            '%' => {
-                out.push(span(Token::Percent, i, i + 1));
-                i += 1;
-                continue;
-            }
-            '<' => {
-                out.push(span(Token::Lt, i, i + 1));
-                i += 1;
-                continue;
-            }
-            '>' => {
-                out.push(span(Token::Gt, i, i + 1));
-                i += 1;
-                continue;
-            }
-            '^' => {
-                out.push(span(Token::Caret, i, i + 1));
-                i += 1;
-                continue;
-            }
-            _ => {}
+        let tok = match ch {
+            '@' => Some(Token::At),
+            '(' => Some(Token::LParen),
+            ')' => Some(Token::RParen),
+            '[' => Some(Token::LBracket),
+            ']' => Some(Token::RBracket),
+            ',' => Some(Token::Comma),
+            ';' => Some(Token::Semicolon),
+            ':' => Some(Token::Colon),
+            '_' => Some(Token::Underscore),
+            '=' => Some(Token::Equal),
+            '|' => Some(Token::Pipe),
+            '?' => Some(Token::QMark),
+            '!' => Some(Token::Bang),
+            '+' => Some(Token::Plus),
+            '-' => Some(Token::Minus),
+            '*' => Some(Token::Star),
+            '/' => Some(Token::Slash),
+            '%' => Some(Token::Percent),
+            '<' => Some(Token::Lt),
+            '>' => Some(Token::Gt),
+            '^' => Some(Token::Caret),
+            '\\' => Some(Token::Backslash),
+            _ => None,
+        };
+        if let Some(tok) = tok {
+            out.push(span(tok, cs.pos(j), cs.pos(j + 1)));
+            j += 1;
+            continue;
         }
 
-        let ch = bytes[i] as char;
-
-        if (bytes[i] as char) == '"' {
-            i = lex_string_literal(bytes, input, i, &mut out);
+        if ch == '"' {
+            j = lex_string_literal(&cs, j, &mut out);
             continue;
         }
 
         // identifier / keyword
         if is_ident_start(ch) {
-            let start = i;
-            i += 1;
-            while i < len && is_ident_continue(bytes[i] as char) {
-                i += 1;
+            let start = j;
+            j += 1;
+            while cs.is(j, is_ident_continue) {
+                j += 1;
             }
-            let text = &input[start..i];
+            let text = cs.text(start, j);
             let tok = match text {
                 "true" => Token::Bool(true),
                 "false" => Token::Bool(false),
+                "if" => Token::If,
+                "then" => Token::Then,
+                "else" => Token::Else,
+                "of" => Token::Of,
+                "where" => Token::Where,
+                // word spellings of operators
+                "and" => Token::DblAmp,
+                "or" => Token::DblPipe,
+                "not" => Token::Bang,
+                "mod" => Token::Percent,
                 _ => Token::Ident(text.to_string()),
             };
-            out.push(span(tok, start, i));
+            out.push(span(tok, cs.pos(start), cs.pos(j)));
             continue;
         }
 
-        // number
-        if ch.is_ascii_digit() {
-            let start = i;
-            i += 1;
-            while i < len && (bytes[i] as char).is_ascii_digit() {
-                i += 1;
+        // `0x1F`, `0b1010` and `0o17`; `parse_number` checks the digits
+        if ch == '0'
+            && cs.is(j + 1, |c| matches!(c, 'x' | 'X' | 'b' | 'B' | 'o' | 'O'))
+            && cs.is(j + 2, |c| c.is_ascii_alphanumeric())
+        {
+            let start = j;
+            j += 2;
+            while cs.is(j, |c| c.is_ascii_alphanumeric()) {
+                j += 1;
             }
-            if i < len && (bytes[i] as char) == '.' {
-                i += 1;
-                while i < len && (bytes[i] as char).is_ascii_digit() {
-                    i += 1;
+            let text = cs.text(start, j).to_string();
+            out.push(span(Token::Number(text), cs.pos(start), cs.pos(j)));
+            continue;
+        }
+
+        // number; `.5` has no whole part
+        let digit = |c: char| c.is_ascii_digit();
+        let leading_dot = ch == '.' && cs.is(j + 1, digit);
+        if digit(ch) || leading_dot {
+            let start = j;
+            j += 1;
+            while cs.is(j, digit) {
+                j += 1;
+            }
+            // `1..n` is a range, not `1.` then `.n`
+            if !leading_dot && cs.at(j) == Some('.') && cs.at(j + 1) != Some('.') {
+                j += 1;
+                while cs.is(j, digit) {
+                    j += 1;
                 }
             }
-            let text = &input[start..i];
-            out.push(span(Token::Number(text.to_string()), start, i));
+            // an exponent needs its digits, so `2e` stays a number and a name
+            if cs.is(j, |c| matches!(c, 'e' | 'E')) {
+                let sign = usize::from(cs.is(j + 1, |c| matches!(c, '+' | '-')));
+                if cs.is(j + 1 + sign, digit) {
+                    j += 1 + sign;
+                    while cs.is(j, digit) {
+                        j += 1;
+                    }
+                }
+            }
+            // `2i` is imaginary; `2if` stays a number and a name
+            if cs.at(j) == Some('i') && !cs.is(j + 1, is_ident_continue) {
+                j += 1;
+            }
+            let text = cs.text(start, j).to_string();
+            out.push(span(Token::Number(text), cs.pos(start), cs.pos(j)));
             continue;
         }
 
         // unknown → error token
-        let start = i;
-        let bad = bytes[i] as char;
-        i += 1;
         out.push(span(
-            Token::Error(format!("unexpected character '{}'", bad)),
-            start,
-            i,
+            Token::Error(format!("unexpected character '{}'", ch)),
+            cs.pos(j),
+            cs.pos(j + 1),
         ));
+        j += 1;
     }
 
     out
 }
+
+/// Lex `input`, failing with one diagnostic per `Token::Error` so bad
+/// characters and unterminated strings are reported before parsing.
+pub fn lex_checked(input: &str) -> Result<Vec<TokSpan>, Vec<Diagnostic>> {
+    let tokens = lex(input);
+    let errors: Vec<Diagnostic> = tokens
+        .iter()
+        .filter_map(|t| match &t.tok {
+            Token::Error(msg) => {
+                Some(Diagnostic::error(E_LEX, msg.clone()).with_span(Span::new(t.start, t.end)))
+            }
+            _ => None,
+        })
+        .collect();
+    if errors.is_empty() {
+        Ok(tokens)
+    } else {
+        Err(errors)
+    }
+}
@@ -0,0 +1,48 @@
+//! The AM (algorithmic mathematics) language: lexer, parser, checker, and
+//! evaluator, plus an `Interpreter` for embedding it in other programs.
+
+pub mod ast;
+pub mod bigint;
+pub mod builtins;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod check;
+pub mod complex;
+pub mod diagnostic;
+pub mod diff;
+pub mod docs;
+pub mod equiv;
+pub mod eval;
+pub mod explain;
+pub mod format;
+pub mod interpreter;
+pub mod json;
+pub mod latex;
+pub mod lexer;
+pub mod loader;
+pub mod mathml;
+pub mod matrix;
+pub mod native;
+pub mod normalize;
+pub mod parser;
+pub mod printer;
+pub mod profile;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod rewrite;
+pub mod session;
+pub mod settings;
+pub mod simplify;
+pub mod solve;
+pub mod stats;
+pub mod token;
+pub mod types;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use ast::{AlgorithmDef, Expr, Program};
+pub use diagnostic::Diagnostic;
+pub use eval::Value;
+pub use interpreter::{Interpreter, eval_str, run_program};
+pub use lexer::lex;
+pub use parser::parse;
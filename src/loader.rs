@@ -0,0 +1,202 @@
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::ast::{AlgorithmDef, Expr, Test};
+use crate::diagnostic::{Diagnostic, E_IMPORT_CYCLE, E_IMPORT_NOT_FOUND, W_IMPORTED_SCRIPT};
+use crate::normalize::normalize_unicode_to_ascii;
+use crate::parser::parse;
+
+/// A source file and the definitions parsed from it.
+pub struct SourceFile {
+    pub path: String,
+    /// Normalized text, which diagnostic spans point into.
+    pub src: String,
+    pub defs: Vec<AlgorithmDef>,
+    /// Top-level expressions to run; always empty for an imported file.
+    pub script: Vec<Expr>,
+    /// `@test` declarations; those of an imported file are dropped too.
+    pub tests: Vec<Test>,
+    /// Warnings, and any errors found while loading the file.
+    pub diags: Vec<Diagnostic>,
+}
+
+impl SourceFile {
+    pub fn has_errors(&self) -> bool {
+        self.diags.iter().any(Diagnostic::is_error)
+    }
+}
+
+/// Read and parse each of `paths` and, depth first, every file they
+/// import. Each file is loaded once and comes after the files it imports.
+/// Names not found as given are looked up in `search` (see `find_file`).
+///
+/// Top-level expressions of imported files are dropped with a warning. A
+/// file that fails to parse is returned with its errors and no
+/// definitions; a missing or cyclic import is an error at the `import` in
+/// the importing file. Only an unreadable file fails the whole load.
+pub fn load_files(paths: &[String], search: &[PathBuf]) -> Result<Vec<SourceFile>, String> {
+    let mut loader = Loader {
+        search: search.to_vec(),
+        ..Loader::default()
+    };
+    for path in paths {
+        let path = find_file(path, Path::new(""), search).unwrap_or_else(|| PathBuf::from(path));
+        if !loader.loaded.contains(&key(&path)) {
+            loader.load(&path, true)?;
+        }
+    }
+    Ok(loader.files)
+}
+
+/// Directories to look for imports in: each of `includes`, then those
+/// listed in the `AM_PATH` environment variable.
+pub fn search_path(includes: &[String]) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = includes.iter().map(PathBuf::from).collect();
+    if let Some(am_path) = env::var_os("AM_PATH") {
+        dirs.extend(env::split_paths(&am_path).filter(|d| !d.as_os_str().is_empty()));
+    }
+    dirs
+}
+
+/// Find `name` relative to `dir`, then in each of `search` in order. A
+/// name without an extension also matches `name.am`, so `import "stats"`
+/// finds `stats.am`.
+pub fn find_file(name: &str, dir: &Path, search: &[PathBuf]) -> Option<PathBuf> {
+    let mut names = vec![PathBuf::from(name)];
+    if Path::new(name).extension().is_none() {
+        names.push(PathBuf::from(format!("{}.am", name)));
+    }
+    std::iter::once(dir)
+        .chain(search.iter().map(PathBuf::as_path))
+        .flat_map(|d| names.iter().map(move |n| d.join(n)))
+        .find(|p| p.is_file())
+}
+
+pub fn read_source(path: &str) -> Result<String, String> {
+    let src_raw =
+        fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+    Ok(normalize_unicode_to_ascii(&src_raw))
+}
+
+/// The files a run read, for `--watch` to tell when one of them changes.
+#[derive(Debug, Default)]
+pub struct Watcher {
+    paths: Vec<String>,
+    stamps: Vec<Option<SystemTime>>,
+}
+
+impl Watcher {
+    /// Run `run`, which records in its argument the paths it read, and
+    /// watch those files from now on. A run that fails without recording
+    /// any keeps the files watched before.
+    pub fn rerun<E>(
+        &mut self,
+        run: impl FnOnce(&mut Vec<String>) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let result = run(&mut self.paths);
+        self.stamps = modified_times(&self.paths);
+        result
+    }
+
+    pub fn paths(&self) -> &[String] {
+        &self.paths
+    }
+
+    /// Whether a watched file was modified, created, or removed since the
+    /// last run.
+    pub fn changed(&self) -> bool {
+        modified_times(&self.paths) != self.stamps
+    }
+}
+
+fn modified_times(paths: &[String]) -> Vec<Option<SystemTime>> {
+    paths
+        .iter()
+        .map(|p| fs::metadata(p).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+#[derive(Default)]
+struct Loader {
+    search: Vec<PathBuf>,
+    files: Vec<SourceFile>,
+    loaded: HashSet<PathBuf>,
+    // files whose imports are being loaded, outermost first, with the
+    // names they were reached by
+    stack: Vec<(PathBuf, String)>,
+}
+
+impl Loader {
+    fn load(&mut self, path: &Path, root: bool) -> Result<(), String> {
+        let name = path.to_string_lossy().into_owned();
+        let src = read_source(&name)?;
+        let (program, mut diags) = match parse(&src) {
+            Ok(parsed) => parsed,
+            Err(errors) => (Default::default(), errors),
+        };
+
+        let this = key(path);
+        self.stack.push((this.clone(), name.clone()));
+        for import in &program.imports {
+            let dir = path.parent().unwrap_or(Path::new(""));
+            let Some(target) = find_file(&import.path, dir, &self.search) else {
+                diags.push(
+                    Diagnostic::error(
+                        E_IMPORT_NOT_FOUND,
+                        format!("cannot find imported file {:?}", import.path),
+                    )
+                    .with_span(import.span),
+                );
+                continue;
+            };
+            let target_key = key(&target);
+            if let Some(at) = self.stack.iter().position(|(k, _)| *k == target_key) {
+                let mut cycle: Vec<&str> =
+                    self.stack[at..].iter().map(|(_, n)| n.as_str()).collect();
+                cycle.push(&self.stack[at].1);
+                diags.push(
+                    Diagnostic::error(
+                        E_IMPORT_CYCLE,
+                        format!("importing {:?} forms a cycle", import.path),
+                    )
+                    .with_span(import.span)
+                    .with_note(cycle.join(" -> ")),
+                );
+            } else if !self.loaded.contains(&target_key) {
+                self.load(&target, false)?;
+            }
+        }
+        self.stack.pop();
+        self.loaded.insert(this);
+
+        let mut script = program.script;
+        if !root && let Some(first) = script.first() {
+            diags.push(
+                Diagnostic::warning(
+                    W_IMPORTED_SCRIPT,
+                    "top-level expressions in an imported file are not run",
+                )
+                .with_span(first.span),
+            );
+            script.clear();
+        }
+
+        self.files.push(SourceFile {
+            path: name,
+            src,
+            defs: program.defs,
+            script,
+            tests: if root { program.tests } else { Vec::new() },
+            diags,
+        });
+        Ok(())
+    }
+}
+
+// Two paths to the same file compare equal.
+fn key(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
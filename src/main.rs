@@ -1,23 +1,18 @@
 use std::env;
 
-mod ast;
-mod error_handling;
-mod eval;
+mod cli;
+mod completion;
 mod file_processor;
-mod lexer;
-mod normalize;
-mod parser;
 mod repl;
-mod token;
 
-use file_processor::process_file;
 use repl::Repl;
 
 fn main() {
-    let args = env::args().skip(1).collect::<Vec<_>>();
+    let mut args = env::args().skip(1).collect::<Vec<_>>();
+    exit_on_error(cli::load_normalize_map(&mut args));
 
     if !args.is_empty() {
-        exit_on_error(process_file(args));
+        exit_on_error(cli::dispatch(args));
         return;
     }
 
@@ -0,0 +1,390 @@
+use crate::ast::{AlgorithmDef, BinOp, Expr, ExprKind, SeriesKind, UnOp, pipe_args};
+use crate::eval::{pipe_value_names, substitute_exprs};
+use crate::printer::{PREC_ATOM, PREC_UNARY, bin_prec, expr_prec};
+
+// Presentation MathML for definitions, for embedding in web pages. The
+// layout follows the LaTeX exporter: case blocks become a braced table,
+// `/` a fraction, and known builtins their usual notation.
+
+/// Each definition as a `<math display="block">` element, one per line.
+pub fn defs_to_mathml(defs: &[AlgorithmDef]) -> String {
+    defs.iter()
+        .map(|d| format!("<math display=\"block\">{}</math>\n", def_to_mathml(d)))
+        .collect()
+}
+
+/// `Name(x, y) = body` as an `<mrow>`, without the enclosing `<math>`.
+pub fn def_to_mathml(d: &AlgorithmDef) -> String {
+    let mut out = String::from("<mrow>");
+    out.push_str(&format!("<mi>{}</mi><mo>&#x2061;</mo><mo>(</mo>", d.name));
+    for (i, p) in d.params.iter().enumerate() {
+        if i > 0 {
+            out.push_str("<mo>,</mo>");
+        }
+        out.push_str(&ident(p));
+    }
+    out.push_str("<mo>)</mo><mo>=</mo>");
+    write_expr(&mut out, &d.body);
+    for (i, l) in d.locals.iter().enumerate() {
+        out.push_str(if i == 0 {
+            "<mtext>&#xA0;where&#xA0;</mtext>"
+        } else {
+            "<mo>;</mo>"
+        });
+        out.push_str(&def_to_mathml(l));
+    }
+    out.push_str("</mrow>");
+    out
+}
+
+pub fn expr_to_mathml(e: &Expr) -> String {
+    let mut out = String::new();
+    write_expr(&mut out, e);
+    out
+}
+
+fn bin_symbol(op: BinOp) -> &'static str {
+    use BinOp::*;
+    match op {
+        Add => "+",
+        Sub => "&#x2212;",
+        Mul => "&#x22C5;",
+        Div => "/",
+        Pow => "^",
+        Mod => "mod",
+        Eq => "=",
+        Ne => "&#x2260;",
+        Lt => "&lt;",
+        Le => "&#x2264;",
+        Gt => "&gt;",
+        Ge => "&#x2265;",
+        Approx => "&#x2248;",
+        Range => "..",
+        RangeExcl => "..&lt;",
+        And => "&#x2227;",
+        Or => "&#x2228;",
+    }
+}
+
+// A fraction, like a pipeline (set as nested calls), is never wrapped; a
+// series, whose body has no closing bracket, always is.
+fn prec(e: &Expr) -> u8 {
+    match &e.kind {
+        ExprKind::Bin { op: BinOp::Div, .. } | ExprKind::Pipe { .. } => PREC_ATOM,
+        ExprKind::Series { .. } => 0,
+        _ => expr_prec(e),
+    }
+}
+
+fn write_expr(out: &mut String, e: &Expr) {
+    match &e.kind {
+        ExprKind::Number(v) => out.push_str(&format!("<mn>{}</mn>", v)),
+        ExprKind::Imag(v) => out.push_str(&format!("<mrow><mn>{}</mn><mi>i</mi></mrow>", v)),
+        ExprKind::Bool(b) => out.push_str(&format!("<mtext>{}</mtext>", b)),
+        ExprKind::Str(s) => out.push_str(&format!("<ms>{}</ms>", escape(s))),
+        ExprKind::Ident(name) => out.push_str(&ident(name)),
+        ExprKind::AlgRef(name) => out.push_str(&format!("<mi>{}</mi>", name)),
+        ExprKind::Call { is_alg, name, args } => write_call(out, *is_alg, name, args),
+        ExprKind::Unary { op, expr } => {
+            out.push_str("<mrow>");
+            out.push_str(match op {
+                UnOp::Neg => "<mo>&#x2212;</mo>",
+                UnOp::Not => "<mo>&#xAC;</mo>",
+            });
+            write_operand(out, expr, prec(expr) < PREC_UNARY);
+            out.push_str("</mrow>");
+        }
+        ExprKind::Bin {
+            op: BinOp::Div,
+            left,
+            right,
+        } => {
+            out.push_str("<mfrac>");
+            write_row(out, left);
+            write_row(out, right);
+            out.push_str("</mfrac>");
+        }
+        ExprKind::Bin {
+            op: BinOp::Pow,
+            left,
+            right,
+        } => {
+            out.push_str("<msup>");
+            write_operand(out, left, prec(left) < PREC_ATOM);
+            write_row(out, right);
+            out.push_str("</msup>");
+        }
+        ExprKind::Bin { op, left, right } => {
+            let p = bin_prec(*op);
+            let (lp, rp) = (prec(left), prec(right));
+            // same grouping rules as the source printer
+            let (wrap_l, wrap_r) = if p == 4 {
+                (lp <= p, rp <= p)
+            } else {
+                (lp < p, rp <= p)
+            };
+            out.push_str("<mrow>");
+            write_operand(out, left, wrap_l);
+            out.push_str(&format!("<mo>{}</mo>", bin_symbol(*op)));
+            write_operand(out, right, wrap_r);
+            out.push_str("</mrow>");
+        }
+        ExprKind::Case { arms, default } => {
+            out.push_str("<mrow><mo>{</mo><mtable columnalign=\"left\">");
+            for (c, r) in arms {
+                out.push_str("<mtr><mtd>");
+                write_expr(out, r);
+                out.push_str("</mtd><mtd><mtext>if&#xA0;</mtext>");
+                write_expr(out, c);
+                out.push_str("</mtd></mtr>");
+            }
+            if let Some(default) = default {
+                out.push_str("<mtr><mtd>");
+                write_expr(out, default);
+                out.push_str("</mtd><mtd><mtext>otherwise</mtext></mtd></mtr>");
+            }
+            out.push_str("</mtable></mrow>");
+        }
+        // a list of lists is a matrix, set as a table
+        ExprKind::List(items) if is_matrix_literal(items) => {
+            out.push_str("<mrow><mo>(</mo><mtable>");
+            for row in items {
+                let ExprKind::List(cells) = &row.kind else {
+                    unreachable!("matrix rows are lists")
+                };
+                out.push_str("<mtr>");
+                for cell in cells {
+                    out.push_str("<mtd>");
+                    write_expr(out, cell);
+                    out.push_str("</mtd>");
+                }
+                out.push_str("</mtr>");
+            }
+            out.push_str("</mtable><mo>)</mo></mrow>");
+        }
+        ExprKind::List(items) => {
+            out.push_str("<mrow><mo>[</mo>");
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push_str("<mo>,</mo>");
+                }
+                write_expr(out, item);
+            }
+            out.push_str("<mo>]</mo></mrow>");
+        }
+        ExprKind::Tuple(items) => {
+            out.push_str("<mrow><mo>(</mo>");
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push_str("<mo>,</mo>");
+                }
+                write_expr(out, item);
+            }
+            out.push_str("<mo>)</mo></mrow>");
+        }
+        // `x >> F(a) >> G` is G(F(x, a))
+        ExprKind::Pipe { head, steps } => {
+            let mut acc = (**head).clone();
+            for s in steps {
+                let (is_alg, name, args) = match &s.kind {
+                    ExprKind::Call { is_alg, name, args } => (*is_alg, name.clone(), args.clone()),
+                    ExprKind::Ident(name) => (false, name.clone(), Vec::new()),
+                    ExprKind::AlgRef(name) => (true, name.clone(), Vec::new()),
+                    ExprKind::Lambda { params, body } if params.len() == 1 => {
+                        acc = substitute_exprs(body, params, &[acc]);
+                        continue;
+                    }
+                    ExprKind::Case { .. } => {
+                        acc = substitute_exprs(s, &pipe_value_names(), &[acc.clone(), acc]);
+                        continue;
+                    }
+                    _ => (false, String::from("?"), Vec::new()),
+                };
+                let args = pipe_args(&args, acc);
+                acc = Expr::new(ExprKind::Call { is_alg, name, args }, s.span);
+            }
+            write_expr(out, &acc);
+        }
+        ExprKind::Lambda { params, body } => {
+            out.push_str("<mrow>");
+            let params: Vec<String> = params.iter().map(|p| ident(p)).collect();
+            match &params[..] {
+                [p] => out.push_str(p),
+                _ => out.push_str(&format!(
+                    "<mrow><mo>(</mo>{}<mo>)</mo></mrow>",
+                    params.join("<mo>,</mo>")
+                )),
+            }
+            out.push_str("<mo>&#x21A6;</mo>");
+            write_expr(out, body);
+            out.push_str("</mrow>");
+        }
+        ExprKind::Series {
+            kind,
+            index,
+            from,
+            to,
+            body,
+        } => {
+            let symbol = match kind {
+                SeriesKind::Sum => "&#x2211;",
+                SeriesKind::Product => "&#x220F;",
+            };
+            out.push_str(&format!(
+                "<mrow><munderover><mo>{}</mo><mrow>{}<mo>=</mo>",
+                symbol,
+                ident(index)
+            ));
+            write_expr(out, from);
+            out.push_str("</mrow>");
+            write_row(out, to);
+            out.push_str("</munderover>");
+            write_operand(out, body, prec(body) < bin_prec(BinOp::Mul));
+            out.push_str("</mrow>");
+        }
+        ExprKind::Solve { equation, var } => {
+            let var = Expr::new(ExprKind::Ident(var.clone()), e.span);
+            write_call(out, false, "solve", &[(**equation).clone(), var]);
+        }
+    }
+}
+
+fn is_matrix_literal(items: &[Expr]) -> bool {
+    !items.is_empty() && items.iter().all(|r| matches!(r.kind, ExprKind::List(_)))
+}
+
+fn write_call(out: &mut String, is_alg: bool, name: &str, args: &[Expr]) {
+    let fenced = |open: &str, close: &str, out: &mut String| {
+        out.push_str(&format!("<mrow><mo>{}</mo>", open));
+        write_expr(out, &args[0]);
+        out.push_str(&format!("<mo>{}</mo></mrow>", close));
+    };
+    match (name, args.len()) {
+        _ if is_alg => {}
+        ("sqrt", 1) => {
+            out.push_str("<msqrt>");
+            write_expr(out, &args[0]);
+            out.push_str("</msqrt>");
+            return;
+        }
+        ("abs", 1) => return fenced("|", "|", out),
+        ("floor", 1) => return fenced("&#x230A;", "&#x230B;", out),
+        ("ceil", 1) => return fenced("&#x2308;", "&#x2309;", out),
+        ("exp", 1) => {
+            out.push_str("<msup><mi>e</mi>");
+            write_row(out, &args[0]);
+            out.push_str("</msup>");
+            return;
+        }
+        ("factorial", 1) => {
+            out.push_str("<mrow>");
+            write_operand(out, &args[0], prec(&args[0]) < PREC_ATOM);
+            out.push_str("<mo>!</mo></mrow>");
+            return;
+        }
+        ("binomial", 2) => {
+            out.push_str("<mrow><mo>(</mo><mfrac linethickness=\"0\">");
+            write_row(out, &args[0]);
+            write_row(out, &args[1]);
+            out.push_str("</mfrac><mo>)</mo></mrow>");
+            return;
+        }
+        _ => {}
+    }
+    out.push_str("<mrow>");
+    let args = match (name, args.len()) {
+        ("log2" | "log10", 1) => {
+            out.push_str(&format!("<msub><mi>log</mi><mn>{}</mn></msub>", &name[3..]));
+            args
+        }
+        ("log", 2) => {
+            out.push_str("<msub><mi>log</mi>");
+            write_row(out, &args[1]);
+            out.push_str("</msub>");
+            &args[..1]
+        }
+        ("asin" | "acos" | "atan", _) => {
+            out.push_str(&format!("<mi>arc{}</mi>", &name[1..]));
+            args
+        }
+        _ => {
+            out.push_str(&format!("<mi>{}</mi>", name));
+            args
+        }
+    };
+    out.push_str("<mo>&#x2061;</mo><mrow><mo>(</mo>");
+    for (i, a) in args.iter().enumerate() {
+        if i > 0 {
+            out.push_str("<mo>,</mo>");
+        }
+        write_expr(out, a);
+    }
+    out.push_str("<mo>)</mo></mrow></mrow>");
+}
+
+// A child of an element taking a fixed number of arguments must be one
+// element.
+fn write_row(out: &mut String, e: &Expr) {
+    out.push_str("<mrow>");
+    write_expr(out, e);
+    out.push_str("</mrow>");
+}
+
+fn write_operand(out: &mut String, e: &Expr, wrap: bool) {
+    if wrap {
+        out.push_str("<mrow><mo>(</mo>");
+        write_expr(out, e);
+        out.push_str("<mo>)</mo></mrow>");
+    } else {
+        write_expr(out, e);
+    }
+}
+
+const GREEK: &[(&str, char)] = &[
+    ("alpha", 'α'),
+    ("beta", 'β'),
+    ("gamma", 'γ'),
+    ("delta", 'δ'),
+    ("epsilon", 'ε'),
+    ("zeta", 'ζ'),
+    ("eta", 'η'),
+    ("theta", 'θ'),
+    ("iota", 'ι'),
+    ("kappa", 'κ'),
+    ("lambda", 'λ'),
+    ("mu", 'μ'),
+    ("nu", 'ν'),
+    ("xi", 'ξ'),
+    ("pi", 'π'),
+    ("rho", 'ρ'),
+    ("sigma", 'σ'),
+    ("tau", 'τ'),
+    ("upsilon", 'υ'),
+    ("phi", 'φ'),
+    ("chi", 'χ'),
+    ("psi", 'ψ'),
+    ("omega", 'ω'),
+];
+
+// Greek names become letters; longer names stay italic as one word.
+fn ident(name: &str) -> String {
+    match GREEK.iter().find(|(n, _)| *n == name) {
+        Some((_, letter)) => format!("<mi>{}</mi>", letter),
+        None if name.chars().count() == 1 => format!("<mi>{}</mi>", name),
+        None => format!("<mi mathvariant=\"italic\">{}</mi>", name),
+    }
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::new();
+    for ch in s.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,213 @@
+//! Vectors and matrices. A vector is a list of numbers and a matrix a
+//! non-empty list of equally long rows; both are ordinary list values.
+
+use crate::eval::Value;
+
+/// A matrix as rows of numbers.
+pub(crate) fn as_matrix(v: &Value) -> Result<Vec<Vec<f64>>, String> {
+    let rows = v.as_list()?;
+    let rows: Vec<Vec<f64>> = rows
+        .iter()
+        .map(|r| r.as_list()?.iter().map(Value::as_f64).collect())
+        .collect::<Result<_, String>>()
+        .map_err(|_| format!("expected a matrix (a list of rows), got {}", v))?;
+    let width = rows.first().map_or(0, Vec::len);
+    if width == 0 || rows.iter().any(|r| r.len() != width) {
+        return Err(format!(
+            "expected a matrix with rows of one non-zero length, got {}",
+            v
+        ));
+    }
+    Ok(rows)
+}
+
+/// A vector of numbers.
+pub(crate) fn as_vector(v: &Value) -> Result<Vec<f64>, String> {
+    v.as_list()?
+        .iter()
+        .map(Value::as_f64)
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("expected a vector of numbers, got {}", v))
+}
+
+fn is_matrix(v: &Value) -> bool {
+    matches!(v, Value::List(rows) if rows.first().is_some_and(|r| matches!(r, Value::List(_))))
+}
+
+pub(crate) fn vector_value(v: Vec<f64>) -> Value {
+    Value::List(v.into_iter().map(Value::Number).collect())
+}
+
+pub(crate) fn matrix_value(m: Vec<Vec<f64>>) -> Value {
+    Value::List(m.into_iter().map(vector_value).collect())
+}
+
+fn square(v: &Value) -> Result<Vec<Vec<f64>>, String> {
+    let m = as_matrix(v)?;
+    if m.len() != m[0].len() {
+        return Err(format!(
+            "expected a square matrix, got {}x{}",
+            m.len(),
+            m[0].len()
+        ));
+    }
+    Ok(m)
+}
+
+/// Rows and columns swapped; a vector becomes a single column.
+pub fn transpose(v: &Value) -> Result<Value, String> {
+    let m = if is_matrix(v) {
+        as_matrix(v)?
+    } else {
+        vec![as_vector(v)?]
+    };
+    let cols = (0..m[0].len())
+        .map(|j| m.iter().map(|row| row[j]).collect())
+        .collect();
+    Ok(matrix_value(cols))
+}
+
+/// The matrix product. A vector on the right is a column and one on the
+/// left a row, so two vectors give their dot product.
+pub fn matmul(a: &Value, b: &Value) -> Result<Value, String> {
+    let dot = |x: &[f64], y: &[f64]| x.iter().zip(y).map(|(p, q)| p * q).sum::<f64>();
+    let mismatch = |n: usize, m: usize| {
+        Err(format!(
+            "matmul needs the inner dimensions to agree, got {} and {}",
+            n, m
+        ))
+    };
+    match (is_matrix(a), is_matrix(b)) {
+        (false, false) => {
+            let (x, y) = (as_vector(a)?, as_vector(b)?);
+            if x.len() != y.len() {
+                return mismatch(x.len(), y.len());
+            }
+            Ok(Value::Number(dot(&x, &y)))
+        }
+        (true, false) => {
+            let (m, x) = (as_matrix(a)?, as_vector(b)?);
+            if m[0].len() != x.len() {
+                return mismatch(m[0].len(), x.len());
+            }
+            Ok(vector_value(m.iter().map(|row| dot(row, &x)).collect()))
+        }
+        (false, true) => {
+            let (x, m) = (as_vector(a)?, as_matrix(b)?);
+            if x.len() != m.len() {
+                return mismatch(x.len(), m.len());
+            }
+            let cols = (0..m[0].len()).map(|j| x.iter().zip(&m).map(|(p, row)| p * row[j]).sum());
+            Ok(vector_value(cols.collect()))
+        }
+        (true, true) => {
+            let (l, r) = (as_matrix(a)?, as_matrix(b)?);
+            if l[0].len() != r.len() {
+                return mismatch(l[0].len(), r.len());
+            }
+            let product = l
+                .iter()
+                .map(|row| {
+                    (0..r[0].len())
+                        .map(|j| row.iter().zip(&r).map(|(p, rr)| p * rr[j]).sum())
+                        .collect()
+                })
+                .collect();
+            Ok(matrix_value(product))
+        }
+    }
+}
+
+/// The determinant of a square matrix.
+pub fn det(v: &Value) -> Result<Value, String> {
+    let mut m = square(v)?;
+    let n = m.len();
+    let mut det = 1.0;
+    for col in 0..n {
+        let Some(p) = pivot(&m, col) else {
+            return Ok(Value::Number(0.0));
+        };
+        if p != col {
+            m.swap(p, col);
+            det = -det;
+        }
+        det *= m[col][col];
+        let (done, rest) = m.split_at_mut(col + 1);
+        let pivot_row = &done[col];
+        for row in rest {
+            let factor = row[col] / pivot_row[col];
+            for (x, p) in row[col..].iter_mut().zip(&pivot_row[col..]) {
+                *x -= factor * p;
+            }
+        }
+    }
+    Ok(Value::Number(det))
+}
+
+/// The inverse of a square matrix, by Gauss-Jordan elimination.
+pub fn inverse(v: &Value) -> Result<Value, String> {
+    let mut m = square(v)?;
+    let n = m.len();
+    let mut inv: Vec<Vec<f64>> = (0..n)
+        .map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect())
+        .collect();
+    for col in 0..n {
+        let p = pivot(&m, col).ok_or("matrix is singular and has no inverse")?;
+        m.swap(p, col);
+        inv.swap(p, col);
+        let scale = m[col][col];
+        for c in 0..n {
+            m[col][c] /= scale;
+            inv[col][c] /= scale;
+        }
+        for r in (0..n).filter(|&r| r != col) {
+            let factor = m[r][col];
+            for c in 0..n {
+                m[r][c] -= factor * m[col][c];
+                inv[r][c] -= factor * inv[col][c];
+            }
+        }
+    }
+    Ok(matrix_value(inv))
+}
+
+/// The `x` with `A x = b`, by Gaussian elimination with partial pivoting.
+pub fn linsolve(a: &Value, b: &Value) -> Result<Value, String> {
+    let mut m = square(a)?;
+    let mut x = as_vector(b)?;
+    let n = m.len();
+    if x.len() != n {
+        return Err(format!(
+            "linsolve needs one right-hand side per row, got {} for {} rows",
+            x.len(),
+            n
+        ));
+    }
+    for col in 0..n {
+        let p = pivot(&m, col).ok_or("linear system is singular and has no unique solution")?;
+        m.swap(p, col);
+        x.swap(p, col);
+        let (done, rest) = m.split_at_mut(col + 1);
+        let pivot_row = &done[col];
+        for (i, row) in rest.iter_mut().enumerate() {
+            let factor = row[col] / pivot_row[col];
+            for (v, p) in row[col..].iter_mut().zip(&pivot_row[col..]) {
+                *v -= factor * p;
+            }
+            x[col + 1 + i] -= factor * x[col];
+        }
+    }
+    // back substitution, from the last row up
+    for row in (0..n).rev() {
+        let known: f64 = (row + 1..n).map(|c| m[row][c] * x[c]).sum();
+        x[row] = (x[row] - known) / m[row][row];
+    }
+    Ok(vector_value(x))
+}
+
+// The row at or below `col` with the largest entry in column `col`
+// (partial pivoting), or `None` when that column is all zeros there.
+fn pivot(m: &[Vec<f64>], col: usize) -> Option<usize> {
+    let p = (col..m.len()).max_by(|&a, &b| m[a][col].abs().total_cmp(&m[b][col].abs()))?;
+    (m[p][col] != 0.0).then_some(p)
+}
@@ -0,0 +1,166 @@
+//! Typed native functions for `Interpreter::register`: a Rust closure over
+//! plain types becomes a function AM code can call, with its arity taken
+//! from the closure and its arguments and result converted through
+//! `Value`.
+//!
+//! ```
+//! use amlang::Interpreter;
+//!
+//! let mut am = Interpreter::new();
+//! am.register("lookup_price", |sku: String, qty: f64| match sku.as_str() {
+//!     "apple" => Ok(0.5 * qty),
+//!     _ => Err(format!("no price for {}", sku)),
+//! });
+//! am.load_str("@Total(n) = lookup_price(\"apple\", n) + 1").unwrap();
+//! assert_eq!(am.eval_str("@Total(4)").unwrap().to_string(), "3");
+//! assert!(am.eval_str("lookup_price(\"pear\", 1)").is_err());
+//! ```
+
+use crate::eval::Value;
+
+/// A Rust type an AM value can be converted to.
+pub trait FromValue: Sized {
+    fn from_value(v: &Value) -> Result<Self, String>;
+}
+
+/// A Rust type that converts to an AM value; `Result<T, String>` lets a
+/// native function fail with a message.
+pub trait IntoValue {
+    fn into_value(self) -> Result<Value, String>;
+}
+
+/// A closure `Fn(A, B, ...) -> R` of up to six `FromValue` arguments
+/// returning an `IntoValue`. `Args` is the tuple of argument types, which
+/// only tells the implementations apart.
+pub trait NativeFunction<Args>: Send + Sync + 'static {
+    const ARITY: usize;
+    fn call(&self, args: &[Value]) -> Result<Value, String>;
+}
+
+impl FromValue for Value {
+    fn from_value(v: &Value) -> Result<Self, String> {
+        Ok(v.clone())
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(v: &Value) -> Result<Self, String> {
+        v.as_f64()
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(v: &Value) -> Result<Self, String> {
+        let x = v.as_f64()?;
+        if x.fract() != 0.0 || x.abs() > (1u64 << 53) as f64 {
+            return Err(format!("expected an integer, got {}", v));
+        }
+        Ok(x as i64)
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(v: &Value) -> Result<Self, String> {
+        v.as_bool()
+    }
+}
+
+impl FromValue for String {
+    fn from_value(v: &Value) -> Result<Self, String> {
+        v.as_str().map(str::to_string)
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(v: &Value) -> Result<Self, String> {
+        v.as_list()?.iter().map(T::from_value).collect()
+    }
+}
+
+impl IntoValue for Value {
+    fn into_value(self) -> Result<Value, String> {
+        Ok(self)
+    }
+}
+
+impl IntoValue for f64 {
+    fn into_value(self) -> Result<Value, String> {
+        Ok(Value::Number(self))
+    }
+}
+
+impl IntoValue for i64 {
+    fn into_value(self) -> Result<Value, String> {
+        Ok(Value::Number(self as f64))
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Result<Value, String> {
+        Ok(Value::Bool(self))
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Result<Value, String> {
+        Ok(Value::Str(self))
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Result<Value, String> {
+        Ok(Value::Str(self.to_string()))
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Result<Value, String> {
+        let items = self
+            .into_iter()
+            .map(T::into_value)
+            .collect::<Result<_, _>>()?;
+        Ok(Value::List(items))
+    }
+}
+
+impl<T: IntoValue> IntoValue for Result<T, String> {
+    fn into_value(self) -> Result<Value, String> {
+        self?.into_value()
+    }
+}
+
+// Argument `i` (from 0) converted, with its position in any error.
+fn arg<T: FromValue>(args: &[Value], i: usize) -> Result<T, String> {
+    T::from_value(&args[i]).map_err(|e| format!("argument {}: {}", i + 1, e))
+}
+
+macro_rules! native_function {
+    ($($arg:ident),*) => {
+        impl<F, R, $($arg,)*> NativeFunction<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + Send + Sync + 'static,
+            R: IntoValue,
+            $($arg: FromValue,)*
+        {
+            const ARITY: usize = <[&str]>::len(&[$(stringify!($arg)),*]);
+
+            #[allow(unused_variables, unused_mut)]
+            fn call(&self, args: &[Value]) -> Result<Value, String> {
+                let mut i = 0;
+                self($({
+                    i += 1;
+                    arg::<$arg>(args, i - 1)?
+                }),*)
+                .into_value()
+            }
+        }
+    };
+}
+
+native_function!();
+native_function!(A);
+native_function!(A, B);
+native_function!(A, B, C);
+native_function!(A, B, C, D);
+native_function!(A, B, C, D, E);
+native_function!(A, B, C, D, E, G);
@@ -1,6 +1,96 @@
+use std::sync::{PoisonError, RwLock};
+
+use crate::lexer::{is_ident_continue, is_ident_start};
+
+// Mappings from a normalization file, longest first.
+static USER_MAP: RwLock<Vec<(String, String)>> = RwLock::new(Vec::new());
+
+/// Apply `entries` in every later normalization, ahead of the built-in
+/// mappings, so a group can add its own notation or override ours.
+pub fn set_user_map(mut entries: Vec<(String, String)>) {
+    entries.sort_by_key(|(from, _)| std::cmp::Reverse(from.len()));
+    *USER_MAP.write().unwrap_or_else(PoisonError::into_inner) = entries;
+}
+
+/// Read a normalization file: one `"from" = "to"` pair per line, as in
+/// TOML, with `#` comments and an optional `[normalize]` table header.
+pub fn parse_map(text: &str) -> Result<Vec<(String, String)>, String> {
+    let mut entries = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line == "[normalize]" {
+            continue;
+        }
+        let bad = || format!("line {}: expected \"from\" = \"to\", got {}", n + 1, line);
+        let (from, rest) = toml_string(line).ok_or_else(bad)?;
+        let rest = rest.trim_start().strip_prefix('=').ok_or_else(bad)?;
+        let (to, rest) = toml_string(rest.trim_start()).ok_or_else(bad)?;
+        let rest = rest.trim_start();
+        if from.is_empty() || !(rest.is_empty() || rest.starts_with('#')) {
+            return Err(bad());
+        }
+        entries.push((from, to));
+    }
+    Ok(entries)
+}
+
+// A double-quoted string at the start of `s` and the text after it.
+fn toml_string(s: &str) -> Option<(String, &str)> {
+    let mut chars = s.strip_prefix('"')?.char_indices();
+    let mut out = String::new();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((out, &s[i + 2..])),
+            '\\' => out.push(match chars.next()?.1 {
+                'n' => '\n',
+                't' => '\t',
+                'u' => {
+                    let hex: String = (0..4)
+                        .filter_map(|_| chars.next())
+                        .map(|(_, c)| c)
+                        .collect();
+                    char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?
+                }
+                c => c,
+            }),
+            c => out.push(c),
+        }
+    }
+    None
+}
+
+/// `src` with the Unicode spellings of operators replaced by their ASCII
+/// ones. Greek letters are names like any other letter, except where
+/// they are written as operators: `λ x → x` and `λ(x, y) → x` are
+/// lambdas and `Σ(k, 1, n, k)` is a sum, but in `λx`, `(λ)` or `Σ * 2`
+/// they are names.
 pub fn normalize_unicode_to_ascii(src: &str) -> String {
+    let user = USER_MAP
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
     let mut out = String::with_capacity(src.len());
-    for ch in src.chars() {
+    // the end of text already written out
+    let mut skip_to = 0;
+    for (i, ch) in src.char_indices() {
+        if i < skip_to {
+            continue;
+        }
+        if let Some((from, to)) = user
+            .iter()
+            .find(|(from, _)| src[i..].starts_with(from.as_str()))
+        {
+            out.push_str(to);
+            skip_to = i + from.len();
+            continue;
+        }
+        let rest = &src[i + ch.len_utf8()..];
+        let after_name = out.ends_with(is_ident_continue);
+        let next = rest.trim_start().chars().next();
+        let opens_call = !after_name && next == Some('(');
+        let opens_lambda = !after_name
+            && !rest.starts_with(is_ident_continue)
+            && next.is_some_and(|c| is_ident_start(c) || c == '(');
         match ch {
             '\u{00A0}' => out.push(' '),
 
@@ -32,18 +122,235 @@ pub fn normalize_unicode_to_ascii(src: &str) -> String {
                 out.push('>');
             }
             '\u{2212}' => out.push('-'),
-            '\u{00D7}' | '\u{2217}' => out.push('*'),
+            '\u{00D7}' | '\u{2217}' | '\u{00B7}' | '\u{22C5}' => out.push('*'),
             '\u{00F7}' => out.push('/'),
+            '\u{03BB}' if opens_lambda => out.push('\\'),
+            // capital sigma and pi, and the n-ary operators
+            '\u{03A3}' if opens_call => out.push_str("sum"),
+            '\u{03A0}' if opens_call => out.push_str("prod"),
+            '\u{2211}' => out.push_str("sum"),
+            '\u{220F}' => out.push_str("prod"),
 
+            // the imaginary unit, after a coefficient or on its own
+            '\u{2148}' => {
+                if !out.ends_with(|c: char| c.is_ascii_digit() || c == '.') {
+                    out.push('1');
+                }
+                out.push('i');
+            }
             '\u{221E}' => {
                 out.push_str("inf");
             }
+            '\u{2248}' => {
+                out.push('~');
+                out.push('=');
+            }
             '\u{2261}' => {
                 out.push('=');
                 out.push('=');
             }
+            // `√x` and `√(x + 1)` are square roots, `∛x` and `∜x` roots
+            '\u{221A}' | '\u{221B}' | '\u{221C}' if radicand(rest).is_some() => {
+                let (arg, len) = radicand(rest).expect("checked above");
+                let arg = normalize_unicode_to_ascii(arg);
+                match ch {
+                    '\u{221A}' => out.push_str(&format!("sqrt({})", arg)),
+                    '\u{221B}' => out.push_str(&format!("root({}, 3)", arg)),
+                    _ => out.push_str(&format!("root({}, 4)", arg)),
+                }
+                skip_to = i + ch.len_utf8() + len;
+            }
+            // `½` is `(1/2)`
+            _ if fraction(ch).is_some() => {
+                let (n, d) = fraction(ch).expect("checked above");
+                out.push_str(&format!("({}/{})", n, d));
+            }
+            // `x²³` is `x^23` and `x⁻¹` is `x^(-1)`
+            _ if superscript(ch).is_some() => {
+                let run: String = src[i..].chars().map_while(superscript).collect();
+                skip_to = i + src[i..]
+                    .chars()
+                    .take_while(|&c| superscript(c).is_some())
+                    .map(char::len_utf8)
+                    .sum::<usize>();
+                match run.strip_prefix('-') {
+                    Some(_) => out.push_str(&format!("^({})", run)),
+                    None => out.push_str(&format!("^{}", run.trim_start_matches('+'))),
+                }
+            }
             _ => out.push(ch),
         }
     }
     out
 }
+
+// What a root sign applies to at the start of `rest`: a parenthesized
+// expression, or else a name or number. Returns it with the length of
+// `rest` it takes up.
+fn radicand(rest: &str) -> Option<(&str, usize)> {
+    let text = rest.trim_start();
+    let skipped = rest.len() - text.len();
+    if text.starts_with('(') {
+        let mut depth = 0;
+        for (i, c) in text.char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                return Some((&text[1..i], skipped + i + 1));
+            }
+        }
+        return None;
+    }
+    let len = text
+        .find(|c: char| !(is_ident_continue(c) || c == '.'))
+        .unwrap_or(text.len());
+    (len > 0).then(|| (&text[..len], skipped + len))
+}
+
+// The numerator and denominator of a vulgar fraction.
+fn fraction(c: char) -> Option<(u8, u8)> {
+    Some(match c {
+        '½' => (1, 2),
+        '⅓' => (1, 3),
+        '⅔' => (2, 3),
+        '¼' => (1, 4),
+        '¾' => (3, 4),
+        '⅕' => (1, 5),
+        '⅖' => (2, 5),
+        '⅗' => (3, 5),
+        '⅘' => (4, 5),
+        '⅙' => (1, 6),
+        '⅚' => (5, 6),
+        '⅛' => (1, 8),
+        '⅜' => (3, 8),
+        '⅝' => (5, 8),
+        '⅞' => (7, 8),
+        _ => return None,
+    })
+}
+
+// The character a superscript digit or sign stands for.
+fn superscript(c: char) -> Option<char> {
+    match c {
+        '⁰' => Some('0'),
+        '¹' => Some('1'),
+        '²' => Some('2'),
+        '³' => Some('3'),
+        '⁴'..='⁹' => char::from_digit(c as u32 - '⁰' as u32, 10),
+        '⁻' => Some('-'),
+        '⁺' => Some('+'),
+        _ => None,
+    }
+}
+
+// ASCII spellings and the glyphs `denormalize` writes for them, longest
+// first so `<=` is not read as `<`.
+const GLYPHS: &[(&str, &str)] = &[
+    ("<=", "≤"),
+    (">=", "≥"),
+    ("!=", "≠"),
+    ("==", "≡"),
+    ("~=", "≈"),
+    ("->", "→"),
+    ("&&", "∧"),
+    ("||", "∨"),
+    ("!", "¬"),
+    ("*", "×"),
+    ("\\", "λ"),
+];
+
+// Names written as symbols when they stand alone.
+const NAME_GLYPHS: &[(&str, &str)] = &[("inf", "∞"), ("pi", "π"), ("tau", "τ"), ("phi", "φ")];
+
+/// The inverse of `normalize_unicode_to_ascii`, for display: `src` with
+/// operators written as math glyphs (`≤`, `≠`, `→`, `×`, `∞`, ...) and
+/// whole-number powers as superscripts. String literals and comments are
+/// left as they are.
+pub fn denormalize(src: &str) -> String {
+    let mut out = String::with_capacity(src.len());
+    let mut rest = src;
+    while let Some(ch) = rest.chars().next() {
+        // strings and comments are copied whole
+        let verbatim = match ch {
+            '"' => Some(string_len(rest)),
+            '/' if rest.starts_with("//") => Some(rest.find('\n').unwrap_or(rest.len())),
+            '/' if rest.starts_with("/*") => Some(rest.find("*/").map_or(rest.len(), |i| i + 2)),
+            _ => None,
+        };
+        if let Some(len) = verbatim {
+            out.push_str(&rest[..len]);
+            rest = &rest[len..];
+            continue;
+        }
+        if let Some((from, to)) = GLYPHS.iter().find(|(from, _)| rest.starts_with(from)) {
+            out.push_str(to);
+            rest = &rest[from.len()..];
+            // `λx` would read back as a name
+            if *from == "\\" && rest.starts_with(is_ident_continue) {
+                out.push(' ');
+            }
+            continue;
+        }
+        // `x ^ 2` is `x²`, when the exponent is just digits and is not
+        // itself raised to a power
+        if let Some((digits, after)) = superscript_power(rest) {
+            out.extend(digits.chars().map(to_superscript));
+            rest = after;
+            continue;
+        }
+        if is_ident_start(ch) && !out.ends_with(is_ident_continue) {
+            let len = rest
+                .find(|c: char| !is_ident_continue(c))
+                .unwrap_or(rest.len());
+            let word = &rest[..len];
+            match NAME_GLYPHS.iter().find(|(name, _)| *name == word) {
+                Some((_, glyph)) => out.push_str(glyph),
+                None => out.push_str(word),
+            }
+            rest = &rest[len..];
+            continue;
+        }
+        out.push(ch);
+        rest = &rest[ch.len_utf8()..];
+    }
+    out
+}
+
+// The digits of a power written at the start of `s` and the text after it.
+fn superscript_power(s: &str) -> Option<(&str, &str)> {
+    let exponent = s.trim_start_matches(' ').strip_prefix('^')?;
+    let exponent = exponent.trim_start_matches(' ');
+    let len = exponent
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(exponent.len());
+    let after = &exponent[len..];
+    let continues = after.starts_with(|c: char| c == '.' || is_ident_continue(c))
+        || after.trim_start().starts_with('^');
+    (len > 0 && !continues).then(|| (&exponent[..len], after))
+}
+
+// The length of the string literal at the start of `s`, quotes included.
+fn string_len(s: &str) -> usize {
+    let mut escaped = false;
+    for (i, c) in s.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return i + 1,
+            _ => {}
+        }
+    }
+    s.len()
+}
+
+fn to_superscript(digit: char) -> char {
+    match digit {
+        '1' => '¹',
+        '2' => '²',
+        '3' => '³',
+        _ => char::from_u32('⁰' as u32 + digit.to_digit(10).unwrap_or(0)).unwrap_or(digit),
+    }
+}
@@ -1,19 +1,53 @@
-use crate::ast::{AlgorithmDef, BinOp, Expr, UnOp};
-use crate::token::{TokSpan, Token, caret_message};
+use crate::ast::{AlgorithmDef, BinOp, Expr, ExprKind, Import, Program, SeriesKind, Test, UnOp};
+use crate::diagnostic::{
+    Diagnostic, E_BAD_CALLEE, E_BAD_NUMBER, E_EXPECTED_TOKEN, E_UNEXPECTED_TOKEN, W_UNREACHABLE_ARM,
+};
+use crate::lexer::{comments, lex_checked};
+use crate::token::{Span, TokSpan, Token};
 
-pub struct Tokens<'a> {
+type PResult<T> = Result<T, Diagnostic>;
+
+pub struct Tokens {
     items: Vec<TokSpan>,
     pos: usize,
-    src: &'a str, // NEW: keep the source for caret messages
+    // errors recovered from without abandoning the enclosing construct
+    errors: Vec<Diagnostic>,
+    warnings: Vec<Diagnostic>,
+    // whether each token is the first on its source line; empty when the
+    // tokens did not come from a whole program
+    line_starts: Vec<bool>,
 }
 
-impl<'a> Tokens<'a> {
-    pub fn new_with_src(items: Vec<TokSpan>, src: &'a str) -> Self {
-        Self { items, pos: 0, src }
+impl Tokens {
+    pub fn new(items: Vec<TokSpan>) -> Self {
+        Self {
+            items,
+            pos: 0,
+            errors: Vec::new(),
+            warnings: Vec::new(),
+            line_starts: Vec::new(),
+        }
+    }
+    /// Tokens lexed from `src`, knowing where its lines break.
+    fn with_lines(items: Vec<TokSpan>, src: &str) -> Self {
+        let line_starts = (0..items.len())
+            .map(|i| i > 0 && src[items[i - 1].end..items[i].start].contains('\n'))
+            .collect();
+        Self {
+            line_starts,
+            ..Self::new(items)
+        }
+    }
+    /// Warnings noticed while parsing so far; they never fail a parse.
+    pub fn take_warnings(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.warnings)
     }
     pub fn peek(&self) -> Option<&Token> {
         self.items.get(self.pos).map(|t| &t.tok)
     }
+    fn peek_at(&self, ahead: usize) -> Option<&Token> {
+        self.items.get(self.pos + ahead).map(|t| &t.tok)
+    }
     fn peek_span(&self) -> Option<&TokSpan> {
         self.items.get(self.pos)
     }
@@ -34,6 +68,7 @@ impl<'a> Tokens<'a> {
             Some(t)
         }
     }
+    #[allow(clippy::collapsible_if)]
     fn eat(&mut self, want: &Token) -> bool {
         if let Some(t) = self.peek() {
             if t == want {
@@ -43,46 +78,538 @@ impl<'a> Tokens<'a> {
         }
         false
     }
-    fn expect(&mut self, want: &Token, ctx: &str) {
-        if !self.eat(want) {
-            let byte = self
-                .peek_span()
-                .map(|s| s.start)
-                .or_else(|| self.last_span().map(|s| s.end))
-                .unwrap_or(0);
+    // byte offset where the next token starts, or where the input ended
+    fn here(&self) -> usize {
+        self.peek_span()
+            .map(|s| s.start)
+            .or_else(|| self.last_span().map(|s| s.end))
+            .unwrap_or(0)
+    }
+    // span from `start` to the end of the most recently consumed token
+    fn span_from(&self, start: usize) -> Span {
+        let end = self.last_span().map(|s| s.end).unwrap_or(start);
+        Span::new(start, end.max(start))
+    }
+
+    fn expect(&mut self, want: &Token, ctx: &str) -> PResult<()> {
+        if self.eat(want) {
+            Ok(())
+        } else {
             let msg = format!("expected {:?} while parsing {}", want, ctx);
-            let pretty = caret_message(self.src, byte, &msg);
-            panic!("{}", pretty);
+            Err(self.err_here(E_EXPECTED_TOKEN, &msg))
         }
     }
 
-    fn err_here<T>(&self, msg: &str) -> T {
-        let byte = self
-            .peek_span()
-            .map(|s| s.start)
-            .or_else(|| self.last_span().map(|s| s.end))
-            .unwrap_or(0);
-        let pretty = caret_message(self.src, byte, msg);
-        panic!("{}", pretty);
+    // Fail unless every token has been used.
+    fn expect_end(&self) -> PResult<()> {
+        match self.peek() {
+            None => Ok(()),
+            Some(t) => Err(self.err_here(
+                E_UNEXPECTED_TOKEN,
+                &format!("expected the end of the input, found {:?}", t),
+            )),
+        }
+    }
+
+    fn err_here(&self, code: &'static str, msg: &str) -> Diagnostic {
+        let byte = self.here();
+        Diagnostic::error(code, msg).with_span(Span::new(byte, byte))
+    }
+
+    // Does `@ Name ( params )`, optionally marked `@memo`, start at the
+    // current token? Gives the number of tokens it spans.
+    fn def_header_len(&self) -> Option<usize> {
+        let m = if self.at_memo_marker() { 2 } else { 0 };
+        if self.peek_at(m) != Some(&Token::At)
+            || !matches!(self.peek_at(m + 1), Some(Token::Ident(_)))
+            || self.peek_at(m + 2) != Some(&Token::LParen)
+        {
+            return None;
+        }
+        let mut i = m + 3;
+        while let Some(t) = self.peek_at(i) {
+            match t {
+                Token::Ident(_) | Token::Comma => i += 1,
+                Token::RParen => return Some(i + 1),
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    // `@memo` directly before another `@`; a definition named `memo` is
+    // still `@memo(...)`.
+    fn at_memo_marker(&self) -> bool {
+        self.peek() == Some(&Token::At)
+            && matches!(self.peek_at(1), Some(Token::Ident(s)) if s == "memo")
+            && self.peek_at(2) == Some(&Token::At)
+    }
+
+    // Does `@ Name ( params ) =` start at the current token? Contract
+    // clauses may come before the `=`.
+    fn at_def_start(&self) -> bool {
+        self.def_header_len().is_some_and(|n| {
+            self.peek_at(n) == Some(&Token::Equal) || at_contract_word(self.peek_at(n))
+        })
+    }
+
+    // At the top level a header with parameters starts a (possibly broken)
+    // definition even without its `=`: a script expression has no
+    // variables to pass. `@F()` alone is a call.
+    fn at_top_level_def(&self) -> bool {
+        self.def_header_len()
+            .is_some_and(|n| n > 4 || self.peek_at(n) == Some(&Token::Equal))
+            || self.at_def_start()
+    }
+
+    // Does `@test "name"` start at the current token?
+    fn at_test(&self) -> bool {
+        self.peek() == Some(&Token::At)
+            && matches!(self.peek_at(1), Some(Token::Ident(s)) if s == "test")
+            && matches!(self.peek_at(2), Some(Token::String(_)))
+    }
+
+    // Does `import "path"` start at the current token?
+    fn at_import(&self) -> bool {
+        matches!(self.peek(), Some(Token::Ident(s)) if s == "import")
+            && matches!(self.peek_at(1), Some(Token::String(_)))
+    }
+
+    // Where the top-level statement at the current token ends: before the
+    // first line outside brackets that neither follows an unfinished line
+    // nor continues one. A definition's header may span lines, so there
+    // only line breaks after its `=` count.
+    fn statement_end(&self, def: bool) -> usize {
+        let mut depth = 0usize;
+        let mut in_body = !def;
+        let mut i = self.pos;
+        while let Some(t) = self.items.get(i).map(|t| &t.tok) {
+            if in_body
+                && i > self.pos
+                && depth == 0
+                && self.line_starts.get(i) == Some(&true)
+                && !ends_unfinished(&self.items[i - 1].tok)
+                && !continues_line(t)
+            {
+                break;
+            }
+            match t {
+                Token::LParen | Token::LBracket => depth += 1,
+                Token::RParen | Token::RBracket => depth = depth.saturating_sub(1),
+                Token::Equal if depth == 0 => in_body = true,
+                _ => {}
+            }
+            i += 1;
+        }
+        i
+    }
+
+    // Skip to the next token that can start a definition or import, moving
+    // past the one at `from` where the broken definition began.
+    fn sync_to_def(&mut self, from: usize) {
+        if self.pos == from {
+            self.next();
+        }
+        while self.peek().is_some() && !self.at_def_start() && !self.at_import() && !self.at_test()
+        {
+            self.next();
+        }
+    }
+
+    // Skip the rest of a broken case arm: stop before a `;` or `]` at the
+    // arm's own nesting level, or before the next definition.
+    fn sync_to_arm_end(&mut self) {
+        let mut depth = 0usize;
+        while let Some(t) = self.peek() {
+            match t {
+                Token::Semicolon | Token::RBracket if depth == 0 => return,
+                Token::At if self.at_def_start() => return,
+                Token::LParen | Token::LBracket => depth += 1,
+                Token::RParen | Token::RBracket => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            self.next();
+        }
+    }
+}
+
+/// Run `parse` over `ts`, returning its result together with every error
+/// recovered from along the way.
+pub fn parse_with<T>(
+    ts: &mut Tokens,
+    parse: impl FnOnce(&mut Tokens) -> PResult<T>,
+) -> Result<T, Vec<Diagnostic>> {
+    let result = parse(ts);
+    let mut errors = std::mem::take(&mut ts.errors);
+    match result {
+        Ok(v) if errors.is_empty() => Ok(v),
+        Ok(_) => Err(errors),
+        Err(d) => {
+            errors.push(d);
+            Err(errors)
+        }
+    }
+}
+
+/// `parse_with` for text that must hold exactly what `parse` reads, such
+/// as an expression given on its own; anything left over is an error.
+pub fn parse_whole<T>(
+    ts: &mut Tokens,
+    parse: impl FnOnce(&mut Tokens) -> PResult<T>,
+) -> Result<T, Vec<Diagnostic>> {
+    parse_with(ts, |ts| {
+        let v = parse(ts)?;
+        ts.expect_end()?;
+        Ok(v)
+    })
+}
+
+/// Whether a line ending in `t` is unfinished: an operator or separator
+/// still waits for what follows it.
+pub fn ends_unfinished(t: &Token) -> bool {
+    matches!(
+        t,
+        Token::Equal
+            | Token::Arrow
+            | Token::Pipe
+            | Token::QMark
+            | Token::DblPipe
+            | Token::DblAmp
+            | Token::DblGt
+            | Token::Plus
+            | Token::Minus
+            | Token::Star
+            | Token::Slash
+            | Token::Percent
+            | Token::Caret
+            | Token::EqEq
+            | Token::Neq
+            | Token::Approx
+            | Token::DotDot
+            | Token::DotDotLt
+            | Token::Le
+            | Token::Ge
+            | Token::Lt
+            | Token::Gt
+            | Token::Bang
+            | Token::Backslash
+            | Token::Comma
+            | Token::Semicolon
+            | Token::At
+            | Token::If
+            | Token::Then
+            | Token::Else
+            | Token::Of
+            | Token::Where
+    )
+}
+
+// A line starting with `t` continues the one before, since no expression
+// can start with it.
+fn continues_line(t: &Token) -> bool {
+    matches!(
+        t,
+        Token::RParen
+            | Token::RBracket
+            | Token::Comma
+            | Token::Colon
+            | Token::Equal
+            | Token::Arrow
+            | Token::Pipe
+            | Token::QMark
+            | Token::DblPipe
+            | Token::DblAmp
+            | Token::DblGt
+            | Token::Plus
+            | Token::Star
+            | Token::Slash
+            | Token::Percent
+            | Token::Caret
+            | Token::EqEq
+            | Token::Neq
+            | Token::Approx
+            | Token::DotDot
+            | Token::DotDotLt
+            | Token::Le
+            | Token::Ge
+            | Token::Lt
+            | Token::Gt
+            | Token::Then
+            | Token::Else
+            | Token::Of
+            | Token::Where
+    )
+}
+
+/* Program := { Import | AlgDef [';'] | Test [';'] | Expr [';'] }
+   A broken definition or expression is reported and skipped up to the
+   next definition, so a single pass finds the errors in every one. A
+   statement ends at a line break unless brackets are open or the line is
+   unfinished; a `;` separates statements sharing a line.
+*/
+pub fn parse_program(ts: &mut Tokens) -> Result<Program, Vec<Diagnostic>> {
+    let mut program = Program::default();
+    while ts.peek().is_some() {
+        if let Some(import) = parse_import(ts) {
+            program.imports.push(import);
+            continue;
+        }
+        let from = ts.pos;
+        let def = ts.at_top_level_def();
+        let mut sub = Tokens::new(ts.items[from..ts.statement_end(def)].to_vec());
+        let item = if sub.at_test() {
+            parse_test(&mut sub).map(|t| program.tests.push(t))
+        } else if def {
+            parse_alg_def(&mut sub).map(|d| program.defs.push(d))
+        } else {
+            parse_expr(&mut sub).map(|e| program.script.push(e))
+        };
+        ts.pos = from + sub.pos;
+        ts.errors.append(&mut sub.errors);
+        ts.warnings.append(&mut sub.warnings);
+        ts.eat(&Token::Semicolon);
+        if let Err(d) = item {
+            ts.errors.push(d);
+            ts.sync_to_def(from);
+        }
     }
+    parse_with(ts, |_| Ok(program))
 }
 
-/* AlgDef := '@' Ident '(' [Ident {',' Ident}] ')' '=' Expr */
-pub fn parse_alg_def(ts: &mut Tokens) -> AlgorithmDef {
-    ts.expect(&Token::At, "algorithm start '@'");
-    let name = parse_algorithm_name(ts);
-    ts.expect(&Token::LParen, "parameter list '('");
+/* Test := '@' 'test' String ':' Expr */
+fn parse_test(ts: &mut Tokens) -> PResult<Test> {
+    let start = ts.here();
+    ts.next();
+    ts.next();
+    let Some(Token::String(name)) = ts.next() else {
+        unreachable!("checked by at_test")
+    };
+    ts.expect(&Token::Colon, "':' after the test name")?;
+    let expr = parse_expr(ts)?;
+    Ok(Test {
+        name,
+        expr,
+        span: ts.span_from(start),
+    })
+}
+
+/* Import := 'import' String */
+fn parse_import(ts: &mut Tokens) -> Option<Import> {
+    if !ts.at_import() {
+        return None;
+    }
+    let start = ts.here();
+    ts.next();
+    let Some(Token::String(path)) = ts.next() else {
+        return None;
+    };
+    Some(Import {
+        path,
+        span: ts.span_from(start),
+    })
+}
+
+/// Lex and parse the imports, algorithm definitions, and script
+/// expressions in normalized source text, returning them with any parser warnings. Lexer errors are
+/// reported before parsing starts; parse errors are all collected rather
+/// than stopping at the first.
+pub fn parse(src: &str) -> Result<(Program, Vec<Diagnostic>), Vec<Diagnostic>> {
+    let mut ts = Tokens::with_lines(lex_checked(src)?, src);
+    let mut program = parse_program(&mut ts)?;
+    attach_docs(src, &mut program.defs);
+    Ok((program, ts.take_warnings()))
+}
+
+/// Set the `doc` of each of `defs`, parsed from `src`, from the `///`
+/// lines directly above it. A blank line or other comment in between
+/// detaches them, and a `///` after code on its line is not a doc line.
+pub fn attach_docs(src: &str, defs: &mut [AlgorithmDef]) {
+    let docs: Vec<Span> = comments(src)
+        .into_iter()
+        .filter(|c| src[c.start..c.end].starts_with("///"))
+        .filter(|c| {
+            src[..c.start]
+                .rsplit('\n')
+                .next()
+                .is_some_and(|l| l.trim().is_empty())
+        })
+        .collect();
+    // only a single line break may separate a doc line from what follows
+    let adjacent = |end: usize, next: usize| {
+        let gap = &src[end..next];
+        gap.trim().is_empty() && gap.matches('\n').count() <= 1
+    };
+    for d in defs {
+        let mut next = d.span.start;
+        let mut lines = Vec::new();
+        for c in docs.iter().rev().filter(|c| c.end <= d.span.start) {
+            if !adjacent(c.end, next) {
+                break;
+            }
+            let text = &src[c.start + 3..c.end];
+            lines.push(text.strip_prefix(' ').unwrap_or(text).trim_end());
+            next = c.start;
+        }
+        if !lines.is_empty() {
+            lines.reverse();
+            d.doc = Some(lines.join("\n"));
+        }
+    }
+}
+
+/* AlgDef := ['@memo'] '@' Ident '(' [Ident {',' Ident}] ')' {Contract} '=' Expr [Where]
+   Contract := ('requires' | 'ensures') Expr
+   Where := 'where' Local {';' Local}   Local := Ident '(' [Ident {',' Ident}] ')' '=' Expr
+*/
+pub fn parse_alg_def(ts: &mut Tokens) -> PResult<AlgorithmDef> {
+    let start = ts.here();
+    let memo = ts.at_memo_marker();
+    if memo {
+        ts.next();
+        ts.next();
+    }
+    ts.expect(&Token::At, "algorithm start '@'")?;
+    let name = parse_algorithm_name(ts)?;
+    ts.expect(&Token::LParen, "parameter list '('")?;
+    let params = parse_parameter_list(ts);
+    ts.expect(&Token::RParen, "parameter list ')'")?;
+    let span = ts.span_from(start);
+    let (requires, ensures) = parse_contracts(ts)?;
+    ts.expect(&Token::Equal, "definition '='")?;
+    let body = parse_expr(ts)?;
+    let locals = if ts.eat(&Token::Where) {
+        parse_locals(ts)?
+    } else {
+        Vec::new()
+    };
+    Ok(AlgorithmDef {
+        name,
+        params,
+        body,
+        memo,
+        span,
+        locals,
+        requires,
+        ensures,
+        doc: None,
+    })
+}
+
+fn at_contract_word(t: Option<&Token>) -> bool {
+    matches!(t, Some(Token::Ident(w)) if w == "requires" || w == "ensures")
+}
+
+// The `requires` and `ensures` conditions before a definition's `=`.
+fn parse_contracts(ts: &mut Tokens) -> PResult<(Vec<Expr>, Vec<Expr>)> {
+    let (mut requires, mut ensures) = (Vec::new(), Vec::new());
+    while at_contract_word(ts.peek()) {
+        let Some(Token::Ident(word)) = ts.next() else {
+            unreachable!("checked for a contract word")
+        };
+        let cond = parse_contract_condition(ts)?;
+        if word == "requires" {
+            requires.push(cond);
+        } else {
+            ensures.push(cond);
+        }
+    }
+    Ok((requires, ensures))
+}
+
+// A condition runs up to the next contract word or the definition's `=`,
+// so equality inside one is written `==` or kept in parentheses.
+fn parse_contract_condition(ts: &mut Tokens) -> PResult<Expr> {
+    let mut end = ts.pos;
+    let mut depth = 0usize;
+    while let Some(t) = ts.items.get(end).map(|t| &t.tok) {
+        match t {
+            Token::Equal if depth == 0 => break,
+            t if depth == 0 && at_contract_word(Some(t)) => break,
+            Token::LParen | Token::LBracket => depth += 1,
+            Token::RParen | Token::RBracket => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        end += 1;
+    }
+    if end == ts.pos {
+        return Err(ts.err_here(
+            E_EXPECTED_TOKEN,
+            "expected a condition after the contract word",
+        ));
+    }
+    let mut sub = Tokens::new(ts.items[ts.pos..end].to_vec());
+    let cond = parse_expr(&mut sub);
+    ts.errors.append(&mut sub.errors);
+    ts.warnings.append(&mut sub.warnings);
+    let cond = cond?;
+    if sub.peek().is_some() {
+        return Err(sub.err_here(E_UNEXPECTED_TOKEN, "unexpected token in contract condition"));
+    }
+    ts.pos = end;
+    Ok(cond)
+}
+
+// The helpers after `where`. A `;` followed by anything but another
+// helper header is left to end the definition.
+fn parse_locals(ts: &mut Tokens) -> PResult<Vec<AlgorithmDef>> {
+    let mut locals = vec![parse_local(ts)?];
+    while ts.peek() == Some(&Token::Semicolon) && at_local_header(ts, 1) {
+        ts.next();
+        locals.push(parse_local(ts)?);
+    }
+    Ok(locals)
+}
+
+fn parse_local(ts: &mut Tokens) -> PResult<AlgorithmDef> {
+    let start = ts.here();
+    let Some(Token::Ident(name)) = ts.peek().cloned() else {
+        return Err(ts.err_here(E_EXPECTED_TOKEN, "expected a helper name after 'where'"));
+    };
+    ts.next();
+    ts.expect(&Token::LParen, "helper parameter list '('")?;
     let params = parse_parameter_list(ts);
-    ts.expect(&Token::RParen, "parameter list ')'");
-    ts.expect(&Token::Equal, "definition '='");
-    let body = parse_expr(ts);
-    AlgorithmDef { name, params, body }
+    ts.expect(&Token::RParen, "helper parameter list ')'")?;
+    let span = ts.span_from(start);
+    ts.expect(&Token::Equal, "helper definition '='")?;
+    let body = parse_expr(ts)?;
+    Ok(AlgorithmDef {
+        name,
+        params,
+        body,
+        memo: false,
+        span,
+        locals: Vec::new(),
+        requires: Vec::new(),
+        ensures: Vec::new(),
+        doc: None,
+    })
 }
 
-fn parse_algorithm_name(ts: &mut Tokens) -> String {
-    match ts.next() {
-        Some(Token::Ident(s)) => s,
-        other => ts.err_here(&format!("expected identifier after '@', got {:?}", other)),
+// `name(a, b) =` starting `offset` tokens ahead.
+fn at_local_header(ts: &Tokens, offset: usize) -> bool {
+    if !matches!(ts.peek_at(offset), Some(Token::Ident(_)))
+        || ts.peek_at(offset + 1) != Some(&Token::LParen)
+    {
+        return false;
+    }
+    let mut i = offset + 2;
+    while let Some(Token::Ident(_) | Token::Comma) = ts.peek_at(i) {
+        i += 1;
+    }
+    ts.peek_at(i) == Some(&Token::RParen) && ts.peek_at(i + 1) == Some(&Token::Equal)
+}
+
+fn parse_algorithm_name(ts: &mut Tokens) -> PResult<String> {
+    match ts.peek() {
+        Some(Token::Ident(s)) => {
+            let s = s.clone();
+            ts.next();
+            Ok(s)
+        }
+        other => Err(ts.err_here(
+            E_EXPECTED_TOKEN,
+            &format!("expected identifier after '@', got {:?}", other),
+        )),
     }
 }
 
@@ -103,27 +630,220 @@ fn parse_parameter_list(ts: &mut Tokens) -> Vec<String> {
 }
 
 /* Expr := Case | Pipe
-   Pipe := Or { '>>' Or }       // left-assoc into Expr::Pipe
+   Pipe := Or { '>>' (Or | Case) }    // left-assoc into Expr::Pipe
    Case := '[' Arm {';' Arm} ']'   Arm := Cond '?' Expr | '_' '?' Expr
+
+The `_` default is optional; a case without one fails at runtime when no
+condition holds.
 */
-pub fn parse_expr(ts: &mut Tokens) -> Expr {
+pub fn parse_expr(ts: &mut Tokens) -> PResult<Expr> {
     // Case has the lowest precedence; check for it explicitly
-    if let Some(Token::LBracket) = ts.peek() {
+    if at_case(ts) {
         return parse_case(ts);
     }
-    parse_pipe(ts)
+    let e = parse_pipe(ts)?;
+    if ts.eat(&Token::Of) {
+        return parse_match(ts, e);
+    }
+    Ok(e)
 }
 
-fn parse_case(ts: &mut Tokens) -> Expr {
-    ts.expect(&Token::LBracket, "case '['");
+/* Match := Expr 'of' '[' Pattern ('->' | '?') Expr {';' Pattern ('->' | '?') Expr} ']'
+Pattern := '_' | ('<' | '<=' | '>' | '>=' | '!=') Or | Or
+
+A pattern that is a range `a..b` or `a..<b` matches the values in it and
+any other matches its own value. The match becomes a case block on the
+subject; one that is not a plain name is piped in as `_`. */
+fn parse_match(ts: &mut Tokens, subject: Expr) -> PResult<Expr> {
+    let start = subject.span.start;
+    let named = matches!(subject.kind, ExprKind::Ident(_));
+    let scrutinee = if named {
+        subject.clone()
+    } else {
+        Expr::new(ExprKind::Ident("_".to_string()), subject.span)
+    };
+    ts.expect(&Token::LBracket, "'[' after 'of'")?;
+    let mut arms: Vec<(Expr, Expr)> = Vec::new();
+    let mut default: Option<Expr> = None;
+    loop {
+        if at_default_arm(ts) {
+            ts.next();
+            default = Some(parse_default_arm(ts)?);
+        } else {
+            let cond = parse_pattern(ts, &scrutinee)?;
+            if !(ts.eat(&Token::Arrow) || ts.eat(&Token::QMark)) {
+                return Err(ts.err_here(
+                    E_EXPECTED_TOKEN,
+                    "expected '->' or '?' after pattern in match arm",
+                ));
+            }
+            arms.push((cond, parse_expr(ts)?));
+        }
+        if !ts.eat(&Token::Semicolon) {
+            break;
+        }
+    }
+    ts.expect(&Token::RBracket, "closing ']' of match")?;
+    let span = ts.span_from(start);
+    let case = Expr::new(
+        ExprKind::Case {
+            arms,
+            default: default.map(Box::new),
+        },
+        span,
+    );
+    if named {
+        return Ok(case);
+    }
+    Ok(Expr::new(
+        ExprKind::Pipe {
+            head: Box::new(subject),
+            steps: vec![case],
+        },
+        span,
+    ))
+}
+
+// The condition under which `subject` matches the pattern that follows.
+fn parse_pattern(ts: &mut Tokens, subject: &Expr) -> PResult<Expr> {
+    let compare = |op, left: &Expr, right: Expr| {
+        let span = right.span;
+        Expr::new(
+            ExprKind::Bin {
+                op,
+                left: Box::new(left.clone()),
+                right: Box::new(right),
+            },
+            span,
+        )
+    };
+    let op = match ts.peek() {
+        Some(Token::Lt) => Some(BinOp::Lt),
+        Some(Token::Le) => Some(BinOp::Le),
+        Some(Token::Gt) => Some(BinOp::Gt),
+        Some(Token::Ge) => Some(BinOp::Ge),
+        Some(Token::Neq) => Some(BinOp::Ne),
+        _ => None,
+    };
+    if let Some(op) = op {
+        ts.next();
+        return Ok(compare(op, subject, parse_or(ts)?));
+    }
+    let value = parse_or(ts)?;
+    Ok(match value.kind {
+        ExprKind::Bin {
+            op: op @ (BinOp::Range | BinOp::RangeExcl),
+            left,
+            right,
+        } => {
+            let upper = if let BinOp::Range = op {
+                BinOp::Le
+            } else {
+                BinOp::Lt
+            };
+            let low = compare(BinOp::Ge, subject, *left);
+            let high = compare(upper, subject, *right);
+            compare(BinOp::And, &low, high)
+        }
+        _ => compare(BinOp::Eq, subject, value),
+    })
+}
+
+// A `[` opens a case block when an arm's `?` or `->` follows at its own
+// level before the closing `]`, and a list otherwise. A lambda's `->` is
+// not an arm's.
+fn at_case(ts: &Tokens) -> bool {
+    if ts.peek() != Some(&Token::LBracket) {
+        return false;
+    }
+    let mut depth = 0usize;
+    let mut lambda = false;
+    for ahead in 0.. {
+        match ts.peek_at(ahead) {
+            None => return true,
+            Some(Token::LParen | Token::LBracket) => depth += 1,
+            Some(Token::RParen | Token::RBracket) => {
+                depth -= 1;
+                if depth == 0 {
+                    return false;
+                }
+            }
+            Some(_) if depth > 1 => {}
+            Some(Token::Backslash) => lambda = true,
+            Some(Token::Arrow) if lambda => lambda = false,
+            Some(Token::QMark | Token::Arrow | Token::Semicolon) => return true,
+            Some(Token::Comma) => lambda = false,
+            Some(_) => {}
+        }
+    }
+    unreachable!("the scan returns at the end of the tokens")
+}
+
+/* If := 'if' Expr 'then' Expr 'else' Expr
+
+Sugar for `[ c ? a ; _ ? b ]`. Like a lambda's body, the `else` branch
+extends as far as it can. */
+fn parse_if(ts: &mut Tokens) -> PResult<ExprKind> {
+    let cond = parse_expr(ts)?;
+    ts.expect(&Token::Then, "'then' after the condition of 'if'")?;
+    let then_e = parse_expr(ts)?;
+    ts.expect(&Token::Else, "'else' after the 'then' branch")?;
+    let else_e = parse_expr(ts)?;
+    Ok(ExprKind::Case {
+        arms: vec![(cond, then_e)],
+        default: Some(Box::new(else_e)),
+    })
+}
+
+/* List := '[' [Expr {',' Expr}] ']' */
+fn parse_list(ts: &mut Tokens) -> PResult<ExprKind> {
+    ts.expect(&Token::LBracket, "list '['")?;
+    let items = parse_argument_list(ts)?;
+    ts.expect(&Token::RBracket, "closing ']' of list")?;
+    Ok(ExprKind::List(items))
+}
+
+fn parse_case(ts: &mut Tokens) -> PResult<Expr> {
+    let start = ts.here();
+    ts.expect(&Token::LBracket, "case '['")?;
     let mut arms: Vec<(Expr, Expr)> = Vec::new();
     let mut default: Option<Expr> = None;
 
     loop {
-        if ts.eat(&Token::Underscore) {
-            default = Some(parse_default_arm(ts));
+        let arm_start = ts.here();
+        let arm = if default.is_some() {
+            // anything after the default can never be chosen; it is still
+            // parsed for errors, then dropped
+            let mut unreachable = Vec::new();
+            let arm = if at_default_arm(ts) {
+                ts.next();
+                parse_default_arm(ts).map(|_| ())
+            } else {
+                parse_conditional_arm(ts, &mut unreachable)
+            };
+            if arm.is_ok() {
+                ts.warnings.push(
+                    Diagnostic::warning(
+                        W_UNREACHABLE_ARM,
+                        "case arm after the '_' default is unreachable",
+                    )
+                    .with_span(ts.span_from(arm_start)),
+                );
+            }
+            arm
+        } else if at_default_arm(ts) {
+            ts.next();
+            parse_default_arm(ts).map(|d| default = Some(d))
         } else {
-            parse_conditional_arm(ts, &mut arms);
+            parse_conditional_arm(ts, &mut arms)
+        };
+        if let Err(d) = arm {
+            // a broken arm is skipped when the rest of the case is intact
+            ts.sync_to_arm_end();
+            match ts.peek() {
+                Some(Token::Semicolon | Token::RBracket) => ts.errors.push(d),
+                _ => return Err(d),
+            }
         }
 
         if !ts.eat(&Token::Semicolon) {
@@ -131,66 +851,96 @@ fn parse_case(ts: &mut Tokens) -> Expr {
         }
     }
 
-    ts.expect(&Token::RBracket, "closing ']'");
-    let def = default.expect("case block missing default '_' ? expr");
-    Expr::Case {
-        arms,
-        default: Box::new(def),
-    }
+    ts.expect(&Token::RBracket, "closing ']'")?;
+    let span = ts.span_from(start);
+    Ok(Expr::new(
+        ExprKind::Case {
+            arms,
+            default: default.map(Box::new),
+        },
+        span,
+    ))
 }
 
-fn parse_default_arm(ts: &mut Tokens) -> Expr {
+// `_ ?` or `_ ->`; any other `_` starts a condition on the piped value.
+fn at_default_arm(ts: &Tokens) -> bool {
+    ts.peek() == Some(&Token::Underscore)
+        && matches!(ts.peek_at(1), Some(Token::QMark | Token::Arrow))
+}
+
+fn parse_default_arm(ts: &mut Tokens) -> PResult<Expr> {
     if ts.eat(&Token::QMark) || ts.eat(&Token::Arrow) {
         parse_expr(ts)
     } else {
-        ts.err_here("expected '?' or '->' after '_' in case arm")
+        Err(ts.err_here(
+            E_EXPECTED_TOKEN,
+            "expected '?' or '->' after '_' in case arm",
+        ))
     }
 }
 
-fn parse_conditional_arm(ts: &mut Tokens, arms: &mut Vec<(Expr, Expr)>) {
-    let cond = parse_or(ts);
+fn parse_conditional_arm(ts: &mut Tokens, arms: &mut Vec<(Expr, Expr)>) -> PResult<()> {
+    let cond = parse_or(ts)?;
 
     if ts.eat(&Token::QMark) {
-        parse_question_arm(ts, arms, cond);
+        parse_question_arm(ts, arms, cond)
     } else if ts.eat(&Token::Arrow) {
-        let rhs = parse_expr(ts);
+        let rhs = parse_expr(ts)?;
         arms.push((cond, rhs));
+        Ok(())
     } else {
-        ts.err_here::<()>("expected '?' or '->' after condition in case arm");
+        Err(ts.err_here(
+            E_EXPECTED_TOKEN,
+            "expected '?' or '->' after condition in case arm",
+        ))
     }
 }
 
-fn parse_question_arm(ts: &mut Tokens, arms: &mut Vec<(Expr, Expr)>, cond: Expr) {
-    let then_e = parse_expr(ts);
+fn parse_question_arm(ts: &mut Tokens, arms: &mut Vec<(Expr, Expr)>, cond: Expr) -> PResult<()> {
+    let then_e = parse_expr(ts)?;
 
     if ts.eat(&Token::Pipe) {
         // cond ? then | else  desugars into two arms
-        let else_e = parse_expr(ts);
+        let else_e = parse_expr(ts)?;
         arms.push((cond.clone(), then_e));
-        let not_cond = Expr::Unary {
-            op: UnOp::Not,
-            expr: Box::new(cond),
-        };
+        let span = cond.span;
+        let not_cond = Expr::new(
+            ExprKind::Unary {
+                op: UnOp::Not,
+                expr: Box::new(cond),
+            },
+            span,
+        );
         arms.push((not_cond, else_e));
     } else {
         arms.push((cond, then_e));
     }
+    Ok(())
 }
 
-fn parse_pipe(ts: &mut Tokens) -> Expr {
-    let head = parse_or(ts);
+fn parse_pipe(ts: &mut Tokens) -> PResult<Expr> {
+    let start = ts.here();
+    let head = parse_or(ts)?;
     let mut steps: Vec<Expr> = Vec::new();
     while ts.eat(&Token::DblGt) {
-        let step = parse_or(ts);
+        // a case step sees the piped value as `it` or `_`
+        let step = if at_case(ts) {
+            parse_case(ts)?
+        } else {
+            parse_or(ts)?
+        };
         steps.push(step);
     }
     if steps.is_empty() {
-        head
+        Ok(head)
     } else {
-        Expr::Pipe {
-            head: Box::new(head),
-            steps,
-        }
+        Ok(Expr::new(
+            ExprKind::Pipe {
+                head: Box::new(head),
+                steps,
+            },
+            ts.span_from(start),
+        ))
     }
 }
 
@@ -198,19 +948,22 @@ fn parse_pipe(ts: &mut Tokens) -> Expr {
    Postfix here adds function calls after a primary:  name(args)  or  @Name(args)
 */
 
-fn parse_or(ts: &mut Tokens) -> Expr {
+fn parse_or(ts: &mut Tokens) -> PResult<Expr> {
     parse_binary_left_associative(ts, parse_and, &[(Token::DblPipe, BinOp::Or)])
 }
 
-fn parse_and(ts: &mut Tokens) -> Expr {
+fn parse_and(ts: &mut Tokens) -> PResult<Expr> {
     parse_binary_left_associative(ts, parse_cmp, &[(Token::DblAmp, BinOp::And)])
 }
 
-fn parse_cmp(ts: &mut Tokens) -> Expr {
-    let mut node = parse_add(ts);
+fn parse_cmp(ts: &mut Tokens) -> PResult<Expr> {
+    let mut node = parse_add(ts)?;
     let op = match ts.peek() {
         Some(Token::EqEq) | Some(Token::Equal) => Some(BinOp::Eq), // accept '=' as equality too
         Some(Token::Neq) => Some(BinOp::Ne),
+        Some(Token::Approx) => Some(BinOp::Approx),
+        Some(Token::DotDot) => Some(BinOp::Range),
+        Some(Token::DotDotLt) => Some(BinOp::RangeExcl),
         Some(Token::Le) => Some(BinOp::Le),
         Some(Token::Ge) => Some(BinOp::Ge),
         Some(Token::Lt) => Some(BinOp::Lt),
@@ -219,48 +972,48 @@ fn parse_cmp(ts: &mut Tokens) -> Expr {
     };
     if let Some(op) = op {
         ts.next();
-        let rhs = parse_add(ts);
-        node = Expr::Bin {
-            op,
-            left: Box::new(node),
-            right: Box::new(rhs),
-        };
+        let rhs = parse_add(ts)?;
+        node = make_binary_expr(op, node, rhs);
     }
-    node
+    Ok(node)
 }
 
 fn make_binary_expr(op: BinOp, left: Expr, right: Expr) -> Expr {
-    Expr::Bin {
-        op,
-        left: Box::new(left),
-        right: Box::new(right),
-    }
+    let span = left.span.to(right.span);
+    Expr::new(
+        ExprKind::Bin {
+            op,
+            left: Box::new(left),
+            right: Box::new(right),
+        },
+        span,
+    )
 }
 
 fn parse_binary_left_associative<F>(
     ts: &mut Tokens,
     next_level: F,
     operators: &[(Token, BinOp)],
-) -> Expr
+) -> PResult<Expr>
 where
-    F: Fn(&mut Tokens) -> Expr,
+    F: Fn(&mut Tokens) -> PResult<Expr>,
 {
-    let mut node = next_level(ts);
+    let mut node = next_level(ts)?;
     loop {
         let found_op = operators.iter().find(|(token, _)| ts.peek() == Some(token));
 
         if let Some((_, op)) = found_op {
             ts.next(); // consume operator
-            let rhs = next_level(ts);
+            let rhs = next_level(ts)?;
             node = make_binary_expr(*op, node, rhs);
         } else {
             break;
         }
     }
-    node
+    Ok(node)
 }
 
-fn parse_add(ts: &mut Tokens) -> Expr {
+fn parse_add(ts: &mut Tokens) -> PResult<Expr> {
     parse_binary_left_associative(
         ts,
         parse_mul,
@@ -268,7 +1021,7 @@ fn parse_add(ts: &mut Tokens) -> Expr {
     )
 }
 
-fn parse_mul(ts: &mut Tokens) -> Expr {
+fn parse_mul(ts: &mut Tokens) -> PResult<Expr> {
     parse_binary_left_associative(
         ts,
         parse_pow,
@@ -280,121 +1033,254 @@ fn parse_mul(ts: &mut Tokens) -> Expr {
     )
 }
 
-fn parse_pow(ts: &mut Tokens) -> Expr {
-    let mut node = parse_unary(ts);
+fn parse_pow(ts: &mut Tokens) -> PResult<Expr> {
+    let mut node = parse_unary(ts)?;
     if let Some(Token::Caret) = ts.peek() {
         ts.next();
-        let rhs = parse_pow(ts);
-        node = Expr::Bin {
-            op: BinOp::Pow,
-            left: Box::new(node),
-            right: Box::new(rhs),
-        }
+        let rhs = parse_pow(ts)?;
+        node = make_binary_expr(BinOp::Pow, node, rhs);
     }
-    node
+    Ok(node)
 }
 
-fn parse_unary(ts: &mut Tokens) -> Expr {
-    if ts.eat(&Token::Minus) {
-        let e = parse_unary(ts);
-        Expr::Unary {
-            op: UnOp::Neg,
-            expr: Box::new(e),
-        }
+fn parse_unary(ts: &mut Tokens) -> PResult<Expr> {
+    let start = ts.here();
+    let op = if ts.eat(&Token::Minus) {
+        UnOp::Neg
     } else if ts.eat(&Token::Bang) {
-        let e = parse_unary(ts);
-        Expr::Unary {
-            op: UnOp::Not,
-            expr: Box::new(e),
-        }
+        UnOp::Not
     } else {
-        parse_postfix(ts)
-    }
+        return parse_postfix(ts);
+    };
+    let e = parse_unary(ts)?;
+    Ok(Expr::new(
+        ExprKind::Unary {
+            op,
+            expr: Box::new(e),
+        },
+        ts.span_from(start),
+    ))
 }
 
-fn parse_postfix(ts: &mut Tokens) -> Expr {
-    let mut node = parse_primary(ts);
-    parse_function_calls(ts, &mut node);
-    node
+fn parse_postfix(ts: &mut Tokens) -> PResult<Expr> {
+    let node = parse_primary(ts)?;
+    parse_function_calls(ts, node)
 }
 
-fn parse_primary(ts: &mut Tokens) -> Expr {
-    match ts.next() {
-        Some(Token::Number(s)) => parse_number(ts, &s),
-        Some(Token::Bool(b)) => Expr::Bool(b),
-        Some(Token::Ident(s)) => Expr::Ident(s),
-        Some(Token::At) => parse_algorithm_call(ts),
-        Some(Token::LParen) => parse_parenthesized(ts),
-        other => ts.err_here(&format!("unexpected token in expression: {:?}", other)),
+fn parse_primary(ts: &mut Tokens) -> PResult<Expr> {
+    let start = ts.here();
+    let kind = match ts.peek().cloned() {
+        Some(Token::Number(s)) => {
+            ts.next();
+            parse_number(ts, &s)?
+        }
+        Some(Token::Bool(b)) => {
+            ts.next();
+            ExprKind::Bool(b)
+        }
+        Some(Token::String(s)) => {
+            ts.next();
+            ExprKind::Str(s)
+        }
+        Some(Token::Ident(s)) => {
+            ts.next();
+            ExprKind::Ident(s)
+        }
+        // the piped value, in a pipeline step's arguments
+        Some(Token::Underscore) => {
+            ts.next();
+            ExprKind::Ident("_".to_string())
+        }
+        Some(Token::At) => {
+            ts.next();
+            parse_algorithm_call(ts)?
+        }
+        Some(Token::LParen) => {
+            ts.next();
+            parse_parenthesized(ts)?
+        }
+        Some(Token::Backslash) => {
+            ts.next();
+            parse_lambda(ts)?
+        }
+        Some(Token::LBracket) => parse_list(ts)?,
+        Some(Token::If) => {
+            ts.next();
+            parse_if(ts)?
+        }
+        other => {
+            return Err(ts.err_here(
+                E_UNEXPECTED_TOKEN,
+                &format!("unexpected token in expression: {:?}", other),
+            ));
+        }
+    };
+    Ok(Expr::new(kind, ts.span_from(start)))
+}
+
+fn parse_number(ts: &mut Tokens, s: &str) -> PResult<ExprKind> {
+    let radix = match s.get(..2) {
+        Some("0x" | "0X") => 16,
+        Some("0b" | "0B") => 2,
+        Some("0o" | "0O") => 8,
+        _ => 10,
+    };
+    if radix != 10 {
+        return match u64::from_str_radix(&s[2..], radix) {
+            Ok(n) => Ok(ExprKind::Number(n as f64)),
+            Err(_) => Err(ts.err_here(E_BAD_NUMBER, &format!("bad number literal: {}", s))),
+        };
+    }
+    if let Some(coef) = s.strip_suffix('i') {
+        return parse_number(ts, coef).map(|n| match n {
+            ExprKind::Number(v) => ExprKind::Imag(v),
+            other => other,
+        });
+    }
+    match s.parse::<f64>() {
+        Ok(v) => Ok(ExprKind::Number(v)),
+        Err(_) => Err(ts.err_here(E_BAD_NUMBER, &format!("bad number literal: {}", s))),
     }
 }
 
-fn parse_number(ts: &mut Tokens, s: &str) -> Expr {
-    let v: f64 = s
-        .parse()
-        .unwrap_or_else(|_| ts.err_here(&format!("bad number literal: {}", s)));
-    Expr::Number(v)
+// `@Name` alone refers to the algorithm; `parse_function_calls` turns it
+// into a call when arguments follow.
+fn parse_algorithm_call(ts: &mut Tokens) -> PResult<ExprKind> {
+    let name = parse_algorithm_name(ts)?;
+    Ok(ExprKind::AlgRef(name))
 }
 
-fn parse_algorithm_call(ts: &mut Tokens) -> Expr {
-    let name = match ts.next() {
-        Some(Token::Ident(s)) => s,
-        other => ts.err_here(&format!("expected identifier after '@', got {:?}", other)),
+/* Lambda := '\' ( Ident | '(' [Ident {',' Ident}] ')' ) '->' Expr
+
+The body extends as far as it can, so `\x -> x + 1 >> f` pipes inside
+the lambda. */
+fn parse_lambda(ts: &mut Tokens) -> PResult<ExprKind> {
+    let params = if ts.eat(&Token::LParen) {
+        let params = parse_parameter_list(ts);
+        ts.expect(&Token::RParen, "lambda parameters ')'")?;
+        params
+    } else if let Some(Token::Ident(name)) = ts.peek().cloned() {
+        ts.next();
+        vec![name]
+    } else {
+        return Err(ts.err_here(E_EXPECTED_TOKEN, "expected a parameter or '(' after '\\'"));
     };
-    Expr::Call {
-        is_alg: true,
-        name,
-        args: Vec::new(),
-    }
+    ts.expect(&Token::Arrow, "lambda '->'")?;
+    let body = parse_expr(ts)?;
+    Ok(ExprKind::Lambda {
+        params,
+        body: Box::new(body),
+    })
 }
 
-fn parse_parenthesized(ts: &mut Tokens) -> Expr {
-    let e = parse_expr(ts);
-    match ts.next() {
-        Some(Token::RParen) => e,
-        other => ts.err_here(&format!("expected ')', got {:?}", other)),
+// The parenthesized node keeps its own kind; its span grows to cover the
+// parentheses once `parse_primary` wraps it. A comma makes it a tuple.
+fn parse_parenthesized(ts: &mut Tokens) -> PResult<ExprKind> {
+    let e = parse_expr(ts)?;
+    let mut items = vec![e];
+    while ts.eat(&Token::Comma) {
+        items.push(parse_expr(ts)?);
+    }
+    match ts.peek() {
+        Some(Token::RParen) => {
+            ts.next();
+            if items.len() == 1 {
+                Ok(items.remove(0).kind)
+            } else {
+                Ok(ExprKind::Tuple(items))
+            }
+        }
+        other => Err(ts.err_here(E_EXPECTED_TOKEN, &format!("expected ')', got {:?}", other))),
     }
 }
 
-fn parse_function_calls(ts: &mut Tokens, node: &mut Expr) {
+fn parse_function_calls(ts: &mut Tokens, mut node: Expr) -> PResult<Expr> {
     while let Some(Token::LParen) = ts.peek() {
+        let paren = ts.here();
         ts.next(); // consume '('
-        let args = parse_argument_list(ts);
-        ts.expect(&Token::RParen, "closing ')' of call");
-        *node = attach_call_to_node(ts, std::mem::replace(node, Expr::Bool(false)), args);
+        let args = parse_argument_list(ts)?;
+        ts.expect(&Token::RParen, "closing ')' of call")?;
+        let start = node.span.start;
+        let kind =
+            attach_call_to_node(node, args).map_err(|d| d.with_span(Span::new(paren, paren)))?;
+        node = Expr::new(kind, ts.span_from(start));
     }
+    Ok(node)
+}
+
+fn is_solve(name: &str, args: &[Expr]) -> bool {
+    name == "solve" && args.len() == 2 && matches!(&args[1].kind, ExprKind::Ident(v) if v != "_")
+}
+
+fn is_series(name: &str, args: &[Expr]) -> bool {
+    matches!(name, "sum" | "prod")
+        && args.len() == 4
+        && matches!(&args[0].kind, ExprKind::Ident(i) if i != "_")
 }
 
-fn parse_argument_list(ts: &mut Tokens) -> Vec<Expr> {
+#[allow(clippy::collapsible_if)]
+fn parse_argument_list(ts: &mut Tokens) -> PResult<Vec<Expr>> {
     let mut args = Vec::new();
 
     if let Some(t) = ts.peek() {
-        if t != &Token::RParen {
-            args.push(parse_expr(ts));
+        if !matches!(t, Token::RParen | Token::RBracket) {
+            args.push(parse_expr(ts)?);
             while let Some(Token::Comma) = ts.peek() {
                 ts.next();
-                args.push(parse_expr(ts));
+                args.push(parse_expr(ts)?);
             }
         }
     }
 
-    args
+    Ok(args)
 }
 
-fn attach_call_to_node(ts: &mut Tokens, node: Expr, args: Vec<Expr>) -> Expr {
-    match node {
-        Expr::Ident(name) => Expr::Call {
+fn attach_call_to_node(node: Expr, args: Vec<Expr>) -> PResult<ExprKind> {
+    Ok(match node.kind {
+        // `sum(i, 1, n, body)` binds `i` in `body`; `sum(xs)` is a builtin
+        ExprKind::Ident(name) if is_series(&name, &args) => {
+            let [index, from, to, body] = <[Expr; 4]>::try_from(args).expect("four arguments");
+            let ExprKind::Ident(index) = index.kind else {
+                unreachable!("series index is a name")
+            };
+            let kind = match name.as_str() {
+                "sum" => SeriesKind::Sum,
+                _ => SeriesKind::Product,
+            };
+            ExprKind::Series {
+                kind,
+                index,
+                from: Box::new(from),
+                to: Box::new(to),
+                body: Box::new(body),
+            }
+        }
+        // `solve(eq, x)` binds `x` in `eq`
+        ExprKind::Ident(name) if is_solve(&name, &args) => {
+            let [equation, var] = <[Expr; 2]>::try_from(args).expect("two arguments");
+            let ExprKind::Ident(var) = var.kind else {
+                unreachable!("solve variable is a name")
+            };
+            ExprKind::Solve {
+                equation: Box::new(equation),
+                var,
+            }
+        }
+        ExprKind::Ident(name) => ExprKind::Call {
             is_alg: false,
             name,
             args,
         },
-        Expr::Call {
-            is_alg: true, name, ..
-        } => Expr::Call {
+        ExprKind::AlgRef(name) => ExprKind::Call {
             is_alg: true,
             name,
             args,
         },
-        other => ts.err_here(&format!("cannot call non-name expression: {:?}", other)),
-    }
+        other => {
+            return Err(Diagnostic::error(
+                E_BAD_CALLEE,
+                format!("cannot call non-name expression: {:?}", other),
+            ));
+        }
+    })
 }
@@ -0,0 +1,322 @@
+use crate::ast::{AlgorithmDef, BinOp, Expr, ExprKind, Import, Test, UnOp};
+
+// Binding strength of each syntactic level, mirroring the parser's ladder:
+// Pipe → Or → And → Cmp → Add → Mul → Pow → Unary → Postfix/Primary.
+const PREC_CASE: u8 = 0;
+const PREC_PIPE: u8 = 1;
+pub(crate) const PREC_UNARY: u8 = 8;
+pub(crate) const PREC_ATOM: u8 = 9;
+
+pub(crate) fn bin_prec(op: BinOp) -> u8 {
+    use BinOp::*;
+    match op {
+        Or => 2,
+        And => 3,
+        Eq | Ne | Approx | Lt | Le | Gt | Ge | Range | RangeExcl => 4,
+        Add | Sub => 5,
+        Mul | Div | Mod => 6,
+        Pow => 7,
+    }
+}
+
+pub(crate) fn bin_symbol(op: BinOp) -> &'static str {
+    use BinOp::*;
+    match op {
+        Add => "+",
+        Sub => "-",
+        Mul => "*",
+        Div => "/",
+        Pow => "^",
+        Mod => "%",
+        Eq => "=",
+        Ne => "!=",
+        Lt => "<",
+        Le => "<=",
+        Gt => ">",
+        Ge => ">=",
+        Approx => "~=",
+        Range => "..",
+        RangeExcl => "..<",
+        And => "&&",
+        Or => "||",
+    }
+}
+
+pub(crate) fn expr_prec(e: &Expr) -> u8 {
+    match &e.kind {
+        // a lambda's body runs to the end, so it is wrapped like a case
+        ExprKind::Case { .. } | ExprKind::Lambda { .. } => PREC_CASE,
+        ExprKind::Pipe { .. } => PREC_PIPE,
+        ExprKind::Bin { op, .. } => bin_prec(*op),
+        ExprKind::Unary { .. } => PREC_UNARY,
+        _ => PREC_ATOM,
+    }
+}
+
+/// Render a definition as source text that parses back to the same tree.
+pub fn def_to_source(d: &AlgorithmDef) -> String {
+    write_def(d, None)
+}
+
+/// Render an expression as source text, adding only the parentheses the
+/// parser needs to rebuild the same tree.
+pub fn expr_to_source(e: &Expr) -> String {
+    let mut out = String::new();
+    write_expr(&mut out, e, None);
+    out
+}
+
+/// Like `def_to_source`, in the formatter's layout: case blocks get one
+/// arm per line, indented by two spaces per level.
+pub fn def_to_canonical(d: &AlgorithmDef) -> String {
+    write_def(d, Some(0))
+}
+
+/// Like `expr_to_source`, in the formatter's layout.
+pub fn expr_to_canonical(e: &Expr) -> String {
+    let mut out = String::new();
+    write_expr(&mut out, e, Some(0));
+    out
+}
+
+/// A definition's `requires` and `ensures` clauses, each after a space;
+/// empty when it has none.
+pub fn contracts_to_source(d: &AlgorithmDef) -> String {
+    let mut out = String::new();
+    let clauses = d.requires.iter().map(|c| ("requires", c));
+    for (word, c) in clauses.chain(d.ensures.iter().map(|c| ("ensures", c))) {
+        // a bare `=` would end the clause
+        let src = expr_to_source(c);
+        let wrap = src.contains(" = ") && expr_prec(c) < PREC_ATOM;
+        out.push_str(&format!(" {} ", word));
+        write_operand(&mut out, c, wrap, None);
+    }
+    out
+}
+
+/// `@test "name" : expr`, with `expr` laid out by the formatter when
+/// `canonical`.
+pub fn test_to_source(t: &Test, canonical: bool) -> String {
+    let mut out = String::from("@test ");
+    write_string_literal(&mut out, &t.name);
+    out.push_str(" : ");
+    write_expr(&mut out, &t.expr, canonical.then_some(0));
+    out
+}
+
+pub fn import_to_source(i: &Import) -> String {
+    let mut out = String::from("import ");
+    write_string_literal(&mut out, &i.path);
+    out
+}
+
+fn write_def(d: &AlgorithmDef, indent: Option<usize>) -> String {
+    let mut out = String::from(if d.memo { "@memo " } else { "" });
+    out.push_str(&format!(
+        "@{}({}){} = ",
+        d.name,
+        d.params.join(", "),
+        contracts_to_source(d)
+    ));
+    write_expr(&mut out, &d.body, indent);
+    // laid out, each helper gets a line of its own
+    let inner = indent.map(|level| level + 1);
+    for (i, l) in d.locals.iter().enumerate() {
+        out.push_str(match (i, inner) {
+            (0, None) => " where ",
+            (_, None) => "; ",
+            (0, Some(_)) => " where\n  ",
+            (_, Some(_)) => ";\n  ",
+        });
+        out.push_str(&format!("{}({}) = ", l.name, l.params.join(", ")));
+        write_expr(&mut out, &l.body, inner);
+    }
+    out
+}
+
+// `indent` is the nesting depth of case blocks laid out one arm per line,
+// or `None` to keep everything on one line.
+fn write_expr(out: &mut String, e: &Expr, indent: Option<usize>) {
+    match &e.kind {
+        ExprKind::Number(v) => out.push_str(&v.to_string()),
+        ExprKind::Imag(v) => out.push_str(&format!("{}i", v)),
+        ExprKind::Bool(b) => out.push_str(&b.to_string()),
+        ExprKind::Str(s) => write_string_literal(out, s),
+        ExprKind::Ident(s) => out.push_str(s),
+        ExprKind::AlgRef(name) => {
+            out.push('@');
+            out.push_str(name);
+        }
+        ExprKind::Call { is_alg, name, args } => {
+            if *is_alg {
+                out.push('@');
+            }
+            out.push_str(name);
+            out.push('(');
+            for (i, a) in args.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_expr(out, a, indent);
+            }
+            out.push(')');
+        }
+        ExprKind::Unary { op, expr } => {
+            out.push(match op {
+                UnOp::Neg => '-',
+                UnOp::Not => '!',
+            });
+            write_operand(out, expr, expr_prec(expr) < PREC_UNARY, indent);
+        }
+        ExprKind::Bin { op, left, right } => {
+            let p = bin_prec(*op);
+            let (lp, rp) = (expr_prec(left), expr_prec(right));
+            // `^` groups to the right, comparisons don't chain, the rest
+            // group to the left
+            let (wrap_l, wrap_r) = match op {
+                BinOp::Pow => (lp <= p, rp < p),
+                _ if p == 4 => (lp <= p, rp <= p),
+                _ => (lp < p, rp <= p),
+            };
+            write_operand(out, left, wrap_l, indent);
+            out.push(' ');
+            out.push_str(bin_symbol(*op));
+            out.push(' ');
+            write_operand(out, right, wrap_r, indent);
+        }
+        ExprKind::Case { arms, default } => write_case(out, arms, default.as_deref(), indent),
+        ExprKind::List(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_expr(out, item, indent);
+            }
+            out.push(']');
+        }
+        ExprKind::Tuple(items) => {
+            out.push('(');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_expr(out, item, indent);
+            }
+            out.push(')');
+        }
+        ExprKind::Pipe { head, steps } => {
+            write_operand(out, head, expr_prec(head) <= PREC_PIPE, indent);
+            for s in steps {
+                out.push_str(" >> ");
+                // a case block is bracketed already
+                let wrap = expr_prec(s) <= PREC_PIPE && !matches!(s.kind, ExprKind::Case { .. });
+                write_operand(out, s, wrap, indent);
+            }
+        }
+        ExprKind::Lambda { params, body } => {
+            out.push('\\');
+            match &params[..] {
+                [p] => out.push_str(p),
+                _ => out.push_str(&format!("({})", params.join(", "))),
+            }
+            out.push_str(" -> ");
+            write_expr(out, body, indent);
+        }
+        ExprKind::Series {
+            kind,
+            index,
+            from,
+            to,
+            body,
+        } => {
+            out.push_str(&format!("{}({}, ", kind.name(), index));
+            for (i, a) in [from, to, body].into_iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_expr(out, a, indent);
+            }
+            out.push(')');
+        }
+        ExprKind::Solve { equation, var } => {
+            out.push_str("solve(");
+            write_expr(out, equation, indent);
+            out.push_str(&format!(", {})", var));
+        }
+    }
+}
+
+fn write_case(
+    out: &mut String,
+    arms: &[(Expr, Expr)],
+    default: Option<&Expr>,
+    indent: Option<usize>,
+) {
+    let Some(level) = indent else {
+        out.push_str("[ ");
+        for (i, (c, r)) in arms.iter().enumerate() {
+            if i > 0 {
+                out.push_str(" ; ");
+            }
+            write_operand(out, c, expr_prec(c) <= PREC_PIPE, None);
+            out.push_str(" ? ");
+            write_expr(out, r, None);
+        }
+        if let Some(default) = default {
+            if !arms.is_empty() {
+                out.push_str(" ; ");
+            }
+            out.push_str("_ ? ");
+            write_expr(out, default, None);
+        }
+        out.push_str(" ]");
+        return;
+    };
+    let inner = Some(level + 1);
+    let pad = "  ".repeat(level + 1);
+    out.push_str("[\n");
+    for (i, (c, r)) in arms.iter().enumerate() {
+        out.push_str(&pad);
+        write_operand(out, c, expr_prec(c) <= PREC_PIPE, inner);
+        out.push_str(" ? ");
+        write_expr(out, r, inner);
+        if default.is_some() || i + 1 < arms.len() {
+            out.push(';');
+        }
+        out.push('\n');
+    }
+    if let Some(default) = default {
+        out.push_str(&pad);
+        out.push_str("_ ? ");
+        write_expr(out, default, inner);
+        out.push('\n');
+    }
+    out.push_str(&"  ".repeat(level));
+    out.push(']');
+}
+
+fn write_operand(out: &mut String, e: &Expr, wrap: bool, indent: Option<usize>) {
+    if wrap {
+        out.push('(');
+        write_expr(out, e, indent);
+        out.push(')');
+    } else {
+        write_expr(out, e, indent);
+    }
+}
+
+fn write_string_literal(out: &mut String, s: &str) {
+    out.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            _ => out.push(ch),
+        }
+    }
+    out.push('"');
+}
@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+
+use crate::ast::BinOp;
+
+/// Operations counted while one algorithm's body runs.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct OpCounts {
+    /// Times the algorithm was called.
+    pub calls: u64,
+    /// `+` and `-`.
+    pub add: u64,
+    /// `*`, `/`, `%`, and `^`.
+    pub mul: u64,
+    /// `=`, `!=`, `~=`, `<`, `<=`, `>`, and `>=`.
+    pub cmp: u64,
+}
+
+impl OpCounts {
+    fn absorb(&mut self, other: &OpCounts) {
+        self.calls += other.calls;
+        self.add += other.add;
+        self.mul += other.mul;
+        self.cmp += other.cmp;
+    }
+}
+
+/// Operation counts per algorithm, gathered by `eval_expr` when the world
+/// has a profile. Operations outside any algorithm count as top level.
+#[derive(Debug, Default)]
+pub struct Profile {
+    algs: HashMap<String, OpCounts>,
+    top: OpCounts,
+}
+
+impl Profile {
+    pub fn record_call(&mut self, alg: &str) {
+        self.algs.entry(alg.to_string()).or_default().calls += 1;
+    }
+
+    /// Count `op`, run by the body of `alg`.
+    pub fn record_op(&mut self, alg: Option<&str>, op: BinOp) {
+        let counts = match alg {
+            Some(name) => self.algs.entry(name.to_string()).or_default(),
+            None => &mut self.top,
+        };
+        use BinOp::*;
+        match op {
+            Add | Sub => counts.add += 1,
+            Mul | Div | Mod | Pow => counts.mul += 1,
+            Eq | Ne | Approx | Lt | Le | Gt | Ge => counts.cmp += 1,
+            And | Or | Range | RangeExcl => {}
+        }
+    }
+
+    pub fn get(&self, alg: &str) -> Option<&OpCounts> {
+        self.algs.get(alg)
+    }
+
+    pub fn total(&self) -> OpCounts {
+        let mut total = self.top;
+        for c in self.algs.values() {
+            total.absorb(c);
+        }
+        total
+    }
+
+    /// A table with a row per algorithm in name order, then the top level
+    /// and the totals.
+    pub fn table(&self) -> String {
+        let mut names: Vec<&String> = self.algs.keys().collect();
+        names.sort();
+        let mut rows: Vec<(String, OpCounts)> = names
+            .into_iter()
+            .map(|n| (format!("@{}", n), self.algs[n]))
+            .collect();
+        rows.push(("(top level)".to_string(), self.top));
+        rows.push(("total".to_string(), self.total()));
+
+        let width = rows.iter().map(|(n, _)| n.len()).max().unwrap_or(0).max(9);
+        let mut out = format!(
+            "{:<width$} {:>10} {:>10} {:>10} {:>10}\n",
+            "algorithm", "calls", "add", "mul", "cmp"
+        );
+        for (name, c) in rows {
+            out.push_str(&format!(
+                "{:<width$} {:>10} {:>10} {:>10} {:>10}\n",
+                name, c.calls, c.add, c.mul, c.cmp
+            ));
+        }
+        out
+    }
+}
@@ -0,0 +1,242 @@
+//! Python bindings, built with `--features python` (see `pyproject.toml`
+//! for building a wheel with maturin):
+//!
+//! ```python
+//! import amlang
+//!
+//! am = amlang.Interpreter()
+//! [gcd] = am.define("@Gcd(a, b) = [ b = 0 ? a ; _ ? @Gcd(b, a % b) ]")
+//! gcd                          # shown as LaTeX in a notebook
+//! am.call("Gcd", 12, 18)       # 6.0; NumPy scalars work too
+//! ```
+//!
+//! Numbers, booleans, strings, lists, and tuples convert both ways;
+//! complex results become `complex` and exact big integers `int`. Any
+//! error raises `amlang.AmError` with the rendered diagnostics.
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyComplex, PyInt, PyList, PyString, PyTuple};
+
+use crate::ast::AlgorithmDef;
+use crate::diagnostic::Diagnostic;
+use crate::eval::Value;
+use crate::interpreter::Interpreter;
+use crate::latex::def_to_latex;
+use crate::normalize::normalize_unicode_to_ascii;
+use crate::parser::parse as parse_source;
+use crate::printer::def_to_source;
+
+create_exception!(amlang, AmError, PyException);
+
+/// An interpreter session, as `Interpreter` is for Rust.
+#[pyclass(name = "Interpreter")]
+pub struct PyInterpreter {
+    am: Interpreter,
+}
+
+#[pymethods]
+impl PyInterpreter {
+    #[new]
+    fn new() -> Self {
+        Self {
+            am: Interpreter::new(),
+        }
+    }
+
+    /// Add the definitions in `src`, returning them.
+    fn define(&mut self, src: &str) -> PyResult<Vec<Algorithm>> {
+        let normalized = normalize_unicode_to_ascii(src);
+        let (program, _) = parse_source(&normalized).map_err(|d| error(&d, &normalized))?;
+        self.am.load_str(src).map_err(|d| error(&d, &normalized))?;
+        Ok(program.defs.iter().map(Algorithm::from).collect())
+    }
+
+    /// Call an algorithm, or failing that a builtin, by name.
+    #[pyo3(signature = (name, *args))]
+    fn call<'py>(
+        &self,
+        py: Python<'py>,
+        name: &str,
+        args: &Bound<'py, PyTuple>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let args = args
+            .iter()
+            .map(|a| to_value(&a))
+            .collect::<PyResult<Vec<_>>>()?;
+        let v = self.am.call(name, &args).map_err(|d| error(&[d], ""))?;
+        to_py(py, &v)
+    }
+
+    /// Evaluate one expression against the definitions so far.
+    fn eval<'py>(&self, py: Python<'py>, src: &str) -> PyResult<Bound<'py, PyAny>> {
+        let normalized = normalize_unicode_to_ascii(src);
+        let v = self
+            .am
+            .eval_str(src)
+            .map_err(|d| error(&[d], &normalized))?;
+        to_py(py, &v)
+    }
+
+    /// Change a setting as `:set` does, e.g. `set("steps", "5000")`.
+    fn set(&mut self, key: &str, value: &str) -> PyResult<()> {
+        self.am
+            .settings_mut()
+            .set(key, value)
+            .map_err(AmError::new_err)
+    }
+}
+
+/// A parsed definition, displayed as LaTeX by Jupyter.
+#[pyclass(frozen)]
+pub struct Algorithm {
+    #[pyo3(get)]
+    name: String,
+    #[pyo3(get)]
+    params: Vec<String>,
+    #[pyo3(get)]
+    source: String,
+    #[pyo3(get)]
+    latex: String,
+}
+
+impl From<&AlgorithmDef> for Algorithm {
+    fn from(d: &AlgorithmDef) -> Self {
+        Self {
+            name: d.name.clone(),
+            params: d.params.clone(),
+            source: def_to_source(d),
+            latex: def_to_latex(d),
+        }
+    }
+}
+
+#[pymethods]
+impl Algorithm {
+    fn _repr_latex_(&self) -> String {
+        format!("$${}$$", self.latex)
+    }
+
+    fn __repr__(&self) -> String {
+        self.source.clone()
+    }
+}
+
+/// The definitions in `src`, without defining them anywhere.
+#[pyfunction]
+fn parse(src: &str) -> PyResult<Vec<Algorithm>> {
+    let normalized = normalize_unicode_to_ascii(src);
+    let (program, _) = parse_source(&normalized).map_err(|d| error(&d, &normalized))?;
+    Ok(program.defs.iter().map(Algorithm::from).collect())
+}
+
+#[pymodule]
+fn amlang(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyInterpreter>()?;
+    m.add_class::<Algorithm>()?;
+    m.add_function(wrap_pyfunction!(parse, m)?)?;
+    m.add("AmError", m.py().get_type::<AmError>())?;
+    Ok(())
+}
+
+fn error(diags: &[Diagnostic], src: &str) -> PyErr {
+    let src = (!src.is_empty()).then_some(src);
+    let lines: Vec<String> = diags.iter().map(|d| d.render(src)).collect();
+    AmError::new_err(lines.join("\n"))
+}
+
+// Anything that is not a bool, string, list, or tuple is taken as a
+// number, which covers NumPy's scalars through `__float__`.
+fn to_value(obj: &Bound<'_, PyAny>) -> PyResult<Value> {
+    if obj.is_instance_of::<PyBool>() {
+        return Ok(Value::Bool(obj.extract()?));
+    }
+    if let Ok(s) = obj.cast::<PyString>() {
+        return Ok(Value::Str(s.to_str()?.to_string()));
+    }
+    if let Ok(items) = obj.cast::<PyList>() {
+        let items = items
+            .iter()
+            .map(|i| to_value(&i))
+            .collect::<PyResult<_>>()?;
+        return Ok(Value::List(items));
+    }
+    if let Ok(items) = obj.cast::<PyTuple>() {
+        let items = items
+            .iter()
+            .map(|i| to_value(&i))
+            .collect::<PyResult<_>>()?;
+        return Ok(Value::Tuple(items));
+    }
+    Ok(Value::Number(obj.extract::<f64>()?))
+}
+
+fn to_py<'py>(py: Python<'py>, v: &Value) -> PyResult<Bound<'py, PyAny>> {
+    let items = |items: &[Value]| {
+        items
+            .iter()
+            .map(|i| to_py(py, i))
+            .collect::<PyResult<Vec<_>>>()
+    };
+    Ok(match v {
+        Value::Number(n) => n.into_pyobject(py)?.into_any(),
+        Value::Bool(b) => b.into_pyobject(py)?.to_owned().into_any(),
+        Value::Str(s) => s.into_pyobject(py)?.into_any(),
+        Value::Int(n) => py.get_type::<PyInt>().call1((n.to_string(),))?,
+        Value::Complex(z) => PyComplex::from_doubles(py, z.re, z.im).into_any(),
+        Value::List(values) => PyList::new(py, items(values)?)?.into_any(),
+        Value::Tuple(values) => PyTuple::new(py, items(values)?)?.into_any(),
+        other => other.to_string().into_pyobject(py)?.into_any(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bigint::BigInt;
+    use crate::complex::Complex;
+    use std::ffi::CStr;
+
+    fn round_trip(py: Python<'_>, v: &Value) -> Value {
+        to_value(&to_py(py, v).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn python_values_convert_to_am_values() {
+        Python::initialize();
+        Python::attach(|py| {
+            let value = |src: &CStr| to_value(&py.eval(src, None, None).unwrap()).unwrap();
+            assert!(matches!(value(c"True"), Value::Bool(true)));
+            assert!(matches!(value(c"3"), Value::Number(n) if n == 3.0));
+            assert!(matches!(value(c"'x'"), Value::Str(s) if s == "x"));
+            assert_eq!(value(c"[1, (2, False)]").to_string(), "[1, (2, false)]");
+            // anything with `__float__` is a number
+            assert!(
+                matches!(value(c"__import__('fractions').Fraction(1, 4)"), Value::Number(n) if n == 0.25)
+            );
+            assert!(to_value(&py.eval(c"None", None, None).unwrap()).is_err());
+        });
+    }
+
+    #[test]
+    fn am_values_convert_to_python_values() {
+        Python::initialize();
+        Python::attach(|py| {
+            let repr = |v: &Value| to_py(py, v).unwrap().repr().unwrap().to_string();
+            assert_eq!(repr(&Value::Number(1.5)), "1.5");
+            assert_eq!(repr(&Value::Bool(false)), "False");
+            assert_eq!(repr(&Value::Str("hi".to_string())), "'hi'");
+            assert_eq!(repr(&Value::Complex(Complex::new(1.0, -2.0))), "(1-2j)");
+            // exact beyond what a float holds
+            let big = Value::Int(BigInt::from_u64((1 << 60) + 1));
+            assert_eq!(repr(&big), "1152921504606846977");
+            let nested = Value::List(vec![Value::Tuple(vec![
+                Value::Number(1.0),
+                Value::Bool(true),
+            ])]);
+            assert_eq!(repr(&nested), "[(1.0, True)]");
+            assert_eq!(round_trip(py, &nested).to_string(), nested.to_string());
+        });
+    }
+}
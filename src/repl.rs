@@ -1,26 +1,74 @@
-use rustyline::DefaultEditor;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use rustyline::Editor;
 use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+
+use crate::completion::ReplHelper;
+use amlang::ast::{AlgorithmDef, Expr, ExprKind};
+use amlang::diagnostic::Diagnostic;
+use amlang::diff::differentiate;
+use amlang::docs::signature;
+use amlang::equiv::{check_against, equivalent, parse_equiv};
+use amlang::eval::{Env, EvalError, Memo, Paused, Stepper, Value, World, eval_expr};
+use amlang::latex::def_to_latex;
+use amlang::lexer::lex_checked;
+use amlang::loader::{SourceFile, load_files, search_path};
+use amlang::normalize::{denormalize, normalize_unicode_to_ascii};
+use amlang::parser::{Tokens, parse_expr, parse_whole};
+use amlang::printer::expr_to_source;
+use amlang::profile::Profile;
+use amlang::rewrite::Rule;
+use amlang::session::{
+    Input, assign, ast_text, needs_continuation, parse_input, save_source, set_answer,
+};
+use amlang::settings::Settings;
+use amlang::simplify::simplify_with;
+use amlang::types::{check_def_types, infer_returns};
 
-use crate::ast::AlgorithmDef;
-use crate::error_handling::safe_parse;
-use crate::eval::{Env, Value, World, eval_expr};
-use crate::lexer::lex;
-use crate::normalize::normalize_unicode_to_ascii;
-use crate::parser::{Tokens, parse_alg_def, parse_expr};
+// Keeps a runaway definition from locking up the prompt; `:set timeout`
+// changes it.
+const REPL_TIMEOUT: Duration = Duration::from_secs(10);
 
 pub struct Repl {
     world_defs: Vec<AlgorithmDef>,
-    editor: DefaultEditor,
+    editor: Editor<ReplHelper, DefaultHistory>,
+    settings: Settings,
+    // session variables assigned with `name = expr`
+    env: Env,
+    // normalized text each algorithm was parsed from, for error carets
+    sources: HashMap<String, String>,
+    // memoized results, kept between inputs until a definition or
+    // setting changes
+    memo: Memo,
+    // with `:profile` on, the counts from the last evaluation
+    profile: Option<Profile>,
+    // `:trace on` prints each evaluation step
+    tracing: bool,
+    // rewrite rules added with `:rule`, applied by `:simplify`
+    rules: Vec<Rule>,
 }
 
 impl Repl {
     pub fn new() -> Result<Self, String> {
-        let editor =
-            DefaultEditor::new().map_err(|e| format!("Failed to start line editor: {e}"))?;
+        let mut editor = Editor::new().map_err(|e| format!("Failed to start line editor: {e}"))?;
+        editor.set_helper(Some(ReplHelper::default()));
 
         Ok(Self {
             world_defs: Vec::new(),
             editor,
+            settings: Settings {
+                timeout: Some(REPL_TIMEOUT),
+                ..Settings::default()
+            },
+            env: Env::base(),
+            sources: HashMap::new(),
+            memo: Memo::default(),
+            profile: None,
+            tracing: false,
+            rules: Vec::new(),
         })
     }
 
@@ -30,9 +78,22 @@ impl Repl {
 
         let _ = self.editor.load_history(".amlang_history");
 
+        // lines of an input that is still incomplete (open brackets etc.)
+        let mut pending = String::new();
+
         loop {
-            let line = match self.editor.readline("repl> ") {
+            self.refresh_completion();
+            let prompt = if pending.is_empty() {
+                "repl> "
+            } else {
+                "...> "
+            };
+            let line = match self.editor.readline(prompt) {
                 Ok(s) => s,
+                Err(ReadlineError::Interrupted) if !pending.is_empty() => {
+                    pending.clear();
+                    continue;
+                }
                 Err(ReadlineError::Interrupted) => {
                     println!("Ctrl-C pressed, exiting...");
                     break;
@@ -47,7 +108,16 @@ impl Repl {
                 }
             };
 
-            let input = line.trim();
+            if !pending.is_empty() {
+                pending.push('\n');
+            }
+            pending.push_str(&line);
+            if needs_continuation(&pending) {
+                continue;
+            }
+            let buffered = std::mem::take(&mut pending);
+
+            let input = buffered.trim();
             if input.is_empty() {
                 continue;
             }
@@ -69,13 +139,43 @@ impl Repl {
         Ok(())
     }
 
+    fn refresh_completion(&mut self) {
+        if let Some(helper) = self.editor.helper_mut() {
+            helper.refresh(&self.world_defs, &self.env);
+        }
+    }
+
     fn handle_command(&mut self, input: &str) -> bool {
-        match input {
+        let (cmd, rest) = match input.split_once(char::is_whitespace) {
+            Some((cmd, rest)) => (cmd, rest.trim()),
+            None => (input, ""),
+        };
+        match cmd {
             ":help" => {
                 println!("Commands:");
                 println!("  :help        show this help");
-                println!("  :list        list defined algorithms");
-                println!("  :reset       clear all definitions");
+                println!("  :list [--verbose]  list defined algorithms, with their docs");
+                println!("  :doc NAME    show an algorithm's /// documentation");
+                println!("  :ast SRC     show the parse tree of an expression or definition");
+                println!("  :load FILE   load definitions from a .am file");
+                println!("  :save FILE   write all definitions to a .am file");
+                println!("  :latex NAME  show an algorithm as LaTeX");
+                println!("  :diff EXPR VAR [as NAME]  differentiate, optionally defining @NAME");
+                println!("  :simplify EXPR  combine constants and cancel terms");
+                println!("  :rule [LHS => RHS]  add a rewrite rule for :simplify, or list them");
+                println!("  :equiv @A @B [over LO..HI]  compare two algorithms on many inputs");
+                println!("  :fuzz @A FN [over LO..HI]  compare an algorithm with a builtin");
+                println!("  :reset       clear all definitions and variables");
+                println!(
+                    "  :set [K [V]] show or change a setting (depth, mod, memo, steps, timeout, bigint, epsilon, strict, unicode)"
+                );
+                println!("  :cache-stats show how well memoized calls are cached");
+                println!("  :profile     count operations in each evaluation (toggle)");
+                println!("  :trace on|off print each call, case arm, and pipeline stage");
+                println!("  :debug EXPR  step through an evaluation");
+                println!("  x = expr     bind a session variable");
+                println!("  (a, b) = expr  bind the parts of a tuple");
+                println!("  ans          the last evaluated result");
                 println!("  exit, :q     quit");
                 true
             }
@@ -84,79 +184,563 @@ impl Repl {
                     println!("<no algorithms defined>");
                 } else {
                     for d in &self.world_defs {
-                        println!("{}({})", d.name, d.params.join(", "));
+                        println!("{}", signature(d));
+                        if let Some(doc) = d.doc.as_ref().filter(|_| rest == "--verbose") {
+                            for line in doc.lines() {
+                                println!("    {}", line);
+                            }
+                        }
                     }
                 }
                 true
             }
+            ":doc" => {
+                self.handle_doc(rest);
+                true
+            }
             ":reset" => {
                 self.world_defs.clear();
-                println!("Definitions cleared.");
+                self.sources.clear();
+                self.memo.clear();
+                self.rules.clear();
+                self.env = Env::base();
+                println!("Definitions and variables cleared.");
+                true
+            }
+            ":ast" => {
+                show_ast(rest);
+                true
+            }
+            ":load" => {
+                self.handle_load(rest);
+                true
+            }
+            ":save" => {
+                self.handle_save(rest);
+                true
+            }
+            ":set" => {
+                self.handle_set(rest);
+                true
+            }
+            ":latex" => {
+                self.handle_latex(rest);
+                true
+            }
+            ":diff" => {
+                self.handle_diff(rest);
+                true
+            }
+            ":equiv" => {
+                self.handle_equiv(rest);
+                true
+            }
+            ":fuzz" => {
+                self.handle_fuzz(rest);
+                true
+            }
+            ":simplify" => {
+                if let Some(text) = simplified(rest, &self.rules) {
+                    println!("{}", self.pretty(&text));
+                }
+                true
+            }
+            ":rule" => {
+                self.handle_rule(rest);
+                true
+            }
+            ":profile" => {
+                self.profile = match self.profile {
+                    Some(_) => None,
+                    None => Some(Profile::default()),
+                };
+                let state = if self.profile.is_some() { "on" } else { "off" };
+                println!("Profiling {}.", state);
+                true
+            }
+            ":trace" => {
+                match rest {
+                    "on" | "off" => {
+                        self.tracing = rest == "on";
+                        println!("Tracing {}.", rest);
+                    }
+                    _ => eprintln!("usage: :trace on|off"),
+                }
+                true
+            }
+            ":debug" => {
+                self.handle_debug(rest);
+                true
+            }
+            ":cache-stats" => {
+                println!(
+                    "{} cached result(s), {} hit(s), {} miss(es)",
+                    self.memo.len(),
+                    self.memo.hits,
+                    self.memo.misses
+                );
                 true
             }
             _ => false,
         }
     }
 
-    fn process_input(&mut self, input: &str) {
-        let normalized = normalize_unicode_to_ascii(input);
-        let tokens = lex(&normalized);
+    fn handle_load(&mut self, path: &str) {
+        if path.is_empty() {
+            eprintln!("usage: :load FILE");
+            return;
+        }
+        let files = match load_files(&[path.to_string()], &search_path(&[])) {
+            Ok(files) => files,
+            Err(e) => {
+                eprintln!("{e}");
+                return;
+            }
+        };
+        for f in &files {
+            for d in &f.diags {
+                eprintln!("{}", d.render_in(Some(&f.src), &f.path));
+            }
+        }
+        if files.iter().any(SourceFile::has_errors) {
+            return;
+        }
+        for f in &files {
+            for d in &f.defs {
+                self.add_or_replace_algorithm(d.clone(), &f.src);
+            }
+        }
+        // a script runs once everything it may call is defined
+        for f in &files {
+            for e in &f.script {
+                self.evaluate_and_print_expression(e, &f.src);
+            }
+        }
+    }
+
+    fn handle_save(&self, path: &str) {
+        if path.is_empty() {
+            eprintln!("usage: :save FILE");
+            return;
+        }
+        match std::fs::write(path, save_source(&self.world_defs)) {
+            Ok(()) => println!("Saved {} algorithm(s) to {}", self.world_defs.len(), path),
+            Err(e) => eprintln!("Could not write {}: {}", path, e),
+        }
+    }
 
-        if tokens.is_empty() {
+    fn handle_rule(&mut self, text: &str) {
+        if text.is_empty() {
+            if self.rules.is_empty() {
+                println!("<no rules defined>");
+            }
+            for r in &self.rules {
+                println!("{}", r);
+            }
             return;
         }
+        match Rule::parse(text) {
+            Ok(rule) => {
+                println!("Added rule {}", rule);
+                self.rules.push(rule);
+            }
+            Err(e) => eprintln!("{e}"),
+        }
+    }
 
-        let mut ts = Tokens::new_with_src(tokens, &normalized);
+    fn handle_doc(&self, name: &str) {
+        if name.is_empty() {
+            eprintln!("usage: :doc NAME");
+            return;
+        }
+        let name = name.trim_start_matches('@');
+        match self.world_defs.iter().find(|d| d.name == name) {
+            Some(d) => {
+                println!("{}", signature(d));
+                println!();
+                println!("{}", d.doc.as_deref().unwrap_or("<no documentation>"));
+            }
+            None => eprintln!("unknown algorithm: {}", name),
+        }
+    }
 
-        if input.starts_with('@') {
-            self.handle_algorithm_definition(&mut ts);
-        } else {
-            self.handle_expression(&mut ts);
+    fn handle_latex(&self, name: &str) {
+        if name.is_empty() {
+            eprintln!("usage: :latex NAME");
+            return;
+        }
+        let name = name.trim_start_matches('@');
+        match self.world_defs.iter().find(|d| d.name == name) {
+            Some(d) => println!("{}", def_to_latex(d)),
+            None => eprintln!("unknown algorithm: {}", name),
         }
     }
 
-    fn handle_algorithm_definition(&mut self, ts: &mut Tokens) {
-        let def = match safe_parse(|| parse_alg_def(ts)) {
-            Ok(def) => def,
+    fn handle_equiv(&self, args: &str) {
+        let (left, right, sampler) = match parse_equiv(args) {
+            Ok(spec) => spec,
+            Err(e) => {
+                eprintln!("usage: :equiv @A @B [over LO..HI] ({e})");
+                return;
+            }
+        };
+        let mut world = World::new(&self.world_defs);
+        world.settings = self.settings.clone();
+        match equivalent(&world, &left, &right, &sampler) {
+            Ok(Ok(n)) => println!(
+                "@{} and @{} agree on {} input(s) from {}",
+                left, right, n, sampler
+            ),
+            Ok(Err(c)) => println!(
+                "counterexample: {}",
+                c.describe(&format!("@{left}"), &format!("@{right}"))
+            ),
+            Err(e) => eprintln!("{e}"),
+        }
+    }
+
+    // `:fuzz @A FN`: the builtin FN stands in for a trusted implementation.
+    fn handle_fuzz(&self, args: &str) {
+        let (alg, func, sampler) = match parse_equiv(args) {
+            Ok(spec) => spec,
+            Err(_) => {
+                eprintln!("usage: :fuzz @A FN [over LO..HI]");
+                return;
+            }
+        };
+        let world = World::new(&[]);
+        if !world.builtins.contains(&func) {
+            eprintln!("unknown function: {}", func);
+            return;
+        }
+        let reference = |args: &[Value]| world.builtins.call(&func, args);
+        match check_against(&self.world_defs, &alg, reference, &sampler) {
+            Ok(Ok(n)) => println!(
+                "@{} and {} agree on {} input(s) from {}",
+                alg, func, n, sampler
+            ),
+            Ok(Err(c)) => println!("counterexample: {}", c.describe(&format!("@{alg}"), &func)),
+            Err(e) => eprintln!("{e}"),
+        }
+    }
+
+    // `:diff EXPR VAR [as NAME]`. A derivative defined as @NAME takes the
+    // parameters of the algorithm differentiated, or else just VAR.
+    fn handle_diff(&mut self, args: &str) {
+        let (args, name) = match args.rsplit_once(" as ") {
+            Some((args, name)) => (args, Some(name.trim().trim_start_matches('@'))),
+            None => (args, None),
+        };
+        let Some((src, var)) = args.trim().rsplit_once(char::is_whitespace) else {
+            eprintln!("usage: :diff EXPR VAR [as NAME]");
+            return;
+        };
+        let normalized = normalize_unicode_to_ascii(src);
+        let parsed = lex_checked(&normalized)
+            .and_then(|tokens| parse_whole(&mut Tokens::new(tokens), parse_expr));
+        let expr = match parsed {
+            Ok(expr) => expr,
+            Err(errors) => {
+                print_diagnostics(&errors, &normalized);
+                return;
+            }
+        };
+        let world = World::new(&self.world_defs);
+        let derivative = match differentiate(&world, &expr, var) {
+            Ok(d) => expr_to_source(&d),
             Err(e) => {
                 eprintln!("{e}");
                 return;
             }
         };
+        let Some(name) = name else {
+            println!("{}", self.pretty(&derivative));
+            return;
+        };
+        let params = match &expr.kind {
+            ExprKind::AlgRef(alg) => match world.algs.get(alg) {
+                Some(def) => def.params.join(", "),
+                None => var.to_string(),
+            },
+            _ => var.to_string(),
+        };
+        self.process_input(&format!("@{}({}) = {}", name, params, derivative));
+    }
+
+    // Evaluate `src` under the debugger's own prompt, pausing before each
+    // sub-expression until told to move on.
+    fn handle_debug(&mut self, src: &str) {
+        if src.is_empty() {
+            eprintln!("usage: :debug EXPR");
+            return;
+        }
+        let normalized = normalize_unicode_to_ascii(src);
+        let parsed = lex_checked(&normalized)
+            .and_then(|tokens| parse_whole(&mut Tokens::new(tokens), parse_expr));
+        let expr = match parsed {
+            Ok(expr) => expr,
+            Err(errors) => {
+                print_diagnostics(&errors, &normalized);
+                return;
+            }
+        };
+        let mut world = World::new(&self.world_defs);
+        world.settings = self.settings.clone();
+        let mut stepper = Stepper::new(&world, std::mem::take(&mut self.env), &expr);
+        let mut breakpoints: Vec<String> = Vec::new();
+        println!("Debugging. Commands: step, next, continue, print VAR, break [NAME],");
+        println!("delete NAME, where, quit.");
+
+        let mut at = Paused::At(&expr);
+        loop {
+            let e = match at {
+                Paused::At(e) => e,
+                Paused::Done(v) => {
+                    println!("= {}", self.pretty(&v.to_string()));
+                    break;
+                }
+            };
+            let indent = "  ".repeat(stepper.depth());
+            match stepper.alg() {
+                Some(name) => println!("{}@{}: {}", indent, name, expr_to_source(e)),
+                None => println!("{}{}", indent, expr_to_source(e)),
+            }
 
-        self.add_or_replace_algorithm(def);
+            // read commands until one moves evaluation on
+            let moved = loop {
+                let Ok(line) = self.editor.readline("debug> ") else {
+                    break None;
+                };
+                let (cmd, arg) = match line.trim().split_once(char::is_whitespace) {
+                    Some((cmd, arg)) => (cmd, arg.trim()),
+                    None => (line.trim(), ""),
+                };
+                match cmd {
+                    "s" | "step" => break Some(stepper.step()),
+                    "n" | "next" => break Some(stepper.step_over()),
+                    "c" | "continue" => break Some(stepper.continue_to(&breakpoints)),
+                    "q" | "quit" => break None,
+                    "p" | "print" => match stepper.get(arg) {
+                        Some(v) => println!("{} = {}", arg, v),
+                        None => eprintln!("unknown variable: {}", arg),
+                    },
+                    "b" | "break" if arg.is_empty() => {
+                        for b in &breakpoints {
+                            println!("@{}", b);
+                        }
+                    }
+                    "b" | "break" => {
+                        let name = arg.trim_start_matches('@').to_string();
+                        if !world.algs.contains_key(&name) {
+                            eprintln!("unknown algorithm: {}", name);
+                        } else if !breakpoints.contains(&name) {
+                            breakpoints.push(name);
+                        }
+                    }
+                    "d" | "delete" => {
+                        let name = arg.trim_start_matches('@');
+                        breakpoints.retain(|b| b != name);
+                    }
+                    "w" | "where" => {
+                        for (depth, call) in stepper.calls().iter().enumerate() {
+                            println!("{}{}", "  ".repeat(depth), call);
+                        }
+                    }
+                    "" => {}
+                    other => eprintln!("unknown debugger command: {}", other),
+                }
+            };
+            match moved {
+                Some(Ok(next)) => at = next,
+                Some(Err(err)) => {
+                    eprintln!("{}", self.render_error(&err, &normalized));
+                    break;
+                }
+                None => {
+                    println!("Debugging stopped.");
+                    break;
+                }
+            }
+        }
+        self.env = stepper.into_env();
     }
 
-    fn add_or_replace_algorithm(&mut self, def: AlgorithmDef) {
-        if let Some(pos) = self.world_defs.iter().position(|d| d.name == def.name) {
-            self.world_defs[pos] = def;
+    // `text` as it is printed: with glyphs once `:set unicode on`.
+    fn pretty(&self, text: &str) -> String {
+        if self.settings.unicode {
+            denormalize(text)
         } else {
-            self.world_defs.push(def);
+            text.to_string()
         }
-        let d = self.world_defs.last().unwrap();
-        println!("Defined: {}({})", d.name, d.params.join(", "));
     }
 
-    fn handle_expression(&mut self, ts: &mut Tokens) {
-        let expr = match safe_parse(|| parse_expr(ts)) {
-            Ok(expr) => expr,
+    fn handle_set(&mut self, rest: &str) {
+        let mut parts = rest.split_whitespace();
+        match (parts.next(), parts.next()) {
+            (None, _) => {
+                for key in Settings::KEYS {
+                    println!("{} = {}", key, self.settings.get(key).unwrap_or_default());
+                }
+            }
+            (Some(key), None) => match self.settings.get(key) {
+                Some(v) => println!("{} = {}", key, v),
+                None => eprintln!("unknown setting: {}", key),
+            },
+            (Some(key), Some(value)) => match self.settings.set(key, value) {
+                Ok(()) => {
+                    self.memo.clear();
+                    println!("{} = {}", key, value);
+                }
+                Err(e) => eprintln!("{e}"),
+            },
+        }
+    }
+
+    fn process_input(&mut self, input: &str) {
+        let normalized = normalize_unicode_to_ascii(input);
+        let (input, warnings) = match parse_input(&normalized) {
+            Ok(Some(parsed)) => parsed,
+            Ok(None) => return,
+            Err(errors) => {
+                print_diagnostics(&errors, &normalized);
+                return;
+            }
+        };
+        print_diagnostics(&warnings, &normalized);
+        match input {
+            Input::Define(def) => self.handle_algorithm_definition(def, &normalized),
+            Input::Assign(names, expr) => self.handle_assignment(names, &expr, &normalized),
+            Input::Eval(expr) => self.evaluate_and_print_expression(&expr, &normalized),
+        }
+    }
+
+    fn handle_assignment(&mut self, names: Vec<String>, expr: &Expr, src: &str) {
+        let v = match self.eval(expr) {
+            Ok(v) => v,
             Err(e) => {
-                eprintln!("{e}");
+                eprintln!("{}", self.render_error(&e, src));
                 return;
             }
         };
+        match assign(&mut self.env, names, v) {
+            Ok(bound) => {
+                for (name, v) in bound {
+                    println!("{} = {}", name, self.pretty(&v.to_string()));
+                }
+                self.print_profile();
+            }
+            Err(e) => eprintln!("{e}"),
+        }
+    }
+
+    fn handle_algorithm_definition(&mut self, def: AlgorithmDef, src: &str) {
+        // reject ill-typed definitions now rather than when they first run
+        let mut defs: Vec<&AlgorithmDef> = self
+            .world_defs
+            .iter()
+            .filter(|d| d.name != def.name)
+            .collect();
+        defs.push(&def);
+        let errors = check_def_types(&def, &infer_returns(&defs));
+        if !errors.is_empty() {
+            print_diagnostics(&errors, src);
+            return;
+        }
 
-        self.evaluate_and_print_expression(&expr);
+        self.add_or_replace_algorithm(def, src);
     }
 
-    fn evaluate_and_print_expression(&mut self, expr: &crate::ast::Expr) {
-        let world = World::new(&self.world_defs);
-        let mut env = Env::base();
+    fn add_or_replace_algorithm(&mut self, def: AlgorithmDef, src: &str) {
+        println!("Defined: {}({})", def.name, def.params.join(", "));
+        self.sources.insert(def.name.clone(), src.to_string());
+        self.memo.clear();
+        if let Some(pos) = self.world_defs.iter().position(|d| d.name == def.name) {
+            self.world_defs[pos] = def;
+        } else {
+            self.world_defs.push(def);
+        }
+    }
+
+    fn evaluate_and_print_expression(&mut self, expr: &Expr, src: &str) {
+        match self.eval(expr) {
+            Ok(v) => {
+                println!("= {}", self.pretty(&v.to_string()));
+                self.print_profile();
+                set_answer(&mut self.env, v);
+            }
+            Err(e) => eprintln!("{}", self.render_error(&e, src)),
+        }
+    }
+
+    // Evaluate against the session's definitions, carrying the memo cache
+    // over from earlier inputs.
+    fn eval(&mut self, expr: &Expr) -> Result<Value, EvalError> {
+        let mut world = World::new(&self.world_defs);
+        world.settings = self.settings.clone();
+        world.memo = Mutex::new(std::mem::take(&mut self.memo));
+        if self.profile.is_some() {
+            world.profile = Some(Mutex::default());
+        }
+        if self.tracing {
+            world.trace = Some(Box::new(|depth, event| {
+                println!("{}{}", "  ".repeat(depth), event)
+            }));
+        }
+        let result = eval_expr(&world, &mut self.env, expr);
+        self.memo = world.memo.into_inner().unwrap();
+        self.profile = world.profile.map(|p| p.into_inner().unwrap());
+        result
+    }
+
+    fn print_profile(&self) {
+        if let Some(p) = &self.profile {
+            print!("{}", p.table());
+        }
+    }
 
-        match eval_expr(&world, &mut env, expr) {
-            Ok(Value::Number(n)) => println!("= {}", n),
-            Ok(Value::Bool(b)) => println!("= {}", b),
-            Err(e) => eprintln!("runtime error: {e}"),
+    // `src` is the input line; errors inside an algorithm body point into
+    // the text that algorithm was defined in instead.
+    fn render_error(&self, e: &EvalError, src: &str) -> String {
+        match &e.alg {
+            Some(name) => e.render(self.sources.get(name).map(String::as_str)),
+            None => e.render(Some(src)),
         }
     }
 }
+
+// `src` simplified, or `None` once the problem is reported.
+fn simplified(src: &str, rules: &[Rule]) -> Option<String> {
+    if src.is_empty() {
+        eprintln!("usage: :simplify EXPR");
+        return None;
+    }
+    let normalized = normalize_unicode_to_ascii(src);
+    let parsed = lex_checked(&normalized)
+        .and_then(|tokens| parse_whole(&mut Tokens::new(tokens), parse_expr));
+    match parsed {
+        Ok(expr) => Some(expr_to_source(&simplify_with(&expr, rules))),
+        Err(errors) => {
+            print_diagnostics(&errors, &normalized);
+            None
+        }
+    }
+}
+
+// Parse without evaluating and print the tree the way `--ast` does.
+fn show_ast(src: &str) {
+    if src.is_empty() {
+        eprintln!("usage: :ast EXPR | :ast @Name(params) = body");
+        return;
+    }
+    let normalized = normalize_unicode_to_ascii(src);
+    match ast_text(&normalized) {
+        Ok(tree) => print!("{}", tree),
+        Err(errors) => print_diagnostics(&errors, &normalized),
+    }
+}
+
+fn print_diagnostics(diags: &[Diagnostic], src: &str) {
+    for d in diags {
+        eprintln!("{}", d.render(Some(src)));
+    }
+}
@@ -0,0 +1,180 @@
+//! Term rewriting with rules written in AM, such as `x + 0 => x` or
+//! `sqrt(x^2) => abs(x)`. Each name on the left is a pattern variable that
+//! matches any expression (the same one wherever it repeats); everything
+//! else must match exactly. `:simplify` applies `standard_rules` along with
+//! any the session has added.
+
+use std::collections::HashMap;
+use std::mem::discriminant;
+use std::sync::OnceLock;
+
+use crate::ast::{Expr, ExprKind, for_each_expr};
+use crate::diagnostic::Diagnostic;
+use crate::lexer::lex_checked;
+use crate::normalize::normalize_unicode_to_ascii;
+use crate::parser::{Tokens, parse_expr, parse_whole};
+use crate::printer::expr_to_source;
+
+/// Rewriting stops after this many passes over an expression even if
+/// rules still apply, as they do forever for one such as `x + y => y + x`.
+pub const MAX_REWRITE_PASSES: usize = 100;
+
+/// Identities that hold for every real input and always make the
+/// expression smaller.
+const STANDARD_RULES: &[&str] = &[
+    "sqrt(x^2) => abs(x)",
+    "abs(abs(x)) => abs(x)",
+    "abs(-x) => abs(x)",
+    "ln(exp(x)) => x",
+    "x - -y => x + y",
+    "x + -y => x - y",
+    "not not x => x",
+];
+
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub lhs: Expr,
+    pub rhs: Expr,
+}
+
+impl Rule {
+    /// `LHS => RHS`. Every name in `RHS` must appear in `LHS`.
+    pub fn parse(text: &str) -> Result<Rule, String> {
+        let Some((lhs, rhs)) = text.split_once("=>") else {
+            return Err(format!("expected a rule such as x + 0 => x, got {}", text));
+        };
+        let (lhs, rhs) = (expr(lhs)?, expr(rhs)?);
+        let vars = names(&lhs);
+        if let Some(v) = names(&rhs).iter().find(|v| !vars.contains(v)) {
+            return Err(format!("{} is not bound by the left side of the rule", v));
+        }
+        Ok(Rule { lhs, rhs })
+    }
+
+    /// `e` rewritten by this rule, if it matches at the top.
+    pub fn apply(&self, e: &Expr) -> Option<Expr> {
+        let mut bound = HashMap::new();
+        if !matches(&self.lhs, e, &mut bound) {
+            return None;
+        }
+        let mut out = instantiate(&self.rhs, &bound);
+        out.span = e.span;
+        Some(out)
+    }
+}
+
+impl std::fmt::Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} => {}",
+            expr_to_source(&self.lhs),
+            expr_to_source(&self.rhs)
+        )
+    }
+}
+
+/// The rules `:simplify` always applies.
+pub fn standard_rules() -> &'static [Rule] {
+    static RULES: OnceLock<Vec<Rule>> = OnceLock::new();
+    RULES.get_or_init(|| {
+        STANDARD_RULES
+            .iter()
+            .map(|r| Rule::parse(r).expect("standard rules parse"))
+            .collect()
+    })
+}
+
+/// One bottom-up pass: at each node, children first, the first of `rules`
+/// that matches is applied once.
+pub fn rewrite_once(e: &Expr, rules: &[Rule]) -> Expr {
+    let e = e.map_children(|c| rewrite_once(c, rules));
+    rules.iter().find_map(|r| r.apply(&e)).unwrap_or(e)
+}
+
+/// `e` rewritten by `rules` until none applies, or for at most
+/// `MAX_REWRITE_PASSES` passes.
+pub fn rewrite(e: &Expr, rules: &[Rule]) -> Expr {
+    let mut e = e.clone();
+    for _ in 0..MAX_REWRITE_PASSES {
+        let next = rewrite_once(&e, rules);
+        if expr_to_source(&next) == expr_to_source(&e) {
+            break;
+        }
+        e = next;
+    }
+    e
+}
+
+fn expr(src: &str) -> Result<Expr, String> {
+    let src = normalize_unicode_to_ascii(src.trim());
+    let first = |d: Vec<Diagnostic>| d[0].message.clone();
+    let tokens = lex_checked(&src).map_err(first)?;
+    parse_whole(&mut Tokens::new(tokens), parse_expr).map_err(first)
+}
+
+// The names used in `e`, which on a rule's left side are its variables.
+fn names(e: &Expr) -> Vec<&str> {
+    let mut out = Vec::new();
+    for_each_expr(e, &mut |e| {
+        if let ExprKind::Ident(name) = &e.kind {
+            out.push(name.as_str());
+        }
+    });
+    out
+}
+
+fn matches<'e>(pattern: &Expr, e: &'e Expr, bound: &mut HashMap<String, &'e Expr>) -> bool {
+    use ExprKind::*;
+    match (&pattern.kind, &e.kind) {
+        (Ident(v), _) => match bound.get(v) {
+            Some(prev) => expr_to_source(prev) == expr_to_source(e),
+            None => {
+                bound.insert(v.clone(), e);
+                true
+            }
+        },
+        (Number(a), Number(b)) => a == b,
+        (
+            Call {
+                is_alg: pa,
+                name: pn,
+                args: pargs,
+            },
+            Call { is_alg, name, args },
+        ) => {
+            pa == is_alg
+                && pn == name
+                && pargs.len() == args.len()
+                && pargs.iter().zip(args).all(|(p, a)| matches(p, a, bound))
+        }
+        (Unary { op: po, expr: pe }, Unary { op, expr }) => {
+            discriminant(po) == discriminant(op) && matches(pe, expr, bound)
+        }
+        (
+            Bin {
+                op: po,
+                left: pl,
+                right: pr,
+            },
+            Bin { op, left, right },
+        ) => {
+            discriminant(po) == discriminant(op)
+                && matches(pl, left, bound)
+                && matches(pr, right, bound)
+        }
+        // anything else has to be written the same way
+        _ => {
+            discriminant(&pattern.kind) == discriminant(&e.kind)
+                && names(pattern).is_empty()
+                && expr_to_source(pattern) == expr_to_source(e)
+        }
+    }
+}
+
+fn instantiate(template: &Expr, bound: &HashMap<String, &Expr>) -> Expr {
+    match &template.kind {
+        ExprKind::Ident(v) => bound[v].clone(),
+        _ => template.map_children(|c| instantiate(c, bound)),
+    }
+}
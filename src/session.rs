@@ -0,0 +1,237 @@
+//! Line handling for the interactive prompt, kept apart from the terminal:
+//! what an input is, and how its results are bound into the session.
+
+use crate::ast::{AlgorithmDef, Expr, def_tree, expr_tree};
+use crate::builtins::BUILTINS;
+use crate::diagnostic::Diagnostic;
+use crate::eval::{Env, Value};
+use crate::lexer::{lex, lex_checked};
+use crate::normalize::normalize_unicode_to_ascii;
+use crate::parser::{Tokens, attach_docs, ends_unfinished, parse_alg_def, parse_expr, parse_whole};
+use crate::printer::def_to_canonical;
+use crate::token::{TokSpan, Token};
+
+/// The prompt's commands, for completion.
+pub const COMMANDS: &[&str] = &[
+    ":help",
+    ":list",
+    ":doc",
+    ":ast",
+    ":load",
+    ":save",
+    ":latex",
+    ":diff",
+    ":simplify",
+    ":rule",
+    ":equiv",
+    ":fuzz",
+    ":cache-stats",
+    ":profile",
+    ":trace",
+    ":debug",
+    ":reset",
+    ":set",
+    ":q",
+    ":quit",
+];
+
+/// The name the result of the last expression is bound to.
+pub const ANSWER: &str = "ans";
+
+/// One complete input at the prompt.
+#[derive(Debug)]
+pub enum Input {
+    /// `@Name(params) = body`
+    Define(AlgorithmDef),
+    /// `name = expr`, or `(a, b) = expr` to bind the parts of a tuple
+    Assign(Vec<String>, Expr),
+    Eval(Expr),
+}
+
+/// Parse `src`, already normalized, as one input, together with its
+/// warnings. `None` if it holds only whitespace and comments.
+pub fn parse_input(src: &str) -> Result<Option<(Input, Vec<Diagnostic>)>, Vec<Diagnostic>> {
+    let tokens = lex_checked(src)?;
+    if tokens.is_empty() {
+        return Ok(None);
+    }
+    if let Some((names, taken)) = assignment_target(&tokens) {
+        let mut ts = Tokens::new(tokens[taken..].to_vec());
+        let expr = parse_whole(&mut ts, parse_expr)?;
+        return Ok(Some((Input::Assign(names, expr), ts.take_warnings())));
+    }
+    let mut ts = Tokens::new(tokens);
+    let input = if src.trim_start().starts_with('@') {
+        let mut def = parse_whole(&mut ts, parse_alg_def)?;
+        attach_docs(src, std::slice::from_mut(&mut def));
+        Input::Define(def)
+    } else {
+        Input::Eval(parse_whole(&mut ts, parse_expr)?)
+    };
+    Ok(Some((input, ts.take_warnings())))
+}
+
+/// The parse tree `:ast` shows for `src`, already normalized: of a
+/// definition if it starts with `@`, and otherwise of an expression, where
+/// `=` is equality.
+pub fn ast_text(src: &str) -> Result<String, Vec<Diagnostic>> {
+    let mut ts = Tokens::new(lex_checked(src)?);
+    if src.trim_start().starts_with('@') {
+        parse_whole(&mut ts, parse_alg_def).map(|d| def_tree(&d))
+    } else {
+        parse_whole(&mut ts, parse_expr).map(|e| expr_tree(&e, 0))
+    }
+}
+
+/// Bind `v` in `env`: a single name takes the whole value, and several
+/// take the parts of a tuple of as many. Returns the bindings made.
+pub fn assign(
+    env: &mut Env,
+    mut names: Vec<String>,
+    v: Value,
+) -> Result<Vec<(String, Value)>, String> {
+    let bound: Vec<(String, Value)> = match v {
+        v if names.len() == 1 => vec![(names.remove(0), v)],
+        Value::Tuple(items) if items.len() == names.len() => names.into_iter().zip(items).collect(),
+        other => {
+            return Err(format!(
+                "cannot unpack {} into {} names",
+                other,
+                names.len()
+            ));
+        }
+    };
+    for (name, v) in &bound {
+        env.set(name.clone(), v.clone());
+    }
+    Ok(bound)
+}
+
+/// Bind `v` as the result of the last expression, for later inputs to
+/// use as `ans`. Assignments and failed evaluations leave it alone.
+pub fn set_answer(env: &mut Env, v: Value) {
+    env.set(ANSWER.to_string(), v);
+}
+
+/// `defs` as the source `:save` writes, with their `///` docs, for
+/// `:load` to read back.
+pub fn save_source(defs: &[AlgorithmDef]) -> String {
+    let mut out = String::new();
+    for d in defs {
+        for line in d.doc.iter().flat_map(|doc| doc.lines()) {
+            out.push_str(format!("/// {line}").trim_end());
+            out.push('\n');
+        }
+        out.push_str(&def_to_canonical(d));
+        out.push('\n');
+    }
+    out
+}
+
+/// What the prompt can complete: commands, algorithm names after `@`, and
+/// builtins, parameters, and session variables anywhere else.
+#[derive(Debug, Default)]
+pub struct Completions {
+    algorithms: Vec<String>,
+    // everything that may appear as a bare name in an expression
+    names: Vec<String>,
+}
+
+impl Completions {
+    /// The candidates for a session with `defs` and variables in `env`.
+    pub fn new(defs: &[AlgorithmDef], env: &Env) -> Self {
+        let mut algorithms: Vec<String> = defs.iter().map(|d| d.name.clone()).collect();
+        algorithms.sort();
+
+        let mut names: Vec<String> = algorithms.clone();
+        names.extend(BUILTINS.iter().map(|b| b.name.to_string()));
+        names.extend(defs.iter().flat_map(|d| d.params.iter().cloned()));
+        names.extend(env.names().cloned());
+        names.sort();
+        names.dedup();
+        Self { algorithms, names }
+    }
+
+    /// The completions of the word ending at byte `pos` of `line`, and
+    /// where that word starts.
+    pub fn complete(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+        let before = &line[..pos];
+        let start = before
+            .char_indices()
+            .rev()
+            .find(|&(_, c)| !(c.is_alphanumeric() || c == '_' || c == ':'))
+            .map(|(i, c)| i + c.len_utf8())
+            .unwrap_or(0);
+        let word = &before[start..];
+
+        let pool: Vec<&str> = if word.starts_with(':') {
+            // commands only start a line
+            match start {
+                0 => COMMANDS.to_vec(),
+                _ => Vec::new(),
+            }
+        } else if before[..start].ends_with('@') {
+            self.algorithms.iter().map(String::as_str).collect()
+        } else {
+            self.names.iter().map(String::as_str).collect()
+        };
+        let found = pool
+            .into_iter()
+            .filter(|c| c.starts_with(word))
+            .map(str::to_string)
+            .collect();
+        (start, found)
+    }
+}
+
+// `name = expr` at the prompt binds a session variable, and `(a, b) = expr`
+// the parts of a tuple; anywhere else `=` keeps meaning equality. Returns
+// the names and the number of tokens before the expression.
+fn assignment_target(tokens: &[TokSpan]) -> Option<(Vec<String>, usize)> {
+    let tok = |i: usize| tokens.get(i).map(|t| &t.tok);
+    if let (Some(Token::Ident(name)), Some(Token::Equal)) = (tok(0), tok(1)) {
+        return Some((vec![name.clone()], 2));
+    }
+    if tok(0) != Some(&Token::LParen) {
+        return None;
+    }
+    let mut names = Vec::new();
+    let mut i = 1;
+    while let Some(Token::Ident(name)) = tok(i) {
+        names.push(name.clone());
+        match tok(i + 1) {
+            Some(Token::Comma) => i += 2,
+            Some(Token::RParen) if names.len() > 1 && tok(i + 2) == Some(&Token::Equal) => {
+                return Some((names, i + 3));
+            }
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// Whether the prompt should read another line before handling `src`:
+/// while brackets are open, a string literal is unterminated, or the last
+/// token leaves the line unfinished.
+pub fn needs_continuation(src: &str) -> bool {
+    if src.trim_start().starts_with(':') {
+        return false;
+    }
+    let tokens = lex(&normalize_unicode_to_ascii(src));
+    let mut depth = 0i32;
+    for t in &tokens {
+        match t.tok {
+            Token::LParen | Token::LBracket => depth += 1,
+            Token::RParen | Token::RBracket => depth -= 1,
+            _ => {}
+        }
+    }
+    if depth > 0 {
+        return true;
+    }
+    match tokens.last().map(|t| &t.tok) {
+        Some(Token::Error(msg)) => msg.starts_with("unterminated"),
+        Some(t) => ends_unfinished(t),
+        None => false,
+    }
+}
@@ -0,0 +1,152 @@
+use std::time::Duration;
+
+/// Default ceiling for nested algorithm calls before evaluation is aborted.
+/// Frames live on the heap, so this only guards against runaway recursion.
+pub const DEFAULT_MAX_DEPTH: usize = 10_000;
+
+/// Default tolerance of `~=`.
+pub const DEFAULT_EPSILON: f64 = 1e-9;
+
+/// How `%` treats negative operands.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ModMode {
+    /// Sign follows the dividend, as in C and Rust: `-1 % 5 = -1`.
+    Truncated,
+    /// Result is always in `[0, |b|)`, as in number theory: `-1 % 5 = 4`.
+    Euclidean,
+}
+
+/// Evaluation options shared by the CLI flags and the REPL's `:set`.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub max_depth: usize,
+    pub mod_mode: ModMode,
+    /// Cache the results of every algorithm, not just those marked `@memo`.
+    pub memoize: bool,
+    /// Evaluation steps one evaluation may take; `None` for no limit.
+    pub max_steps: Option<u64>,
+    /// Wall-clock time one evaluation may take; `None` for no limit.
+    pub timeout: Option<Duration>,
+    /// Keep integers exact past 2^53 instead of rounding them to f64.
+    pub bigint: bool,
+    /// Tolerance of `~=`, relative to the larger operand once that
+    /// exceeds 1 and absolute below.
+    pub epsilon: f64,
+    /// Fail when finite real inputs give NaN, infinity, or a complex
+    /// number, instead of carrying it on.
+    pub strict: bool,
+    /// Print results and definitions with math glyphs such as `≤` and
+    /// `→`; evaluation is unaffected.
+    pub unicode: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            max_depth: DEFAULT_MAX_DEPTH,
+            mod_mode: ModMode::Euclidean,
+            memoize: false,
+            max_steps: None,
+            timeout: None,
+            bigint: false,
+            epsilon: DEFAULT_EPSILON,
+            strict: false,
+            unicode: false,
+        }
+    }
+}
+
+impl Settings {
+    pub const KEYS: &'static [&'static str] = &[
+        "depth", "mod", "memo", "steps", "timeout", "bigint", "epsilon", "strict", "unicode",
+    ];
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "depth" => {
+                self.max_depth = value.parse().map_err(|_| {
+                    format!("depth must be a non-negative integer, got '{}'", value)
+                })?;
+            }
+            "mod" => {
+                self.mod_mode = match value {
+                    "truncated" => ModMode::Truncated,
+                    "euclidean" => ModMode::Euclidean,
+                    _ => {
+                        return Err(format!(
+                            "mod must be 'truncated' or 'euclidean', got '{}'",
+                            value
+                        ));
+                    }
+                }
+            }
+            "memo" => self.memoize = parse_switch(key, value)?,
+            "bigint" => self.bigint = parse_switch(key, value)?,
+            "strict" => self.strict = parse_switch(key, value)?,
+            "unicode" => self.unicode = parse_switch(key, value)?,
+            "epsilon" => {
+                self.epsilon = value
+                    .parse()
+                    .ok()
+                    .filter(|e: &f64| *e >= 0.0)
+                    .ok_or_else(|| {
+                        format!("epsilon must be a non-negative number, got '{}'", value)
+                    })?;
+            }
+            "steps" => self.max_steps = parse_limit(key, value)?,
+            "timeout" => self.timeout = parse_limit(key, value)?.map(Duration::from_millis),
+            _ => return Err(format!("unknown setting: {}", key)),
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        match key {
+            "depth" => Some(self.max_depth.to_string()),
+            "mod" => Some(
+                match self.mod_mode {
+                    ModMode::Truncated => "truncated",
+                    ModMode::Euclidean => "euclidean",
+                }
+                .to_string(),
+            ),
+            "memo" => Some(show_switch(self.memoize)),
+            "bigint" => Some(show_switch(self.bigint)),
+            "strict" => Some(show_switch(self.strict)),
+            "unicode" => Some(show_switch(self.unicode)),
+            "epsilon" => Some(self.epsilon.to_string()),
+            "steps" => Some(show_limit(self.max_steps)),
+            "timeout" => Some(show_limit(self.timeout.map(|t| t.as_millis() as u64))),
+            _ => None,
+        }
+    }
+}
+
+fn parse_switch(key: &str, value: &str) -> Result<bool, String> {
+    match value {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(format!("{} must be 'on' or 'off', got '{}'", key, value)),
+    }
+}
+
+fn show_switch(on: bool) -> String {
+    if on { "on" } else { "off" }.to_string()
+}
+
+// A count, or `off` for no limit. Timeouts are given in milliseconds.
+fn parse_limit(key: &str, value: &str) -> Result<Option<u64>, String> {
+    match value {
+        "off" => Ok(None),
+        _ => value.parse().map(Some).map_err(|_| {
+            format!(
+                "{} must be a non-negative integer or 'off', got '{}'",
+                key, value
+            )
+        }),
+    }
+}
+
+fn show_limit(limit: Option<u64>) -> String {
+    limit.map_or("off".to_string(), |n| n.to_string())
+}
@@ -0,0 +1,238 @@
+//! Algebraic tidying: constants combined, `x - x` cancelled, double
+//! negation removed, and chains of `+` and `*` flattened so that their
+//! constants meet, then the identities of `rewrite::standard_rules`
+//! applied. Used for `:simplify` and for cleaner exports.
+//!
+//! `+` is taken to be numeric addition, so a chain holding a string
+//! literal is left in its written order.
+
+use crate::ast::{AlgorithmDef, BinOp, Expr, ExprKind, UnOp};
+use crate::printer::expr_to_source;
+use crate::rewrite::{MAX_REWRITE_PASSES, Rule, rewrite_once, standard_rules};
+use crate::token::Span;
+
+/// `e` simplified bottom-up.
+pub fn simplify(e: &Expr) -> Expr {
+    simplify_with(e, &[])
+}
+
+/// `e` simplified, applying `rules` as well as the standard ones until
+/// nothing changes.
+pub fn simplify_with(e: &Expr, rules: &[Rule]) -> Expr {
+    let mut e = tidy(e);
+    for _ in 0..MAX_REWRITE_PASSES {
+        let rewritten = rewrite_once(&rewrite_once(&e, standard_rules()), rules);
+        let next = tidy(&rewritten);
+        if expr_to_source(&next) == expr_to_source(&e) {
+            break;
+        }
+        e = next;
+    }
+    e
+}
+
+fn tidy(e: &Expr) -> Expr {
+    let e = e.map_children(tidy);
+    let at = e.span;
+    match e.kind {
+        ExprKind::Unary {
+            op: UnOp::Neg,
+            expr,
+        } => match expr.kind {
+            ExprKind::Number(n) => num(-n, at),
+            ExprKind::Unary {
+                op: UnOp::Neg,
+                expr: inner,
+            } => *inner,
+            kind => unary(UnOp::Neg, Expr::new(kind, expr.span), at),
+        },
+        ExprKind::Bin {
+            op: BinOp::Add | BinOp::Sub,
+            ..
+        } => sum_chain(e),
+        ExprKind::Bin { op: BinOp::Mul, .. } => product_chain(e),
+        ExprKind::Bin {
+            op: op @ (BinOp::Div | BinOp::Pow),
+            left,
+            right,
+        } => match (op, number(&left), number(&right)) {
+            (BinOp::Div, Some(a), Some(b)) if (a / b).is_finite() && (a / b).fract() == 0.0 => {
+                num(a / b, at)
+            }
+            (BinOp::Pow, Some(a), Some(b)) if a.powf(b).is_finite() => num(a.powf(b), at),
+            (_, _, Some(1.0)) => *left,
+            (BinOp::Pow, _, Some(0.0)) => num(1.0, at),
+            _ => bin(op, *left, *right, at),
+        },
+        kind => Expr::new(kind, at),
+    }
+}
+
+/// `d` with its body simplified.
+pub fn simplify_def(d: &AlgorithmDef) -> AlgorithmDef {
+    AlgorithmDef {
+        body: simplify(&d.body),
+        locals: d.locals.iter().map(simplify_def).collect(),
+        ..d.clone()
+    }
+}
+
+// A chain of `+` and `-`: its numbers summed into one constant at the end
+// and terms that cancel removed.
+fn sum_chain(e: Expr) -> Expr {
+    let mut terms = Vec::new();
+    collect_terms(&e, true, &mut terms);
+    if terms
+        .iter()
+        .any(|(_, t)| matches!(t.kind, ExprKind::Str(_)))
+    {
+        return e;
+    }
+    let mut constant = 0.0;
+    let mut kept: Vec<(bool, &Expr)> = Vec::new();
+    for (positive, t) in terms {
+        if let Some(n) = number(t) {
+            constant += if positive { n } else { -n };
+            continue;
+        }
+        let src = expr_to_source(t);
+        match kept
+            .iter()
+            .position(|(p, k)| *p != positive && expr_to_source(k) == src)
+        {
+            Some(i) => {
+                kept.remove(i);
+            }
+            None => kept.push((positive, t)),
+        }
+    }
+
+    let at = e.span;
+    let mut out: Option<Expr> = None;
+    for (positive, t) in kept {
+        let t = t.clone();
+        out = Some(match out {
+            None if positive => t,
+            None => unary(UnOp::Neg, t, at),
+            Some(acc) if positive => bin(BinOp::Add, acc, t, at),
+            Some(acc) => bin(BinOp::Sub, acc, t, at),
+        });
+    }
+    match out {
+        None => num(constant, at),
+        Some(acc) if constant > 0.0 => bin(BinOp::Add, acc, num(constant, at), at),
+        Some(acc) if constant < 0.0 => bin(BinOp::Sub, acc, num(-constant, at), at),
+        Some(acc) => acc,
+    }
+}
+
+fn collect_terms<'e>(e: &'e Expr, positive: bool, out: &mut Vec<(bool, &'e Expr)>) {
+    match &e.kind {
+        ExprKind::Bin {
+            op: BinOp::Add,
+            left,
+            right,
+        } => {
+            collect_terms(left, positive, out);
+            collect_terms(right, positive, out);
+        }
+        ExprKind::Bin {
+            op: BinOp::Sub,
+            left,
+            right,
+        } => {
+            collect_terms(left, positive, out);
+            collect_terms(right, !positive, out);
+        }
+        ExprKind::Unary {
+            op: UnOp::Neg,
+            expr,
+        } => collect_terms(expr, !positive, out),
+        _ => out.push((positive, e)),
+    }
+}
+
+// A chain of `*`: its numbers multiplied into one constant at the front.
+fn product_chain(e: Expr) -> Expr {
+    let mut factors = Vec::new();
+    collect_factors(&e, &mut factors);
+    if factors.iter().any(|f| matches!(f.kind, ExprKind::Str(_))) {
+        return e;
+    }
+    let mut constant = 1.0;
+    let mut kept: Vec<&Expr> = Vec::new();
+    for f in factors {
+        match f.kind {
+            ExprKind::Number(n) => constant *= n,
+            ExprKind::Unary {
+                op: UnOp::Neg,
+                ref expr,
+            } => {
+                constant = -constant;
+                kept.push(expr);
+            }
+            _ => kept.push(f),
+        }
+    }
+
+    let at = e.span;
+    if constant == 0.0 || kept.is_empty() {
+        return num(constant, at);
+    }
+    let rest = kept
+        .into_iter()
+        .cloned()
+        .reduce(|acc, f| bin(BinOp::Mul, acc, f, at))
+        .expect("a factor is kept");
+    match constant {
+        1.0 => rest,
+        -1.0 => unary(UnOp::Neg, rest, at),
+        c => bin(BinOp::Mul, num(c, at), rest, at),
+    }
+}
+
+fn collect_factors<'e>(e: &'e Expr, out: &mut Vec<&'e Expr>) {
+    match &e.kind {
+        ExprKind::Bin {
+            op: BinOp::Mul,
+            left,
+            right,
+        } => {
+            collect_factors(left, out);
+            collect_factors(right, out);
+        }
+        _ => out.push(e),
+    }
+}
+
+fn number(e: &Expr) -> Option<f64> {
+    match e.kind {
+        ExprKind::Number(n) => Some(n),
+        _ => None,
+    }
+}
+
+fn num(n: f64, at: Span) -> Expr {
+    Expr::new(ExprKind::Number(n), at)
+}
+
+fn unary(op: UnOp, e: Expr, at: Span) -> Expr {
+    Expr::new(
+        ExprKind::Unary {
+            op,
+            expr: Box::new(e),
+        },
+        at,
+    )
+}
+
+fn bin(op: BinOp, left: Expr, right: Expr, at: Span) -> Expr {
+    Expr::new(
+        ExprKind::Bin {
+            op,
+            left: Box::new(left),
+            right: Box::new(right),
+        },
+        at,
+    )
+}
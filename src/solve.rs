@@ -0,0 +1,149 @@
+//! `solve(lhs = rhs, x)`: the real roots of an equation in one variable.
+//! Linear and quadratic equations are solved exactly from their
+//! derivatives; anything else is scanned for sign changes, which are then
+//! narrowed down by bisection.
+
+use crate::ast::{BinOp, Expr, ExprKind};
+use crate::diff::differentiate;
+use crate::eval::{World, free_names};
+use crate::simplify::simplify;
+
+/// Roots are looked for between `-SOLVE_RANGE` and `SOLVE_RANGE` when the
+/// equation cannot be solved exactly.
+pub const SOLVE_RANGE: f64 = 100.0;
+
+/// The scan evaluates the equation at this many evenly spaced points, so
+/// roots closer together than the spacing may be missed.
+pub const SOLVE_SAMPLES: usize = 4000;
+
+/// The roots of `equation` in `var`, in increasing order. `at(f, x)`
+/// evaluates `f` with `var` bound to `x`.
+pub(crate) fn solve<E: From<String>>(
+    world: &World,
+    equation: &Expr,
+    var: &str,
+    mut at: impl FnMut(&Expr, f64) -> Result<f64, E>,
+) -> Result<Vec<f64>, E> {
+    // `lhs = rhs` is solved as `lhs - rhs = 0`
+    let f = match &equation.kind {
+        ExprKind::Bin {
+            op: BinOp::Eq,
+            left,
+            right,
+        } => Expr::new(
+            ExprKind::Bin {
+                op: BinOp::Sub,
+                left: left.clone(),
+                right: right.clone(),
+            },
+            equation.span,
+        ),
+        _ => equation.clone(),
+    };
+    if let Some(roots) = solve_polynomial(world, &f, var, &mut at) {
+        return roots;
+    }
+    scan(&f, &mut at)
+}
+
+// Exact roots when `f` is linear or quadratic in `var`, or `None` when it
+// is neither or a coefficient cannot be worked out.
+fn solve_polynomial<E: From<String>>(
+    world: &World,
+    f: &Expr,
+    var: &str,
+    at: &mut impl FnMut(&Expr, f64) -> Result<f64, E>,
+) -> Option<Result<Vec<f64>, E>> {
+    let d1 = simplify(&differentiate(world, f, var).ok()?);
+    let c = at(f, 0.0).ok()?;
+    if !mentions(&d1, var) {
+        let b = at(&d1, 0.0).ok()?;
+        return Some(match (b, c) {
+            (0.0, 0.0) => Err(E::from(format!(
+                "every value of {} solves the equation",
+                var
+            ))),
+            (0.0, _) => Ok(Vec::new()),
+            _ => Ok(vec![-c / b + 0.0]),
+        });
+    }
+    let d2 = simplify(&differentiate(world, &d1, var).ok()?);
+    if mentions(&d2, var) {
+        return None;
+    }
+    let a = at(&d2, 0.0).ok()? / 2.0;
+    let b = at(&d1, 0.0).ok()?;
+    let disc = b * b - 4.0 * a * c;
+    Some(Ok(if disc < 0.0 {
+        Vec::new()
+    } else if disc == 0.0 {
+        vec![-b / (2.0 * a) + 0.0]
+    } else {
+        let r = disc.sqrt();
+        let mut roots = vec![(-b - r) / (2.0 * a), (-b + r) / (2.0 * a)];
+        roots.sort_by(f64::total_cmp);
+        roots
+    }))
+}
+
+// Sample `f` across the range and bisect each sign change. A change
+// across a pole, where `f` does not shrink towards zero, is no root.
+fn scan<E>(f: &Expr, at: &mut impl FnMut(&Expr, f64) -> Result<f64, E>) -> Result<Vec<f64>, E> {
+    let step = 2.0 * SOLVE_RANGE / SOLVE_SAMPLES as f64;
+    let mut first_error = None;
+    let mut sample = |x: f64| match at(f, x) {
+        Ok(y) if y.is_finite() => Some(y),
+        Ok(_) => None,
+        Err(e) => {
+            first_error.get_or_insert(e);
+            None
+        }
+    };
+    let mut roots: Vec<f64> = Vec::new();
+    let mut prev: Option<(f64, f64)> = None;
+    let mut any = false;
+    for i in 0..=SOLVE_SAMPLES {
+        let x = -SOLVE_RANGE + i as f64 * step;
+        let Some(y) = sample(x) else {
+            prev = None;
+            continue;
+        };
+        any = true;
+        if y == 0.0 {
+            roots.push(x);
+        } else if let Some((px, py)) = prev
+            && py != 0.0
+            && py.signum() != y.signum()
+        {
+            let (mut lo, mut hi, mut f_lo) = (px, x, py);
+            let scale = py.abs().max(y.abs());
+            loop {
+                let mid = lo + (hi - lo) / 2.0;
+                if mid == lo || mid == hi {
+                    break;
+                }
+                match sample(mid) {
+                    Some(0.0) => (lo, hi) = (mid, mid),
+                    Some(m) if m.signum() == f_lo.signum() => (lo, f_lo) = (mid, m),
+                    Some(_) => hi = mid,
+                    None => break,
+                }
+            }
+            let root = lo + (hi - lo) / 2.0;
+            if sample(root).is_some_and(|m| m.abs() <= scale) {
+                roots.push(root);
+            }
+        }
+        prev = Some((x, y));
+    }
+    match first_error {
+        Some(e) if !any => Err(e),
+        _ => Ok(roots),
+    }
+}
+
+fn mentions(e: &Expr, var: &str) -> bool {
+    let mut names = Vec::new();
+    free_names(e, &[], &mut names);
+    names.contains(&var)
+}
@@ -0,0 +1,106 @@
+//! Descriptive statistics over lists of numbers.
+
+use crate::eval::Value;
+use crate::matrix::as_vector;
+
+/// The arithmetic mean.
+pub fn mean(v: &Value) -> Result<Value, String> {
+    let xs = data("mean", v, 1)?;
+    Ok(Value::Number(average(&xs)))
+}
+
+/// The middle value, or the mean of the two middle values.
+pub fn median(v: &Value) -> Result<Value, String> {
+    quantile_of("median", v, 0.5)
+}
+
+/// The sample variance, dividing by `n - 1`.
+pub fn variance(v: &Value) -> Result<Value, String> {
+    let xs = data("variance", v, 2)?;
+    Ok(Value::Number(sample_variance(&xs)))
+}
+
+/// The sample standard deviation.
+pub fn stddev(v: &Value) -> Result<Value, String> {
+    let xs = data("stddev", v, 2)?;
+    Ok(Value::Number(sample_variance(&xs).sqrt()))
+}
+
+/// The `q`-th quantile for `q` in `[0, 1]`, interpolating linearly between
+/// the sorted values.
+pub fn quantile(v: &Value, q: &Value) -> Result<Value, String> {
+    let q = q.as_f64()?;
+    if !(0.0..=1.0).contains(&q) {
+        return Err(format!("quantile expects q between 0 and 1, got {}", q));
+    }
+    quantile_of("quantile", v, q)
+}
+
+/// The most frequent value; a tie goes to the smallest.
+pub fn mode(v: &Value) -> Result<Value, String> {
+    let xs = sorted(data("mode", v, 1)?);
+    let mut best = (xs[0], 0);
+    for run in xs.chunk_by(|a, b| a == b) {
+        if run.len() > best.1 {
+            best = (run[0], run.len());
+        }
+    }
+    Ok(Value::Number(best.0))
+}
+
+/// Pearson's correlation coefficient of two equally long lists.
+pub fn correlation(a: &Value, b: &Value) -> Result<Value, String> {
+    let (xs, ys) = (data("correlation", a, 2)?, data("correlation", b, 2)?);
+    if xs.len() != ys.len() {
+        return Err(format!(
+            "correlation needs lists of one length, got {} and {}",
+            xs.len(),
+            ys.len()
+        ));
+    }
+    let (mx, my) = (average(&xs), average(&ys));
+    let (mut sxy, mut sxx, mut syy) = (0.0, 0.0, 0.0);
+    for (x, y) in xs.iter().zip(&ys) {
+        sxy += (x - mx) * (y - my);
+        sxx += (x - mx) * (x - mx);
+        syy += (y - my) * (y - my);
+    }
+    if sxx == 0.0 || syy == 0.0 {
+        return Err("correlation is undefined for a constant list".to_string());
+    }
+    Ok(Value::Number(sxy / (sxx * syy).sqrt()))
+}
+
+// The numbers of `v`, at least `min` of them.
+fn data(name: &str, v: &Value, min: usize) -> Result<Vec<f64>, String> {
+    let xs = as_vector(v)?;
+    if xs.len() < min {
+        return Err(match min {
+            1 => format!("{} of an empty list", name),
+            _ => format!("{} needs at least {} values, got {}", name, min, xs.len()),
+        });
+    }
+    Ok(xs)
+}
+
+fn quantile_of(name: &str, v: &Value, q: f64) -> Result<Value, String> {
+    let xs = sorted(data(name, v, 1)?);
+    let pos = q * (xs.len() - 1) as f64;
+    let (below, above) = (pos.floor() as usize, pos.ceil() as usize);
+    let t = pos - below as f64;
+    Ok(Value::Number(xs[below] + (xs[above] - xs[below]) * t))
+}
+
+fn average(xs: &[f64]) -> f64 {
+    xs.iter().sum::<f64>() / xs.len() as f64
+}
+
+fn sample_variance(xs: &[f64]) -> f64 {
+    let m = average(xs);
+    xs.iter().map(|x| (x - m) * (x - m)).sum::<f64>() / (xs.len() - 1) as f64
+}
+
+fn sorted(mut xs: Vec<f64>) -> Vec<f64> {
+    xs.sort_by(f64::total_cmp);
+    xs
+}
@@ -8,6 +8,7 @@ pub enum Token {
     RBracket,
     Comma,
     Semicolon,
+    Colon,
     Underscore,
     Equal,
     Arrow,
@@ -23,23 +24,33 @@ pub enum Token {
     Percent,
     EqEq,
     Neq,
+    Approx,
+    DotDot,
+    DotDotLt,
     Le,
     Ge,
     Lt,
     Gt,
     Bang,
+    Backslash,
     Ident(String),
     Number(String),
     Bool(bool),
     String(String),
 
+    // keywords
+    If,
+    Then,
+    Else,
+    Of,
+    Where,
+
     // unknown
     Error(String),
 
     Caret,
 }
 
-#[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct TokSpan {
     pub tok: Token,
@@ -51,7 +62,33 @@ pub fn span(tok: Token, start: usize, end: usize) -> TokSpan {
     TokSpan { tok, start, end }
 }
 
-pub fn caret_message(src: &str, byte: usize, msg: &str) -> String {
+/// Byte range of a syntax node in the source it was parsed from.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// Smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+/// 1-based line and column of `byte` in `src`.
+pub fn line_col(src: &str, byte: usize) -> (usize, usize) {
+    let (line, col, _) = locate(src, byte);
+    (line, col)
+}
+
+// line, column, and the byte offset where that line starts
+fn locate(src: &str, byte: usize) -> (usize, usize, usize) {
     let mut line = 1usize;
     let mut col = 1usize;
     let mut last_nl = 0usize;
@@ -67,6 +104,14 @@ pub fn caret_message(src: &str, byte: usize, msg: &str) -> String {
             col += 1
         }
     }
+    (line, col, last_nl)
+}
+
+/// Render `msg` with the source line containing `span` and a caret
+/// underline beneath the spanned text.
+pub fn span_message(src: &str, file: &str, span: Span, label: &str, msg: &str) -> String {
+    let byte = span.start;
+    let (line, col, last_nl) = locate(src, byte);
     let line_end = src[last_nl..]
         .find('\n')
         .map(|x| last_nl + x)
@@ -77,7 +122,12 @@ pub fn caret_message(src: &str, byte: usize, msg: &str) -> String {
     for _ in 1..col {
         caret.push(' ');
     }
-    caret.push('^');
+    let from = byte.min(line_end);
+    let to = span.end.clamp(from, line_end);
+    let width = src.get(from..to).map_or(0, |t| t.chars().count()).max(1);
+    for _ in 0..width {
+        caret.push('^');
+    }
 
-    format!("error: {msg} \n --> input:{line}:{col}\n{line:>3} | {line_text}\n | {caret} here")
+    format!("{label}: {msg} \n --> {file}:{line}:{col}\n{line:>3} | {line_text}\n | {caret} here")
 }
@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::ast::{AlgorithmDef, BinOp, Expr, ExprKind, UnOp};
+use crate::builtins::BUILTINS;
+use crate::diagnostic::{Diagnostic, E_TYPE_MISMATCH};
+
+/// What an expression is statically known to produce. `Unknown` covers
+/// parameters, whose type depends on the caller, and anything mixing types.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ty {
+    Number,
+    Bool,
+    Str,
+    Unknown,
+}
+
+impl fmt::Display for Ty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Ty::Number => "number",
+            Ty::Bool => "bool",
+            Ty::Str => "string",
+            Ty::Unknown => "unknown",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Ty {
+    // the type both branches agree on, if any
+    fn join(self, other: Ty) -> Ty {
+        if self == other { self } else { Ty::Unknown }
+    }
+}
+
+/// Infer Number/Bool/String types across `defs` and report operands that
+/// can never have the type their operator needs, e.g. `x + (x > 1)`.
+pub fn check_types(defs: &[&AlgorithmDef]) -> Vec<Diagnostic> {
+    let returns = infer_returns(defs);
+    defs.iter()
+        .flat_map(|d| check_def_types(d, &returns))
+        .collect()
+}
+
+/// The return type of each algorithm in `defs`.
+pub fn infer_returns<'d>(defs: &[&'d AlgorithmDef]) -> HashMap<&'d str, Ty> {
+    // Return types depend on each other through calls, so iterate until
+    // they settle; each pass can only fix more of them.
+    let mut returns: HashMap<&str, Ty> = HashMap::new();
+    for _ in 0..=defs.len() {
+        let mut changed = false;
+        for d in defs {
+            let mut inf = Inference {
+                returns: &returns,
+                errors: Vec::new(),
+            };
+            let ty = inf.infer(&d.body);
+            if returns.insert(&d.name, ty) != Some(ty) {
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    returns
+}
+
+/// Type errors in one definition, given every algorithm's return type.
+pub fn check_def_types(def: &AlgorithmDef, returns: &HashMap<&str, Ty>) -> Vec<Diagnostic> {
+    let mut inf = Inference {
+        returns,
+        errors: Vec::new(),
+    };
+    inf.infer(&def.body);
+    for c in def.requires.iter().chain(&def.ensures) {
+        inf.expect(c, Ty::Bool);
+    }
+    for l in &def.locals {
+        inf.infer(&l.body);
+    }
+    inf.errors
+}
+
+struct Inference<'r> {
+    returns: &'r HashMap<&'r str, Ty>,
+    errors: Vec<Diagnostic>,
+}
+
+impl Inference<'_> {
+    fn infer(&mut self, e: &Expr) -> Ty {
+        match &e.kind {
+            ExprKind::Number(_) | ExprKind::Imag(_) => Ty::Number,
+            ExprKind::Bool(_) => Ty::Bool,
+            ExprKind::Str(_) => Ty::Str,
+            ExprKind::Ident(_) | ExprKind::AlgRef(_) => Ty::Unknown,
+            ExprKind::Unary { op, expr } => {
+                let want = match op {
+                    UnOp::Neg => Ty::Number,
+                    UnOp::Not => Ty::Bool,
+                };
+                self.expect(expr, want);
+                want
+            }
+            ExprKind::Bin { op, left, right } => self.binary(*op, left, right),
+            ExprKind::Case { arms, default } => {
+                let mut ty = default.as_deref().map(|d| self.infer(d));
+                for (c, r) in arms {
+                    self.expect(c, Ty::Bool);
+                    let arm = self.infer(r);
+                    ty = Some(ty.map_or(arm, |t| t.join(arm)));
+                }
+                ty.unwrap_or(Ty::Unknown)
+            }
+            ExprKind::Call { is_alg, name, args } => self.call(*is_alg, name, args),
+            ExprKind::List(items) | ExprKind::Tuple(items) => {
+                for item in items {
+                    self.infer(item);
+                }
+                Ty::Unknown
+            }
+            ExprKind::Pipe { head, steps } => {
+                // the piped value's type is not matched against the step
+                let mut ty = self.infer(head);
+                for s in steps {
+                    ty = match &s.kind {
+                        ExprKind::Call { is_alg, name, args } => self.call(*is_alg, name, args),
+                        ExprKind::Ident(name) => self.call(false, name, &[]),
+                        ExprKind::AlgRef(name) => self.call(true, name, &[]),
+                        ExprKind::Case { .. } => self.infer(s),
+                        _ => Ty::Unknown,
+                    };
+                }
+                ty
+            }
+            ExprKind::Lambda { body, .. } => {
+                self.infer(body);
+                Ty::Unknown
+            }
+            ExprKind::Series { from, to, body, .. } => {
+                self.expect(from, Ty::Number);
+                self.expect(to, Ty::Number);
+                self.expect(body, Ty::Number);
+                Ty::Number
+            }
+            ExprKind::Solve { equation, .. } => {
+                self.infer(equation);
+                Ty::Unknown
+            }
+        }
+    }
+
+    // Infer `e` and report it when its type is known to differ from `want`.
+    fn expect(&mut self, e: &Expr, want: Ty) {
+        let ty = self.infer(e);
+        self.mismatch(e, ty, want);
+    }
+
+    // Mirrors `eval_binary_operation`: a string on either side switches to
+    // concatenation and string comparison.
+    fn binary(&mut self, op: BinOp, left: &Expr, right: &Expr) -> Ty {
+        use BinOp::*;
+        let (lt, rt) = (self.infer(left), self.infer(right));
+        let has_str = lt == Ty::Str || rt == Ty::Str;
+        let unknown = lt == Ty::Unknown || rt == Ty::Unknown;
+        let operand = match op {
+            And | Or => Ty::Bool,
+            _ if has_str => {
+                return match op {
+                    Add => Ty::Str,
+                    Eq | Ne => Ty::Bool,
+                    Lt | Le | Gt | Ge => {
+                        self.mismatch(left, lt, Ty::Str);
+                        self.mismatch(right, rt, Ty::Str);
+                        Ty::Bool
+                    }
+                    _ => {
+                        let (e, t) = if lt == Ty::Str {
+                            (left, lt)
+                        } else {
+                            (right, rt)
+                        };
+                        self.mismatch(e, t, Ty::Number);
+                        Ty::Number
+                    }
+                };
+            }
+            // an unknown operand may still turn out to be a string
+            Add if unknown => {
+                self.mismatch(left, lt, Ty::Number);
+                self.mismatch(right, rt, Ty::Number);
+                return Ty::Unknown;
+            }
+            _ => Ty::Number,
+        };
+        self.mismatch(left, lt, operand);
+        self.mismatch(right, rt, operand);
+        match op {
+            Add | Sub | Mul | Div | Pow | Mod => Ty::Number,
+            Range | RangeExcl => Ty::Unknown,
+            _ => Ty::Bool,
+        }
+    }
+
+    fn mismatch(&mut self, e: &Expr, ty: Ty, want: Ty) {
+        if ty != Ty::Unknown && want != Ty::Unknown && ty != want {
+            self.errors.push(
+                Diagnostic::error(E_TYPE_MISMATCH, format!("expected {}, found {}", want, ty))
+                    .with_span(e.span),
+            );
+        }
+    }
+
+    // Algorithms return what their body was inferred to; builtins take and
+    // return numbers, except for `len`, `is_prime`, `unknown`, and those
+    // over sequences.
+    fn call(&mut self, is_alg: bool, name: &str, args: &[Expr]) -> Ty {
+        if let Some(ty) = self.returns.get(name) {
+            for a in args {
+                self.infer(a);
+            }
+            return *ty;
+        }
+        if is_alg || !BUILTINS.iter().any(|b| b.name == name) {
+            for a in args {
+                self.infer(a);
+            }
+            return Ty::Unknown;
+        }
+        let (param, ret) = match name {
+            "len" => (Ty::Str, Ty::Number),
+            "is_prime" | "unknown" => (Ty::Number, Ty::Bool),
+            "assert" => (Ty::Unknown, Ty::Bool),
+            "error" => (Ty::Unknown, Ty::Unknown),
+            "contains" => (Ty::Unknown, Ty::Bool),
+            "to_base" => (Ty::Number, Ty::Str),
+            "from_base" => (Ty::Unknown, Ty::Number),
+            "list" | "divmod" | "map" | "filter" | "fold" | "iterate" | "simulate" | "sort"
+            | "reverse" | "transpose" | "matmul" | "inverse" | "linsolve" => {
+                (Ty::Unknown, Ty::Unknown)
+            }
+            "sum" | "prod" | "bisect" | "newton" | "det" | "mean" | "median" | "variance"
+            | "stddev" | "quantile" | "mode" | "correlation" | "binary_search" => {
+                (Ty::Unknown, Ty::Number)
+            }
+            _ => (Ty::Number, Ty::Number),
+        };
+        for a in args {
+            self.expect(a, param);
+        }
+        ret
+    }
+}
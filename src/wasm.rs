@@ -0,0 +1,130 @@
+//! JavaScript bindings for running AM in a web page, built with
+//! `--features wasm` for `wasm32-unknown-unknown`:
+//!
+//! ```js
+//! const am = new Playground();
+//! am.define("@Double(x) = x * 2");   // warnings, as a JSON array
+//! am.eval("Double(21)");             // '{"result":42,"type":"number"}'
+//! ```
+//!
+//! Results and diagnostics are JSON strings in the shapes `--json` and
+//! `--error-format=json` print; a method that fails throws the JSON
+//! array of its errors. There is no clock on this target, so limit
+//! runaway definitions with `set("steps", ...)` rather than a timeout.
+
+use wasm_bindgen::prelude::*;
+
+use crate::builtins::Builtins;
+use crate::check::check_defs_with;
+use crate::diagnostic::Diagnostic;
+use crate::interpreter::Interpreter;
+use crate::json::result_to_json;
+use crate::normalize::normalize_unicode_to_ascii;
+use crate::parser::parse;
+
+// Inputs are named this in diagnostics.
+const INPUT: &str = "input";
+
+/// Steps each evaluation may take unless changed with `set`.
+pub const PLAYGROUND_STEPS: u64 = 1_000_000;
+
+/// An interpreter session, as `Interpreter` is for Rust.
+#[wasm_bindgen]
+pub struct Playground {
+    am: Interpreter,
+}
+
+impl Default for Playground {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl Playground {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Playground {
+        let mut am = Interpreter::new();
+        am.settings_mut().max_steps = Some(PLAYGROUND_STEPS);
+        Playground { am }
+    }
+
+    /// Add the definitions in `src`, returning its warnings.
+    pub fn define(&mut self, src: &str) -> Result<String, JsValue> {
+        let normalized = normalize_unicode_to_ascii(src);
+        match self.am.load_str(src) {
+            Ok(warnings) => Ok(diagnostics_json(&warnings, &normalized)),
+            Err(errors) => Err(diagnostics_json(&errors, &normalized).into()),
+        }
+    }
+
+    /// Evaluate an expression against the definitions so far.
+    pub fn eval(&self, src: &str) -> Result<String, JsValue> {
+        let normalized = normalize_unicode_to_ascii(src);
+        match self.am.eval_str(src) {
+            Ok(v) => Ok(result_to_json(&v)),
+            Err(d) => Err(diagnostics_json(&[d], &normalized).into()),
+        }
+    }
+
+    /// Change a setting as `:set` does, e.g. `set("steps", "5000")`.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), JsValue> {
+        self.am
+            .settings_mut()
+            .set(key, value)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+}
+
+/// Every problem with `src` as a JSON array, without defining anything:
+/// for underlining an editor's contents as they are typed.
+#[wasm_bindgen]
+pub fn diagnostics(src: &str) -> String {
+    let normalized = normalize_unicode_to_ascii(src);
+    let found = match parse(&normalized) {
+        Ok((program, mut diags)) => {
+            diags.extend(check_defs_with(&program.defs, &[], &Builtins::standard()));
+            diags
+        }
+        Err(errors) => errors,
+    };
+    diagnostics_json(&found, &normalized)
+}
+
+fn diagnostics_json(diags: &[Diagnostic], src: &str) -> String {
+    let items: Vec<String> = diags.iter().map(|d| d.to_json(Some(src), INPUT)).collect();
+    format!("[{}]", items.join(","))
+}
+
+// Only what succeeds can run off wasm: a thrown `JsValue` needs a host.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn results_and_warnings_come_back_as_json() {
+        let mut am = Playground::new();
+        assert_eq!(am.am.settings().max_steps, Some(PLAYGROUND_STEPS));
+        let warnings = am.define("@Double(x) = x * 2\n@Const(y) = 1").unwrap();
+        assert!(warnings.starts_with("[{\"code\":\"W0001\""), "{warnings}");
+        assert!(warnings.contains("\"line\":2"), "{warnings}");
+        assert_eq!(am.define("@Triple(x) = x * 3").unwrap(), "[]");
+        assert_eq!(
+            am.eval("Double(21)").unwrap(),
+            "{\"result\":42,\"type\":\"number\"}"
+        );
+        am.set("steps", "10").unwrap();
+        assert_eq!(am.am.settings().max_steps, Some(10));
+    }
+
+    #[test]
+    fn diagnostics_cover_parse_and_check_errors() {
+        assert_eq!(diagnostics("@Double(x) = x * 2"), "[]");
+        let parse = diagnostics("@F(x) = (x");
+        assert!(parse.contains("\"code\":\"E0"), "{parse}");
+        assert!(parse.contains("\"file\":\"input\""), "{parse}");
+        let check = diagnostics("@F(x) = y");
+        assert!(check.contains("\"code\":\"E0301\""), "{check}");
+        assert!(check.contains("\"line\":1,\"column\":9"), "{check}");
+    }
+}
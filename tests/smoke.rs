@@ -1,11 +1,14 @@
-use std::process::Command;
+use std::process::{Command, Output};
 
-fn run(file: &str, call: &str) -> String {
-    let out = Command::new(env!("CARGO_BIN_EXE_amlang"))
-        .args([file, "--call", call])
+fn run_with(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_amlang"))
+        .args(args)
         .output()
-        .expect("run failed");
-    String::from_utf8(out.stdout).unwrap()
+        .expect("run failed")
+}
+
+fn run(file: &str, call: &str) -> String {
+    String::from_utf8(run_with(&[file, "--call", call]).stdout).unwrap()
 }
 
 #[test]
@@ -13,3 +16,2389 @@ fn add_works() {
     let s = run("examples/add.am", "Add(1,4)");
     assert!(s.trim().ends_with("= 5"));
 }
+
+#[test]
+fn string_labels() {
+    let s = run("examples/strings.am", "Label(-2)");
+    assert!(s.trim().ends_with("= \"x is negative\""));
+    let s = run("examples/strings.am", "len(Sign(0))");
+    assert!(s.trim().ends_with("= 4"));
+}
+
+#[test]
+fn recursion_depth_is_capped() {
+    let out = run_with(&[
+        "examples/factorial.am",
+        "--call",
+        "Fact(50)",
+        "--max-depth",
+        "20",
+    ]);
+    let err = String::from_utf8(out.stderr).unwrap();
+    assert!(!out.status.success());
+    assert!(err.contains("maximum recursion depth exceeded"));
+    let s = run("examples/factorial.am", "Fact(5)");
+    assert!(s.trim().ends_with("= 120"));
+}
+
+#[test]
+fn deep_recursion_does_not_overflow() {
+    let s = run("examples/add.am", "Add(5000, 3)");
+    assert!(s.trim().ends_with("= 5003"));
+}
+
+#[test]
+fn tail_calls_run_in_constant_depth() {
+    let out = run_with(&[
+        "examples/gcd.am",
+        "--call",
+        "Countdown(100000)",
+        "--max-depth",
+        "10",
+    ]);
+    assert!(
+        String::from_utf8(out.stdout)
+            .unwrap()
+            .trim()
+            .ends_with("= 0")
+    );
+    let s = run("examples/gcd.am", "Gcd(1071, 462)");
+    assert!(s.trim().ends_with("= 21"));
+}
+
+#[test]
+fn math_builtins() {
+    let s = run("examples/add.am", "log(8, 2) + log2(4) + exp(0)");
+    assert!(s.trim().ends_with("= 6"));
+    let out = run_with(&["examples/add.am", "--call", "atan2(1)"]);
+    let err = String::from_utf8(out.stderr).unwrap();
+    assert!(err.contains("atan2 expects 2 args, got 1"));
+}
+
+#[test]
+fn rounding_and_clamping() {
+    let s = run("examples/add.am", "trunc(-2.7) + clamp(15, 0, 10)");
+    assert!(s.trim().ends_with("= 8"));
+    let out = run_with(&["examples/add.am", "--call", "clamp(1, 5, 0)"]);
+    assert!(String::from_utf8(out.stderr).unwrap().contains("lo <= hi"));
+}
+
+#[test]
+fn number_theory_builtins() {
+    let s = run(
+        "examples/add.am",
+        "gcd(1071, 462) + lcm(4, 6) + mod_pow(2, 10, 1000)",
+    );
+    assert!(s.trim().ends_with("= 57"));
+    let s = run("examples/add.am", "binomial(52, 5)");
+    assert!(s.trim().ends_with("= 2598960"));
+    let s = run("examples/add.am", "is_prime(97) && !is_prime(91)");
+    assert!(s.trim().ends_with("= true"));
+}
+
+#[test]
+fn modulo_semantics_are_selectable() {
+    let s = run("examples/add.am", "-1 % 5");
+    assert!(s.trim().ends_with("= 4"));
+    let out = run_with(&["examples/add.am", "--call", "-1 % 5", "--mod", "truncated"]);
+    assert!(
+        String::from_utf8(out.stdout)
+            .unwrap()
+            .trim()
+            .ends_with("= -1")
+    );
+    let s = run("examples/add.am", "2 ^ 3 ^ 2");
+    assert!(s.trim().ends_with("= 512"));
+}
+
+#[test]
+fn logical_operators_short_circuit() {
+    let s = run("examples/add.am", "false && undefined_name > 1");
+    assert!(s.trim().ends_with("= false"));
+    let s = run("examples/add.am", "true || len(1)");
+    assert!(s.trim().ends_with("= true"));
+}
+
+#[test]
+fn runtime_errors_point_at_the_source() {
+    let out = run_with(&["examples/add.am", "--call", "Add(1, y)"]);
+    let err = String::from_utf8(out.stderr).unwrap();
+    assert!(!out.status.success());
+    assert!(err.contains("unknown identifier: y"));
+    assert!(err.contains("Add(1, y)"));
+    assert!(err.contains("       ^ here"));
+}
+
+#[test]
+fn runtime_errors_show_the_call_trace() {
+    let out = run_with(&[
+        "examples/factorial.am",
+        "--call",
+        "Fact(50)",
+        "--max-depth",
+        "20",
+    ]);
+    let err = String::from_utf8(out.stderr).unwrap();
+    assert!(err.contains("trace: in @Fact(50) → in @Fact(49)"));
+    assert!(err.contains("more calls"));
+}
+
+#[test]
+fn unknown_names_suggest_close_matches() {
+    let out = run_with(&["examples/add.am", "--call", "sqr(4)"]);
+    let err = String::from_utf8(out.stderr).unwrap();
+    assert!(err.contains("unknown function: sqr (did you mean `sqrt`?)"));
+    let out = run_with(&["examples/add.am", "--call", "@Ad(1, 2)"]);
+    let err = String::from_utf8(out.stderr).unwrap();
+    assert!(err.contains("unknown algorithm: Ad (did you mean `Add`?)"));
+}
+
+#[test]
+fn errors_can_be_reported_as_json() {
+    let out = run_with(&[
+        "examples/add.am",
+        "--call",
+        "Add(1, y)",
+        "--error-format=json",
+    ]);
+    let err = String::from_utf8(out.stderr).unwrap();
+    assert!(!out.status.success());
+    assert!(err.contains("\"code\":\"E0201\""));
+    assert!(err.contains("\"span\":{\"start\":7,\"end\":8,\"line\":1,\"column\":8}"));
+}
+
+#[test]
+fn parse_errors_are_all_reported() {
+    let path = std::env::temp_dir().join("amlang_smoke_broken.am");
+    std::fs::write(
+        &path,
+        "@A(x) = [ x > 0 ? x * ; _ ? 0 ]\n@B(x) = x + 1\n@C(x) = (x + 1\n@D(x) = [ x > 1 ? 1 ]\n",
+    )
+    .unwrap();
+    let out = run_with(&[path.to_str().unwrap()]);
+    let err = String::from_utf8(out.stderr).unwrap();
+    assert!(!out.status.success());
+    assert_eq!(err.matches("error[").count(), 2);
+}
+
+#[test]
+fn lexer_errors_are_reported_before_parsing() {
+    let out = run_with(&["examples/add.am", "--call", "Add(1, 2) $ 3"]);
+    let err = String::from_utf8(out.stderr).unwrap();
+    assert!(!out.status.success());
+    assert!(err.contains("error[E0001]: unexpected character '$'"));
+}
+
+#[test]
+fn check_reports_problems_in_definitions() {
+    let path = std::env::temp_dir().join("amlang_smoke_check.am");
+    std::fs::write(
+        &path,
+        "@F(x, x) = x + y\n@G(n) = @F(n) + sqr(n)\n@G(n) = n\n",
+    )
+    .unwrap();
+    let out = run_with(&[path.to_str().unwrap(), "--check"]);
+    let err = String::from_utf8(out.stderr).unwrap();
+    assert!(!out.status.success());
+    for code in ["E0301", "E0302", "E0303", "E0304", "E0305"] {
+        assert!(err.contains(code), "missing {code} in {err}");
+    }
+    let out = run_with(&["examples/add.am", "--check"]);
+    assert!(out.status.success());
+}
+
+#[test]
+fn suspicious_code_warns_and_can_be_denied() {
+    let path = std::env::temp_dir().join("amlang_smoke_warn.am");
+    std::fs::write(&path, "@sqrt(x, y) = [ 1 < 2 ? x ; _ ? 0 ; x > 1 ? 2 ]\n").unwrap();
+    let out = run_with(&[path.to_str().unwrap(), "--check"]);
+    let err = String::from_utf8(out.stderr).unwrap();
+    assert!(out.status.success());
+    for code in ["W0001", "W0002", "W0003", "W0004"] {
+        assert!(err.contains(code), "missing {code} in {err}");
+    }
+    let out = run_with(&[path.to_str().unwrap(), "--check", "--deny-warnings"]);
+    assert!(!out.status.success());
+}
+
+#[test]
+fn ill_typed_definitions_are_rejected_before_running() {
+    let path = std::env::temp_dir().join("amlang_smoke_types.am");
+    std::fs::write(
+        &path,
+        "@F(x) = [ x > 0 ? 1 ; _ ? x + (x > 1) ]\n@G(x) = !@Sq(x)\n@Sq(x) = x * x\n",
+    )
+    .unwrap();
+    let out = run_with(&[path.to_str().unwrap(), "--call", "F(1)"]);
+    let err = String::from_utf8(out.stderr).unwrap();
+    assert!(!out.status.success());
+    assert_eq!(err.matches("E0306").count(), 2, "{err}");
+    assert!(err.contains("expected bool, found number"));
+}
+
+#[test]
+fn interpreter_can_be_embedded() {
+    use amlang::{Interpreter, Value};
+
+    let mut am = Interpreter::new();
+    am.load_str("@Sq(x) = x * x\n@Hyp(a, b) = sqrt(@Sq(a) + @Sq(b))")
+        .unwrap();
+    let v = am.call("Hyp", &[Value::Number(3.0), Value::Number(4.0)]);
+    assert_eq!(v.unwrap().to_string(), "5");
+
+    // a bad definition is rejected and leaves the session unchanged
+    let errors = am.load_str("@Sq(x) = @Cube(x)").unwrap_err();
+    assert_eq!(errors[0].code, "E0302");
+    assert_eq!(
+        am.call("Sq", &[Value::Number(3.0)]).unwrap().to_string(),
+        "9"
+    );
+    assert_eq!(am.call("Nope", &[]).unwrap_err().code, "E0202");
+}
+
+#[test]
+fn one_shot_evaluation() {
+    assert_eq!(amlang::eval_str("1 + 2 * 3").unwrap().to_string(), "7");
+    assert_eq!(amlang::eval_str("1 +").unwrap_err().code, "E0101");
+    let src = "@Fact(n) = [ n <= 1 ? 1 ; _ ? n * @Fact(n - 1) ]";
+    let v = amlang::run_program(src, "Fact", &[5.0]).unwrap();
+    assert_eq!(v.to_string(), "120");
+}
+
+#[test]
+fn ast_can_be_printed_as_json() {
+    let out = run_with(&["examples/add.am", "--ast-json"]);
+    let s = String::from_utf8(out.stdout).unwrap();
+    assert!(out.status.success());
+    assert!(s.starts_with("[{\"name\":\"S\",\"params\":[\"x\"],\"body\":{\"kind\":{\"Bin\""));
+    assert!(s.contains("{\"Call\":{\"is_alg\":true,\"name\":\"Add\""));
+}
+
+#[test]
+fn call_results_can_be_printed_as_json() {
+    let out = run_with(&["examples/add.am", "--call", "Add(1,4)", "--json"]);
+    let s = String::from_utf8(out.stdout).unwrap();
+    assert_eq!(s.trim(), "{\"result\":5,\"type\":\"number\"}");
+    let out = run_with(&["examples/strings.am", "--call", "Label(-2)", "--json"]);
+    let s = String::from_utf8(out.stdout).unwrap();
+    assert_eq!(
+        s.trim(),
+        "{\"result\":\"x is negative\",\"type\":\"string\"}"
+    );
+    let out = run_with(&["examples/add.am", "--call", "Nope(1)", "--json"]);
+    let err = String::from_utf8(out.stderr).unwrap();
+    assert!(!out.status.success());
+    assert!(err.starts_with("{\"code\":\"E0202\""), "{err}");
+}
+
+#[test]
+fn several_files_can_be_loaded_together() {
+    let dir = std::env::temp_dir().join("amlang_smoke_multi");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("lib.am"), "@Sq(x) = x * x\n").unwrap();
+    std::fs::write(dir.join("main.am"), "@Hyp(a, b) = sqrt(@Sq(a) + @Sq(b))\n").unwrap();
+    let s = run(dir.to_str().unwrap(), "Hyp(3, 4)");
+    assert!(s.trim().ends_with("= 5"));
+
+    let other = std::env::temp_dir().join("amlang_smoke_dup.am");
+    std::fs::write(&other, "@Sq(x) = x\n").unwrap();
+    let out = run_with(&[dir.to_str().unwrap(), other.to_str().unwrap(), "--check"]);
+    let err = String::from_utf8(out.stderr).unwrap();
+    assert!(!out.status.success());
+    assert!(
+        err.contains("E0305") && err.contains("amlang_smoke_dup.am:1:1"),
+        "{err}"
+    );
+}
+
+#[test]
+fn files_can_import_each_other() {
+    let dir = std::env::temp_dir().join("amlang_smoke_import");
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    std::fs::write(dir.join("lib/sq.am"), "@Sq(x) = x * x\n").unwrap();
+    std::fs::write(
+        dir.join("main.am"),
+        "import \"lib/sq.am\"\n@Hyp(a, b) = sqrt(@Sq(a) + @Sq(b))\n",
+    )
+    .unwrap();
+    let s = run(dir.join("main.am").to_str().unwrap(), "Hyp(3, 4)");
+    assert!(s.trim().ends_with("= 5"));
+
+    std::fs::write(dir.join("a.am"), "import \"b.am\"\n@A(x) = x\n").unwrap();
+    std::fs::write(dir.join("b.am"), "import \"a.am\"\n@B(x) = x\n").unwrap();
+    let out = run_with(&[dir.join("a.am").to_str().unwrap(), "--check"]);
+    let err = String::from_utf8(out.stderr).unwrap();
+    assert!(!out.status.success());
+    assert!(err.contains("E0402"), "{err}");
+}
+
+#[test]
+fn watching_reruns_when_a_loaded_file_changes() {
+    use amlang::loader::{Watcher, load_files};
+    use std::time::{Duration, SystemTime};
+
+    let dir = std::env::temp_dir().join("amlang_smoke_watch");
+    std::fs::create_dir_all(&dir).unwrap();
+    let (main, lib) = (dir.join("main.am"), dir.join("lib.am"));
+    std::fs::write(&lib, "@Sq(x) = x * x\n").unwrap();
+    std::fs::write(&main, "import \"lib.am\"\n@F(x) = @Sq(x)\n").unwrap();
+    let roots = vec![main.to_str().unwrap().to_string()];
+    let load = |loaded: &mut Vec<String>| {
+        let files = load_files(&roots, &[])?;
+        *loaded = files.into_iter().map(|f| f.path).collect();
+        Ok::<_, String>(())
+    };
+    let touch = |path: &std::path::Path, secs: u64| {
+        let file = std::fs::File::options().write(true).open(path).unwrap();
+        let later = SystemTime::now() + Duration::from_secs(secs);
+        file.set_modified(later).unwrap();
+    };
+
+    // imported files are watched too
+    let mut watcher = Watcher::default();
+    watcher.rerun(load).unwrap();
+    assert_eq!(watcher.paths().len(), 2);
+    assert!(!watcher.changed());
+    touch(&lib, 10);
+    assert!(watcher.changed());
+
+    // a re-run starts from the files as they are now
+    watcher.rerun(load).unwrap();
+    assert!(!watcher.changed());
+
+    // a failed run keeps watching what it watched before
+    let failed = watcher.rerun(|_| Err("broken"));
+    assert_eq!(failed, Err("broken"));
+    assert_eq!(watcher.paths().len(), 2);
+    assert!(!watcher.changed());
+    touch(&main, 20);
+    assert!(watcher.changed());
+
+    watcher.rerun(load).unwrap();
+    std::fs::remove_file(&lib).unwrap();
+    assert!(watcher.changed());
+}
+
+#[test]
+fn imports_search_include_dirs_and_am_path() {
+    let dir = std::env::temp_dir().join("amlang_smoke_am_path");
+    std::fs::create_dir_all(dir.join("libs")).unwrap();
+    std::fs::write(dir.join("libs/stats.am"), "@Mean2(a, b) = (a + b) / 2\n").unwrap();
+    let main = dir.join("main.am");
+    std::fs::write(&main, "import \"stats\"\n@Mid(x) = @Mean2(0, x)\n").unwrap();
+    let main = main.to_str().unwrap();
+    let libs = dir.join("libs");
+
+    let out = run_with(&[main, "--check"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8(out.stderr).unwrap().contains("E0401"));
+
+    let out = run_with(&[
+        main,
+        "--include",
+        libs.to_str().unwrap(),
+        "--call",
+        "Mid(8)",
+    ]);
+    assert!(
+        String::from_utf8(out.stdout)
+            .unwrap()
+            .trim()
+            .ends_with("= 4")
+    );
+
+    let out = Command::new(env!("CARGO_BIN_EXE_amlang"))
+        .args([main, "--call", "Mid(8)"])
+        .env("AM_PATH", &libs)
+        .output()
+        .unwrap();
+    assert!(
+        String::from_utf8(out.stdout)
+            .unwrap()
+            .trim()
+            .ends_with("= 4")
+    );
+}
+
+#[test]
+fn files_can_end_with_script_expressions() {
+    let path = std::env::temp_dir().join("amlang_smoke_script.am");
+    std::fs::write(&path, "@Sq(x) = x * x\n@Sq(3) + 1\n\"done\"\n2 * 3; -1\n").unwrap();
+    let out = run_with(&[path.to_str().unwrap()]);
+    let s = String::from_utf8(out.stdout).unwrap();
+    assert_eq!(s, "= 10\n= \"done\"\n= 6\n= -1\n");
+
+    std::fs::write(&path, "1 + 2\n").unwrap();
+    let s = String::from_utf8(run_with(&[path.to_str().unwrap()]).stdout).unwrap();
+    assert_eq!(s, "= 3\n");
+}
+
+#[test]
+fn script_lines_end_at_line_breaks() {
+    let path = std::env::temp_dir().join("amlang_smoke_lines.am");
+    let src = "@F(x) = x * 2\nF(2)\n-1\n(3 + 4)\n@G(x) =\n  x\n  + 1\nG(1) +\n  (2\n  - 1)\n";
+    std::fs::write(&path, src).unwrap();
+    let out = run_with(&[path.to_str().unwrap()]);
+    let s = String::from_utf8(out.stdout).unwrap();
+    assert_eq!(s, "= 4\n= -1\n= 7\n= 3\n");
+}
+
+#[test]
+fn many_calls_run_in_one_process() {
+    let out = run_with(&[
+        "examples/add.am",
+        "--call",
+        "Add(1,4)",
+        "--call",
+        "Add(2,2)",
+    ]);
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "= 5\n= 4\n");
+
+    let batch = std::env::temp_dir().join("amlang_smoke_batch.txt");
+    std::fs::write(&batch, "Add(1, 2)\n// skipped\n\nNope(1)\nAdd(3, 3)\n").unwrap();
+    let out = run_with(&[
+        "examples/add.am",
+        "--calls",
+        batch.to_str().unwrap(),
+        "--csv",
+    ]);
+    let s = String::from_utf8(out.stdout).unwrap();
+    let err = String::from_utf8(out.stderr).unwrap();
+    assert!(!out.status.success());
+    assert_eq!(
+        s,
+        "call,result\n\"Add(1, 2)\",3\nNope(1),\n\"Add(3, 3)\",6\n"
+    );
+    assert!(err.contains("amlang_smoke_batch.txt:4:1"), "{err}");
+    assert!(err.contains("1 of 3 calls failed"));
+}
+
+#[test]
+fn subcommands_have_usage_text() {
+    let out = run_with(&["check", "examples/add.am"]);
+    let s = String::from_utf8(out.stdout).unwrap();
+    assert!(s.contains("3 algorithm(s) checked, no errors"));
+
+    let out = run_with(&["check", "--help"]);
+    assert!(
+        String::from_utf8(out.stdout)
+            .unwrap()
+            .starts_with("usage: amlang check")
+    );
+
+    let out = run_with(&["check", "examples/add.am", "--call", "Add(1,2)"]);
+    let err = String::from_utf8(out.stderr).unwrap();
+    assert!(!out.status.success());
+    assert!(err.contains("unknown flag: --call") && err.contains("usage: amlang check"));
+
+    let out = run_with(&["run", "examples/add.am", "--call", "Add(1,2)"]);
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "= 3\n");
+}
+
+#[test]
+fn fmt_lays_out_files_canonically() {
+    let out = run_with(&["fmt", "examples/add.am"]);
+    let formatted = String::from_utf8(out.stdout).unwrap();
+    assert!(formatted.contains("@P(x) = [\n  x = 0 ? 0;\n  _ ? x - 1\n]\n"));
+    assert!(formatted.contains("@Add(a, b) = [\n  b = 0 ? a;\n"));
+
+    let dir = std::env::temp_dir().join("amlang_smoke_fmt");
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("messy.am");
+    std::fs::write(&file, "// keep me\n@A(x)=x+1; // and me\n\n\n-A(2)\n").unwrap();
+    let path = file.to_str().unwrap();
+
+    let out = run_with(&["fmt", "--check", path]);
+    assert!(!out.status.success());
+    assert!(
+        String::from_utf8(out.stdout)
+            .unwrap()
+            .contains("is not formatted")
+    );
+
+    assert!(run_with(&["fmt", "--write", path]).status.success());
+    let written = std::fs::read_to_string(&file).unwrap();
+    assert_eq!(written, "// keep me\n@A(x) = x + 1; // and me\n\n-A(2)\n");
+    assert!(run_with(&["fmt", "--check", path]).status.success());
+
+    std::fs::write(&file, "@A(x) = x + /* lost */ 1\n").unwrap();
+    let out = run_with(&["fmt", path]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8(out.stderr).unwrap().contains("E0501"));
+}
+
+#[test]
+fn definitions_export_as_latex() {
+    let out = run_with(&["examples/add.am", "--latex"]);
+    let s = String::from_utf8(out.stdout).unwrap();
+    assert!(s.contains("\\operatorname{P}(x) = \\begin{cases}\n  0 & \\text{if } x = 0 \\\\\n"));
+    assert!(s.contains("x - 1 & \\text{otherwise}\n\\end{cases}"));
+    assert!(!s.contains("Loaded"));
+
+    let dir = std::env::temp_dir().join("amlang_smoke_latex");
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("quad.am");
+    std::fs::write(
+        &file,
+        "@Root(a, b, c) = (-b + sqrt(b^2 - 4*a*c)) / (2*a)\n@Big(x) = x >= 10\n",
+    )
+    .unwrap();
+    let out = run_with(&[file.to_str().unwrap(), "--latex"]);
+    let s = String::from_utf8(out.stdout).unwrap();
+    assert!(s.contains("\\frac{-b + \\sqrt{b^{2} - 4 \\cdot a \\cdot c}}{2 \\cdot a}"));
+    assert!(s.contains("x \\geq 10"));
+}
+
+#[test]
+fn definitions_export_as_mathml() {
+    let out = run_with(&["examples/add.am", "--mathml"]);
+    let s = String::from_utf8(out.stdout).unwrap();
+    assert_eq!(s.lines().count(), 3);
+    assert!(s.starts_with(
+        "<math display=\"block\"><mrow><mi>S</mi><mo>&#x2061;</mo><mo>(</mo><mi>x</mi><mo>)</mo>"
+    ));
+    assert!(s.contains("<mtable columnalign=\"left\">"));
+    assert!(s.contains("<mtext>otherwise</mtext>"));
+    assert!(s.contains("<mo>&gt;</mo>"));
+}
+
+#[test]
+fn memoized_algorithms_run_in_linear_time() {
+    use amlang::{Interpreter, Value};
+
+    // without the cache this would take billions of calls
+    let mut am = Interpreter::new();
+    am.load_str("@memo @Fib(n) = [ n < 2 ? n ; _ ? @Fib(n - 1) + @Fib(n - 2) ]")
+        .unwrap();
+    let v = am.call("Fib", &[Value::Number(80.0)]).unwrap();
+    assert_eq!(v.to_string(), "23416728348467684");
+
+    // `@memo` is only a marker when another definition follows it
+    am.load_str("@memo(x) = x + 1").unwrap();
+    assert_eq!(
+        am.call("memo", &[Value::Number(1.0)]).unwrap().to_string(),
+        "2"
+    );
+
+    let dir = std::env::temp_dir().join("amlang_smoke_memo");
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("fib.am");
+    std::fs::write(
+        &file,
+        "@Fib(n) = [ n < 2 ? n ; _ ? @Fib(n - 1) + @Fib(n - 2) ]\n",
+    )
+    .unwrap();
+    let out = run_with(&[file.to_str().unwrap(), "--memoize", "--call", "Fib(70)"]);
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        "= 190392490709135\n"
+    );
+}
+
+#[test]
+fn runaway_evaluation_hits_the_budget() {
+    let dir = std::env::temp_dir().join("amlang_smoke_budget");
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("loop.am");
+    std::fs::write(&file, "@Loop(n) = @Loop(n + 1)\n").unwrap();
+    let path = file.to_str().unwrap();
+
+    let out = run_with(&[path, "--call", "Loop(0)", "--max-steps", "1000"]);
+    assert!(!out.status.success());
+    let err = String::from_utf8(out.stderr).unwrap();
+    assert!(err.contains("E0205") && err.contains("evaluation budget exceeded (1000 steps)"));
+
+    let out = run_with(&[path, "--call", "Loop(0)", "--timeout", "100"]);
+    let err = String::from_utf8(out.stderr).unwrap();
+    assert!(err.contains("timed out after 100 ms"));
+
+    let out = run_with(&[
+        "examples/add.am",
+        "--call",
+        "Add(2,3)",
+        "--max-steps",
+        "1000",
+    ]);
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "= 5\n");
+}
+
+#[test]
+fn profile_counts_operations_per_algorithm() {
+    let out = run_with(&["examples/add.am", "--call", "Add(3,4)", "--profile"]);
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "= 7\n");
+    let table = String::from_utf8(out.stderr).unwrap();
+    let row = |name: &str| -> Vec<String> {
+        let line = table.lines().find(|l| l.starts_with(name)).unwrap();
+        line.split_whitespace()
+            .skip(1)
+            .map(str::to_string)
+            .collect()
+    };
+    assert!(table.starts_with("algorithm"));
+    // calls, add, mul, cmp
+    assert_eq!(row("@Add"), ["5", "0", "0", "9"]);
+    assert_eq!(row("@S"), ["4", "4", "0", "0"]);
+    assert_eq!(row("total"), ["13", "8", "0", "13"]);
+}
+
+#[test]
+fn trace_shows_calls_arms_and_pipeline_stages() {
+    let out = run_with(&["examples/add.am", "--call", "Add(2,1)", "--trace"]);
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "= 3\n");
+    let trace = String::from_utf8(out.stderr).unwrap();
+    assert!(trace.starts_with(
+        "→ @Add(2, 1)\n  case b > 0 matched\n  → @P(1)\n    case _ (default)\n  ← @P = 0\n"
+    ));
+
+    let dir = std::env::temp_dir().join("amlang_smoke_trace");
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("pipe.am");
+    std::fs::write(&file, "@Tw(x) = x >> sqrt >> floor\n").unwrap();
+    let out = run_with(&[file.to_str().unwrap(), "--call", "Tw(16)", "--trace"]);
+    assert_eq!(
+        String::from_utf8(out.stderr).unwrap(),
+        "→ @Tw(16)\n  pipe 16 >> sqrt\n  pipe 4 >> floor\n← @Tw = 4\n"
+    );
+}
+
+#[test]
+fn stepper_pauses_at_sub_expressions_and_breakpoints() {
+    use amlang::eval::{Env, Paused, Stepper, World};
+    use amlang::printer::expr_to_source;
+
+    let src = "@Sq(x) = x * x\n@F(a) = @Sq(a + 1) - 1\n@F(2)";
+    let (program, _) = amlang::parse(src).unwrap();
+    let world = World::new(&program.defs);
+    let at = |p: Paused| match p {
+        Paused::At(e) => expr_to_source(e),
+        Paused::Done(v) => format!("= {}", v),
+    };
+
+    let mut s = Stepper::new(&world, Env::base(), &program.script[0]);
+    assert_eq!(at(s.step().unwrap()), "2");
+    assert_eq!(at(s.step().unwrap()), "@Sq(a + 1) - 1");
+    assert_eq!(s.alg(), Some("F"));
+    assert_eq!(s.get("a").unwrap().to_string(), "2");
+    assert_eq!(at(s.step().unwrap()), "@Sq(a + 1)");
+    assert_eq!(at(s.step().unwrap()), "a + 1");
+    // stepping over the call lands back in @F
+    s.step().unwrap();
+    s.step().unwrap();
+    assert_eq!(at(s.step_over().unwrap()), "1");
+    assert_eq!(s.depth(), 1);
+    assert_eq!(at(s.step().unwrap()), "= 8");
+
+    let mut s = Stepper::new(&world, Env::base(), &program.script[0]);
+    assert_eq!(at(s.continue_to(&["Sq".to_string()]).unwrap()), "x * x");
+    assert_eq!(s.get("x").unwrap().to_string(), "3");
+    assert_eq!(s.calls(), ["@F(2)", "@Sq(3)"]);
+    assert_eq!(at(s.continue_to(&[]).unwrap()), "= 8");
+}
+
+#[test]
+fn explain_shows_the_working_of_a_call() {
+    let out = run_with(&["examples/add.am", "--call", "Add(2,1)", "--explain"]);
+    let s = String::from_utf8(out.stdout).unwrap();
+    assert!(s.starts_with(
+        "@Add(2, 1)\n  where a = 2, b = 1\n  if 1 = 0: false\n  if 1 > 0: true\n  = S(@Add(2, P(1)))\n    @P(1)\n"
+    ));
+    assert!(s.contains("    @S(2)\n      where x = 2\n      = 2 + 1\n      = 3\n"));
+    assert!(
+        s.ends_with("  = S(2)\n    @S(2)\n      where x = 2\n      = 2 + 1\n      = 3\n  = 3\n")
+    );
+
+    let out = run_with(&[
+        "examples/add.am",
+        "--call",
+        "S(1) * 2",
+        "--explain=markdown",
+    ]);
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        "- = `S(1) * 2`\n  - **`@S(1)`**\n    - where `x = 1`\n    - = `1 + 1`\n    - = `2`\n- = `2 * 2`\n- = `4`\n"
+    );
+}
+
+#[test]
+fn intervals_propagate_bounds_and_compare_three_valued() {
+    use amlang::{Interpreter, Value};
+
+    let mut am = Interpreter::new();
+    am.load_str(
+        "@Area(r) = 3 * r ^ 2
+@Sign(x) = [ x > 0 ? 1 ; x < 0 ? -1 ; _ ? 0 ]",
+    )
+    .unwrap();
+    let show = |am: &Interpreter, src: &str| am.eval_str(src).unwrap().to_string();
+
+    assert_eq!(show(&am, "interval(1, 2) + interval(10, 20)"), "[11, 22]");
+    assert_eq!(show(&am, "interval(1, 2) - interval(10, 20)"), "[-19, -8]");
+    assert_eq!(show(&am, "interval(-1, 2) * interval(3, 4)"), "[-4, 8]");
+    assert_eq!(show(&am, "1 / interval(2, 4)"), "[0.25, 0.5]");
+    assert_eq!(show(&am, "1 / interval(-1, 1)"), "[-inf, inf]");
+    assert_eq!(show(&am, "interval(-2, 3) ^ 2"), "[0, 9]");
+    assert_eq!(show(&am, "-interval(1, 2)"), "[-2, -1]");
+    assert_eq!(show(&am, "sqrt(interval(4, 9))"), "[2, 3]");
+    assert_eq!(show(&am, "width(interval(1, 1.5) * 2)"), "1");
+
+    let area = am.call("Area", &[Value::Interval(1.0, 2.0)]).unwrap();
+    assert_eq!(area.as_interval().unwrap(), (3.0, 12.0));
+
+    // decided when every pair agrees, unknown when they do not
+    assert_eq!(show(&am, "interval(1, 2) < interval(3, 4)"), "true");
+    assert_eq!(show(&am, "interval(1, 3) < interval(2, 4)"), "unknown");
+    assert_eq!(show(&am, "interval(1, 3) < 2 && false"), "false");
+    assert_eq!(show(&am, "interval(1, 3) < 2 || true"), "true");
+    assert_eq!(show(&am, "!(interval(1, 3) < 2)"), "unknown");
+    assert_eq!(show(&am, "@Sign(interval(1, 2))"), "1");
+    let err = am.eval_str("@Sign(interval(-1, 2))").unwrap_err();
+    assert!(err.message.contains("got unknown"), "{}", err.message);
+    assert!(am.eval_str("interval(1, 2) % 2").is_err());
+    assert!(am.eval_str("interval(2, 1)").is_err());
+}
+
+#[test]
+fn interpreter_calls_keep_big_integer_arguments_exact() {
+    use amlang::bigint::BigInt;
+    use amlang::{Interpreter, Value};
+
+    let mut am = Interpreter::new();
+    am.load_str("@Id(n) = n\n@Next(n) = n + 1").unwrap();
+    am.settings_mut().set("bigint", "on").unwrap();
+    // 2^60 + 1 has no f64 of its own
+    let big = Value::Int(BigInt::from_u64((1 << 60) + 1));
+    assert_eq!(
+        am.call("Id", std::slice::from_ref(&big))
+            .unwrap()
+            .to_string(),
+        "1152921504606846977"
+    );
+    assert_eq!(
+        am.call("Next", &[big]).unwrap().to_string(),
+        "1152921504606846978"
+    );
+}
+
+#[test]
+fn bigint_mode_keeps_large_integers_exact() {
+    use amlang::{Interpreter, Value};
+
+    let mut am = Interpreter::new();
+    am.load_str("@Fact(n) = [ n <= 1 ? 1 ; _ ? n * @Fact(n - 1) ]")
+        .unwrap();
+    // f64 rounds 30! by default
+    let rounded = am.call("Fact", &[Value::Number(30.0)]).unwrap();
+    assert_ne!(rounded.to_string(), "265252859812191058636308480000000");
+
+    am.settings_mut().set("bigint", "on").unwrap();
+    let show = |am: &Interpreter, src: &str| am.eval_str(src).unwrap().to_string();
+    let exact = am.call("Fact", &[Value::Number(30.0)]).unwrap();
+    assert_eq!(exact.to_string(), "265252859812191058636308480000000");
+    assert_eq!(show(&am, "factorial(25)"), "15511210043330985984000000");
+    assert_eq!(
+        show(&am, "binomial(100, 50)"),
+        "100891344545564193334812497256"
+    );
+    assert_eq!(show(&am, "2 ^ 64 + 1"), "18446744073709551617");
+    assert_eq!(show(&am, "(2 ^ 64 + 1) - 2 ^ 64"), "1");
+    assert_eq!(show(&am, "-(2 ^ 64) % 7"), "5");
+    assert_eq!(show(&am, "2 ^ 64 / 2 ^ 60"), "16");
+    assert_eq!(show(&am, "2 ^ 64 + 1 > 2 ^ 64"), "true");
+    // fractions and non-integers stay in floating point
+    assert_eq!(show(&am, "2 ^ 64 / 3"), (2f64.powi(64) / 3.0).to_string());
+    assert_eq!(show(&am, "1 / 4 + 2 ^ 0.5 * 0"), "0.25");
+
+    let dir = std::env::temp_dir().join("amlang_smoke_bigint");
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("fact.am");
+    std::fs::write(&file, "@Fact(n) = [ n <= 1 ? 1 ; _ ? n * @Fact(n - 1) ]\n").unwrap();
+    let out = run_with(&[
+        file.to_str().unwrap(),
+        "--bigint",
+        "--call",
+        "Fact(25)",
+        "--json",
+    ]);
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        "{\"result\":15511210043330985984000000,\"type\":\"number\"}\n"
+    );
+}
+
+#[test]
+fn complex_numbers_have_literals_arithmetic_and_builtins() {
+    use amlang::Interpreter;
+    use amlang::normalize::normalize_unicode_to_ascii;
+
+    let mut am = Interpreter::new();
+    am.load_str("@Mag2(z) = re(z * conj(z))").unwrap();
+    let show = |am: &Interpreter, src: &str| am.eval_str(src).unwrap().to_string();
+
+    assert_eq!(show(&am, "sqrt(-1)"), "1i");
+    assert_eq!(show(&am, "sqrt(-4) + 1"), "1+2i");
+    assert_eq!(show(&am, "1i * 1i"), "-1");
+    assert_eq!(show(&am, "1i ^ 2"), "-1");
+    assert_eq!(show(&am, "(1 + 2i) * (3 - 1i)"), "5+5i");
+    assert_eq!(show(&am, "(1 + 2i) / (1 - 2i)"), "-0.6+0.8i");
+    assert_eq!(show(&am, "-(1 - 2i)"), "-1+2i");
+    assert_eq!(show(&am, "abs(3 + 4i)"), "5");
+    assert_eq!(show(&am, "re(3 + 4i) + im(3 + 4i)"), "7");
+    assert_eq!(show(&am, "conj(3 + 4i)"), "3-4i");
+    assert_eq!(show(&am, "arg(2i) * 2 = pi"), "true");
+    assert_eq!(show(&am, "@Mag2(3 + 4i)"), "25");
+    assert_eq!(show(&am, "3 + 4i = 3 + 4i"), "true");
+    assert!(am.eval_str("1i < 2i").is_err());
+    assert!(am.eval_str("floor(1 + 1i)").is_err());
+
+    // `ⅈ` is the unit on its own or after a coefficient
+    let src = normalize_unicode_to_ascii("2ⅈ + ⅈ");
+    assert_eq!(src, "2i + 1i");
+    assert_eq!(show(&am, &src), "3i");
+}
+
+#[test]
+fn a_bare_i_is_the_imaginary_unit_unless_a_parameter() {
+    use amlang::Interpreter;
+
+    let mut am = Interpreter::new();
+    am.load_str("@Rot(z) = z * i\n@Twice(i) = 2 * i").unwrap();
+    let show = |am: &Interpreter, src: &str| am.eval_str(src).unwrap().to_string();
+    assert_eq!(show(&am, "i * i"), "-1");
+    assert_eq!(show(&am, "i * i = -1"), "true");
+    assert_eq!(show(&am, "(1 + 2i) * (3 - i)"), "5+5i");
+    assert_eq!(show(&am, "@Rot(1)"), "1i");
+    assert_eq!(show(&am, "@Twice(3)"), "6");
+
+    let out = run_with(&["examples/add.am", "--call", "i*i", "--call", "(1+2i)*(3-i)"]);
+    let s = String::from_utf8(out.stdout).unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(s.contains("= -1\n") && s.contains("= 5+5i\n"), "{s}");
+}
+
+#[test]
+fn approximate_equality_uses_the_configured_epsilon() {
+    use amlang::Interpreter;
+    use amlang::normalize::normalize_unicode_to_ascii;
+
+    let mut am = Interpreter::new();
+    let show = |am: &Interpreter, src: &str| am.eval_str(src).unwrap().to_string();
+    assert_eq!(show(&am, "0.1 + 0.2 = 0.3"), "false");
+    assert_eq!(show(&am, "0.1 + 0.2 ~= 0.3"), "true");
+    assert_eq!(show(&am, "1 ~= 1.001"), "false");
+    // relative once the operands exceed 1
+    assert_eq!(show(&am, "1000000000000 + 1 ~= 1000000000000"), "true");
+    assert_eq!(show(&am, "inf ~= 2 ^ 1000"), "false");
+    assert_eq!(
+        show(&am, &normalize_unicode_to_ascii("0.1 + 0.2 ≈ 0.3")),
+        "true"
+    );
+
+    am.settings_mut().set("epsilon", "0.01").unwrap();
+    assert_eq!(show(&am, "1 ~= 1.001"), "true");
+    assert!(am.settings_mut().set("epsilon", "-1").is_err());
+
+    let dir = std::env::temp_dir().join("amlang_smoke_approx");
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("close.am");
+    std::fs::write(&file, "@Close(a, b) = [ a ~= b ? \"yes\" ; _ ? \"no\" ]\n").unwrap();
+    let out = run_with(&[
+        file.to_str().unwrap(),
+        "--epsilon",
+        "0.5",
+        "--call",
+        "Close(1, 1.2)",
+    ]);
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "= \"yes\"\n");
+}
+
+#[test]
+fn strict_mode_reports_nan_and_infinity_where_they_appear() {
+    use amlang::Interpreter;
+
+    let mut am = Interpreter::new();
+    am.load_str("@Inv(x) = 1 / x\n@Root(x) = sqrt(x) + 1")
+        .unwrap();
+    // by default they flow through
+    assert_eq!(am.eval_str("@Inv(0)").unwrap().to_string(), "inf");
+
+    am.settings_mut().set("strict", "on").unwrap();
+    let message = |am: &Interpreter, src: &str| am.eval_str(src).unwrap_err().message;
+    assert_eq!(message(&am, "@Inv(0)"), "division by zero");
+    assert_eq!(message(&am, "@Root(-4)"), "sqrt(-4) is not a real number");
+    assert_eq!(message(&am, "5 % 0"), "modulo by zero");
+    assert_eq!(message(&am, "10 ^ 400"), "10 ^ 400 overflows");
+    assert_eq!(message(&am, "ln(0 - 1)"), "ln(-1) is undefined");
+    // values that are already infinite may still be used
+    assert_eq!(am.eval_str("inf + 1").unwrap().to_string(), "inf");
+    assert_eq!(am.eval_str("@Inv(4)").unwrap().to_string(), "0.25");
+
+    let dir = std::env::temp_dir().join("amlang_smoke_strict");
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("inv.am");
+    std::fs::write(&file, "@Inv(x) = 1 / x\n").unwrap();
+    let out = run_with(&[file.to_str().unwrap(), "--strict", "--call", "Inv(0)"]);
+    assert!(!out.status.success());
+    let err = String::from_utf8(out.stderr).unwrap();
+    assert!(err.contains("division by zero"), "{}", err);
+    assert!(err.contains("1:11"), "{}", err);
+}
+
+#[test]
+fn algorithms_are_values_that_parameters_can_call() {
+    use amlang::Interpreter;
+
+    let src = "@Square(x) = x * x
+@Twice(f, x) = f(f(x))
+@Apply(f, x) = x >> f
+@Bisect(f, lo, hi) = [ hi - lo < 0.000001 ? lo ; f((lo + hi) / 2) > 0 ? @Bisect(f, lo, (lo + hi) / 2) ; _ ? @Bisect(f, (lo + hi) / 2, hi) ]
+@Cubic(x) = x ^ 3 - 2";
+    let mut am = Interpreter::new();
+    am.load_str(src).unwrap();
+    let show = |am: &Interpreter, src: &str| am.eval_str(src).unwrap().to_string();
+
+    assert_eq!(show(&am, "@Square"), "@Square");
+    assert_eq!(show(&am, "@Twice(@Square, 3)"), "81");
+    assert_eq!(show(&am, "@Apply(@Square, 5)"), "25");
+    assert_eq!(show(&am, "4 >> @Square"), "16");
+    assert_eq!(show(&am, "@Square = @Square"), "true");
+    let root: f64 = show(&am, "@Bisect(@Cubic, 0, 2)").parse().unwrap();
+    assert!((root - 2f64.cbrt()).abs() < 1e-5);
+    assert!(am.eval_str("@Square + 1").is_err());
+
+    // the checker flags references to algorithms that do not exist
+    let diags = am.load_str("@G(x) = @Twice(@Sqaure, x)").unwrap_err();
+    assert!(
+        diags
+            .iter()
+            .any(|d| d.message.contains("unknown algorithm: Sqaure"))
+    );
+}
+
+#[test]
+fn lambdas_capture_their_environment_and_can_be_passed_around() {
+    use amlang::Interpreter;
+
+    let src = "@Twice(f, x) = f(f(x))
+@Adder(n) = \\x -> x + n
+@Apply(f, x) = f(x)
+@Compose(f, g) = \\x -> g(f(x))";
+    let mut am = Interpreter::new();
+    am.load_str(src).unwrap();
+    let show = |am: &Interpreter, src: &str| am.eval_str(src).unwrap().to_string();
+
+    assert_eq!(show(&am, "@Twice(\\x -> x * x, 3)"), "81");
+    assert_eq!(show(&am, "5 >> \\x -> x * 2"), "10");
+    assert_eq!(show(&am, "2 >> (\\x -> x + 1) >> (\\y -> y * 10)"), "30");
+    assert_eq!(show(&am, "@Apply(@Adder(2), 40)"), "42");
+    assert_eq!(
+        show(&am, "@Apply(@Compose(@Adder(1), \\(x) -> x * x), 4)"),
+        "25"
+    );
+    assert_eq!(show(&am, "@Apply(λ x → x - 1, 1)"), "0");
+    // printed with the captured values written in
+    assert_eq!(show(&am, "@Adder(3)"), "\\x -> x + 3");
+    assert!(am.eval_str("@Apply(\\(a, b) -> a + b, 1)").is_err());
+    assert!(am.eval_str("(\\x -> x) + 1").is_err());
+
+    // the checker sees the lambda's parameters, and only them
+    assert!(am.load_str("@G(y) = @Apply(\\x -> x + y, 1)").is_ok());
+    let diags = am.load_str("@H(y) = @Apply(\\x -> x + z, y)").unwrap_err();
+    assert!(
+        diags
+            .iter()
+            .any(|d| d.message.contains("unknown identifier: z"))
+    );
+}
+
+#[test]
+fn pipeline_placeholder_puts_the_value_at_any_argument() {
+    use amlang::Interpreter;
+
+    let mut am = Interpreter::new();
+    am.load_str("@Sub(a, b) = a - b\n@Rev(x) = x >> @Sub(10, _)")
+        .unwrap();
+    let show = |am: &Interpreter, src: &str| am.eval_str(src).unwrap().to_string();
+
+    assert_eq!(show(&am, "3 >> @Sub(10, _)"), "7");
+    assert_eq!(show(&am, "3 >> @Sub(10)"), "-7");
+    assert_eq!(show(&am, "@Rev(4)"), "6");
+    assert_eq!(show(&am, "8 >> log(_, 2)"), "3");
+    assert_eq!(show(&am, "5 >> clamp(_, 0, _)"), "5");
+    assert_eq!(show(&am, "2 >> @Sub(_, 1) >> max(0, _)"), "1");
+    assert!(am.eval_str("_ + 1").is_err());
+
+    // the checker counts `_` as the piped value's slot
+    let diags = am.load_str("@Bad(x) = x >> @Sub(1, _, 2)").unwrap_err();
+    assert!(diags.iter().any(|d| d.message.contains("expects 2 args")));
+}
+
+#[test]
+fn case_blocks_route_piped_values_as_it() {
+    use amlang::Interpreter;
+
+    let mut am = Interpreter::new();
+    am.load_str("@Abs(x) = x >> [ it < 0 ? -it ; _ ? it ]\n@Half(x) = x / 2")
+        .unwrap();
+    let show = |am: &Interpreter, src: &str| am.eval_str(src).unwrap().to_string();
+
+    assert_eq!(show(&am, "@Abs(-3)"), "3");
+    assert_eq!(show(&am, "@Abs(4)"), "4");
+    assert_eq!(
+        show(&am, "9 >> [ _ % 2 = 0 ? @Half(_) ; _ ? 3 * _ + 1 ] >> sqrt"),
+        "5.291502622129181"
+    );
+    assert_eq!(
+        show(&am, "-8 >> [ it < 0 -> \"neg\" ; _ -> \"pos\" ]"),
+        "\"neg\""
+    );
+    // `it` is only bound inside the step
+    assert!(am.eval_str("(1 >> [ it > 0 ? 1 ; _ ? 0 ]) + it").is_err());
+
+    let diags = am.load_str("@Bad(x) = [ it > 0 ? 1 ; _ ? 0 ]").unwrap_err();
+    assert!(
+        diags
+            .iter()
+            .any(|d| d.message.contains("unknown identifier: it"))
+    );
+}
+
+#[test]
+fn ranges_build_sequences_for_map_filter_and_fold() {
+    use amlang::Interpreter;
+
+    let mut am = Interpreter::new();
+    am.load_str(
+        "@Square(x) = x * x
+@SumOfSquares(n) = sum(map(1..n, @Square))
+@Evens(n) = filter(0..<n, \\k -> k % 2 = 0)
+@Fact(n) = fold(1..n, 1, \\(acc, k) -> acc * k)",
+    )
+    .unwrap();
+    let show = |am: &Interpreter, src: &str| am.eval_str(src).unwrap().to_string();
+
+    assert_eq!(show(&am, "1..5"), "[1, 2, 3, 4, 5]");
+    assert_eq!(show(&am, "1..<5"), "[1, 2, 3, 4]");
+    assert_eq!(show(&am, "5..1"), "[]");
+    assert_eq!(show(&am, "1..2 + 1"), "[1, 2, 3]");
+    assert_eq!(show(&am, "@SumOfSquares(10)"), "385");
+    assert_eq!(show(&am, "@Evens(7)"), "[0, 2, 4, 6]");
+    assert_eq!(show(&am, "@Fact(6)"), "720");
+    assert_eq!(show(&am, "prod(1..5)"), "120");
+    assert_eq!(show(&am, "len(1..<10)"), "9");
+    assert_eq!(show(&am, "1..3 >> map(_, \\x -> x * 10)"), "[10, 20, 30]");
+    assert_eq!(show(&am, "map(1..2, \\x -> 1..x)"), "[[1], [1, 2]]");
+    assert_eq!(show(&am, "(1..3) = list(1, 2, 3)"), "true");
+
+    assert!(am.eval_str("map(1..3, 2)").is_err());
+    assert!(am.eval_str("filter(1..3, \\x -> x)").is_err());
+    assert!(am.eval_str("1..1000000000000").is_err());
+
+    let out = run_with(&["examples/add.am", "--call", "1..3", "--json"]);
+    let out = String::from_utf8(out.stdout).unwrap();
+    assert!(out.contains(r#""result":[1,2,3],"type":"list""#), "{out}");
+}
+
+#[test]
+fn series_notation_binds_the_index_for_the_body() {
+    use amlang::Interpreter;
+
+    let mut am = Interpreter::new();
+    am.load_str(
+        "@Geometric(n, x) = sum(i, 0, n, x^i)
+@Fact(n) = Π(k, 1, n, k)",
+    )
+    .unwrap();
+    let show = |am: &Interpreter, src: &str| am.eval_str(src).unwrap().to_string();
+
+    assert_eq!(show(&am, "sum(i, 1, 10, i)"), "55");
+    assert_eq!(show(&am, "Σ(k, 1, 4, k^2)"), "30");
+    assert_eq!(show(&am, "prod(i, 1, 0, i)"), "1");
+    assert_eq!(show(&am, "@Geometric(3, 2)"), "15");
+    assert_eq!(show(&am, "@Fact(5)"), "120");
+    assert_eq!(show(&am, "sum(list(1, 2, 3))"), "6");
+    assert!(am.eval_str("sum(k, 1, 3, k) + k").is_err());
+    // an index named `i` hides the imaginary unit only inside the body
+    assert_eq!(show(&am, "sum(i, 1, 3, i) + i"), "6+1i");
+
+    let errors = am.load_str("@Bad(n) = sum(i, 1, n, j)").unwrap_err();
+    assert!(
+        errors
+            .iter()
+            .any(|d| d.message.contains("unknown identifier: j"))
+    );
+
+    let out = run_with(&["examples/add.am", "--call", "Σ(i, 1, 3, i)"]);
+    assert_eq!(String::from_utf8(out.stdout).unwrap().trim(), "= 6");
+}
+
+#[test]
+fn iterate_applies_a_function_n_times_without_recursing() {
+    use amlang::Interpreter;
+
+    let mut am = Interpreter::new();
+    am.load_str(
+        "@NewtonStep(x) = x - (x^2 - 2) / (2 * x)
+@Sqrt2(n) = iterate(1, n, @NewtonStep)",
+    )
+    .unwrap();
+    let show = |am: &Interpreter, src: &str| am.eval_str(src).unwrap().to_string();
+
+    assert_eq!(show(&am, "@Sqrt2(6)"), "1.414213562373095");
+    assert_eq!(show(&am, "iterate(1, 10, \\x -> x * 2)"), "1024");
+    assert_eq!(show(&am, "iterate(5, 0, \\x -> x * 2)"), "5");
+    assert_eq!(show(&am, "iterate(0, 100000, \\x -> x + 1)"), "100000");
+
+    assert!(am.eval_str("iterate(1, -1, \\x -> x)").is_err());
+    assert!(am.eval_str("iterate(1, 2.5, \\x -> x)").is_err());
+    assert!(am.eval_str("iterate(1, 3, 2)").is_err());
+}
+
+#[test]
+fn bisect_and_newton_find_roots_of_user_functions() {
+    use amlang::Interpreter;
+
+    let mut am = Interpreter::new();
+    am.load_str(
+        "@F(x) = x^2 - 2
+@DF(x) = 2 * x
+@CubeRoot(a) = newton(\\x -> x^3 - a, \\x -> 3 * x^2, 1, 0.000000001)",
+    )
+    .unwrap();
+    let num = |am: &Interpreter, src: &str| am.eval_str(src).unwrap().as_f64().unwrap();
+
+    assert!((num(&am, "bisect(@F, 0, 2, 0.0000001)") - 2f64.sqrt()).abs() < 1e-6);
+    assert!((num(&am, "newton(@F, @DF, 1, 0.000000001)") - 2f64.sqrt()).abs() < 1e-12);
+    assert!((num(&am, "@CubeRoot(27)") - 3.0).abs() < 1e-9);
+    assert_eq!(num(&am, "bisect(\\x -> x - 1, 0, 2, 0.001)"), 1.0);
+
+    let message = |am: &Interpreter, src: &str| am.eval_str(src).unwrap_err().message;
+    assert!(message(&am, "bisect(@F, 2, 3, 0.001)").contains("opposite signs"));
+    assert!(message(&am, "bisect(@F, 0, 2, 0)").contains("positive tolerance"));
+    assert!(message(&am, "newton(@F, \\x -> 0, 1, 0.1)").contains("zero derivative"));
+    assert!(
+        message(&am, "newton(\\x -> x^2 + 1, \\x -> 2 * x, 0.5, 0.0001)")
+            .contains("did not converge")
+    );
+}
+
+#[test]
+fn differentiate_applies_the_rules_and_inlines_algorithms() {
+    use amlang::diff::differentiate;
+    use amlang::eval::World;
+    use amlang::parser::{Tokens, parse_expr, parse_with};
+    use amlang::printer::expr_to_source;
+
+    let (program, _) = amlang::parse(
+        "@Cubic(x) = x^3 + 2 * x
+@Wave(x) = sin(x^2)
+@Ramp(x) = [ x < 0 ? 0 ; _ ? x^2 ]
+@Down(n) = [ n = 0 ? x ; _ ? @Down(n - 1) ]",
+    )
+    .unwrap();
+    let world = World::new(&program.defs);
+    let d = |src: &str| {
+        let expr = parse_with(&mut Tokens::new(amlang::lex(src)), parse_expr).unwrap();
+        differentiate(&world, &expr, "x").map(|e| expr_to_source(&e))
+    };
+
+    assert_eq!(d("@Cubic").unwrap(), "3 * x ^ 2 + 2");
+    assert_eq!(d("@Cubic(x)").unwrap(), "3 * x ^ 2 + 2");
+    assert_eq!(d("@Wave(x)").unwrap(), "cos(x ^ 2) * (2 * x)");
+    assert_eq!(d("@Ramp").unwrap(), "[ x < 0 ? 0 ; _ ? 2 * x ]");
+    assert_eq!(d("2^x").unwrap(), "2 ^ x * ln(2)");
+    assert_eq!(d("1 / x").unwrap(), "-1 / x ^ 2");
+    assert_eq!(d("x >> sqrt").unwrap(), "1 / (2 * sqrt(x))");
+    assert_eq!(d("y * x").unwrap(), "y");
+    assert_eq!(
+        d("sum(i, 1, 3, x^i)").unwrap(),
+        "sum(i, 1, 3, i * x ^ (i - 1))"
+    );
+
+    assert!(d("x = 1").unwrap_err().contains("cannot differentiate"));
+    assert!(d("floor(x)").is_err());
+    assert!(d("@Down(1)").unwrap_err().contains("nest more than"));
+
+    let out = run_with(&["examples/add.am", "--diff", "x", "--call", "x^2 * 3"]);
+    assert_eq!(String::from_utf8(out.stdout).unwrap().trim(), "2 * x * 3");
+}
+
+#[test]
+fn simplify_combines_constants_and_cancels_terms() {
+    use amlang::parser::{Tokens, parse_expr, parse_with};
+    use amlang::printer::expr_to_source;
+    use amlang::simplify::simplify;
+
+    let s = |src: &str| {
+        let expr = parse_with(&mut Tokens::new(amlang::lex(src)), parse_expr).unwrap();
+        expr_to_source(&simplify(&expr))
+    };
+
+    assert_eq!(s("1 + x + 2"), "x + 3");
+    assert_eq!(s("2 * x * 3"), "6 * x");
+    assert_eq!(s("x - y + y"), "x");
+    assert_eq!(s("x - x"), "0");
+    assert_eq!(s("-(-x)"), "x");
+    assert_eq!(s("(x + 1) * 1 + 0"), "x + 1");
+    assert_eq!(s("2 ^ 3 * x / 1"), "8 * x");
+    assert_eq!(s("-x * 3"), "-3 * x");
+    assert_eq!(s("x - 5 + 2"), "x - 3");
+    assert_eq!(s("[ x > 0 ? x + 0 ; _ ? 1 * x ]"), "[ x > 0 ? x ; _ ? x ]");
+    assert_eq!(s("6 / 4 + x"), "6 / 4 + x");
+    assert_eq!(s("\"a\" + 1 + 2"), "\"a\" + 1 + 2");
+
+    let out = run_with(&[
+        "examples/add.am",
+        "--diff",
+        "x",
+        "--simplify",
+        "--call",
+        "x^2 * 3",
+    ]);
+    assert_eq!(String::from_utf8(out.stdout).unwrap().trim(), "6 * x");
+}
+
+#[test]
+fn solve_returns_the_roots_of_an_equation_as_a_list() {
+    use amlang::Interpreter;
+
+    let mut am = Interpreter::new();
+    am.load_str(
+        "@Lin(a) = solve(2 * x + a = 10, x)
+@Q(x) = x^2 - 5 * x + 6
+@Roots() = solve(@Q(t) = 0, t)",
+    )
+    .unwrap();
+    let show = |am: &Interpreter, src: &str| am.eval_str(src).unwrap().to_string();
+
+    assert_eq!(show(&am, "@Lin(4)"), "[3]");
+    assert_eq!(show(&am, "@Roots()"), "[2, 3]");
+    assert_eq!(show(&am, "solve(x^2 = 4, x)"), "[-2, 2]");
+    assert_eq!(show(&am, "solve(x^2 = -1, x)"), "[]");
+    assert_eq!(show(&am, "solve(x^3 = 8, x)"), "[2]");
+    assert_eq!(show(&am, "solve(1 / x = 0, x)"), "[]");
+    assert_eq!(
+        show(
+            &am,
+            "len(solve(sin(x) = 0, x) >> filter(_, \\r -> abs(r) < 7))"
+        ),
+        "5"
+    );
+    let ln2 = am.eval_str("solve(exp(x) = 2, x)").unwrap().to_string();
+    assert!(ln2.starts_with("[0.693147"), "{ln2}");
+
+    assert!(
+        am.eval_str("solve(x = x, x)")
+            .unwrap_err()
+            .message
+            .contains("every value of x")
+    );
+    assert!(am.load_str("@Bad() = solve(y = 1, x)").is_err());
+}
+
+#[test]
+fn matrices_support_literals_elementwise_ops_and_linear_algebra() {
+    use amlang::Interpreter;
+
+    let am = Interpreter::new();
+    let show = |src: &str| am.eval_str(src).unwrap().to_string();
+
+    assert_eq!(show("[[1, 2], [3, 4]]"), "[[1, 2], [3, 4]]");
+    assert_eq!(show("[]"), "[]");
+    assert_eq!(show("[1, 2] + [3, 4]"), "[4, 6]");
+    assert_eq!(show("2 * [1, 2]"), "[2, 4]");
+    assert_eq!(show("-[[1, 2], [3, 4]]"), "[[-1, -2], [-3, -4]]");
+    assert_eq!(
+        show("matmul([[1, 2], [3, 4]], [[5, 6], [7, 8]])"),
+        "[[19, 22], [43, 50]]"
+    );
+    assert_eq!(show("matmul([1, 2], [3, 4])"), "11");
+    assert_eq!(show("det([[1, 2], [3, 4]])"), "-2");
+    assert_eq!(
+        show("transpose([[1, 2, 3], [4, 5, 6]])"),
+        "[[1, 4], [2, 5], [3, 6]]"
+    );
+    assert_eq!(show("inverse([[2, 0], [0, 4]])"), "[[0.5, 0], [0, 0.25]]");
+    // a bracket holding arms is still a case block
+    assert_eq!(show("[ 1 > 0 ? 5 ; _ ? 6 ]"), "5");
+
+    let err = am.eval_str("inverse([[1, 2], [2, 4]])").unwrap_err();
+    assert!(err.message.contains("singular"), "{}", err.message);
+    assert!(am.eval_str("[1, 2] + [1]").is_err());
+}
+
+#[test]
+fn linsolve_solves_square_systems_and_rejects_singular_ones() {
+    use amlang::Interpreter;
+
+    let am = Interpreter::new();
+    let show = |src: &str| am.eval_str(src).unwrap().to_string();
+
+    assert_eq!(show("linsolve([[2, 1], [1, 3]], [3, 5])"), "[0.8, 1.4]");
+    // a zero on the diagonal needs a row swap
+    assert_eq!(show("linsolve([[0, 1], [1, 0]], [2, 3])"), "[3, 2]");
+
+    let err = am
+        .eval_str("linsolve([[1, 2], [2, 4]], [1, 2])")
+        .unwrap_err();
+    assert!(err.message.contains("singular"), "{}", err.message);
+    assert!(am.eval_str("linsolve([[1, 2], [3, 4]], [1])").is_err());
+}
+
+#[test]
+fn statistics_builtins_summarise_lists() {
+    use amlang::Interpreter;
+
+    let am = Interpreter::new();
+    let show = |src: &str| am.eval_str(src).unwrap().to_string();
+
+    assert_eq!(show("mean([1, 2, 3, 4])"), "2.5");
+    assert_eq!(show("median([3, 1, 2])"), "2");
+    assert_eq!(show("median([4, 1, 3, 2])"), "2.5");
+    assert_eq!(show("variance([1, 2, 3, 4, 5])"), "2.5");
+    assert_eq!(show("stddev([2, 4, 6])"), "2");
+    assert_eq!(show("quantile([1, 2, 3, 4, 5], 0.25)"), "2");
+    assert_eq!(show("mode([3, 1, 3, 2, 1])"), "1");
+    assert_eq!(show("correlation([1, 2, 3], [3, 2, 1])"), "-1");
+
+    assert!(am.eval_str("mean([])").is_err());
+    assert!(am.eval_str("variance([1])").is_err());
+    assert!(am.eval_str("quantile([1, 2], 2)").is_err());
+    assert!(am.eval_str("correlation([1, 2], [1, 2, 3])").is_err());
+}
+
+#[test]
+fn simulate_collects_the_results_of_repeated_calls() {
+    use amlang::Interpreter;
+
+    let mut am = Interpreter::new();
+    am.load_str("@Seven() = 7").unwrap();
+    let show = |am: &Interpreter, src: &str| am.eval_str(src).unwrap().to_string();
+
+    assert_eq!(show(&am, "simulate(3, \\() -> 2)"), "[2, 2, 2]");
+    assert_eq!(show(&am, "simulate(0, @Seven)"), "[]");
+    assert_eq!(show(&am, "mean(simulate(4, @Seven))"), "7");
+    assert_eq!(show(&am, "len(simulate(200000, \\() -> 1))"), "200000");
+
+    assert!(am.eval_str("simulate(-1, @Seven)").is_err());
+    assert!(am.eval_str("simulate(2, \\x -> x)").is_err());
+}
+
+#[test]
+fn sorting_and_searching_builtins_work_on_lists() {
+    use amlang::Interpreter;
+
+    let am = Interpreter::new();
+    let show = |src: &str| am.eval_str(src).unwrap().to_string();
+
+    assert_eq!(show("sort([3, 1, 2])"), "[1, 2, 3]");
+    assert_eq!(show("sort([\"pear\", \"apple\"])"), "[\"apple\", \"pear\"]");
+    assert_eq!(show("reverse([1, 2, 3])"), "[3, 2, 1]");
+    assert_eq!(show("reverse(\"abc\")"), "\"cba\"");
+    assert_eq!(show("contains([1, 2, 3], 2)"), "true");
+    assert_eq!(show("contains([1, 2, 3], 5)"), "false");
+    assert_eq!(show("binary_search([1, 3, 5, 7], 5)"), "2");
+    assert_eq!(show("binary_search([1, 3, 5, 7], 4)"), "-1");
+
+    assert!(am.eval_str("sort([1, \"a\"])").is_err());
+}
+
+#[test]
+fn number_literals_accept_scientific_notation() {
+    let show = |src: &str| amlang::eval_str(src).unwrap().to_string();
+
+    assert_eq!(show("1e-9"), "0.000000001");
+    assert_eq!(show("6.02e23"), "602000000000000000000000");
+    assert_eq!(show("2E+8"), "200000000");
+    assert_eq!(show("1.5e3i"), "1500i");
+    assert_eq!(
+        amlang::lex("2e + 1")[1].tok,
+        amlang::token::Token::Ident("e".to_string())
+    );
+}
+
+#[test]
+fn integer_literals_and_builtins_handle_other_bases() {
+    let show = |src: &str| amlang::eval_str(src).unwrap().to_string();
+
+    assert_eq!(show("0x1F"), "31");
+    assert_eq!(show("0b1010 + 0o17"), "25");
+    assert_eq!(show("to_base(255, 16)"), "\"ff\"");
+    assert_eq!(show("to_base(-10, 2)"), "\"-1010\"");
+    assert_eq!(show("from_base(\"ff\", 16)"), "255");
+
+    assert_eq!(amlang::eval_str("0b102").unwrap_err().code, "E0103");
+    assert!(amlang::eval_str("from_base(\"z\", 10)").is_err());
+    assert!(amlang::eval_str("to_base(5, 1)").is_err());
+}
+
+#[test]
+fn number_literals_may_start_with_a_dot() {
+    let show = |src: &str| amlang::eval_str(src).unwrap().to_string();
+
+    assert_eq!(show(".5"), "0.5");
+    assert_eq!(show("-.25"), "-0.25");
+    assert_eq!(show("4 * .5e1"), "20");
+    assert_eq!(show("sum(1..3)"), "6");
+}
+
+#[test]
+fn lexer_keeps_multibyte_characters_whole() {
+    use amlang::token::Token;
+
+    let tokens = amlang::lex("\"héllo ✓\" + ¤ /* é */ 2");
+    assert_eq!(tokens[0].tok, Token::String("héllo ✓".to_string()));
+    assert_eq!(
+        tokens[2].tok,
+        Token::Error("unexpected character '¤'".to_string())
+    );
+    let src = "\"héllo ✓\" + ¤ /* é */ 2";
+    assert_eq!(&src[tokens[2].start..tokens[2].end], "¤");
+    assert_eq!(tokens[3].tok, Token::Number("2".to_string()));
+
+    let errs = amlang::lexer::lex_checked(src).unwrap_err();
+    let shown = errs[0].render_in(Some(src), "<call>");
+    assert!(shown.contains("<call>:1:13"), "{}", shown);
+}
+
+#[test]
+fn unicode_letters_are_valid_names() {
+    use amlang::Interpreter;
+
+    let mut am = Interpreter::new();
+    am.load_str("@Rot(θ, Δx) = cos(θ) * Δx\n@Σ2(λ) = λ * 2")
+        .unwrap();
+    let show = |am: &Interpreter, src: &str| am.eval_str(src).unwrap().to_string();
+
+    assert_eq!(show(&am, "@Rot(0, 3)"), "3");
+    assert_eq!(show(&am, "@Σ2(4)"), "8");
+    // written as operators, λ, Σ and Π still are
+    assert_eq!(show(&am, "map([1, 2], λ x → x + 1)"), "[2, 3]");
+    assert_eq!(show(&am, "Σ(k, 1, 3, k)"), "6");
+
+    let (program, _) = amlang::parse("@Rot(θ, Δx) = cos(θ) * Δx").unwrap();
+    let tex = amlang::latex::def_to_latex(&program.defs[0]);
+    assert!(
+        tex.contains("\\theta") && tex.contains("\\mathit{\\Delta{}x}"),
+        "{}",
+        tex
+    );
+}
+
+#[test]
+fn constants_are_defined_in_bodies_and_at_the_top_level() {
+    use amlang::Interpreter;
+
+    let mut am = Interpreter::new();
+    am.load_str("@Area(r) = π * r^2\n@Growth(t) = e^t\n@Shadow(e) = e + 1")
+        .unwrap();
+    let show = |am: &Interpreter, src: &str| am.eval_str(src).unwrap().to_string();
+
+    assert_eq!(show(&am, "@Area(1) = pi"), "true");
+    assert_eq!(show(&am, "@Growth(1) = e"), "true");
+    assert_eq!(show(&am, "@Shadow(1)"), "2");
+    assert_eq!(show(&am, "tau = τ && tau = 2 * pi"), "true");
+    assert_eq!(show(&am, "phi^2 - φ"), "1");
+}
+
+#[test]
+fn superscripts_normalize_to_powers() {
+    use amlang::normalize::normalize_unicode_to_ascii;
+
+    assert_eq!(normalize_unicode_to_ascii("x² + y³"), "x^2 + y^3");
+    assert_eq!(normalize_unicode_to_ascii("2¹⁰"), "2^10");
+    assert_eq!(normalize_unicode_to_ascii("x⁻¹"), "x^(-1)");
+
+    let mut am = amlang::Interpreter::new();
+    am.load_str("@Hyp(a, b) = sqrt(a² + b²)").unwrap();
+    assert_eq!(am.eval_str("@Hyp(3, 4)").unwrap().to_string(), "5");
+    assert_eq!(am.eval_str("4⁻¹").unwrap().to_string(), "0.25");
+}
+
+#[test]
+fn root_glyphs_normalize_to_root_calls() {
+    use amlang::normalize::normalize_unicode_to_ascii;
+
+    assert_eq!(normalize_unicode_to_ascii("√x + 1"), "sqrt(x) + 1");
+    assert_eq!(normalize_unicode_to_ascii("√(a² + b²)"), "sqrt(a^2 + b^2)");
+    assert_eq!(normalize_unicode_to_ascii("∛(-8)"), "root(-8, 3)");
+    assert_eq!(normalize_unicode_to_ascii("∜16"), "root(16, 4)");
+
+    let show = |src: &str| amlang::eval_str(src).unwrap().to_string();
+    assert_eq!(show("√(9 + 16)"), "5");
+    assert_eq!(show("∛(-8)"), "-2");
+    assert_eq!(show("root(32, 5)"), "2");
+    assert!(amlang::eval_str("root(-4, 2)").is_err());
+}
+
+#[test]
+fn fractions_and_middle_dots_normalize_to_arithmetic() {
+    use amlang::normalize::normalize_unicode_to_ascii;
+
+    assert_eq!(normalize_unicode_to_ascii("½·x"), "(1/2)*x");
+    assert_eq!(normalize_unicode_to_ascii("a ⋅ ¾"), "a * (3/4)");
+
+    let show = |src: &str| amlang::eval_str(src).unwrap().to_string();
+    assert_eq!(show("8 · ¼"), "2");
+    assert_eq!(show("3 ⋅ ⅔"), "2");
+}
+
+#[test]
+fn a_normalize_map_adds_and_overrides_mappings() {
+    let dir = std::env::temp_dir();
+    let map = dir.join("amlang_smoke_normalize.toml");
+    std::fs::write(
+        &map,
+        "[normalize]\n# definitions with :=\n\"：=\" = \"=\"\n\"⊕\" = \"+\" # xor-looking plus\n\"\\u00D7\" = \"-\"\n",
+    )
+    .unwrap();
+    let file = dir.join("amlang_smoke_normalize.am");
+    std::fs::write(&file, "@F(x) ：= x ⊕ 1\n").unwrap();
+
+    let out = run_with(&[
+        "--normalize-map",
+        map.to_str().unwrap(),
+        file.to_str().unwrap(),
+        "--call",
+        "@F(2) × 10",
+    ]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert_eq!(String::from_utf8(out.stdout).unwrap().trim(), "= -7");
+
+    let parsed = amlang::normalize::parse_map("\"a\" = b");
+    assert!(parsed.unwrap_err().contains("line 1"));
+}
+
+#[test]
+fn unicode_output_writes_glyphs_that_read_back() {
+    use amlang::normalize::{denormalize, normalize_unicode_to_ascii};
+
+    let src = "@F(x) = [ x <= 1 && x != 0 ? x ^ 2 * inf ; _ ? \\k -> k ] // a -> b";
+    let pretty = denormalize(src);
+    assert_eq!(
+        pretty,
+        "@F(x) = [ x ≤ 1 ∧ x ≠ 0 ? x² × ∞ ; _ ? λ k → k ] // a -> b"
+    );
+    assert_eq!(denormalize("2 ^ 3 ^ 2 + \"<=\""), "2 ^ 3² + \"<=\"");
+    assert_eq!(
+        normalize_unicode_to_ascii(&pretty),
+        "@F(x) = [ x <= 1 && x != 0 ? x^2 * inf ; _ ? \\ k -> k ] // a -> b"
+    );
+
+    let out = run_with(&["examples/add.am", "--unicode", "--call", "list(1 / 0, pi)"]);
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap().trim(),
+        "= [∞, 3.141592653589793]"
+    );
+}
+
+#[test]
+fn if_then_else_is_a_two_arm_case() {
+    use amlang::Interpreter;
+
+    let mut am = Interpreter::new();
+    am.load_str(
+        "@Abs(x) = if x < 0 then -x else x
+@Sign(x) = if x < 0 then -1 else if x = 0 then 0 else 1",
+    )
+    .unwrap();
+    let show = |am: &Interpreter, src: &str| am.eval_str(src).unwrap().to_string();
+
+    assert_eq!(show(&am, "@Abs(-3)"), "3");
+    assert_eq!(
+        show(&am, "list(@Sign(-2), @Sign(0), @Sign(7))"),
+        "[-1, 0, 1]"
+    );
+    assert_eq!(show(&am, "1 + if 1 > 0 then 2 else 3"), "3");
+    assert!(am.eval_str("if 1 > 0 then 2").is_err());
+}
+
+#[test]
+fn word_operators_read_as_their_symbols() {
+    let show = |src: &str| amlang::eval_str(src).unwrap().to_string();
+
+    assert_eq!(show("1 > 0 and 2 > 0"), "true");
+    assert_eq!(show("1 < 0 or not (2 < 0)"), "true");
+    assert_eq!(show("17 mod 5"), "2");
+    assert_eq!(show("1 > 0 && !(17 % 5 = 2)"), "false");
+    assert_eq!(show("mod_pow(2, 10, 1000)"), "24");
+}
+
+#[test]
+fn match_arms_compare_the_subject_with_values_and_ranges() {
+    use amlang::Interpreter;
+
+    let mut am = Interpreter::new();
+    am.load_str(
+        "@Sign(x) = x of [ 0 -> 0 ; <0 -> -1 ; _ -> 1 ]
+@Grade(s) = s * 10 of [ 90..100 -> \"A\" ; 80..<90 -> \"B\" ; != 0 -> \"C\" ; _ -> \"none\" ]",
+    )
+    .unwrap();
+    let show = |am: &Interpreter, src: &str| am.eval_str(src).unwrap().to_string();
+
+    assert_eq!(
+        show(&am, "list(@Sign(-4), @Sign(0), @Sign(3))"),
+        "[-1, 0, 1]"
+    );
+    assert_eq!(
+        show(&am, "list(@Grade(9.5), @Grade(8), @Grade(5), @Grade(0))"),
+        "[\"A\", \"B\", \"C\", \"none\"]"
+    );
+    assert_eq!(show(&am, "1 of [ 1 -> 2 ]"), "2");
+    assert_eq!(am.eval_str("3 of [ 1 -> 2 ]").unwrap_err().code, "E0206");
+}
+
+#[test]
+fn a_case_without_default_warns_and_fails_cleanly() {
+    let path = std::env::temp_dir().join("amlang_smoke_no_default.am");
+    std::fs::write(
+        &path,
+        "@Pos(x) = [ x > 0 ? 1 ]\n@Sign(x) = [ x < 0 ? -1 ; x >= 0 ? 1 ]\n",
+    )
+    .unwrap();
+    let file = path.to_str().unwrap();
+
+    let out = run_with(&[file, "--call", "Sign(0)"]);
+    let err = String::from_utf8(out.stderr).unwrap();
+    assert!(out.status.success());
+    assert_eq!(err.matches("warning[W0006]").count(), 1);
+    assert!(err.contains("@Pos(x) = [ x > 0 ? 1 ]"));
+
+    let out = run_with(&[file, "--call", "Pos(-2)"]);
+    let err = String::from_utf8(out.stderr).unwrap();
+    assert!(!out.status.success());
+    assert!(err.contains("error[E0206]: no case arm matched and there is no '_' default"));
+    assert!(err.contains("1:11"));
+}
+
+#[test]
+fn where_helpers_are_scoped_to_their_algorithm() {
+    use amlang::Interpreter;
+    use amlang::printer::def_to_source;
+
+    let mut am = Interpreter::new();
+    let warnings = am
+        .load_str(
+            "@F(x) = g(x) + h(x) where g(y) = y + 1; h(z) = [ z > 0 ? z + h(z - 1) ; _ ? 0 ]\n\
+             @G(x) = x >> g where g(t) = t * 10\n",
+        )
+        .unwrap();
+    assert!(warnings.is_empty());
+    assert_eq!(am.eval_str("@F(3)").unwrap().to_string(), "10");
+    assert_eq!(am.eval_str("@G(2)").unwrap().to_string(), "20");
+    assert!(am.eval_str("g(2)").is_err());
+
+    let err = am.load_str("@H(x) = g(x, 1) where g(y) = y").unwrap_err();
+    assert_eq!(err[0].code, "E0303");
+
+    let src = "@G(x) = x >> g where g(t) = t * 10; k(a, b) = a";
+    let (program, _) = amlang::parse(src).unwrap();
+    assert_eq!(def_to_source(&program.defs[0]), src);
+}
+
+#[test]
+fn where_helpers_recurse_as_deep_as_algorithms() {
+    use amlang::Interpreter;
+
+    let mut am = Interpreter::new();
+    am.load_str(
+        "@T(n) = g(n) where g(k) = [ k <= 0 ? 0 ; _ ? g(k - 1) ]\n\
+         @S(n) = s(n) where s(k) = [ k <= 0 ? 0 ; _ ? 1 + s(k - 1) ]\n",
+    )
+    .unwrap();
+    // tail calls reuse the helper's frame
+    assert_eq!(am.eval_str("@T(100000)").unwrap().to_string(), "0");
+    assert_eq!(am.eval_str("@S(5000)").unwrap().to_string(), "5000");
+    am.settings_mut().max_depth = 100;
+    let err = am.eval_str("@S(500)").unwrap_err();
+    assert_eq!(err.code, "E0204");
+    assert!(err.message.contains("while calling s"), "{}", err.message);
+}
+
+#[test]
+fn tuples_return_several_values_and_unpack_into_parameters() {
+    use amlang::Interpreter;
+
+    let mut am = Interpreter::new();
+    am.load_str(
+        "@Ext(a, b) = [ b = 0 ? (a, 1, 0) ; _ ? @Ext(b, a % b) >> \\(g, x, y) -> (g, y, x - floor(a / b) * y) ]\n\
+         @Digits(q, r) = q * 10 + r",
+    )
+    .unwrap();
+    let show = |am: &Interpreter, src: &str| am.eval_str(src).unwrap().to_string();
+
+    assert_eq!(show(&am, "@Ext(240, 46)"), "(2, -9, 47)");
+    assert_eq!(show(&am, "divmod(17, -5)"), "(-4, -3)");
+    assert_eq!(show(&am, "divmod(17, 5) >> @Digits"), "32");
+    assert_eq!(show(&am, "(1, 2) = (1, 2)"), "true");
+    assert!(am.eval_str("(1, 2) + 1").is_err());
+}
+
+#[test]
+fn assert_and_error_stop_evaluation_with_their_message() {
+    use amlang::Interpreter;
+
+    let mut am = Interpreter::new();
+    am.load_str(
+        "@Fact(n) = [ assert(n >= 0, \"n must be nonnegative\") && n < 2 ? 1 ; _ ? n * @Fact(n - 1) ]\n\
+         @Inv(x) = [ x = 0 ? error(\"no inverse of 0\") ; _ ? 1 / x ]",
+    )
+    .unwrap();
+
+    assert_eq!(am.eval_str("@Fact(5)").unwrap().to_string(), "120");
+    let err = am.eval_str("@Fact(-1)").unwrap_err();
+    assert_eq!(err.message, "assertion failed: n must be nonnegative");
+    assert_eq!(err.span.map(|s| s.start), Some(13));
+    let err = am.eval_str("@Inv(0)").unwrap_err();
+    assert_eq!(err.message, "no inverse of 0");
+    assert_eq!(err.code, "E0200");
+}
+
+#[test]
+fn contracts_are_checked_on_every_call() {
+    use amlang::Interpreter;
+    use amlang::printer::def_to_source;
+
+    let src = "@Sqrt(x) requires x >= 0 ensures result >= 0 = sqrt(x)\n\
+               @Bad(x) ensures (result = x) = x + 1";
+    let mut am = Interpreter::new();
+    am.load_str(src).unwrap();
+
+    assert_eq!(am.eval_str("@Sqrt(9)").unwrap().to_string(), "3");
+    let err = am.eval_str("@Sqrt(-4)").unwrap_err();
+    assert_eq!(err.code, "E0207");
+    assert_eq!(err.message, "precondition of @Sqrt violated: x >= 0");
+    let err = am.eval_str("@Bad(1)").unwrap_err();
+    assert_eq!(
+        err.message,
+        "postcondition of @Bad violated: result = x (result = 2)"
+    );
+
+    let (program, _) = amlang::parse(src).unwrap();
+    let printed: Vec<String> = program.defs.iter().map(def_to_source).collect();
+    assert_eq!(printed.join("\n"), src);
+}
+
+#[test]
+fn test_declarations_report_each_result() {
+    let path = std::env::temp_dir().join("amlang_smoke_tests.am");
+    let src = "@Add(a, b) = a + b\n\
+               @test \"add works\" : Add(1, 4) = 5\n\
+               @test \"add is wrong\" : Add(1, 1) = 3\n";
+    std::fs::write(&path, src).unwrap();
+    let out = run_with(&["test", path.to_str().unwrap()]);
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(!out.status.success());
+    assert!(stdout.contains("test add works ... ok"), "{stdout}");
+    assert!(stdout.contains("test add is wrong ... FAILED"), "{stdout}");
+    assert!(stdout.contains("1 passed, 1 failed"), "{stdout}");
+    assert!(
+        stderr.contains("E0601") && stderr.contains(":3:"),
+        "{stderr}"
+    );
+
+    std::fs::write(&path, "@Add(a, b) = a + b\n@test \"ok\" : Add(2, 2) = 4\n").unwrap();
+    assert!(run_with(&["test", path.to_str().unwrap()]).status.success());
+
+    // a file of tests alone still runs, pointing at `test`
+    std::fs::write(&path, "@test \"t\" : 1 + 1 = 2\n").unwrap();
+    let out = run_with(&["run", path.to_str().unwrap()]);
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(stdout.contains("Loaded 0 algorithm(s)"), "{stdout}");
+    assert!(stdout.contains("-- test "), "{stdout}");
+    assert!(run_with(&["test", path.to_str().unwrap()]).status.success());
+}
+
+#[test]
+fn golden_files_match_their_expected_output() {
+    let out = run_with(&["test", "--golden", "tests/golden"]);
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert!(out.status.success(), "{stdout}");
+    assert!(
+        stdout.contains("golden tests/golden/factorial.am ... ok"),
+        "{stdout}"
+    );
+
+    let dir = std::env::temp_dir().join("amlang_smoke_golden");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("sq.am"), "@Sq(x) = x * x\nSq(3)\n").unwrap();
+    std::fs::write(dir.join("sq.expected"), "= 10\n").unwrap();
+    let out = run_with(&["test", "--golden", dir.to_str().unwrap()]);
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(!out.status.success());
+    assert!(
+        stderr.contains("expected: = 10") && stderr.contains("got: = 9"),
+        "{stderr}"
+    );
+
+    let out = run_with(&["test", "--golden", dir.to_str().unwrap(), "--update"]);
+    assert!(out.status.success());
+    assert_eq!(
+        std::fs::read_to_string(dir.join("sq.expected")).unwrap(),
+        "= 9\n"
+    );
+}
+
+#[test]
+fn equiv_reports_the_first_counterexample() {
+    let path = std::env::temp_dir().join("amlang_smoke_equiv.am");
+    let src = "@Fact(n) = [ n <= 1 ? 1 ; _ ? n * @Fact(n - 1) ]\n\
+               @Fact2(n) = [ n < 2 ? 1 ; _ ? @Fact2(n - 1) * n ]\n\
+               @Broken(n) = [ n = 7 ? 0 ; n <= 1 ? 1 ; _ ? n * @Broken(n - 1) ]\n";
+    std::fs::write(&path, src).unwrap();
+    let file = path.to_str().unwrap();
+
+    let out = run_with(&[file, "--equiv", "@Fact @Fact2 over 0..20"]);
+    assert!(out.status.success());
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert!(
+        stdout.contains("agree on 21 input(s) from 0..20"),
+        "{stdout}"
+    );
+
+    let out = run_with(&[file, "--equiv", "Fact Broken"]);
+    assert!(!out.status.success());
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(
+        stderr.contains("@Fact(7) = 5040 but @Broken(7) = 0"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn check_against_compares_an_algorithm_with_rust() {
+    use amlang::Value;
+    use amlang::equiv::{Sampler, check_against};
+
+    fn gcd(a: i64, b: i64) -> i64 {
+        if b == 0 { a.abs() } else { gcd(b, a % b) }
+    }
+    let reference = |args: &[Value]| {
+        let (a, b) = (args[0].as_f64()? as i64, args[1].as_f64()? as i64);
+        Ok(Value::Number(gcd(a, b) as f64))
+    };
+    let (good, _) = amlang::parse("@Gcd(a, b) = [ b = 0 ? abs(a) ; _ ? @Gcd(b, a % b) ]").unwrap();
+    let tried = check_against(&good.defs, "Gcd", reference, &Sampler::new(0, 30));
+    assert_eq!(tried, Ok(Ok(961)));
+
+    let (bad, _) = amlang::parse("@Gcd(a, b) = [ b = 0 ? a ; _ ? @Gcd(b, a - b) ]").unwrap();
+    let c = check_against(&bad.defs, "Gcd", reference, &Sampler::new(0, 30))
+        .unwrap()
+        .unwrap_err();
+    assert!(c.describe("@Gcd", "gcd").starts_with("@Gcd("), "{c:?}");
+    assert_ne!(c.left, c.right);
+
+    let (typo, _) = amlang::parse("@Gcd(a, b) = @Gdc(b, a)").unwrap();
+    assert!(check_against(&typo.defs, "Gcd", reference, &Sampler::new(0, 3)).is_err());
+}
+
+#[test]
+fn doc_comments_attach_to_the_definition_below() {
+    let src = "/// The greatest common divisor.\n\
+               ///\n\
+               /// Euclid's algorithm.\n\
+               @Gcd(a, b) = [ b = 0 ? a ; _ ? @Gcd(b, a % b) ]\n\
+               /// detached by the blank line\n\
+               \n\
+               @Id(x) = x // not documentation\n\
+               /// Twice x.\n\
+               @memo @Double(x) = 2 * x\n";
+    let (program, _) = amlang::parse(src).unwrap();
+    let docs: Vec<Option<&str>> = program.defs.iter().map(|d| d.doc.as_deref()).collect();
+    assert_eq!(
+        docs,
+        [
+            Some("The greatest common divisor.\n\nEuclid's algorithm."),
+            None,
+            Some("Twice x."),
+        ]
+    );
+}
+
+#[test]
+fn doc_prints_a_reference_of_signatures_and_docs() {
+    let path = std::env::temp_dir().join("amlang_smoke_doc.am");
+    let src = "/// Squares <x>.\n@Sq(x) requires x >= 0 = x * x\n@Id(x) = x\n";
+    std::fs::write(&path, src).unwrap();
+    let file = path.to_str().unwrap();
+
+    let out = run_with(&["doc", file, "--latex"]);
+    assert!(out.status.success());
+    let md = String::from_utf8(out.stdout).unwrap();
+    assert!(
+        md.contains("## `Sq(x) requires x >= 0`\n\nSquares <x>.\n"),
+        "{md}"
+    );
+    assert!(md.contains("## `Id(x)`"), "{md}");
+    assert!(md.contains("$$\n\\operatorname{Sq}(x) = "), "{md}");
+
+    let out = run_with(&["doc", file, "--html"]);
+    let html = String::from_utf8(out.stdout).unwrap();
+    assert!(html.contains("<p>Squares &lt;x&gt;.</p>"), "{html}");
+    assert!(!html.contains("\\["), "{html}");
+}
+
+#[test]
+fn the_ast_can_be_walked_and_rebuilt() {
+    use amlang::ast::{ExprKind, for_each_expr};
+    use amlang::printer::expr_to_source;
+
+    let (program, _) = amlang::parse("@F(x) = [ x > 0 ? x * 2 ; _ ? -abs(x) ]").unwrap();
+    let body = &program.defs[0].body;
+    let mut idents = Vec::new();
+    for_each_expr(body, &mut |e| {
+        if let ExprKind::Ident(name) = &e.kind {
+            idents.push(name.as_str());
+        }
+    });
+    assert_eq!(idents, ["x", "x", "x"]);
+    assert_eq!(body.children().len(), 3);
+
+    fn rename(e: &amlang::Expr) -> amlang::Expr {
+        match &e.kind {
+            ExprKind::Ident(name) if name == "x" => {
+                amlang::Expr::new(ExprKind::Ident("y".into()), e.span)
+            }
+            _ => e.map_children(rename),
+        }
+    }
+    assert_eq!(
+        expr_to_source(&rename(body)),
+        "[ y > 0 ? y * 2 ; _ ? -abs(y) ]"
+    );
+}
+
+#[test]
+fn rewrite_rules_match_patterns_and_power_simplify() {
+    use amlang::printer::expr_to_source;
+    use amlang::rewrite::{Rule, rewrite};
+    use amlang::simplify::{simplify, simplify_with};
+
+    let parse = |src: &str| {
+        let (program, _) = amlang::parse(src).unwrap();
+        program.script.into_iter().next().unwrap()
+    };
+    let e = parse("sqrt((a + 1)^2) * 1");
+    assert_eq!(expr_to_source(&simplify(&e)), "abs(a + 1)");
+
+    let double = [Rule::parse("x + x => 2 * x").unwrap()];
+    assert_eq!(double[0].to_string(), "x + x => 2 * x");
+    assert_eq!(
+        expr_to_source(&rewrite(&parse("f(y) + f(y)"), &double)),
+        "2 * f(y)"
+    );
+    assert_eq!(
+        expr_to_source(&rewrite(&parse("f(y) + f(z)"), &double)),
+        "f(y) + f(z)"
+    );
+    let e = parse("sqrt(b^2) + abs(b)");
+    assert_eq!(expr_to_source(&simplify_with(&e, &double)), "2 * abs(b)");
+
+    assert!(Rule::parse("x + 0 => y").is_err());
+    assert!(Rule::parse("x + 0").is_err());
+    // a rule that never stops applying is cut off rather than looping
+    let swap = Rule::parse("x + y => y + x").unwrap();
+    rewrite(&parse("a + b"), &[swap]);
+}
+
+#[cfg(feature = "capi")]
+#[test]
+fn the_c_abi_loads_calls_and_reports_errors() {
+    use amlang::capi::{am_call, am_free, am_last_error, am_load, am_new};
+    use std::ffi::{CStr, CString};
+
+    unsafe {
+        let am = am_new();
+        assert!(am_last_error(am).is_null());
+        let src = CString::new("@Hyp(a, b) = sqrt(a^2 + b^2)").unwrap();
+        assert_eq!(am_load(am, src.as_ptr()), 0);
+
+        let name = CString::new("Hyp").unwrap();
+        let args = [3.0, 4.0];
+        let mut out = 0.0;
+        assert_eq!(am_call(am, name.as_ptr(), args.as_ptr(), 2, &mut out), 0);
+        assert_eq!(out, 5.0);
+
+        let missing = CString::new("Nope").unwrap();
+        assert_eq!(
+            am_call(am, missing.as_ptr(), args.as_ptr(), 2, &mut out),
+            -1
+        );
+        let error = CStr::from_ptr(am_last_error(am)).to_str().unwrap();
+        assert!(error.contains("Nope"), "{}", error);
+
+        let bad = CString::new("@Bad(x) = x +").unwrap();
+        assert_eq!(am_load(am, bad.as_ptr()), -1);
+        assert!(!am_last_error(am).is_null());
+        am_free(am);
+    }
+}
+
+#[test]
+fn registered_closures_take_typed_arguments() {
+    use amlang::{Interpreter, Value};
+
+    let mut am = Interpreter::new();
+    am.register("scale", |xs: Vec<f64>, k: i64| {
+        xs.into_iter().map(|x| x * k as f64).collect::<Vec<_>>()
+    });
+    am.register("shout", |s: String| s.to_uppercase());
+    am.register("answer", || 42.0);
+
+    let v = am.eval_str("scale([1, 2, 3], 2)").unwrap();
+    assert_eq!(v.to_string(), "[2, 4, 6]");
+    assert_eq!(am.eval_str("shout(\"hi\")").unwrap().to_string(), "\"HI\"");
+    assert_eq!(am.call("answer", &[]).unwrap().to_string(), "42");
+
+    let wrong = am.eval_str("scale([1], 0.5)").unwrap_err();
+    assert!(wrong.message.contains("argument 2"), "{}", wrong.message);
+    let wrong = am.call("shout", &[Value::Number(1.0)]).unwrap_err();
+    assert!(wrong.message.contains("argument 1"), "{}", wrong.message);
+    // the arity comes from the closure and is checked when loading
+    assert!(am.load_str("@Bad(x) = shout(x, x)").is_err());
+}
+
+#[test]
+fn one_interpreter_serves_many_threads() {
+    use amlang::{Interpreter, Value};
+    use std::sync::Arc;
+
+    let mut am = Interpreter::new();
+    am.load_str("@memo @Fib(n) = [ n < 2 ? n ; _ ? @Fib(n - 1) + @Fib(n - 2) ]")
+        .unwrap();
+    let am = Arc::new(am);
+    let handles: Vec<_> = (0..8)
+        .map(|i| {
+            let am = Arc::clone(&am);
+            std::thread::spawn(move || am.call("Fib", &[Value::Number(20.0 + i as f64)]))
+        })
+        .collect();
+    let fibs: Vec<String> = handles
+        .into_iter()
+        .map(|h| h.join().unwrap().unwrap().to_string())
+        .collect();
+    assert_eq!(fibs[0], "6765");
+    assert_eq!(fibs[7], "196418");
+}
+
+#[test]
+fn map_calls_an_algorithm_on_every_csv_row() {
+    let dir = std::env::temp_dir();
+    let file = dir.join("amlang_smoke_map.am");
+    std::fs::write(&file, "@Hyp(a, b) = sqrt(a^2 + b^2)\n").unwrap();
+    let csv = dir.join("amlang_smoke_map.csv");
+    let rows: String = (1..=50).map(|k| format!("{},{}\n", 3 * k, 4 * k)).collect();
+    std::fs::write(&csv, format!("a,b\n{rows}")).unwrap();
+
+    let spec = format!("Hyp {}", csv.display());
+    let out = run_with(&["run", file.to_str().unwrap(), "--map", &spec]);
+    assert!(out.status.success());
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 51);
+    assert_eq!(lines[0], "a,b,result");
+    assert_eq!(lines[1], "3,4,5");
+    assert_eq!(lines[50], "150,200,250");
+
+    std::fs::write(&csv, "3,4\n1,x\n").unwrap();
+    let out = run_with(&["run", file.to_str().unwrap(), "--map", &spec]);
+    assert!(!out.status.success());
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert_eq!(stdout, "3,4,5\n1,x,\n");
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(stderr.contains("1 of 2 rows failed"), "{}", stderr);
+}
+
+#[test]
+fn a_failed_series_leaves_its_index_unbound() {
+    use amlang::eval::{Env, Value, World, eval_expr};
+
+    let (program, _) = amlang::parse("@F(x) = sum(j, 1, 2, error(\"deep\"))").unwrap();
+    let world = World::new(&program.defs);
+    let eval = |env: &mut Env, src: &str| {
+        let (program, _) = amlang::parse(src).unwrap();
+        eval_expr(&world, env, &program.script[0])
+    };
+    let mut env = Env::base();
+    assert!(eval(&mut env, "sum(k, 1, 3, 1 / k + error(\"boom\"))").is_err());
+    assert!(eval(&mut env, "k").is_err());
+    // the error may come from a call made by the body
+    assert!(eval(&mut env, "prod(k, 1, 3, @F(k))").is_err());
+    assert!(eval(&mut env, "k").is_err());
+
+    // a binding the index hid comes back
+    env.set("k".to_string(), Value::Number(7.0));
+    assert!(eval(&mut env, "sum(k, 1, 3, error(\"boom\"))").is_err());
+    assert_eq!(eval(&mut env, "k").unwrap().to_string(), "7");
+}
+
+#[test]
+fn a_failed_pipeline_step_leaves_it_unbound() {
+    use amlang::eval::{Env, Value, World, eval_expr};
+
+    let world = World::new(&[]);
+    let eval = |env: &mut Env, src: &str| {
+        let (program, _) = amlang::parse(src).unwrap();
+        eval_expr(&world, env, &program.script[0])
+    };
+    let mut env = Env::base();
+    assert!(eval(&mut env, "[1, 2] >> [ it = 0 ? 1 ; _ ? error(\"boom\") ]").is_err());
+    assert!(eval(&mut env, "it").is_err());
+    assert!(eval(&mut env, "_").is_err());
+
+    env.set("it".to_string(), Value::Number(3.0));
+    assert!(eval(&mut env, "5 >> [ it > 1 ? error(\"boom\") ; _ ? 0 ]").is_err());
+    assert_eq!(eval(&mut env, "it").unwrap().to_string(), "3");
+}
+
+#[test]
+fn session_assignments_bind_names_for_later_inputs() {
+    use amlang::eval::{Env, World, eval_expr};
+    use amlang::session::{Input, assign, parse_input};
+
+    let parse = |src: &str| parse_input(src).unwrap().unwrap().0;
+    let world = World::new(&[]);
+    let mut env = Env::base();
+
+    let Input::Assign(names, expr) = parse("x = 2 + 1") else {
+        panic!("expected an assignment");
+    };
+    let v = eval_expr(&world, &mut env, &expr).unwrap();
+    assign(&mut env, names, v).unwrap();
+    let Input::Assign(names, expr) = parse("(q, r) = divmod(17, 5)") else {
+        panic!("expected an assignment");
+    };
+    let v = eval_expr(&world, &mut env, &expr).unwrap();
+    let bound = assign(&mut env, names, v).unwrap();
+    assert_eq!(bound.len(), 2);
+
+    // the bindings outlive the input that made them
+    let Input::Eval(expr) = parse("x * q + r") else {
+        panic!("expected an expression");
+    };
+    assert_eq!(
+        eval_expr(&world, &mut env, &expr).unwrap().to_string(),
+        "11"
+    );
+
+    // `=` anywhere else is still equality
+    assert!(matches!(parse("x = 3 == true"), Input::Assign(..)));
+    assert!(matches!(parse("1 + x = 3"), Input::Eval(_)));
+    assert!(matches!(parse("(x) = 3"), Input::Eval(_)));
+    assert!(matches!(parse("@F(n) = n"), Input::Define(_)));
+    assert!(parse_input("// nothing here").unwrap().is_none());
+
+    let err = assign(
+        &mut env,
+        vec!["a".into(), "b".into()],
+        amlang::Value::Number(5.0),
+    );
+    assert_eq!(err.unwrap_err(), "cannot unpack 5 into 2 names");
+}
+
+#[test]
+fn ans_holds_the_last_expression_result() {
+    use amlang::eval::{Env, World, eval_expr};
+    use amlang::session::{Input, assign, parse_input, set_answer};
+
+    let world = World::new(&[]);
+    let mut env = Env::base();
+    let mut enter = |src: &str| -> Result<String, String> {
+        let (input, _) = parse_input(src).unwrap().unwrap();
+        match input {
+            Input::Eval(e) => {
+                let v = eval_expr(&world, &mut env, &e).map_err(|e| e.message)?;
+                set_answer(&mut env, v.clone());
+                Ok(v.to_string())
+            }
+            Input::Assign(names, e) => {
+                let v = eval_expr(&world, &mut env, &e).map_err(|e| e.message)?;
+                assign(&mut env, names, v).map(|_| String::new())
+            }
+            Input::Define(_) => unreachable!(),
+        }
+    };
+    assert!(enter("ans").is_err());
+    assert_eq!(enter("6 * 7").unwrap(), "42");
+    assert_eq!(enter("ans + 1").unwrap(), "43");
+    // neither an assignment nor an error replaces it
+    enter("x = 100").unwrap();
+    assert!(enter("1 / nope").is_err());
+    assert_eq!(enter("ans").unwrap(), "43");
+}
+
+#[test]
+fn saved_sessions_load_back_unchanged() {
+    use amlang::loader::{load_files, search_path};
+    use amlang::session::save_source;
+
+    let src = "/// Twice x.\n@Double(x) = x * 2\n@memo @Fib(n) = [ n < 2 ? n ; _ ? @Fib(n - 1) + @Fib(n - 2) ]\n";
+    let (program, _) = amlang::parse(src).unwrap();
+    let saved = save_source(&program.defs);
+    assert!(saved.starts_with("/// Twice x.\n@Double(x)"), "{}", saved);
+
+    let path = std::env::temp_dir().join("amlang_smoke_save.am");
+    std::fs::write(&path, &saved).unwrap();
+    let files = load_files(&[path.to_str().unwrap().to_string()], &search_path(&[])).unwrap();
+    assert!(files[0].diags.is_empty());
+    assert_eq!(files[0].defs[0].doc.as_deref(), Some("Twice x."));
+    // saving what was loaded gives the same text again
+    assert_eq!(save_source(&files[0].defs), saved);
+}
+
+#[test]
+fn unfinished_input_continues_on_the_next_line() {
+    use amlang::session::needs_continuation;
+
+    for open in [
+        "@F(x) = [ x > 0 ?",
+        "@F(x) = [ x > 0 ? x ;\n_ ? 0",
+        "max(1,",
+        "\"unterminated",
+        "1 +",
+        "@G(x) =",
+        "x |>",
+    ] {
+        assert!(needs_continuation(open), "{:?} should continue", open);
+    }
+    for done in [
+        "@F(x) = [ x > 0 ? x ;\n_ ? 0 ]",
+        "max(1, 2)",
+        "\"a string\"",
+        "1 + 2",
+        "-1",
+        ":load lib(",
+        "",
+    ] {
+        assert!(!needs_continuation(done), "{:?} should not continue", done);
+    }
+}
+
+#[test]
+fn completion_offers_commands_algorithms_and_names() {
+    use amlang::eval::{Env, Value};
+    use amlang::session::Completions;
+
+    let (program, _) = amlang::parse("@Gcd(a, b) = a\n@Gauss(n) = n").unwrap();
+    let mut env = Env::base();
+    env.set("gamma_total".to_string(), Value::Number(1.0));
+    let c = Completions::new(&program.defs, &env);
+
+    assert_eq!(c.complete(":lo", 3), (0, vec![":load".to_string()]));
+    // a command only starts a line
+    assert_eq!(c.complete("x :lo", 5).1, Vec::<String>::new());
+    assert_eq!(c.complete("1 + @Ga", 7), (5, vec!["Gauss".to_string()]));
+    let (start, found) = c.complete("ga", 2);
+    assert_eq!(start, 0);
+    assert!(found.contains(&"gamma_total".to_string()), "{:?}", found);
+    assert!(!found.contains(&"Gauss".to_string()));
+    assert!(c.complete("sq", 2).1.contains(&"sqrt".to_string()));
+}
+
+#[test]
+fn ast_shows_the_tree_of_an_expression_or_definition() {
+    use amlang::session::ast_text;
+
+    assert_eq!(
+        ast_text("1 + x * 2").unwrap(),
+        "Bin(Add)\n  Number(1)\n  Bin(Mul)\n    Ident(x)\n    Number(2)\n"
+    );
+    // at the prompt `x = 1` assigns, but here it is an equality
+    assert!(ast_text("x = 1").unwrap().starts_with("Bin(Eq)"));
+    let tree = ast_text("@Sq(x) = x ^ 2").unwrap();
+    assert!(
+        tree.starts_with("AlgorithmDef Sq(x)\nbody:\n  Bin(Pow)\n"),
+        "{}",
+        tree
+    );
+    assert!(ast_text("1 +").is_err());
+}
+
+#[test]
+fn factorial_and_binomial_of_huge_arguments_overflow_at_once() {
+    use amlang::Interpreter;
+
+    let mut am = Interpreter::new();
+    for big in [false, true] {
+        am.settings_mut().bigint = big;
+        for src in [
+            "factorial(9000000000000)",
+            "binomial(9000000000000, 4500000000000)",
+            "binomial(9000000000000, 3000)",
+        ] {
+            assert_eq!(am.eval_str(src).unwrap().to_string(), "inf", "{}", src);
+        }
+    }
+    am.settings_mut().bigint = false;
+    assert_eq!(
+        am.eval_str("factorial(170) > 7e306").unwrap().to_string(),
+        "true"
+    );
+    assert_eq!(
+        am.eval_str("binomial(9000000000000, 1)")
+            .unwrap()
+            .to_string(),
+        "9000000000000"
+    );
+}
+
+#[test]
+fn interpreter_calls_pass_argument_values_through_unchanged() {
+    use amlang::complex::Complex;
+    use amlang::{Interpreter, Value};
+
+    let mut am = Interpreter::new();
+    am.load_str("@Id(x) = x").unwrap();
+    for v in [
+        Value::Str("say \"hi\"\n".to_string()),
+        Value::Number(-0.0),
+        Value::Number(0.1 + 0.2),
+        Value::Interval(1.0, 2.5),
+        Value::Complex(Complex { re: 1.0, im: -2.0 }),
+        Value::List(vec![Value::Bool(true), Value::AlgRef("Id".to_string())]),
+    ] {
+        assert_eq!(am.call("Id", std::slice::from_ref(&v)).unwrap(), v);
+    }
+    let batch = am.call_batch("Id", &[vec![Value::Number(-0.0)]]);
+    assert!(matches!(batch[0], Ok(Value::Number(x)) if x.is_sign_negative()));
+
+    // arguments are not evaluated again, so they cost no steps and do not
+    // reach a host function that replaced the builtin making them
+    let big = Value::List((0..10_000).map(|i| Value::Number(i as f64)).collect());
+    am.settings_mut().max_steps = Some(100);
+    assert_eq!(am.call("Id", std::slice::from_ref(&big)).unwrap(), big);
+    am.register("interval", |_: f64, _: f64| -> Result<f64, String> {
+        Err("replaced".to_string())
+    });
+    let v = Value::Interval(1.0, 2.0);
+    assert_eq!(am.call("Id", std::slice::from_ref(&v)).unwrap(), v);
+}
+
+#[test]
+fn input_left_after_an_expression_is_an_error() {
+    use amlang::session::parse_input;
+
+    let err = amlang::eval_str("1 2").unwrap_err();
+    assert!(err.message.contains("end of the input"), "{}", err.message);
+    assert!(parse_input("x = 1 2").is_err());
+    assert!(parse_input("@F(x) = x 2").is_err());
+
+    let out = run_with(&["run", "examples/add.am", "--call", "S(1) 2"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8(out.stdout).unwrap().is_empty());
+    let err = String::from_utf8(out.stderr).unwrap();
+    assert!(err.contains("E0101") && err.contains("1:6"), "{}", err);
+}